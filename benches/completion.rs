@@ -0,0 +1,75 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use mini_async_repl::{
+    command::{Command, CommandArgInfo, CommandArgType, TrivialCommandHandler},
+    repl::ReplBuilder,
+    runtime::block_on,
+    script::Replay,
+    Repl,
+};
+
+const COMMANDS: usize = 10_000;
+
+/// One command per "API endpoint", like in machine-generated REPLs.
+fn builder() -> ReplBuilder {
+    let mut builder = Repl::builder().out(Box::new(std::io::sink()) as Box<dyn std::io::Write>);
+    for i in 0..COMMANDS {
+        builder = builder.add(
+            &format!("endpoint-{}-{}", i % 100, i),
+            Command::new(
+                "Generated command",
+                vec![CommandArgInfo::new(CommandArgType::I32)],
+                Box::new(TrivialCommandHandler::new()),
+            ),
+        );
+    }
+    builder
+}
+
+fn completion(c: &mut Criterion) {
+    let repl = builder().build().unwrap();
+    let registry = repl.registry();
+
+    c.bench_function("candidates unbounded", |b| {
+        b.iter(|| registry.candidates(black_box("endpoint-"), None))
+    });
+    c.bench_function("candidates limit 50", |b| {
+        b.iter(|| registry.candidates(black_box("endpoint-"), Some(50)))
+    });
+    c.bench_function("candidates unique", |b| {
+        b.iter(|| registry.candidates(black_box("endpoint-42-4242"), Some(50)))
+    });
+    c.bench_function("add and remove", |b| {
+        b.iter(|| {
+            registry
+                .add(
+                    "endpoint-new",
+                    Command::new("", vec![], Box::new(TrivialCommandHandler::new())),
+                )
+                .unwrap();
+            registry.remove("endpoint-new")
+        })
+    });
+}
+
+fn dispatch(c: &mut Criterion) {
+    let mut repl = builder().completion_limit(50).build().unwrap();
+
+    c.bench_function("handle line", |b| {
+        b.iter(|| block_on(repl.handle_line(black_box("endpoint-42-4242 13"))).unwrap())
+    });
+    c.bench_function("handle ambiguous line", |b| {
+        b.iter(|| block_on(repl.handle_line(black_box("endpoint-4 13"))).unwrap())
+    });
+    let session = Replay::new([
+        "endpoint-42-4242 13",
+        "endpoint-4 13",
+        "endpoint-7-707 x",
+        "endpoint-9",
+    ]);
+    c.bench_function("replay session", |b| {
+        b.iter(|| block_on(repl.replay(black_box(&session))).unwrap())
+    });
+}
+
+criterion_group!(benches, completion, dispatch);
+criterion_main!(benches);
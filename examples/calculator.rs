@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use anyhow::{self, Context};
+use mini_async_repl::{
+    command::{Command, CommandArgInfo, CommandArgType, SyncCommandHandler},
+    eval::Evaluator,
+    CommandStatus, Repl,
+};
+
+/// Evaluates sums and differences of numbers and variables, e.g. `x = 2` and `x + 1.5`.
+#[derive(Default)]
+struct Calculator {
+    variables: HashMap<String, f64>,
+}
+
+impl Calculator {
+    fn term(&self, term: &str) -> anyhow::Result<f64> {
+        match self.variables.get(term) {
+            Some(value) => Ok(*value),
+            None => term
+                .parse()
+                .with_context(|| format!("unknown variable '{term}'")),
+        }
+    }
+
+    fn expression(&self, expression: &str) -> anyhow::Result<f64> {
+        let mut words = expression.split_whitespace();
+        let mut value = self.term(words.next().context("empty expression")?)?;
+        while let Some(op) = words.next() {
+            let term = self.term(words.next().context("missing term")?)?;
+            value = match op {
+                "+" => value + term,
+                "-" => value - term,
+                _ => anyhow::bail!("unknown operator '{op}'"),
+            };
+        }
+        Ok(value)
+    }
+}
+
+impl Evaluator for Calculator {
+    fn eval(&mut self, input: &str) -> anyhow::Result<Option<String>> {
+        if let Some((name, expression)) = input.split_once('=') {
+            let value = self.expression(expression)?;
+            self.variables.insert(name.trim().to_string(), value);
+            return Ok(None);
+        }
+        self.expression(input).map(|value| Some(value.to_string()))
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // commands take precedence over expressions, and can be given the last result as `$_`
+    let round_cmd = Command::new(
+        "Round X to the nearest integer",
+        vec![CommandArgInfo::new_with_name(CommandArgType::F32, "X")],
+        Box::new(SyncCommandHandler::new(|args: Vec<String>| {
+            println!("{}", args[0].parse::<f32>()?.round());
+            Ok(CommandStatus::Done)
+        })),
+    );
+
+    #[rustfmt::skip]
+    let mut repl = Repl::builder()
+        .description("Calculator: enter expressions like `x = 2` and `x + 1.5`, or commands")
+        .prompt("calc> ")
+        .add("round", round_cmd)
+        .evaluator(Calculator::default())
+        .build()
+        .context("Failed to create repl")?;
+
+    repl.run().await.context("Critical REPL error")?;
+
+    Ok(())
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::io_other_error)]
+
 use std::time::Instant;
 
 use anyhow::{self, Context};
@@ -21,12 +23,12 @@ impl OkCommandHandler {
     }
 }
 impl ExecuteCommand for OkCommandHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let valid = validate(args, args_info);
         if let Err(e) = valid {
             return Box::pin(lift_validation_err(Err(e)));
         }
@@ -50,16 +52,16 @@ impl RecoverableErrorHandler {
     }
 }
 impl ExecuteCommand for RecoverableErrorHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let valid = validate(args, args_info);
         if let Err(e) = valid {
             return Box::pin(lift_validation_err(Err(e)));
         }
-        Box::pin(self.handle_command(args[0].clone()))
+        Box::pin(self.handle_command(args[0].to_string()))
     }
 }
 
@@ -80,16 +82,16 @@ impl CriticalErrorHandler {
     }
 }
 impl ExecuteCommand for CriticalErrorHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let valid = validate(args, args_info);
         if let Err(e) = valid {
             return Box::pin(lift_validation_err(Err(e)));
         }
-        Box::pin(self.handle_command(args[0].clone()))
+        Box::pin(self.handle_command(args[0].to_string()))
     }
 }
 
@@ -117,12 +119,12 @@ impl RouletteErrorHandler {
     }
 }
 impl ExecuteCommand for RouletteErrorHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let valid = validate(args, args_info);
         if let Err(e) = valid {
             return Box::pin(lift_validation_err(Err(e)));
         }
@@ -25,17 +25,17 @@ impl LsCommandHandler {
     }
 }
 impl ExecuteCommand for LsCommandHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let valid = validate(args, args_info);
         if let Err(e) = valid {
             return Box::pin(lift_validation_err(Err(e)));
         }
 
-        let dir_buf: PathBuf = args[0].clone().into();
+        let dir_buf = PathBuf::from(args[0]);
         Box::pin(self.handle_command(dir_buf))
     }
 }
@@ -51,12 +51,12 @@ impl IpAddrCommandHandler {
     }
 }
 impl ExecuteCommand for IpAddrCommandHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let valid = validate(args, args_info);
         if let Err(e) = valid {
             return Box::pin(lift_validation_err(Err(e)));
         }
@@ -66,13 +66,14 @@ impl ExecuteCommand for IpAddrCommandHandler {
         match ip {
             Ok(ip) => Box::pin(self.handle_command(ip)),
             Err(e) => Box::pin(lift_validation_err(Err(ArgsError::WrongArgumentValue {
-                argument: args[0].clone(),
+                argument: args[0].to_string(),
                 error: e.to_string(),
             }))),
         }
     }
 }
 
+#[allow(clippy::useless_conversion)]
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     #[rustfmt::skip]
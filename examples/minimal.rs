@@ -1,7 +1,8 @@
 use anyhow::{self, Context};
 use mini_async_repl::{
     command::{
-        lift_validation_err, validate, Command, CommandArgInfo, CommandArgType, ExecuteCommand,
+        lift_validation_err, validate, Command, CommandArgInfo, CommandArgType, CommandContext,
+        ExecuteCommand, ExecuteCommandWithContext,
     },
     CommandStatus, Repl,
 };
@@ -19,16 +20,16 @@ impl SayHelloCommandHandler {
     }
 }
 impl ExecuteCommand for SayHelloCommandHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let valid = validate(args, args_info);
         if valid.is_err() {
             return Box::pin(lift_validation_err(valid));
         }
-        Box::pin(self.handle_command(args[0].clone()))
+        Box::pin(self.handle_command(args[0].to_string()))
     }
 }
 
@@ -42,24 +43,17 @@ impl AddCommandHandler {
         Ok(CommandStatus::Done)
     }
 }
-impl ExecuteCommand for AddCommandHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
-        if valid.is_err() {
-            return Box::pin(lift_validation_err(valid));
-        }
-
-        let x = args[0].parse::<i32>();
-        let y = args[1].parse::<i32>();
-
-        match (x, y) {
-            (Ok(x), Ok(y)) => Box::pin(self.handle_command(x, y)),
-            _ => panic!("Unreachable, validator should have covered this"),
-        }
+impl ExecuteCommandWithContext for AddCommandHandler {
+    fn execute<'a>(
+        &'a mut self,
+        ctx: CommandContext<'a>,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        Box::pin(async move {
+            // arguments have been validated, so these only fail for unknown names
+            let x = ctx.arg::<i32>("X")?;
+            let y = ctx.arg::<i32>("Y")?;
+            self.handle_command(x, y).await
+        })
     }
 }
 
@@ -74,7 +68,7 @@ async fn main() -> anyhow::Result<()> {
         Box::new(SayHelloCommandHandler::new()),
     );
 
-    let add_cmd = Command::new(
+    let add_cmd = Command::new_with_context(
         "Add X to Y",
         vec![
             CommandArgInfo::new_with_name(CommandArgType::I32, "X"),
@@ -1,14 +1,14 @@
 use anyhow::{self, Context};
 use mini_async_repl::{
     command::{
-        lift_validation_err, validate, Command, CommandArgInfo, CommandArgType, ExecuteCommand,
+        lift_validation_err, validate, Command, CommandArgInfo, CommandArgType, CommandContext,
+        ExecuteCommand, ExecuteCommandWithContext,
     },
     CommandStatus, Repl,
 };
-use std::cell::RefCell;
 use std::future::Future;
 use std::pin::Pin;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
 struct CountCommandHandler {}
 impl CountCommandHandler {
@@ -23,24 +23,16 @@ impl CountCommandHandler {
         Ok(CommandStatus::Done)
     }
 }
-impl ExecuteCommand for CountCommandHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
-        if let Err(e) = valid {
-            return Box::pin(lift_validation_err(Err(e)));
-        }
-
-        let x = args[0].parse::<i32>();
-        let y = args[1].parse::<i32>();
-
-        match (x, y) {
-            (Ok(x), Ok(y)) => Box::pin(self.handle_command(x, y)),
-            _ => panic!("Unreachable, validator should have covered this"),
-        }
+impl ExecuteCommandWithContext for CountCommandHandler {
+    fn execute<'a>(
+        &'a mut self,
+        ctx: CommandContext<'a>,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        Box::pin(async move {
+            let x = ctx.arg::<i32>("X")?;
+            let y = ctx.arg::<i32>("Y")?;
+            self.handle_command(x, y).await
+        })
     }
 }
 
@@ -54,46 +46,39 @@ impl SayCommandHandler {
         Ok(CommandStatus::Done)
     }
 }
-impl ExecuteCommand for SayCommandHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
-        if let Err(e) = valid {
-            return Box::pin(lift_validation_err(Err(e)));
-        }
-
-        let x = args[0].parse::<f32>();
-        match x {
-            Ok(x) => Box::pin(self.handle_command(x)),
-            _ => panic!("Unreachable, validator should have covered this"),
-        }
+impl ExecuteCommandWithContext for SayCommandHandler {
+    fn execute<'a>(
+        &'a mut self,
+        ctx: CommandContext<'a>,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        Box::pin(async move {
+            let x = ctx.arg::<f32>("X")?;
+            self.handle_command(x).await
+        })
     }
 }
 
 struct OutXCommandHandler {
-    outside_x: Rc<RefCell<String>>,
+    outside_x: Arc<Mutex<String>>,
 }
 impl OutXCommandHandler {
-    pub fn new(outside_x: Rc<RefCell<String>>) -> Self {
+    pub fn new(outside_x: Arc<Mutex<String>>) -> Self {
         Self { outside_x }
     }
     async fn handle_command(&mut self) -> anyhow::Result<CommandStatus> {
-        let mut x = self.outside_x.borrow_mut();
+        let mut x = self.outside_x.lock().unwrap();
         *x += "x";
         println!("{}", x);
         Ok(CommandStatus::Done)
     }
 }
 impl ExecuteCommand for OutXCommandHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let valid = validate(args, args_info);
         if let Err(e) = valid {
             return Box::pin(lift_validation_err(Err(e)));
         }
@@ -101,16 +86,17 @@ impl ExecuteCommand for OutXCommandHandler {
     }
 }
 
+#[allow(clippy::unnecessary_cast)]
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let outside_x = Rc::new(RefCell::new(String::from("Out x")));
+    let outside_x = Arc::new(Mutex::new(String::from("Out x")));
 
     #[rustfmt::skip]
     let mut repl = Repl::builder()
         .description("Example REPL")
         .prompt("=> ")
         .text_width(60 as usize)
-        .add("count", Command::new(
+        .add("count", Command::new_with_context(
         	"Count from X to Y",
         	vec![
         		CommandArgInfo::new_with_name(CommandArgType::I32, "X"),
@@ -118,7 +104,7 @@ async fn main() -> anyhow::Result<()> {
         	],
         	Box::new(CountCommandHandler::new()),
         ))
-        .add("say", Command::new(
+        .add("say", Command::new_with_context(
         	"Say X",
         	vec![CommandArgInfo::new_with_name(CommandArgType::F32, "X")],
         	Box::new(SayCommandHandler::new()),
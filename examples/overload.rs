@@ -28,12 +28,13 @@ impl DescribeCommandHandler {
     }
 }
 impl ExecuteCommand for DescribeCommandHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        let valid = validate(args.clone(), args_info.clone());
+    #[allow(clippy::single_match)]
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let valid = validate(args, args_info);
         if let Err(e) = valid {
             return Box::pin(lift_validation_err(Err(e)));
         }
@@ -42,14 +43,14 @@ impl ExecuteCommand for DescribeCommandHandler {
         // providing one CommandHandler for each overload.
         // For now I think it's better not to constraint approaches
         // because it's not yet clear to me what the best design is.
-        let variant_1 = validate(args.clone(), args_info);
+        let variant_1 = validate(args, args_info);
         if let Ok(()) = variant_1 {
             return Box::pin(self.handle_variant_1());
         }
 
         let variant_2 = validate(
-            args.clone(),
-            vec![
+            args,
+            &[
                 CommandArgInfo::new_with_name(CommandArgType::I32, "a"),
                 CommandArgInfo::new_with_name(CommandArgType::I32, "b"),
             ],
@@ -67,15 +68,15 @@ impl ExecuteCommand for DescribeCommandHandler {
         }
 
         let variant_3 = validate(
-            args.clone(),
-            vec![
+            args,
+            &[
                 CommandArgInfo::new_with_name(CommandArgType::I32, "a"),
                 CommandArgInfo::new_with_name(CommandArgType::String, "b"),
             ],
         );
         if let Ok(()) = variant_3 {
             let a = args[0].parse::<i32>();
-            let b = args[1].clone();
+            let b = args[1].to_string();
 
             match a {
                 Ok(a) => {
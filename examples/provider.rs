@@ -0,0 +1,57 @@
+use anyhow::{self, Context};
+use mini_async_repl::{
+    command::{Command, CommandArgInfo, CommandArgType, SyncCommandHandler},
+    provider::CommandProvider,
+    CommandStatus, Repl,
+};
+
+/// A pack of commands that could live in a separate crate.
+struct MathCommands;
+
+impl CommandProvider for MathCommands {
+    fn commands(&self) -> Vec<(String, Command)> {
+        let binary = |desc: &str, op: fn(i32, i32) -> i32| {
+            Command::new(
+                desc,
+                vec![
+                    CommandArgInfo::new_with_name(CommandArgType::I32, "X"),
+                    CommandArgInfo::new_with_name(CommandArgType::I32, "Y"),
+                ],
+                Box::new(SyncCommandHandler::new(move |args: Vec<String>| {
+                    println!("{}", op(args[0].parse()?, args[1].parse()?));
+                    Ok(CommandStatus::Done)
+                })),
+            )
+        };
+        vec![
+            ("add".into(), binary("Add X to Y", |x, y| x + y)),
+            ("mul".into(), binary("Multiply X by Y", |x, y| x * y)),
+        ]
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let echo_cmd = Command::new(
+        "Print the given text",
+        vec![CommandArgInfo::new_with_name(
+            CommandArgType::String,
+            "text",
+        )],
+        Box::new(SyncCommandHandler::new(|args: Vec<String>| {
+            println!("{}", args[0]);
+            Ok(CommandStatus::Done)
+        })),
+    );
+
+    #[rustfmt::skip]
+    let mut repl = Repl::builder()
+        .add_provider(MathCommands)
+        .add("echo", echo_cmd)
+        .build()
+        .context("Failed to create repl")?;
+
+    repl.run().await.context("Critical REPL error")?;
+
+    Ok(())
+}
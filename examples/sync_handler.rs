@@ -0,0 +1,65 @@
+use anyhow::{self, Context};
+use mini_async_repl::{
+    command::{Command, CommandArgInfo, CommandArgType, SyncCommandHandler},
+    CommandStatus, Repl,
+};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut total = 0;
+    let sum_cmd = Command::new(
+        "Add X to the running total",
+        vec![CommandArgInfo::new_with_name(CommandArgType::I32, "X")],
+        Box::new(SyncCommandHandler::new(move |args: Vec<String>| {
+            total += args[0].parse::<i32>()?;
+            println!("total = {}", total);
+            Ok(CommandStatus::Done)
+        })),
+    );
+
+    // runs on a blocking thread, so it does not stall the async runtime
+    let sleep_cmd = Command::new(
+        "Sleep for X milliseconds",
+        vec![CommandArgInfo::new_with_name(CommandArgType::I32, "X")],
+        Box::new(
+            SyncCommandHandler::new(|args: Vec<String>| {
+                let millis = args[0].parse::<u64>()?;
+                std::thread::sleep(std::time::Duration::from_millis(millis));
+                println!("Woke up after {}ms", millis);
+                Ok(CommandStatus::Done)
+            })
+            .spawn_blocking(),
+        ),
+    );
+
+    // the whole handler is executed on a blocking thread
+    let fib_cmd = Command::new_heavy(
+        "Compute the N-th Fibonacci number (slowly)",
+        vec![CommandArgInfo::new_with_name(CommandArgType::I32, "N")],
+        Box::new(SyncCommandHandler::new(|args: Vec<String>| {
+            fn fib(n: u64) -> u64 {
+                if n < 2 {
+                    n
+                } else {
+                    fib(n - 1) + fib(n - 2)
+                }
+            }
+            let n = args[0].parse::<u64>()?;
+            println!("fib({}) = {}", n, fib(n));
+            Ok(CommandStatus::Done)
+        })),
+    );
+
+    #[rustfmt::skip]
+    let mut repl = Repl::builder()
+        .add("sum", sum_cmd)
+        .add("sleep", sleep_cmd)
+        .add("fib", fib_cmd)
+        .build()
+        .context("Failed to create repl")?;
+
+    // `cargo run --example sync_handler -- fib 30` runs a single command and exits
+    repl.run_args(std::env::args()).await?;
+
+    Ok(())
+}
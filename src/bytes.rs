@@ -0,0 +1,162 @@
+//! Sizes in bytes written with a unit, the values of
+//! [`Bytes`](crate::command::CommandArgType::Bytes) arguments.
+//!
+//! A size is a number, possibly with a fractional part, followed by an optional unit:
+//! decimal units `k`, `M`, `G`, `T`, `P` and `E` are powers of 1000, and binary units `Ki`,
+//! `Mi`, `Gi`, `Ti`, `Pi` and `Ei` powers of 1024. Units can end with `B` and are not case
+//! sensitive, so `10k`, `10kB` and `10KB` are all 10000 bytes. Sizes must be a whole number
+//! of bytes fitting in a `u64`.
+//!
+//! [`format`] writes sizes back with the largest unit keeping them exact, so that they parse
+//! to the same value, while [`Locale::format_size`](crate::locale::Locale::format_size)
+//! rounds them for display:
+//!
+//! ```rust
+//! use mini_async_repl::bytes;
+//!
+//! assert_eq!(bytes::parse("4MiB"), Ok(4 << 20));
+//! assert_eq!(bytes::parse("1.5GB"), Ok(1_500_000_000));
+//! assert_eq!(bytes::format(4 << 20), "4MiB");
+//! assert_eq!(bytes::format(1_500_000_000), "1500MB");
+//! ```
+
+use thiserror;
+
+/// Prefixes of units, with the exponent of their power of 1000 or 1024.
+const PREFIXES: [(&str, u32); 7] = [
+    ("", 0),
+    ("k", 1),
+    ("M", 2),
+    ("G", 3),
+    ("T", 4),
+    ("P", 5),
+    ("E", 6),
+];
+
+/// Error of parsing a size, see [`parse`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BytesError {
+    #[error("invalid size '{0}', expected a number of bytes like 10k, 4MiB or 1.5GB")]
+    InvalidNumber(String),
+    #[error("unknown unit '{0}', expected one of B, k, M, G, T, P, E, Ki, Mi, Gi, Ti, Pi, Ei")]
+    UnknownUnit(String),
+    #[error("size '{0}' is not a whole number of bytes")]
+    Fraction(String),
+    #[error("size '{0}' is too large, the largest one is 16EiB")]
+    TooLarge(String),
+}
+
+/// Number of bytes in `unit`, e.g. 1024 for `KiB`.
+fn unit_size(unit: &str) -> Option<u64> {
+    let lower = unit.to_ascii_lowercase();
+    let prefix = lower.strip_suffix('b').unwrap_or(&lower);
+    let (prefix, base) = match prefix.strip_suffix('i') {
+        Some(prefix) if !prefix.is_empty() => (prefix, 1024u64),
+        Some(_) => return None,
+        None => (prefix, 1000),
+    };
+    PREFIXES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(prefix))
+        .map(|(_, exponent)| base.pow(*exponent))
+}
+
+/// Number of bytes of size `value`, see the [module documentation](self).
+pub fn parse(value: &str) -> Result<u64, BytesError> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(end);
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.len() + fraction.len() == 0 || fraction.contains('.') {
+        return Err(BytesError::InvalidNumber(value.into()));
+    }
+    let unit = unit.trim_start();
+    let unit_size = unit_size(unit).ok_or_else(|| BytesError::UnknownUnit(unit.into()))?;
+    let too_large = || BytesError::TooLarge(value.into());
+
+    let whole = whole.trim_start_matches('0');
+    let whole: u128 = match whole {
+        "" => 0,
+        _ => whole.parse().map_err(|_| too_large())?,
+    };
+    let mut bytes = whole.checked_mul(unit_size.into()).ok_or_else(too_large)?;
+    let fraction = fraction.trim_end_matches('0');
+    if !fraction.is_empty() {
+        // no unit is a multiple of 10^19, as they are at most 2^60 or 10^18
+        let scale = u32::try_from(fraction.len())
+            .ok()
+            .and_then(|digits| 10u128.checked_pow(digits))
+            .filter(|scale| *scale <= 10u128.pow(18))
+            .ok_or_else(|| BytesError::Fraction(value.into()))?;
+        let fraction: u128 = fraction.parse().expect("fraction is made of digits");
+        let fraction = fraction * u128::from(unit_size);
+        if !fraction.is_multiple_of(scale) {
+            return Err(BytesError::Fraction(value.into()));
+        }
+        bytes += fraction / scale;
+    }
+    u64::try_from(bytes).map_err(|_| too_large())
+}
+
+/// `bytes` with the unit giving the smallest whole number, e.g. `4MiB` or `1500MB`, which
+/// [`parse`] reads back to `bytes`.
+pub fn format(bytes: u64) -> String {
+    let decimal = PREFIXES
+        .iter()
+        .map(|(prefix, exponent)| (format!("{prefix}B"), 1000u64.pow(*exponent)));
+    let binary = PREFIXES[1..].iter().map(|(prefix, exponent)| {
+        (
+            format!("{}iB", prefix.to_uppercase()),
+            1024u64.pow(*exponent),
+        )
+    });
+    let (unit, size) = decimal
+        .chain(binary)
+        .filter(|(_, size)| bytes.is_multiple_of(*size))
+        .min_by_key(|(_, size)| bytes / size)
+        .expect("bytes are a whole number of B");
+    format!("{}{unit}", bytes / size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse("10k"), Ok(10_000));
+        assert_eq!(parse("10KB"), Ok(10_000));
+        assert_eq!(parse("4MiB"), Ok(4 << 20));
+        assert_eq!(parse("4mi"), Ok(4 << 20));
+        assert_eq!(parse("1.5GB"), Ok(1_500_000_000));
+        assert_eq!(parse(".5KiB"), Ok(512));
+        assert_eq!(parse("2 GiB"), Ok(2 << 30));
+        assert_eq!(parse("512"), Ok(512));
+        assert_eq!(parse("7b"), Ok(7));
+        assert_eq!(parse("16EiB"), Err(BytesError::TooLarge("16EiB".into())));
+        assert_eq!(parse("18446744073709551615B"), Ok(u64::MAX));
+        assert_eq!(parse("0.1KiB"), Err(BytesError::Fraction("0.1KiB".into())));
+        assert_eq!(
+            parse("1.0000000000000000001EB"),
+            Err(BytesError::Fraction("1.0000000000000000001EB".into()))
+        );
+        assert_eq!(parse("10x"), Err(BytesError::UnknownUnit("x".into())));
+        assert_eq!(parse("10iB"), Err(BytesError::UnknownUnit("iB".into())));
+        assert_eq!(parse("-1k"), Err(BytesError::InvalidNumber("-1k".into())));
+        assert_eq!(
+            parse("1.2.3"),
+            Err(BytesError::InvalidNumber("1.2.3".into()))
+        );
+        assert_eq!(parse(""), Err(BytesError::InvalidNumber("".into())));
+
+        assert_eq!(format(0), "0B");
+        assert_eq!(format(1000), "1kB");
+        assert_eq!(format(2_048_000), "2000KiB");
+        assert_eq!(format(1023), "1023B");
+        for bytes in [0, 1, 1000, 1024, 1536, 1_500_000_000, u64::MAX, 1 << 63] {
+            assert_eq!(parse(&format(bytes)), Ok(bytes));
+        }
+    }
+}
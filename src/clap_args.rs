@@ -0,0 +1,182 @@
+//! Commands with arguments parsed by [`clap`].
+//!
+//! Allows reusing existing [`clap::Command`] definitions so that the syntax inside the REPL
+//! is identical to the one on the command line:
+//!
+//! ```rust
+//! # use mini_async_repl::{clap_args, CommandStatus, Repl};
+//! let cli = clap::Command::new("greet")
+//!     .about("Greet someone")
+//!     .arg(clap::Arg::new("name").required(true))
+//!     .arg(clap::Arg::new("loud").long("loud").action(clap::ArgAction::SetTrue));
+//! let greet = clap_args::command(cli, |matches| {
+//!     let name: &String = matches.get_one("name").unwrap();
+//!     if matches.get_flag("loud") {
+//!         println!("HELLO {}!", name.to_uppercase());
+//!     } else {
+//!         println!("Hello {}!", name);
+//!     }
+//!     Ok(CommandStatus::Done)
+//! });
+//! let repl = Repl::builder().add("greet", greet).build().unwrap();
+//! ```
+//!
+//! Requires the `clap` feature.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use clap::error::{ContextKind, ContextValue, ErrorKind};
+use clap::ArgMatches;
+
+use crate::command::{
+    ArgsError, Command, CommandArgInfo, CommandArgType, CommandContext, CommandStatus,
+    ExecuteCommandWithContext,
+};
+
+/// Create a [`Command`] parsing its arguments with `cli` and passing the matches to `f`.
+///
+/// Description is taken from [`clap::Command::get_about`] and positional arguments become
+/// the `args_info` shown in help. Parse errors are reported as [`ArgsError`], while
+/// `--help` and `--version` write clap's output to the output of the REPL.
+pub fn command<F>(cli: clap::Command, f: F) -> Command
+where
+    F: FnMut(ArgMatches) -> anyhow::Result<CommandStatus> + Send + 'static,
+{
+    let description = cli.get_about().map(|about| about.to_string());
+    let args_info = cli
+        .get_positionals()
+        .map(|arg| CommandArgInfo::new_with_name(CommandArgType::String, arg.get_id().as_str()))
+        .collect();
+    let handler = ClapCommandHandler {
+        cli: cli.no_binary_name(true),
+        f,
+    };
+    Command::new_with_context(
+        description.as_deref().unwrap_or_default(),
+        args_info,
+        Box::new(handler),
+    )
+    .raw_args()
+}
+
+/// Handler parsing arguments with [`clap`], see [`command`].
+pub struct ClapCommandHandler<F> {
+    cli: clap::Command,
+    f: F,
+}
+
+impl<F> ClapCommandHandler<F>
+where
+    F: FnMut(ArgMatches) -> anyhow::Result<CommandStatus>,
+{
+    fn handle(&mut self, mut ctx: CommandContext) -> anyhow::Result<CommandStatus> {
+        match self.cli.try_get_matches_from_mut(ctx.args()) {
+            Ok(matches) => (self.f)(matches),
+            Err(err) => match err.kind() {
+                ErrorKind::DisplayHelp
+                | ErrorKind::DisplayVersion
+                | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => {
+                    write!(ctx.out(), "{}", err.render())?;
+                    Ok(CommandStatus::Done)
+                }
+                _ => Err(args_error(&err).into()),
+            },
+        }
+    }
+}
+
+fn args_error(err: &clap::Error) -> ArgsError {
+    if let Some(ContextValue::String(argument)) = err.get(ContextKind::InvalidValue) {
+        if matches!(
+            err.kind(),
+            ErrorKind::InvalidValue | ErrorKind::ValueValidation
+        ) {
+            return ArgsError::WrongArgumentValue {
+                argument: argument.clone(),
+                error: err.kind().to_string(),
+            };
+        }
+    }
+    // first line of the rendered error, without usage and tips
+    let message = err.to_string();
+    let message = message.lines().next().unwrap_or_default();
+    ArgsError::Invalid(message.trim_start_matches("error: ").into())
+}
+
+impl<F> ExecuteCommandWithContext for ClapCommandHandler<F>
+where
+    F: FnMut(ArgMatches) -> anyhow::Result<CommandStatus> + Send,
+{
+    fn execute<'a>(
+        &'a mut self,
+        ctx: CommandContext<'a>,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let result = self.handle(ctx);
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use crate::output::{Output, Sink};
+    use clap::{value_parser, Arg, ArgAction};
+    use std::sync::{Arc, Mutex};
+
+    fn cli() -> clap::Command {
+        clap::Command::new("move")
+            .about("Move by X steps")
+            .arg(
+                Arg::new("steps")
+                    .required(true)
+                    .value_parser(value_parser!(i32)),
+            )
+            .arg(Arg::new("fast").long("fast").action(ArgAction::SetTrue))
+    }
+
+    #[tokio::test]
+    async fn clap_command() {
+        let moves = Arc::new(Mutex::new(vec![]));
+        let recorded = moves.clone();
+        let mut cmd = command(cli(), move |matches| {
+            let steps = *matches.get_one::<i32>("steps").unwrap();
+            recorded
+                .lock()
+                .unwrap()
+                .push((steps, matches.get_flag("fast")));
+            Ok(CommandStatus::Done)
+        });
+        assert_eq!(cmd.description, "Move by X steps");
+        assert_eq!(cmd.arg_types(), vec!["String"]);
+
+        assert!(cmd.execute(&["3"]).await.is_ok());
+        assert!(cmd.execute(&["--fast", "2"]).await.is_ok());
+        assert_eq!(*moves.lock().unwrap(), vec![(3, false), (2, true)]);
+
+        let err = cmd.execute(&["x"]).await.unwrap_err();
+        let err = err.downcast::<ArgsError>().unwrap();
+        assert!(matches!(err, ArgsError::WrongArgumentValue { argument, .. } if argument == "x"));
+        let err = cmd.execute(&["1", "--slow"]).await.unwrap_err();
+        let err = err.downcast::<ArgsError>().unwrap();
+        assert!(matches!(err, ArgsError::Invalid(msg) if msg.contains("--slow")));
+        assert!(cmd.execute(&[]).await.unwrap_err().is::<ArgsError>());
+        assert_eq!(moves.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn help_goes_to_output() {
+        let cmd = command(cli(), |_matches| Ok(CommandStatus::Done));
+        let mut out = Sink::new(Output::Writer(Box::new(std::io::sink())));
+        out.start_capture();
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock::default());
+        let status = cmd
+            .execute_shared(&["--help"], &mut out, None, &mut Vec::new(), &clock)
+            .await;
+        assert_eq!(status.unwrap(), CommandStatus::Done);
+        let help = out.take_capture().unwrap();
+        assert!(help.starts_with("Move by X steps"));
+        assert!(help.contains("Usage:") && help.contains("<steps>"));
+    }
+}
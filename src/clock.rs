@@ -0,0 +1,176 @@
+//! Source of time of the REPL, see [`ReplBuilder::clock`](crate::repl::ReplBuilder::clock).
+//!
+//! Command cooldowns, the idle timeout, retry delays, demo pauses and the completion cache
+//! read the time and wait through a [`Clock`]. [`SystemClock`] is the real time, used by
+//! default, and [`ManualClock`] only moves forward when told to, so tests can check
+//! time-dependent behavior without sleeping:
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use mini_async_repl::clock::{Clock, ManualClock};
+//!
+//! let clock = ManualClock::new();
+//! let start = clock.now();
+//! clock.advance(Duration::from_secs(30));
+//! assert_eq!(clock.now() - start, Duration::from_secs(30));
+//! ```
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crate::runtime::{self, default_runtime, BoxFuture, Runtime};
+
+/// Source of the current time and of timers.
+pub trait Clock: Send + Sync {
+    /// Current time.
+    fn now(&self) -> Instant;
+    /// Returns a future that completes once `duration` has elapsed on this clock.
+    fn sleep(&self, duration: Duration) -> BoxFuture<()>;
+}
+
+/// Await `future` until `duration` has elapsed on `clock`, returning `None` if the time ran
+/// out, like [`runtime::timeout`].
+pub async fn timeout<F: Future>(
+    clock: &dyn Clock,
+    duration: Duration,
+    future: F,
+) -> Option<F::Output> {
+    runtime::race(clock.sleep(duration), future).await
+}
+
+/// Real time, with timers of a [`Runtime`].
+#[derive(Clone)]
+pub struct SystemClock {
+    runtime: Arc<dyn Runtime>,
+}
+
+impl SystemClock {
+    pub fn new(runtime: Arc<dyn Runtime>) -> Self {
+        Self { runtime }
+    }
+}
+
+impl Default for SystemClock {
+    /// Real time with timers of the [`default_runtime`].
+    fn default() -> Self {
+        Self::new(default_runtime())
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        self.runtime.sleep(duration)
+    }
+}
+
+/// Clock standing still until [advanced](ManualClock::advance), for tests.
+///
+/// Clones share the same time, so a test can keep one to advance the clock given to the
+/// REPL. Sleeps complete when the clock is advanced past their end.
+#[derive(Clone)]
+pub struct ManualClock {
+    state: Arc<Mutex<ManualState>>,
+}
+
+struct ManualState {
+    start: Instant,
+    elapsed: Duration,
+    /// End of pending sleeps, as time elapsed since `start`, with their waker.
+    sleepers: Vec<(Duration, Waker)>,
+}
+
+impl ManualClock {
+    /// Clock starting at the current time.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ManualState {
+                start: Instant::now(),
+                elapsed: Duration::ZERO,
+                sleepers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Move the clock forward by `duration`, waking sleeps that are over.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.elapsed += duration;
+        let elapsed = state.elapsed;
+        let (over, pending) = std::mem::take(&mut state.sleepers)
+            .into_iter()
+            .partition(|(end, _)| *end <= elapsed);
+        state.sleepers = pending;
+        drop(state);
+        for (_, waker) in over {
+            waker.wake();
+        }
+    }
+
+    /// Time the clock has been advanced by since it was created.
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().elapsed
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        let state = self.state.lock().unwrap();
+        state.start + state.elapsed
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        let state = self.state.clone();
+        let end = self.elapsed() + duration;
+        Box::pin(std::future::poll_fn(move |cx| {
+            let mut state = state.lock().unwrap();
+            if state.elapsed >= end {
+                Poll::Ready(())
+            } else {
+                state.sleepers.push((end, cx.waker().clone()));
+                Poll::Pending
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+    use std::task::Context;
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut sleep = clock.sleep(Duration::from_secs(10));
+        assert!(sleep.as_mut().poll(&mut cx).is_pending());
+        clock.clone().advance(Duration::from_secs(5));
+        assert!(sleep.as_mut().poll(&mut cx).is_pending());
+        clock.advance(Duration::from_secs(5));
+        assert!(sleep.as_mut().poll(&mut cx).is_ready());
+        assert_eq!(clock.now() - start, Duration::from_secs(10));
+        assert_eq!(clock.elapsed(), Duration::from_secs(10));
+
+        let mut slow = Box::pin(timeout(&clock, Duration::from_secs(1), async {
+            clock.sleep(Duration::from_secs(2)).await
+        }));
+        assert!(slow.as_mut().poll(&mut cx).is_pending());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(slow.as_mut().poll(&mut cx), Poll::Ready(None));
+    }
+}
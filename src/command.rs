@@ -3,17 +3,60 @@
 use anyhow;
 use thiserror;
 
+use std::borrow::Cow;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
-pub trait ExecuteCommand {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>>;
+use futures::lock::{Mutex as AsyncMutex, MutexGuard as AsyncMutexGuard};
+
+use crate::bytes;
+use crate::clock::{Clock, SystemClock};
+use crate::fs::{self, RealFileSystem};
+use crate::locale::Locale;
+use crate::output::{Output, Sink, Verbosity};
+use crate::prefix::edit_distance;
+use crate::runtime::{self, default_runtime, Runtime};
+use crate::validate::{All, Typed, TypedCheck, Types, Validate};
+
+/// Command handler.
+///
+/// Arguments are borrowed from the line being executed, so no allocations are
+/// needed to call the handler. Handlers that need owned arguments (e.g. to move
+/// them into a spawned task) can convert them with `args.iter().map(|a| a.to_string())`
+/// or use the [`SyncCommandHandler`] adapter which passes a `Vec<String>`.
+pub trait ExecuteCommand: Send {
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>>;
+
+    /// Called with the runtime of the REPL when the command is added to it, see
+    /// [`ReplBuilder::runtime`](crate::repl::ReplBuilder::runtime), for handlers spawning
+    /// blocking tasks or timers.
+    fn set_runtime(&mut self, _runtime: &Arc<dyn Runtime>) {}
+}
+
+/// Variant of [`ExecuteCommand`] for handlers whose futures are [`Send`].
+///
+/// Use it when the handler needs to await APIs that require `Send` futures.
+/// Commands created with [`Command::new_send`] can also be driven from
+/// work-stealing executors using [`Command::execute_send`].
+pub trait SendExecuteCommand: Send {
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + Send + 'a>>;
+
+    /// Same as [`ExecuteCommand::set_runtime`].
+    fn set_runtime(&mut self, _runtime: &Arc<dyn Runtime>) {}
 }
 
 pub async fn lift_validation_err(
@@ -24,37 +67,469 @@ pub async fn lift_validation_err(
         .expect_err("Not a validation error, this is a bug.")
 }
 
+#[derive(Default)]
 pub struct TrivialCommandHandler {}
 impl TrivialCommandHandler {
     pub fn new() -> Self {
         Self {}
     }
 
-    async fn handle_command(&mut self, _args: Vec<String>) -> anyhow::Result<CommandStatus> {
+    async fn handle_command(&mut self, _args: &[&str]) -> anyhow::Result<CommandStatus> {
         Ok(CommandStatus::Done)
     }
 }
 
 impl ExecuteCommand for TrivialCommandHandler {
-    fn execute(
-        &mut self,
-        args: Vec<String>,
-        _args_info: Vec<CommandArgInfo>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        _args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        Box::pin(self.handle_command(args))
+    }
+}
+
+impl SendExecuteCommand for TrivialCommandHandler {
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        _args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + Send + 'a>> {
         Box::pin(self.handle_command(args))
     }
 }
 
+/// State of the REPL available to handlers created with [`Command::new_with_context`].
+pub struct CommandContext<'a> {
+    args: &'a [&'a str],
+    args_info: &'a [CommandArgInfo],
+    /// Arguments parsed according to their types
+    values: Vec<ArgValue<'a>>,
+    out: &'a mut Sink,
+    /// Current directory of the REPL, see [`CommandContext::cwd`].
+    cwd: Option<&'a Path>,
+    /// Changes of the session applied once the command returns.
+    changes: Option<&'a mut Vec<SessionChange>>,
+    /// Clock of the REPL, see [`CommandContext::clock`].
+    clock: Option<Arc<dyn Clock>>,
+}
+
+impl<'a> CommandContext<'a> {
+    pub(crate) fn new(
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+        out: &'a mut Sink,
+    ) -> Self {
+        let values = args
+            .iter()
+            .zip(args_info)
+            .map(|(arg, info)| ArgValue::parse(arg, info.arg_type))
+            .collect();
+        Self {
+            args,
+            args_info,
+            values,
+            out,
+            cwd: None,
+            changes: None,
+            clock: None,
+        }
+    }
+
+    pub(crate) fn with_cwd(mut self, cwd: Option<&'a Path>) -> Self {
+        self.cwd = cwd;
+        self
+    }
+
+    pub(crate) fn with_changes(mut self, changes: &'a mut Vec<SessionChange>) -> Self {
+        self.changes = Some(changes);
+        self
+    }
+
+    pub(crate) fn with_clock(mut self, clock: Option<Arc<dyn Clock>>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Arguments the command has been called with.
+    pub fn args(&self) -> &'a [&'a str] {
+        self.args
+    }
+
+    /// Value of the argument named `name`, converted to `T`.
+    ///
+    /// Arguments are parsed according to their [types](CommandArgType) when the context is
+    /// created, so e.g. `ctx.arg::<i32>("X")?` does not parse the argument again, and it
+    /// cannot fail for an `i32` argument that has been validated.
+    /// ```
+    /// # use mini_async_repl::command::{ArgsError, CommandContext};
+    /// fn add(ctx: &CommandContext) -> Result<i32, ArgsError> {
+    ///     Ok(ctx.arg::<i32>("X")? + ctx.arg::<i32>("Y")?)
+    /// }
+    /// ```
+    pub fn arg<T>(&self, name: &str) -> Result<T, ArgsError>
+    where
+        T: TryFrom<ArgValue<'a>, Error = String>,
+    {
+        let index = self
+            .args_info
+            .iter()
+            .position(|info| info.name.as_deref() == Some(name))
+            .filter(|index| *index < self.values.len())
+            .ok_or_else(|| ArgsError::Invalid(format!("no argument named '{name}'")))?;
+        T::try_from(self.values[index]).map_err(|error| ArgsError::WrongArgumentValue {
+            argument: self.args[index].into(),
+            error,
+        })
+    }
+
+    /// Names and types of the arguments of the command.
+    pub fn args_info(&self) -> &'a [CommandArgInfo] {
+        self.args_info
+    }
+
+    /// Output of the REPL, see [`ReplBuilder::out`](crate::repl::ReplBuilder::out).
+    ///
+    /// Writing here instead of directly to stdout keeps command output ordered with the
+    /// REPL's own messages and the prompt.
+    pub fn out(&mut self) -> &mut dyn Write {
+        &mut *self.out
+    }
+
+    /// Verbosity of the REPL, see [`ReplBuilder::verbosity`](crate::repl::ReplBuilder::verbosity).
+    pub fn verbosity(&self) -> Verbosity {
+        self.out.verbosity
+    }
+
+    /// Locale of the REPL, see [`ReplBuilder::locale`](crate::repl::ReplBuilder::locale),
+    /// to format numbers, durations and sizes for the operator.
+    pub fn locale(&self) -> Locale {
+        self.out.locale
+    }
+
+    /// Current directory of the REPL, changed with its `cd` command, or `None` if it does not
+    /// manage one, see [`ReplBuilder::working_dir`](crate::repl::ReplBuilder::working_dir).
+    ///
+    /// It is not the current directory of the process, so commands should open relative
+    /// paths with [`CommandContext::resolve_path`].
+    pub fn cwd(&self) -> Option<&'a Path> {
+        self.cwd
+    }
+
+    /// Clock of the REPL, see [`ReplBuilder::clock`](crate::repl::ReplBuilder::clock).
+    /// Commands should read the time and wait through it, so that tests can control time.
+    ///
+    /// Outside of a REPL this is a [`SystemClock`].
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock
+            .clone()
+            .unwrap_or_else(|| Arc::new(SystemClock::default()))
+    }
+
+    /// `path` relative to the [current directory](CommandContext::cwd) of the REPL, or
+    /// unchanged if it is absolute or the REPL does not manage one.
+    pub fn resolve_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        match self.cwd {
+            Some(cwd) => cwd.join(path),
+            None => path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Show `mode` in the prompt once the command returns, see
+    /// [`Repl::set_mode`](crate::Repl::set_mode). `None` leaves the current mode.
+    pub fn set_mode(&mut self, mode: Option<&str>) {
+        if let Some(changes) = &mut self.changes {
+            changes.push(SessionChange::Mode(mode.map(str::to_string)));
+        }
+    }
+
+    /// Add command `name` once this command returns, until `scope` ends, e.g. `disconnect`
+    /// and the commands of a connection added by `connect`, see
+    /// [`Repl::add_temporary`](crate::Repl::add_temporary).
+    ///
+    /// With [`Scope::Mode`], call [`CommandContext::set_mode`] first so that the command
+    /// belongs to the mode entered by this command.
+    pub fn add_temporary(&mut self, name: &str, cmd: Command, scope: Scope) {
+        if let Some(changes) = &mut self.changes {
+            changes.push(SessionChange::Temporary {
+                name: name.into(),
+                cmd: Box::new(cmd),
+                scope,
+            });
+        }
+    }
+
+    /// Pass a structured result to the caller. With
+    /// [`ReplBuilder::json_output`](crate::repl::ReplBuilder::json_output) it is added to the
+    /// `data` of the response, otherwise it is printed as a line of JSON.
+    ///
+    /// Requires the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn emit(&mut self, value: serde_json::Value) -> std::io::Result<()> {
+        self.out.emit(value)
+    }
+}
+
+/// Variant of [`ExecuteCommand`] for handlers using the [`CommandContext`].
+pub trait ExecuteCommandWithContext: Send {
+    fn execute<'a>(
+        &'a mut self,
+        ctx: CommandContext<'a>,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>>;
+}
+
+/// Adapter for registering synchronous functions as command handlers.
+///
+/// Arguments are validated against the command's `args_info` before calling the
+/// function, so it only has to parse them. The function runs directly in the REPL
+/// loop; use [`SyncCommandHandler::spawn_blocking`] for functions that may block
+/// for a long time.
+/// ```rust
+/// # use mini_async_repl::{CommandStatus, command::{Command, SyncCommandHandler}};
+/// let cmd = Command::new(
+///     "Print all arguments",
+///     vec![],
+///     Box::new(SyncCommandHandler::new(|args: Vec<String>| {
+///         println!("{}", args.join(" "));
+///         Ok(CommandStatus::Done)
+///     })),
+/// );
+/// ```
+pub struct SyncCommandHandler<F> {
+    f: F,
+}
+
+impl<F> SyncCommandHandler<F>
+where
+    F: FnMut(Vec<String>) -> anyhow::Result<CommandStatus>,
+{
+    pub fn new(f: F) -> Self {
+        Self { f }
+    }
+
+    fn call(
+        &mut self,
+        args: &[&str],
+        args_info: &[CommandArgInfo],
+    ) -> anyhow::Result<CommandStatus> {
+        validate(args, args_info)?;
+        (self.f)(args.iter().map(|arg| arg.to_string()).collect())
+    }
+}
+
+impl<F> SyncCommandHandler<F>
+where
+    F: FnMut(Vec<String>) -> anyhow::Result<CommandStatus> + Send + 'static,
+{
+    /// Run the function using [`Runtime::spawn_blocking`] instead of directly in the REPL loop.
+    pub fn spawn_blocking(self) -> BlockingCommandHandler<F> {
+        BlockingCommandHandler {
+            f: Arc::new(Mutex::new(self.f)),
+            runtime: default_runtime(),
+            fixed_runtime: false,
+        }
+    }
+}
+
+impl<F> ExecuteCommand for SyncCommandHandler<F>
+where
+    F: FnMut(Vec<String>) -> anyhow::Result<CommandStatus> + Send,
+{
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        Box::pin(async move { self.call(args, args_info) })
+    }
+}
+
+impl<F> SendExecuteCommand for SyncCommandHandler<F>
+where
+    F: FnMut(Vec<String>) -> anyhow::Result<CommandStatus> + Send,
+{
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + Send + 'a>> {
+        Box::pin(async move { self.call(args, args_info) })
+    }
+}
+
+/// [`SyncCommandHandler`] running the function on a blocking thread.
+///
+/// Created using [`SyncCommandHandler::spawn_blocking`].
+pub struct BlockingCommandHandler<F> {
+    f: Arc<Mutex<F>>,
+    runtime: Arc<dyn Runtime>,
+    /// Whether the runtime has been set with [`BlockingCommandHandler::runtime`]
+    fixed_runtime: bool,
+}
+
+impl<F> BlockingCommandHandler<F> {
+    /// Runtime used to spawn blocking tasks. Defaults to the one of the REPL the command
+    /// is added to, see [`ReplBuilder::runtime`](crate::repl::ReplBuilder::runtime), or to
+    /// [`default_runtime`] outside of a REPL.
+    pub fn runtime<R: Runtime + 'static>(mut self, runtime: R) -> Self {
+        self.runtime = Arc::new(runtime);
+        self.fixed_runtime = true;
+        self
+    }
+}
+
+impl<F> SendExecuteCommand for BlockingCommandHandler<F>
+where
+    F: FnMut(Vec<String>) -> anyhow::Result<CommandStatus> + Send + 'static,
+{
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + Send + 'a>> {
+        let f = self.f.clone();
+        Box::pin(async move {
+            validate(args, args_info)?;
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            runtime::spawn_blocking(self.runtime.as_ref(), move || {
+                let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
+                f(args)
+            })
+            .await?
+        })
+    }
+
+    fn set_runtime(&mut self, runtime: &Arc<dyn Runtime>) {
+        if !self.fixed_runtime {
+            self.runtime = runtime.clone();
+        }
+    }
+}
+
+impl<F> ExecuteCommand for BlockingCommandHandler<F>
+where
+    F: FnMut(Vec<String>) -> anyhow::Result<CommandStatus> + Send + 'static,
+{
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        SendExecuteCommand::execute(self, args, args_info)
+    }
+
+    fn set_runtime(&mut self, runtime: &Arc<dyn Runtime>) {
+        SendExecuteCommand::set_runtime(self, runtime)
+    }
+}
+
+/// Provides TAB-completion candidates for values of an argument.
+///
+/// Implemented for all `Fn(&str) -> Vec<String>` closures. Completers that query
+/// slow data sources can be cached, see
+/// [`ReplBuilder::completion_cache_ttl`](crate::repl::ReplBuilder::completion_cache_ttl).
+pub trait ArgCompleter: Send + Sync {
+    /// Returns values of the argument that start with `prefix`.
+    fn complete(&self, prefix: &str) -> Vec<String>;
+
+    /// Whether values of the argument are file system paths, in which glob patterns are
+    /// expanded, see [`ReplBuilder::globbing`](crate::repl::ReplBuilder::globbing).
+    fn is_path(&self) -> bool {
+        false
+    }
+}
+
+impl<F> ArgCompleter for F
+where
+    F: Fn(&str) -> Vec<String> + Send + Sync,
+{
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        self(prefix)
+    }
+}
+
+/// [`ArgCompleter`] of file system paths, for arguments naming files or directories.
+///
+/// Unlike [`ReplBuilder::with_filename_completion`], which offers files for any argument,
+/// this only applies to the arguments it is set on:
+///
+/// ```
+/// # use mini_async_repl::command::{CommandArgInfo, CommandArgType, PathCompleter};
+/// let config = CommandArgInfo::new_with_name(CommandArgType::String, "CONFIG")
+///     .with_completer(PathCompleter::new().extensions(["toml"]));
+/// let out_dir = CommandArgInfo::new_with_name(CommandArgType::String, "OUT_DIR")
+///     .with_completer(PathCompleter::new().dirs_only());
+/// ```
+///
+/// Directories are always offered, with a trailing separator, so that completion can
+/// continue inside them. Hidden entries are only offered when the prefix starts with a dot.
+///
+/// [`ReplBuilder::with_filename_completion`]: crate::repl::ReplBuilder::with_filename_completion
+#[derive(Debug, Clone, Default)]
+pub struct PathCompleter {
+    extensions: Vec<String>,
+    dirs_only: bool,
+}
+
+impl PathCompleter {
+    /// Completer offering all files and directories.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only offer files with one of the `extensions`, given without the leading dot.
+    pub fn extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only offer directories.
+    pub fn dirs_only(mut self) -> Self {
+        self.dirs_only = true;
+        self
+    }
+
+    fn accepts_file(&self, name: &str) -> bool {
+        if self.dirs_only {
+            return false;
+        }
+        let extension = std::path::Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str());
+        self.extensions.is_empty()
+            || extension.is_some_and(|ext| self.extensions.iter().any(|e| e == ext))
+    }
+}
+
+impl ArgCompleter for PathCompleter {
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        fs::complete(&RealFileSystem, prefix, |entry| {
+            entry.is_dir || self.accepts_file(&entry.name)
+        })
+    }
+
+    fn is_path(&self) -> bool {
+        true
+    }
+}
+
 #[derive(Clone)]
 pub struct CommandArgInfo {
     pub arg_type: CommandArgType,
     pub name: Option<String>,
+    /// Completion of the argument values, see [`CommandArgInfo::with_completer`].
+    pub completer: Option<Arc<dyn ArgCompleter>>,
 }
 impl CommandArgInfo {
     pub fn new(arg_type: CommandArgType) -> Self {
         CommandArgInfo {
             arg_type,
             name: None,
+            completer: None,
         }
     }
 
@@ -62,18 +537,170 @@ impl CommandArgInfo {
         CommandArgInfo {
             arg_type,
             name: Some(name.into()),
+            completer: None,
         }
     }
 
-    pub fn to_string(self) -> String {
-        format!("{}:{}", self.name.unwrap_or("".to_string()), self.arg_type)
+    /// Complete values of this argument using given `completer`.
+    pub fn with_completer<C: ArgCompleter + 'static>(mut self, completer: C) -> Self {
+        self.completer = Some(Arc::new(completer));
+        self
+    }
+}
+
+impl Display for CommandArgInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            self.name.as_deref().unwrap_or(""),
+            self.arg_type
+        )
+    }
+}
+
+/// Value of an argument parsed according to its [`CommandArgType`], see
+/// [`CommandContext::arg`].
+///
+/// Other types can be read from arguments by implementing `TryFrom<ArgValue>`, with the
+/// reason why the value cannot be converted as error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArgValue<'a> {
+    text: &'a str,
+    parsed: Option<Parsed>,
+}
+
+/// Value of a number argument, see [`ArgValue::parsed`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parsed {
+    I32(i32),
+    F32(f32),
+    /// Number of bytes of a [`Bytes`](CommandArgType::Bytes) argument.
+    Bytes(u64),
+}
+
+impl<'a> ArgValue<'a> {
+    fn parse(text: &'a str, arg_type: CommandArgType) -> Self {
+        let parsed = match arg_type {
+            CommandArgType::I32 => text.parse().ok().map(Parsed::I32),
+            CommandArgType::F32 => text.parse().ok().map(Parsed::F32),
+            CommandArgType::Bytes => bytes::parse(text).ok().map(Parsed::Bytes),
+            CommandArgType::String | CommandArgType::Custom => None,
+        };
+        Self { text, parsed }
+    }
+
+    /// Argument as given on the command line, e.g. `007` or `4MiB`.
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    /// Value of a number argument, `None` for [`String`](CommandArgType::String) and
+    /// [`Custom`](CommandArgType::Custom) arguments and for numbers that do not parse.
+    pub fn parsed(&self) -> Option<Parsed> {
+        self.parsed
+    }
+
+    /// Text to parse numbers from: the number of bytes of sizes, as units are not
+    /// understood by `FromStr`, and the argument otherwise.
+    fn number_text(&self) -> Cow<'a, str> {
+        match self.parsed {
+            Some(Parsed::Bytes(value)) => value.to_string().into(),
+            _ => self.text.into(),
+        }
     }
 }
 
+impl Display for ArgValue<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl TryFrom<ArgValue<'_>> for i32 {
+    type Error = String;
+
+    fn try_from(value: ArgValue<'_>) -> Result<Self, Self::Error> {
+        match value.parsed {
+            Some(Parsed::I32(value)) => Ok(value),
+            Some(Parsed::F32(_)) => Err("expected an integer".into()),
+            Some(Parsed::Bytes(value)) => i32::try_from(value).map_err(|e| format!("{e}")),
+            None => value.text.parse().map_err(|e| format!("{e}")),
+        }
+    }
+}
+
+impl TryFrom<ArgValue<'_>> for f32 {
+    type Error = String;
+
+    fn try_from(value: ArgValue<'_>) -> Result<Self, Self::Error> {
+        match value.parsed {
+            Some(Parsed::F32(value)) => Ok(value),
+            _ => value.number_text().parse().map_err(|e| format!("{e}")),
+        }
+    }
+}
+
+impl<'a> TryFrom<ArgValue<'a>> for &'a str {
+    type Error = String;
+
+    fn try_from(value: ArgValue<'a>) -> Result<Self, Self::Error> {
+        match value.parsed {
+            None => Ok(value.text),
+            Some(_) => Err("expected text".into()),
+        }
+    }
+}
+
+impl TryFrom<ArgValue<'_>> for String {
+    type Error = String;
+
+    fn try_from(value: ArgValue<'_>) -> Result<Self, Self::Error> {
+        Ok(value.text.to_string())
+    }
+}
+
+/// Types parsed from the text of any argument, or from the number of bytes of sizes.
+macro_rules! parsed_arg_value {
+    ($($ty:ty),*) => {
+        $(
+            impl TryFrom<ArgValue<'_>> for $ty {
+                type Error = String;
+
+                fn try_from(value: ArgValue<'_>) -> Result<Self, Self::Error> {
+                    value.number_text().parse().map_err(|e| format!("{e}"))
+                }
+            }
+        )*
+    };
+}
+
+parsed_arg_value!(i64, u32, u64, usize, f64);
+
+/// Types parsed from the text of any argument.
+macro_rules! text_arg_value {
+    ($($ty:ty),*) => {
+        $(
+            impl TryFrom<ArgValue<'_>> for $ty {
+                type Error = String;
+
+                fn try_from(value: ArgValue<'_>) -> Result<Self, Self::Error> {
+                    value.text.parse().map_err(|e| format!("{e}"))
+                }
+            }
+        )*
+    };
+}
+
+text_arg_value!(bool, char, std::path::PathBuf);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CommandArgType {
     I32,
     F32,
+    /// Size in bytes with an optional unit, e.g. `10k`, `4MiB` or `1.5GB`, see
+    /// [`bytes`](crate::bytes). Handlers read it as a `u64` with [`CommandContext::arg`].
+    Bytes,
     String,
     Custom,
 }
@@ -83,19 +710,259 @@ impl Display for CommandArgType {
         match self {
             CommandArgType::I32 => write!(f, "i32"),
             CommandArgType::F32 => write!(f, "f32"),
+            CommandArgType::Bytes => write!(f, "bytes"),
             CommandArgType::String => write!(f, "String"),
             CommandArgType::Custom => write!(f, "Custom"),
         }
     }
 }
 
+impl CommandArgType {
+    /// How narrow the set of accepted values is, used to order overloads.
+    pub(crate) fn specificity(self) -> u8 {
+        match self {
+            CommandArgType::I32 => 3,
+            CommandArgType::F32 | CommandArgType::Bytes => 2,
+            CommandArgType::Custom => 1,
+            CommandArgType::String => 0,
+        }
+    }
+}
+
+/// What a command of a [mutex group](Command::mutex_group) does when another command of
+/// the group is running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum WhenBusy {
+    /// Wait until the running command finishes.
+    #[default]
+    Wait,
+    /// Fail with an error.
+    Reject,
+}
+
+/// How long a temporary command exists, see [`Repl::add_temporary`](crate::Repl::add_temporary).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Until the mode of the session changes, e.g. when the mode entered along with adding
+    /// the command is left.
+    #[default]
+    Mode,
+    /// Until the session ends.
+    Session,
+}
+
+/// Change of the session requested by a handler through [`CommandContext`].
+pub(crate) enum SessionChange {
+    Mode(Option<String>),
+    Temporary {
+        name: String,
+        cmd: Box<Command>,
+        scope: Scope,
+    },
+}
+
+/// Group of commands that cannot run concurrently, see [`Command::mutex_group`].
+pub(crate) struct MutexGroup {
+    pub(crate) name: String,
+    when_busy: WhenBusy,
+    /// Lock shared by the commands of the group, replaced by the registry's one when the
+    /// command is added.
+    pub(crate) lock: Arc<AsyncMutex<()>>,
+}
+
+impl MutexGroup {
+    async fn acquire(&self) -> anyhow::Result<AsyncMutexGuard<'_, ()>> {
+        match self.when_busy {
+            WhenBusy::Wait => Ok(self.lock.lock().await),
+            WhenBusy::Reject => self.lock.try_lock().ok_or_else(|| {
+                anyhow::anyhow!("another command of group '{}' is running", self.name)
+            }),
+        }
+    }
+}
+
+/// Condition for running a command, e.g. being connected, see [`Command::precondition`].
+#[derive(Clone)]
+pub struct Precondition {
+    check: Arc<dyn Fn() -> bool + Send + Sync>,
+    explanation: String,
+    hide: bool,
+}
+
+impl Precondition {
+    /// Run the command only while `check` returns true, printing `explanation` (e.g.
+    /// "not connected, run 'connect' first") otherwise.
+    pub fn new(check: impl Fn() -> bool + Send + Sync + 'static, explanation: &str) -> Self {
+        Self {
+            check: Arc::new(check),
+            explanation: explanation.into(),
+            hide: false,
+        }
+    }
+
+    /// Also omit the command from the completion of command names while `check` returns
+    /// false. It is still shown in help.
+    pub fn hide_from_completion(mut self) -> Self {
+        self.hide = true;
+        self
+    }
+
+    /// Returns true if the command can run.
+    pub fn holds(&self) -> bool {
+        (self.check)()
+    }
+
+    /// Message printed instead of running the command.
+    pub fn explanation(&self) -> &str {
+        &self.explanation
+    }
+}
+
+/// Policy retrying a command after transient errors, see [`Command::retry`].
+///
+/// By default any error other than [`ArgsError`] and [`CriticalError`] is retried, waiting
+/// `backoff` before the first retry and doubling it before each of the next ones.
+#[derive(Clone)]
+pub struct Retry {
+    attempts: u32,
+    backoff: Duration,
+    when: Arc<dyn Fn(&anyhow::Error) -> bool + Send + Sync>,
+}
+
+impl Retry {
+    /// Run the command up to `attempts` times in total, waiting `backoff` between the first
+    /// two attempts.
+    pub fn new(attempts: u32, backoff: Duration) -> Self {
+        Self {
+            attempts,
+            backoff,
+            when: Arc::new(|_| true),
+        }
+    }
+
+    /// Only retry errors for which `predicate` returns true, e.g. timeouts.
+    pub fn when(
+        mut self,
+        predicate: impl Fn(&anyhow::Error) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.when = Arc::new(predicate);
+        self
+    }
+
+    /// Time to wait before running the command again after failing `attempt` (starting at
+    /// 1) with `err`, or `None` if it should not be retried.
+    pub(crate) fn delay(&self, attempt: u32, err: &anyhow::Error) -> Option<Duration> {
+        if attempt >= self.attempts
+            || err.is::<ArgsError>()
+            || err.is::<CriticalError>()
+            || !(self.when)(err)
+        {
+            return None;
+        }
+        Some(self.backoff.saturating_mul(1 << (attempt - 1).min(16)))
+    }
+
+    /// Maximum number of runs of the command.
+    pub(crate) fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
+
+/// Command handler, either producing local or [`Send`] futures.
+pub(crate) enum Handler {
+    Local(Box<dyn ExecuteCommand>),
+    Send(Box<dyn SendExecuteCommand>),
+    /// Local handler receiving [`CommandContext`], see [`Command::new_with_context`].
+    Context(Box<dyn ExecuteCommandWithContext>),
+    /// Handler executed on a blocking thread, see [`Command::new_heavy`].
+    Heavy {
+        handler: Arc<Mutex<Box<dyn SendExecuteCommand>>>,
+        runtime: Arc<dyn Runtime>,
+    },
+}
+
+impl Handler {
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+        out: &'a mut Sink,
+        cwd: Option<&'a Path>,
+        changes: &'a mut Vec<SessionChange>,
+        clock: Option<Arc<dyn Clock>>,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        match self {
+            Handler::Local(handler) => handler.execute(args, args_info),
+            Handler::Send(handler) => handler.execute(args, args_info),
+            Handler::Context(handler) => handler.execute(
+                CommandContext::new(args, args_info, out)
+                    .with_cwd(cwd)
+                    .with_changes(changes)
+                    .with_clock(clock),
+            ),
+            Handler::Heavy { handler, runtime } => {
+                Box::pin(execute_heavy(handler, runtime, args, args_info))
+            }
+        }
+    }
+
+    fn execute_send<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Option<Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + Send + 'a>>> {
+        match self {
+            Handler::Local(_) | Handler::Context(_) => None,
+            Handler::Send(handler) => Some(handler.execute(args, args_info)),
+            Handler::Heavy { handler, runtime } => {
+                Some(Box::pin(execute_heavy(handler, runtime, args, args_info)))
+            }
+        }
+    }
+}
+
 pub struct Command {
     /// Command desctiption that will be displayed in the help message
     pub(crate) description: String,
     /// Names and types of arguments to the command
     pub(crate) args_info: Vec<CommandArgInfo>,
     /// Command handler which should validate arguments and perform command logic
-    pub(crate) handler: Box<dyn ExecuteCommand>,
+    pub(crate) handler: AsyncMutex<Handler>,
+    /// Whether the handler runs on a blocking thread, see [`Command::new_heavy`]
+    heavy: bool,
+    /// Group of related commands, see [`Command::category`]
+    pub(crate) category: Option<String>,
+    /// Whether to omit the command from help, see [`Command::hidden`]
+    pub(crate) hidden: bool,
+    /// Whether to show hints for the command, see [`Command::hints`]
+    pub(crate) hints: bool,
+    /// Whether to complete arguments of the command, see [`Command::completion`]
+    pub(crate) completion: bool,
+    /// Deprecation note, see [`Command::deprecated`]
+    pub(crate) deprecated: Option<String>,
+    /// Link to the documentation, see [`Command::docs_url`]
+    pub(crate) docs_url: Option<String>,
+    /// Short names accepted for the command, see [`Command::abbreviation`]
+    pub(crate) abbreviations: Vec<String>,
+    /// Whether running the command needs confirmation, see [`Command::destructive`]
+    pub(crate) destructive: bool,
+    /// Commands that cannot run concurrently with this one, see [`Command::mutex_group`]
+    pub(crate) mutex_group: Option<MutexGroup>,
+    /// Minimum time between runs, see [`Command::cooldown`]
+    pub(crate) cooldown: Option<Duration>,
+    /// When the command was last run, if it has a cooldown
+    last_run: Mutex<Option<Instant>>,
+    /// How the command is retried after errors, see [`Command::retry`]
+    pub(crate) retry: Option<Retry>,
+    /// Conditions for running the command, see [`Command::precondition`]
+    preconditions: Vec<Precondition>,
+    /// Constraints on the arguments, see [`Command::validator`]
+    validator: All,
+    /// Whether arguments are checked by the handler only, see [`Command::raw_args`]
+    pub(crate) raw_args: bool,
+    /// Whether arguments can be given as JSON, see [`Command::json_args`]
+    #[cfg(feature = "json")]
+    pub(crate) json_args: bool,
 }
 
 impl Command {
@@ -104,73 +971,599 @@ impl Command {
         args_info: Vec<CommandArgInfo>,
         handler: Box<dyn ExecuteCommand>,
     ) -> Self {
+        Self::with_handler(desc, args_info, Handler::Local(handler))
+    }
+
+    fn with_handler(desc: &str, args_info: Vec<CommandArgInfo>, handler: Handler) -> Self {
         Self {
             description: desc.into(),
             args_info,
-            handler,
+            heavy: matches!(handler, Handler::Heavy { .. }),
+            handler: AsyncMutex::new(handler),
+            category: None,
+            hidden: false,
+            hints: true,
+            completion: true,
+            deprecated: None,
+            docs_url: None,
+            abbreviations: Vec::new(),
+            destructive: false,
+            mutex_group: None,
+            cooldown: None,
+            last_run: Mutex::new(None),
+            retry: None,
+            preconditions: Vec::new(),
+            validator: All::new(),
+            raw_args: false,
+            #[cfg(feature = "json")]
+            json_args: false,
         }
     }
 
-    pub fn execute(
-        &mut self,
+    /// Create a command with a handler receiving [`CommandContext`], e.g. to write its
+    /// output through the REPL.
+    pub fn new_with_context(
+        desc: &str,
+        args_info: Vec<CommandArgInfo>,
+        handler: Box<dyn ExecuteCommandWithContext>,
+    ) -> Self {
+        Self::with_handler(desc, args_info, Handler::Context(handler))
+    }
+
+    /// Create a command with a handler returning [`Send`] futures.
+    pub fn new_send(
+        desc: &str,
+        args_info: Vec<CommandArgInfo>,
+        handler: Box<dyn SendExecuteCommand>,
+    ) -> Self {
+        Self::with_handler(desc, args_info, Handler::Send(handler))
+    }
+
+    /// Create a command for CPU-bound or otherwise long-running work.
+    ///
+    /// The handler future is driven to completion on a blocking thread (see
+    /// [`Runtime::spawn_blocking`]), so it does not stall the REPL loop or other
+    /// tasks running on the same executor. When added to a [`Repl`](crate::Repl)
+    /// the runtime configured with [`ReplBuilder::runtime`](crate::repl::ReplBuilder::runtime)
+    /// is used.
+    pub fn new_heavy(
+        desc: &str,
+        args_info: Vec<CommandArgInfo>,
+        handler: Box<dyn SendExecuteCommand>,
+    ) -> Self {
+        let handler = Handler::Heavy {
+            handler: Arc::new(Mutex::new(handler)),
+            runtime: default_runtime(),
+        };
+        Self::with_handler(desc, args_info, handler)
+    }
+
+    /// Assign the command to a category (e.g. "filesystem"), available through
+    /// [`Repl::commands`](crate::Repl::commands).
+    pub fn category(mut self, category: &str) -> Self {
+        self.category = Some(category.into());
+        self
+    }
+
+    /// Omit the command from the help message. It can still be executed.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Mark the command as destructive, so that it is confirmed before running according to
+    /// [`ReplBuilder::confirm_destructive`](crate::repl::ReplBuilder::confirm_destructive).
+    pub fn destructive(mut self, destructive: bool) -> Self {
+        self.destructive = destructive;
+        self
+    }
+
+    /// Prevent the command from running concurrently with other commands of the mutex
+    /// group named `group`, e.g. from another session sharing the registry. `when_busy`
+    /// decides whether the command waits for the running one or fails.
+    pub fn mutex_group(mut self, group: &str, when_busy: WhenBusy) -> Self {
+        self.mutex_group = Some(MutexGroup {
+            name: group.into(),
+            when_busy,
+            lock: Default::default(),
+        });
+        self
+    }
+
+    /// Refuse to run the command again until `cooldown` has passed since it was last run,
+    /// e.g. to protect a production system from repeated restarts.
+    ///
+    /// A run counts even if the handler fails, but not if the arguments do not match. The
+    /// time is tracked per registry, so it applies to all sessions sharing the command.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = Some(cooldown);
+        self
+    }
+
+    /// Run the command again when it fails with a transient error, e.g. when it calls a
+    /// flaky remote API, see [`Retry`].
+    ///
+    /// Each failed attempt is printed with the time until the next one. Only the last
+    /// error is reported as the result of the command.
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Run the command only while `precondition` holds, e.g. commands that need a
+    /// connection, see [`Precondition`].
+    ///
+    /// Preconditions are checked in the order they are added, before confirmation and
+    /// arguments checks; the explanation of the first one that fails is reported as the
+    /// error of the command. A command with overloads is unavailable only if none of them
+    /// can run.
+    pub fn precondition(mut self, precondition: Precondition) -> Self {
+        self.preconditions.push(precondition);
+        self
+    }
+
+    /// Add a constraint on the arguments (e.g. `start < end`), checked after their types,
+    /// see [`validate`](crate::validate).
+    ///
+    /// Validators run in the order they are added, before the handler. Arguments failing
+    /// them are reported like arguments of the wrong type, and the next overload is tried.
+    /// They do not apply to commands with [`Command::raw_args`].
+    pub fn validator<V: Validate + 'static>(mut self, validator: V) -> Self {
+        self.validator = std::mem::take(&mut self.validator).and(validator);
+        self
+    }
+
+    /// Reject arguments for which `check` returns false, with `message`.
+    ///
+    /// `check` takes all the arguments of the command parsed to the types of its
+    /// parameters, e.g. `.check_args(|start: i32, end: i32| start <= end, "START must not
+    /// be after END")`. Like other [validators](Command::validator) it runs before the
+    /// handler, so the handler does not need to check the combination.
+    pub fn check_args<F, Args>(self, check: F, message: &str) -> Self
+    where
+        F: TypedCheck<Args> + Send + Sync + 'static,
+        Args: 'static,
+    {
+        self.validator(Typed::new(check, message))
+    }
+
+    /// Accept `abbreviation` (e.g. `cfg` for `configure`) as the name of the command.
+    ///
+    /// Unlike a prefix, an abbreviation is accepted even when
+    /// [`ReplBuilder::predict_commands`](crate::repl::ReplBuilder::predict_commands) is
+    /// disabled, and it is shown in help next to the full name. It must not be the name or
+    /// abbreviation of another command.
+    pub fn abbreviation(mut self, abbreviation: &str) -> Self {
+        self.abbreviations.push(abbreviation.into());
+        self
+    }
+
+    /// Show hints (the rest of the name and argument placeholders) for the command.
+    /// Defaults to `true`; has no effect if hints are disabled with
+    /// [`ReplBuilder::with_hints`](crate::repl::ReplBuilder::with_hints).
+    ///
+    /// For a command with multiple overloads, hints are shown unless all of them disable it.
+    pub fn hints(mut self, hints: bool) -> Self {
+        self.hints = hints;
+        self
+    }
+
+    /// Complete the arguments of the command, with argument completers or filenames.
+    /// Defaults to `true`. Disable it along with [`Command::hints`] for commands taking
+    /// free-form text, e.g. `note <anything>`, where candidates are only noise.
+    ///
+    /// For a command with multiple overloads, arguments are completed unless all of them
+    /// disable it. The command name itself is still completed.
+    pub fn completion(mut self, completion: bool) -> Self {
+        self.completion = completion;
+        self
+    }
+
+    /// Pass any number of arguments to the handler without checking them against `args_info`.
+    ///
+    /// For handlers doing their own parsing (e.g. with [`clap`](crate::clap_args)), `args_info`
+    /// is then only used in help and usage messages. During overload resolution such commands
+    /// are tried after all the others, see
+    /// [`CommandRegistry::overloads`](crate::registry::CommandRegistry::overloads).
+    pub fn raw_args(mut self) -> Self {
+        self.raw_args = true;
+        self
+    }
+
+    /// Also accept arguments as a single JSON object or array, see [`json_args`](crate::json_args).
+    #[cfg(feature = "json")]
+    pub fn json_args(mut self) -> Self {
+        self.json_args = true;
+        self
+    }
+
+    /// Mark the command as deprecated, `note` is shown in help and when the command is run.
+    pub fn deprecated(mut self, note: &str) -> Self {
+        self.deprecated = Some(note.into());
+        self
+    }
+
+    /// Link to the full documentation of the command, shown in its help (see
+    /// [`Repl::command_help`](crate::Repl::command_help)) as a hyperlink when the output is
+    /// a terminal.
+    pub fn docs_url(mut self, url: &str) -> Self {
+        self.docs_url = Some(url.into());
+        self
+    }
+
+    /// Whether the command has been created with [`Command::new_heavy`].
+    pub fn is_heavy(&self) -> bool {
+        self.heavy
+    }
+
+    pub(crate) fn set_runtime(&mut self, new_runtime: &Arc<dyn Runtime>) {
+        match self.handler.get_mut() {
+            Handler::Local(handler) => handler.set_runtime(new_runtime),
+            Handler::Send(handler) => handler.set_runtime(new_runtime),
+            Handler::Context(_) => {}
+            Handler::Heavy { runtime, .. } => *runtime = new_runtime.clone(),
+        }
+    }
+
+    /// Execute the command outside of a REPL. Output written to [`CommandContext::out`] goes
+    /// to stdout, and changes of the session requested through the context are ignored.
+    pub fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let handler = self.handler.get_mut();
+        let args_info = &self.args_info;
+        Box::pin(async move {
+            let mut stdout = Sink::new(Output::Stdout);
+            let mut changes = Vec::new();
+            handler
+                .execute(args, args_info, &mut stdout, None, &mut changes, None)
+                .await
+        })
+    }
+
+    /// Same as [`Command::execute`] but returns a [`Send`] future.
+    ///
+    /// Returns `None` if the command has been created with a local handler
+    /// (see [`Command::new`]).
+    pub fn execute_send<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+    ) -> Option<Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + Send + 'a>>> {
+        self.handler.get_mut().execute_send(args, &self.args_info)
+    }
+
+    /// Execute the command through a shared reference, waiting for other executions to finish.
+    pub(crate) async fn execute_shared(
+        &self,
         args: &[&str],
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
-        self.handler.execute(
-            args.iter().map(|s| s.to_string()).collect(),
-            self.args_info.clone(),
-        )
+        out: &mut Sink,
+        cwd: Option<&Path>,
+        changes: &mut Vec<SessionChange>,
+        clock: &Arc<dyn Clock>,
+    ) -> anyhow::Result<CommandStatus> {
+        let _group = match &self.mutex_group {
+            Some(group) => Some(group.acquire().await?),
+            None => None,
+        };
+        let mut handler = self.handler.lock().await;
+        handler
+            .execute(
+                args,
+                &self.args_info,
+                out,
+                cwd,
+                changes,
+                Some(clock.clone()),
+            )
+            .await
+    }
+
+    /// Check the types of `args` and then the [validators](Command::validator).
+    pub(crate) fn validate_args(&self, args: &[&str]) -> std::result::Result<(), ArgsError> {
+        Types.validate(args, &self.args_info)?;
+        self.validator.validate(args, &self.args_info)
+    }
+
+    /// First precondition of the command that does not hold, see [`Command::precondition`].
+    pub(crate) fn unmet_precondition(&self) -> Option<&Precondition> {
+        self.preconditions
+            .iter()
+            .find(|precondition| !precondition.holds())
+    }
+
+    /// Whether the command name is completed, i.e. unless a precondition hiding it fails.
+    pub(crate) fn is_offered(&self) -> bool {
+        self.preconditions
+            .iter()
+            .all(|precondition| !precondition.hide || precondition.holds())
+    }
+
+    /// Time left until the command can be run again, see [`Command::cooldown`].
+    ///
+    /// The clock is only read for commands with a cooldown, as reading the time is not
+    /// supported on every platform.
+    pub(crate) fn cooldown_remaining(&self, clock: &dyn Clock) -> Option<Duration> {
+        let cooldown = self.cooldown?;
+        let last_run = (*self.last_run.lock().unwrap_or_else(PoisonError::into_inner))?;
+        cooldown
+            .checked_sub(clock.now().saturating_duration_since(last_run))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Execute the command like [`Command::execute_shared`], running it again after
+    /// transient errors as configured with [`Command::retry`].
+    pub(crate) async fn execute_retrying(
+        &self,
+        args: &[&str],
+        out: &mut Sink,
+        cwd: Option<&Path>,
+        changes: &mut Vec<SessionChange>,
+        clock: &Arc<dyn Clock>,
+    ) -> anyhow::Result<CommandStatus> {
+        let mut attempt = 1;
+        loop {
+            let result = self.execute_shared(args, out, cwd, changes, clock).await;
+            let (Err(err), Some(retry)) = (&result, &self.retry) else {
+                return result;
+            };
+            let Some(delay) = retry.delay(attempt, err) else {
+                return result;
+            };
+            if out.verbosity != Verbosity::Quiet {
+                out.finish_line()?;
+                writeln!(
+                    out,
+                    "Attempt {attempt}/{} failed: {err}, retrying in {}",
+                    retry.attempts(),
+                    out.locale.format_duration(delay)
+                )?;
+            }
+            clock.sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Whether `arg`, given at `index`, is a path argument, see [`ArgCompleter::is_path`].
+    ///
+    /// Extra arguments of commands with [`Command::raw_args`] are like the last one.
+    pub(crate) fn is_path_arg(&self, index: usize, arg: &str) -> bool {
+        let named = arg
+            .split_once('=')
+            .is_some_and(|(key, _)| self.named_arg(key).is_some());
+        !named
+            && self
+                .positional_arg(index)
+                .and_then(|info| info.completer.as_ref())
+                .is_some_and(|completer| completer.is_path())
+    }
+
+    /// Information of the argument at `index`, the last one for extra arguments of commands
+    /// with [`Command::raw_args`].
+    pub(crate) fn positional_arg(&self, index: usize) -> Option<&CommandArgInfo> {
+        match self.raw_args {
+            true => self.args_info.get(index).or(self.args_info.last()),
+            false => self.args_info.get(index),
+        }
+    }
+
+    /// Information of the argument named `name`, given as `name=value`.
+    pub(crate) fn named_arg(&self, name: &str) -> Option<&CommandArgInfo> {
+        self.args_info
+            .iter()
+            .find(|info| info.name.as_deref() == Some(name))
     }
 
     /// Returns the string description of the argument types
     pub fn arg_types(&self) -> Vec<String> {
         self.args_info
             .iter()
-            .map(|info| {
-                let info_string = info.clone().to_string();
-                let parts = info_string.split(':').collect::<Vec<_>>();
-                parts[1].to_string()
-            })
+            .map(|info| info.arg_type.to_string())
             .collect()
     }
+
+    pub(crate) fn arg_type_list(&self) -> Vec<CommandArgType> {
+        self.args_info.iter().map(|info| info.arg_type).collect()
+    }
+}
+
+async fn execute_heavy(
+    handler: &Arc<Mutex<Box<dyn SendExecuteCommand>>>,
+    runtime: &Arc<dyn Runtime>,
+    args: &[&str],
+    args_info: &[CommandArgInfo],
+) -> anyhow::Result<CommandStatus> {
+    // the blocking thread may outlive the borrowed arguments, so it needs owned copies
+    let handler = handler.clone();
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let args_info = args_info.to_vec();
+    runtime::spawn_blocking(runtime.as_ref(), move || {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let mut handler = handler.lock().unwrap_or_else(PoisonError::into_inner);
+        runtime::block_on(handler.execute(&args, &args_info))
+    })
+    .await?
+}
+
+/// Cooldown started by [`start_cooldown`] for a run of a command.
+pub(crate) struct Cooldown<'a> {
+    cmds: Vec<&'a Command>,
+    started: Instant,
+    previous: Vec<Option<Instant>>,
+}
+
+impl Cooldown<'_> {
+    /// Restore the cooldown as it was before, e.g. when no overload accepted the arguments,
+    /// unless another run started it again since.
+    pub(crate) fn cancel(self) {
+        for (cmd, previous) in self.cmds.iter().zip(self.previous) {
+            let mut last_run = cmd.last_run.lock().unwrap_or_else(PoisonError::into_inner);
+            if *last_run == Some(self.started) {
+                *last_run = previous;
+            }
+        }
+    }
+}
+
+/// Start the cooldown of `cmds`, the overloads of a command, see [`Command::cooldown`].
+/// Returns the time left if it is still running from a previous run, and `None` if the
+/// command has no cooldown, without reading the clock.
+///
+/// The time is checked and recorded while holding the locks of all the overloads, so that
+/// of concurrent runs, e.g. lines of a batch, only the first one starts.
+pub(crate) fn start_cooldown<'a>(
+    cmds: &'a [Arc<Command>],
+    clock: &dyn Clock,
+) -> Result<Option<Cooldown<'a>>, Duration> {
+    let cmds: Vec<&Command> = cmds
+        .iter()
+        .map(Arc::as_ref)
+        .filter(|cmd| cmd.cooldown.is_some())
+        .collect();
+    if cmds.is_empty() {
+        return Ok(None);
+    }
+    // locked in the order of the overloads, which changes never reorder
+    let mut last_runs: Vec<_> = cmds
+        .iter()
+        .map(|cmd| cmd.last_run.lock().unwrap_or_else(PoisonError::into_inner))
+        .collect();
+    let started = clock.now();
+    let remaining = cmds
+        .iter()
+        .zip(&last_runs)
+        .filter_map(|(cmd, last_run)| {
+            let elapsed = started.saturating_duration_since((**last_run)?);
+            cmd.cooldown?.checked_sub(elapsed)
+        })
+        .filter(|remaining| !remaining.is_zero())
+        .max();
+    if let Some(remaining) = remaining {
+        return Err(remaining);
+    }
+    let previous = last_runs
+        .iter_mut()
+        .map(|last_run| last_run.replace(started))
+        .collect();
+    drop(last_runs);
+    Ok(Some(Cooldown {
+        cmds,
+        started,
+        previous,
+    }))
 }
 
-pub fn validate(
-    args: Vec<String>,
-    arg_infos: Vec<CommandArgInfo>,
-) -> std::result::Result<(), ArgsError> {
-    if args.len() != arg_infos.len() {
+/// Check the number and types of `args`, see [`Types`].
+pub fn validate(args: &[&str], arg_infos: &[CommandArgInfo]) -> std::result::Result<(), ArgsError> {
+    Types.validate(args, arg_infos)
+}
+
+/// Put `key=value` arguments in the place of the argument named `key`.
+///
+/// Named arguments can be mixed with positional ones, which fill the remaining places in
+/// order. Only keys equal to the name of an argument are recognized, other arguments
+/// containing `=` are positional. Values of named arguments are checked against their
+/// types here, so that the error mentions the key.
+pub fn bind_named<'a>(
+    args: &[&'a str],
+    arg_infos: &[CommandArgInfo],
+) -> std::result::Result<Vec<&'a str>, ArgsError> {
+    let Some(bound) = bind_partial(args, arg_infos)? else {
+        return Ok(args.to_vec());
+    };
+    if bound.iter().any(Option::is_none) {
         return Err(ArgsError::WrongNumberOfArguments {
             got: args.len(),
             expected: arg_infos.len(),
         });
     }
+    Ok(bound.into_iter().flatten().collect())
+}
 
-    for (i, arg_value) in args.iter().enumerate() {
-        let arg_info = arg_infos[i].clone();
-        let arg_type: CommandArgType = arg_info.arg_type;
-        match arg_type {
-            CommandArgType::I32 => {
-                if let Err(err) = &arg_value.parse::<i32>() {
-                    return Err(ArgsError::WrongArgumentValue {
-                        argument: arg_value.to_string(),
-                        error: err.to_string(),
-                    });
-                }
+/// Same as [`bind_named`], leaving empty the places of the arguments not given.
+///
+/// Returns `None` if there are no named arguments, in which case `args` are unchanged.
+pub(crate) fn bind_partial<'a>(
+    args: &[&'a str],
+    arg_infos: &[CommandArgInfo],
+) -> std::result::Result<Option<Vec<Option<&'a str>>>, ArgsError> {
+    let named = |arg: &'a str| {
+        let (key, value) = arg.split_once('=')?;
+        let index = arg_infos
+            .iter()
+            .position(|info| info.name.as_deref() == Some(key))?;
+        Some((index, value))
+    };
+    // only values of string arguments may contain `=`
+    let takes_text = arg_infos.iter().any(|info| {
+        matches!(
+            info.arg_type,
+            CommandArgType::String | CommandArgType::Custom
+        )
+    });
+    for arg in args.iter().filter(|arg| named(arg).is_none()) {
+        let Some((key, _)) = arg.split_once('=') else {
+            continue;
+        };
+        let suggestion = closest_name(key, arg_infos);
+        if suggestion.is_some() || !takes_text {
+            return Err(ArgsError::UnknownFlag {
+                flag: key.into(),
+                suggestion,
+            });
+        }
+    }
+    if !args.iter().any(|arg| named(arg).is_some()) {
+        return Ok(None);
+    }
+
+    let mut bound: Vec<Option<&str>> = vec![None; arg_infos.len()];
+    let mut positional = Vec::new();
+    for arg in args {
+        match named(arg) {
+            Some((index, _)) if bound[index].is_some() => {
+                let key = arg.split_once('=').unwrap().0;
+                return Err(ArgsError::Invalid(format!(
+                    "argument '{key}' given more than once"
+                )));
             }
-            CommandArgType::F32 => {
-                if let Err(err) = &arg_value.parse::<f32>() {
-                    return Err(ArgsError::WrongArgumentValue {
-                        argument: arg_value.to_string(),
-                        error: err.to_string(),
-                    });
-                }
+            Some((index, value)) => {
+                validate(&[value], &arg_infos[index..=index]).map_err(|err| match err {
+                    ArgsError::WrongArgumentValue { error, .. } => ArgsError::WrongArgumentValue {
+                        argument: arg.to_string(),
+                        error,
+                    },
+                    err => err,
+                })?;
+                bound[index] = Some(value);
             }
-            CommandArgType::String => (),
-            CommandArgType::Custom => (),
+            None => positional.push(*arg),
         }
     }
+    let mut positional = positional.into_iter();
+    for slot in bound.iter_mut().filter(|slot| slot.is_none()) {
+        *slot = positional.next();
+    }
+    if let Some(token) = positional.next() {
+        return Err(ArgsError::UnexpectedToken {
+            token: token.into(),
+            expected: arg_infos.len(),
+        });
+    }
+    Ok(Some(bound))
+}
 
-    Ok(())
+/// Name of the argument closest to the unknown name `key`, if one is close enough to be a
+/// likely typo.
+fn closest_name(key: &str, arg_infos: &[CommandArgInfo]) -> Option<String> {
+    let max_distance = (key.chars().count() / 3).clamp(1, 2);
+    arg_infos
+        .iter()
+        .filter_map(|info| info.name.as_deref())
+        .map(|name| (edit_distance(key, name), name))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name.to_string())
 }
 
 /// Return status of a command.
@@ -180,6 +1573,9 @@ pub enum CommandStatus {
     Done,
     /// Indicates that REPL should quit
     Quit,
+    /// Indicates that REPL should restart, e.g. after reloading its configuration, see
+    /// [`Repl::restart`](crate::Repl::restart)
+    Restart,
 }
 
 /// Special error wrapper used to indicate that a critical error occured.
@@ -233,25 +1629,208 @@ pub enum ArgsError {
     WrongArgumentValue { argument: String, error: String },
     #[error("no command variant found for provided args")]
     NoVariantFound,
+    #[error("invalid arguments: {0}")]
+    Invalid(String),
+    /// Named argument (`name=value`) with a name that no argument has.
+    #[error(
+        "unknown flag '{flag}'{}",
+        suggestion.as_ref().map(|s| format!(", did you mean '{s}'?")).unwrap_or_default()
+    )]
+    UnknownFlag {
+        flag: String,
+        /// Name of an argument close to `flag`, if there is one.
+        suggestion: Option<String>,
+    },
+    /// Argument given after all the arguments of the command.
+    #[error("unexpected argument '{token}', the command takes {expected}")]
+    UnexpectedToken { token: String, expected: usize },
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn path_completer() {
+        let root = std::env::temp_dir().join(format!("repl-paths-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        for file in ["a.toml", "b.txt", ".hidden.toml"] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        let sep = std::path::MAIN_SEPARATOR;
+        let prefix = format!("{}{sep}", root.display());
+        let complete = |completer: PathCompleter, prefix: &str| -> Vec<String> {
+            let values = completer.complete(prefix);
+            values
+                .iter()
+                .map(|value| value[root.as_os_str().len() + 1..].to_string())
+                .collect()
+        };
+
+        let all = complete(PathCompleter::new(), &prefix);
+        assert_eq!(
+            all,
+            vec!["a.toml".to_string(), "b.txt".into(), format!("sub{sep}")]
+        );
+        let toml = PathCompleter::new().extensions(["toml"]);
+        assert_eq!(
+            complete(toml.clone(), &prefix),
+            vec!["a.toml".to_string(), format!("sub{sep}")]
+        );
+        assert_eq!(
+            complete(toml, &format!("{prefix}.")),
+            vec![".hidden.toml".to_string()]
+        );
+        let dirs = PathCompleter::new().dirs_only();
+        assert_eq!(
+            complete(dirs, &format!("{prefix}s")),
+            vec![format!("sub{sep}")]
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
     #[test]
     fn validator_no_args() {
-        let arg_types = vec![];
-        assert!(validate(vec![], arg_types.clone()).is_ok());
-        assert!(validate(vec!["hello".into()], arg_types.clone()).is_err())
+        let arg_types: Vec<CommandArgInfo> = vec![];
+        assert!(validate(&[], &arg_types).is_ok());
+        assert!(matches!(
+            validate(&["hello"], &arg_types),
+            Err(ArgsError::UnexpectedToken { token, expected: 0 }) if token == "hello"
+        ))
+    }
+
+    #[test]
+    fn named_arguments() {
+        let arg_types = vec![
+            CommandArgInfo::new_with_name(CommandArgType::String, "name"),
+            CommandArgInfo::new_with_name(CommandArgType::I32, "count"),
+            CommandArgInfo::new(CommandArgType::String),
+        ];
+        let bound = bind_named(&["count=3", "foo", "x=y"], &arg_types).unwrap();
+        assert_eq!(bound, vec!["foo", "3", "x=y"]);
+        let bound = bind_named(&["a", "1", "b"], &arg_types).unwrap();
+        assert_eq!(bound, vec!["a", "1", "b"]);
+        let bound = bind_named(&["c", "name=a", "count=1"], &arg_types).unwrap();
+        assert_eq!(bound, vec!["a", "1", "c"]);
+
+        let err = bind_named(&["count=x", "a", "b"], &arg_types).unwrap_err();
+        assert!(
+            matches!(err, ArgsError::WrongArgumentValue { argument, .. } if argument == "count=x")
+        );
+        let err = bind_named(&["count=1", "count=2", "a"], &arg_types).unwrap_err();
+        assert!(matches!(err, ArgsError::Invalid(msg) if msg.contains("'count'")));
+        let err = bind_named(&["count=1", "a"], &arg_types).unwrap_err();
+        assert!(matches!(
+            err,
+            ArgsError::WrongNumberOfArguments {
+                got: 2,
+                expected: 3
+            }
+        ));
+
+        let err = bind_named(&["cuont=1", "a", "b"], &arg_types).unwrap_err();
+        assert!(matches!(
+            err,
+            ArgsError::UnknownFlag { flag, suggestion: Some(s) } if flag == "cuont" && s == "count"
+        ));
+        assert_eq!(
+            bind_named(&["verbose=1", "a", "b"], &arg_types).unwrap(),
+            vec!["verbose=1", "a", "b"]
+        );
+        let numbers = vec![CommandArgInfo::new_with_name(CommandArgType::I32, "n")];
+        let err = bind_named(&["verbose=1"], &numbers).unwrap_err();
+        assert!(matches!(
+            err,
+            ArgsError::UnknownFlag {
+                suggestion: None,
+                ..
+            }
+        ));
+        let err = bind_named(&["count=1", "a", "b", "c"], &arg_types).unwrap_err();
+        assert!(matches!(
+            err,
+            ArgsError::UnexpectedToken { token, expected: 3 } if token == "c"
+        ));
+    }
+
+    #[test]
+    fn retry_backoff() {
+        let ms = Duration::from_millis;
+        let retry = Retry::new(4, ms(100)).when(|err| err.to_string() != "fatal");
+        let err = anyhow::anyhow!("timed out");
+        assert_eq!(retry.delay(1, &err), Some(ms(100)));
+        assert_eq!(retry.delay(3, &err), Some(ms(400)));
+        assert_eq!(retry.delay(4, &err), None);
+        assert_eq!(retry.delay(1, &anyhow::anyhow!("fatal")), None);
+        let critical = CriticalError::Critical(anyhow::anyhow!("gone"));
+        assert_eq!(retry.delay(1, &critical.into()), None);
+    }
+
+    #[test]
+    fn typed_arguments() {
+        let args_info = vec![
+            CommandArgInfo::new_with_name(CommandArgType::I32, "count"),
+            CommandArgInfo::new_with_name(CommandArgType::F32, "ratio"),
+            CommandArgInfo::new_with_name(CommandArgType::String, "flag"),
+        ];
+        let mut out = Sink::new(Output::Stdout);
+        let args = ["3", "0.5", "true"];
+        let ctx = CommandContext::new(&args, &args_info, &mut out);
+        assert_eq!(ctx.arg::<i32>("count").unwrap(), 3);
+        assert_eq!(ctx.arg::<f32>("count").unwrap(), 3.0);
+        assert_eq!(ctx.arg::<f32>("ratio").unwrap(), 0.5);
+        assert!(ctx.arg::<bool>("flag").unwrap());
+        assert_eq!(ctx.arg::<&str>("flag").unwrap(), "true");
+        assert_eq!(ctx.arg::<String>("count").unwrap(), "3");
+        assert_eq!(ctx.arg::<f64>("ratio").unwrap(), 0.5);
+        assert!(matches!(
+            ctx.arg::<i32>("ratio"),
+            Err(ArgsError::WrongArgumentValue { argument, .. }) if argument == "0.5"
+        ));
+        assert!(matches!(ctx.arg::<i32>("nope"), Err(ArgsError::Invalid(_))));
+
+        // conversions start from the text of the argument, not from the parsed value
+        let args = ["007", "16777217.5", "0.1"];
+        let ctx = CommandContext::new(&args, &args_info, &mut out);
+        assert_eq!(ctx.arg::<String>("count").unwrap(), "007");
+        assert_eq!(ctx.arg::<i64>("count").unwrap(), 7);
+        assert_eq!(ctx.arg::<f64>("ratio").unwrap(), 16_777_217.5);
+        assert_eq!(ctx.arg::<f64>("flag").unwrap(), 0.1);
+        let args = ["3000000000", "1", ""];
+        let ctx = CommandContext::new(&args, &args_info, &mut out);
+        assert!(ctx.arg::<i32>("count").is_err());
+        assert_eq!(ctx.arg::<i64>("count").unwrap(), 3_000_000_000);
+    }
+
+    #[test]
+    fn bytes_arguments() {
+        let args_info = vec![CommandArgInfo::new_with_name(CommandArgType::Bytes, "size")];
+        assert!(validate(&["1.5GB"], &args_info).is_ok());
+        let err = validate(&["0.1KiB"], &args_info).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to parse argument value '0.1KiB': size '0.1KiB' is not a whole number of bytes"
+        );
+        assert!(validate(&["10 apples"], &args_info).is_err());
+
+        let mut out = Sink::new(Output::Stdout);
+        let ctx = CommandContext::new(&["4MiB"], &args_info, &mut out);
+        assert_eq!(ctx.arg::<u64>("size").unwrap(), 4 << 20);
+        assert_eq!(ctx.arg::<i32>("size").unwrap(), 4 << 20);
+        assert!(ctx.arg::<&str>("size").is_err());
+        assert_eq!(ctx.arg::<String>("size").unwrap(), "4MiB");
+        assert_eq!(ctx.arg::<f64>("size").unwrap(), 4_194_304.0);
+        let ctx = CommandContext::new(&["8EiB"], &args_info, &mut out);
+        assert!(ctx.arg::<i32>("size").is_err());
+        assert_eq!(ctx.arg::<u64>("size").unwrap(), 8 << 60);
     }
 
     #[test]
     fn validator_one_arg() {
         let arg_types = vec![CommandArgInfo::new(CommandArgType::I32)];
-        assert!(validate(vec![], arg_types.clone()).is_err());
-        assert!(validate(vec!["hello".into()], arg_types.clone()).is_err());
-        assert!(validate(vec!["13".into()], arg_types.clone()).is_ok())
+        assert!(validate(&[], &arg_types).is_err());
+        assert!(validate(&["hello"], &arg_types).is_err());
+        assert!(validate(&["13"], &arg_types).is_ok())
     }
 
     #[test]
@@ -262,27 +1841,11 @@ mod tests {
             CommandArgInfo::new(CommandArgType::String),
         ];
 
-        assert!(validate(vec![], arg_types.clone()).is_err());
-        assert!(validate(
-            vec!["1".into(), "2.1".into(), "hello".into()],
-            arg_types.clone()
-        )
-        .is_ok());
-        assert!(validate(
-            vec!["1.2".into(), "2.1".into(), "hello".into()],
-            arg_types.clone()
-        )
-        .is_err());
-        assert!(validate(
-            vec!["1".into(), "a".into(), "hello".into()],
-            arg_types.clone()
-        )
-        .is_err());
-        assert!(validate(
-            vec!["1".into(), "2.1".into(), "hello".into(), "world".into()],
-            arg_types.clone()
-        )
-        .is_err());
+        assert!(validate(&[], &arg_types).is_err());
+        assert!(validate(&["1", "2.1", "hello"], &arg_types).is_ok());
+        assert!(validate(&["1.2", "2.1", "hello"], &arg_types).is_err());
+        assert!(validate(&["1", "a", "hello"], &arg_types).is_err());
+        assert!(validate(&["1", "2.1", "hello", "world"], &arg_types).is_err());
     }
 
     #[tokio::test]
@@ -319,6 +1882,109 @@ mod tests {
         };
     }
 
+    #[tokio::test]
+    async fn command_send_handler() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let mut cmd = Command::new_send(
+            "Example cmd",
+            vec![CommandArgInfo::new(CommandArgType::I32)],
+            Box::new(TrivialCommandHandler::new()),
+        );
+        let future = cmd.execute_send(&["13"]).expect("Send handler");
+        assert_send(&future);
+        assert_eq!(future.await.unwrap(), CommandStatus::Done);
+        assert_eq!(cmd.execute(&["13"]).await.unwrap(), CommandStatus::Done);
+
+        let mut local = Command::new("Local cmd", vec![], Box::new(TrivialCommandHandler::new()));
+        assert!(local.execute_send(&[]).is_none());
+    }
+
+    #[tokio::test]
+    async fn sync_handler() {
+        let mut calls = 0;
+        let mut cmd = Command::new(
+            "Sync cmd",
+            vec![CommandArgInfo::new(CommandArgType::I32)],
+            Box::new(SyncCommandHandler::new(move |args: Vec<String>| {
+                calls += 1;
+                assert_eq!(args, vec!["13".to_string()]);
+                Ok(if calls > 1 {
+                    CommandStatus::Quit
+                } else {
+                    CommandStatus::Done
+                })
+            })),
+        );
+        assert_eq!(cmd.execute(&["13"]).await.unwrap(), CommandStatus::Done);
+        let err = cmd.execute(&["x"]).await.unwrap_err();
+        assert!(err.is::<ArgsError>());
+        assert_eq!(cmd.execute(&["13"]).await.unwrap(), CommandStatus::Quit);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocking_sync_handler() {
+        let handler = SyncCommandHandler::new(|args: Vec<String>| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            assert_eq!(args.len(), 2);
+            Ok(CommandStatus::Done)
+        })
+        .spawn_blocking();
+        let mut cmd = Command::new_send(
+            "Blocking cmd",
+            vec![
+                CommandArgInfo::new(CommandArgType::String),
+                CommandArgInfo::new(CommandArgType::F32),
+            ],
+            Box::new(handler),
+        );
+        let result = cmd.execute_send(&["a", "1.5"]).unwrap().await;
+        assert_eq!(result.unwrap(), CommandStatus::Done);
+        assert!(cmd.execute(&["a"]).await.is_err());
+
+        // the runtime of the REPL is used unless one has been set
+        #[derive(Default)]
+        struct Counting(std::sync::atomic::AtomicUsize);
+        impl Runtime for Counting {
+            fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) -> runtime::BoxFuture<()> {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                runtime::ThreadRuntime.spawn_blocking(f)
+            }
+            fn sleep(&self, duration: Duration) -> runtime::BoxFuture<()> {
+                runtime::ThreadRuntime.sleep(duration)
+            }
+        }
+        let counting = Arc::new(Counting::default());
+        let repl_runtime: Arc<dyn Runtime> = counting.clone();
+        let sync = || SyncCommandHandler::new(|_args| Ok(CommandStatus::Done));
+        let mut cmd = Command::new_send("", vec![], Box::new(sync().spawn_blocking()));
+        cmd.set_runtime(&repl_runtime);
+        cmd.execute(&[]).await.unwrap();
+        let handler = sync().spawn_blocking().runtime(runtime::ThreadRuntime);
+        let mut cmd = Command::new("", vec![], Box::new(handler));
+        cmd.set_runtime(&repl_runtime);
+        cmd.execute(&[]).await.unwrap();
+        assert_eq!(counting.0.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn heavy_command() {
+        let loop_thread = std::thread::current().id();
+        let mut cmd = Command::new_heavy(
+            "Heavy cmd",
+            vec![CommandArgInfo::new(CommandArgType::I32)],
+            Box::new(SyncCommandHandler::new(move |_args: Vec<String>| {
+                assert_ne!(std::thread::current().id(), loop_thread);
+                Ok(CommandStatus::Done)
+            })),
+        );
+        assert!(cmd.is_heavy());
+        assert_eq!(cmd.execute(&["1"]).await.unwrap(), CommandStatus::Done);
+        assert!(cmd.execute(&["x"]).await.unwrap_err().is::<ArgsError>());
+        let result = cmd.execute_send(&["2"]).unwrap().await;
+        assert_eq!(result.unwrap(), CommandStatus::Done);
+    }
+
     #[tokio::test]
     async fn command_with_critical() {
         struct WithCriticalCommandHandler {}
@@ -327,21 +1993,18 @@ mod tests {
                 WithCriticalCommandHandler {}
             }
 
-            async fn handle_command(
-                &mut self,
-                _args: Vec<String>,
-            ) -> anyhow::Result<CommandStatus> {
+            async fn handle_command(&mut self, _args: &[&str]) -> anyhow::Result<CommandStatus> {
                 let err = std::io::Error::new(std::io::ErrorKind::InvalidData, "example error");
                 Err(CriticalError::Critical(err.into()).into())
             }
         }
 
         impl ExecuteCommand for WithCriticalCommandHandler {
-            fn execute(
-                &mut self,
-                args: Vec<String>,
-                _args_info: Vec<CommandArgInfo>,
-            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
+            fn execute<'a>(
+                &'a mut self,
+                args: &'a [&'a str],
+                _args_info: &'a [CommandArgInfo],
+            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
                 Box::pin(self.handle_command(args))
             }
         }
@@ -1,103 +1,1036 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::clock::Clock;
+use crate::command::{validate, ArgCompleter, Command, CommandArgInfo, CommandArgType};
+use crate::embed::Candidate;
+#[cfg(feature = "terminal")]
+use crate::fs::{self, FileSystem};
+use crate::paths::{self, HomeResolver};
+use crate::prefix::starts_with;
+use crate::registry::{CommandRegistry, NAMESPACE_SEPARATORS};
+use crate::repl::split_args;
+use crate::tokenize::Token;
+#[cfg(feature = "terminal")]
 use rustyline::{
-    completion::{Completer, FilenameCompleter, Pair},
+    completion::Completer,
     hint::Hinter,
+    validate::{ValidationContext, ValidationResult, Validator},
 };
-use rustyline_derive::{Helper, Highlighter, Validator};
-use trie_rs::Trie;
+#[cfg(feature = "terminal")]
+use rustyline_derive::Helper;
 
-use crate::repl::split_args;
+#[cfg(feature = "terminal")]
+use crate::printer::Printer;
+#[cfg(feature = "terminal")]
+use crate::repl::InputStatus;
+#[cfg(feature = "terminal")]
+use crate::style::Theme;
 
-#[derive(Helper, Validator, Highlighter)]
+/// Decides whether typed input is complete, see
+/// [`ReplBuilder::input_validator`](crate::repl::ReplBuilder::input_validator).
+#[cfg(feature = "terminal")]
+pub(crate) type InputValidator = dyn Fn(&str) -> InputStatus;
+
+#[cfg_attr(feature = "terminal", derive(Helper))]
 pub(crate) struct Completion {
-    pub(crate) trie: Rc<Trie<u8>>,
+    pub(crate) registry: Arc<CommandRegistry>,
     pub(crate) with_hints: bool,
     pub(crate) with_completion: bool,
-    pub(crate) filename_completer: Option<FilenameCompleter>,
+    pub(crate) completion_limit: Option<usize>,
+    pub(crate) cache: Rc<CompletionCache>,
+    /// Values previously given to arguments, see
+    /// [`ReplBuilder::argument_history`](crate::repl::ReplBuilder::argument_history).
+    pub(crate) value_history: Option<ValueHistory>,
+    /// Home directory of `~` in paths, see
+    /// [`ReplBuilder::home_dir`](crate::repl::ReplBuilder::home_dir).
+    pub(crate) home: Rc<HomeResolver>,
+    /// Directory of relative paths, see
+    /// [`ReplBuilder::working_dir`](crate::repl::ReplBuilder::working_dir).
+    pub(crate) working_dir: Option<Rc<RefCell<PathBuf>>>,
+    /// Listings of filename completion, if enabled, see
+    /// [`ReplBuilder::file_system`](crate::repl::ReplBuilder::file_system).
+    #[cfg(feature = "terminal")]
+    pub(crate) file_system: Option<Rc<dyn FileSystem>>,
+    #[cfg(feature = "terminal")]
+    pub(crate) theme: Theme,
+    /// Whether to apply the theme, see [`Repl::set_option`](crate::Repl::set_option).
+    #[cfg(feature = "terminal")]
+    pub(crate) color: bool,
+    /// Printer to keep informed of the line being edited.
+    #[cfg(feature = "terminal")]
+    pub(crate) printer: Option<Printer>,
+    #[cfg(feature = "terminal")]
+    pub(crate) validator: Option<Rc<InputValidator>>,
 }
 
+#[cfg(feature = "terminal")]
+impl rustyline::highlight::Highlighter for Completion {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        if !self.color || self.theme.hint.is_plain() {
+            Cow::Borrowed(hint)
+        } else {
+            Cow::Owned(self.theme.hint.paint(hint))
+        }
+    }
+
+    fn highlight_candidate<'c>(
+        &self,
+        candidate: &'c str,
+        _completion: rustyline::CompletionType,
+    ) -> Cow<'c, str> {
+        if !self.color || self.theme.candidate.is_plain() {
+            Cow::Borrowed(candidate)
+        } else {
+            Cow::Owned(self.theme.candidate.paint(candidate))
+        }
+    }
+
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if self.color {
+            crate::highlight::highlight(line, pos, &self.theme)
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+
+    fn highlight_char(&self, line: &str, pos: usize) -> bool {
+        // called on each edit, with hints when the cursor moves
+        if let Some(printer) = &self.printer {
+            printer.edit(line, pos);
+        }
+        self.color && crate::highlight::is_active(&self.theme)
+    }
+}
+
+#[cfg(feature = "terminal")]
 impl Hinter for Completion {
-    type Hint = String;
+    type Hint = ReplHint;
 
     fn hint(&self, line: &str, pos: usize, _ctx: &rustyline::Context<'_>) -> Option<Self::Hint> {
-        if !self.with_hints {
-            return None;
+        if let Some(printer) = &self.printer {
+            printer.edit(line, pos);
         }
-        let start = whitespace_before(line);
-        let prefix = &line[start..pos];
-        if pos < line.len() || prefix.is_empty() {
-            None
-        } else {
-            let candidates = completion_candidates(&self.trie, prefix);
-            if candidates.len() == 1 {
-                Some(candidates[0][(pos - start)..].into())
-            } else {
-                None
-            }
+        Completion::hint(self, line, pos)
+    }
+}
+
+#[cfg(feature = "terminal")]
+impl Validator for Completion {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(self.validate_input(ctx.input()))
+    }
+}
+
+#[cfg(feature = "terminal")]
+impl Completion {
+    /// Check `input` with the validator, if there is one.
+    fn validate_input(&self, input: &str) -> ValidationResult {
+        match self.validator.as_ref().map(|validator| validator(input)) {
+            None | Some(InputStatus::Complete) => ValidationResult::Valid(None),
+            Some(InputStatus::Incomplete) => ValidationResult::Incomplete,
+            Some(InputStatus::Invalid(message)) => ValidationResult::Invalid(Some(message)),
         }
     }
 }
 
+#[cfg(feature = "terminal")]
+impl rustyline::completion::Candidate for Candidate {
+    fn display(&self) -> &str {
+        &self.display
+    }
+
+    fn replacement(&self) -> &str {
+        &self.replacement
+    }
+}
+
+#[cfg(feature = "terminal")]
 impl Completer for Completion {
-    type Candidate = Pair;
+    type Candidate = Candidate;
 
     fn complete(
         &self,
         line: &str,
         pos: usize,
-        ctx: &rustyline::Context<'_>,
+        _ctx: &rustyline::Context<'_>,
     ) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
+        Ok(Completion::complete(self, line, pos))
+    }
+}
+
+/// Hint shown after the cursor.
+pub(crate) struct ReplHint {
+    /// Text shown.
+    pub(crate) display: String,
+    /// Part of [`ReplHint::display`] inserted when the hint is accepted.
+    #[cfg_attr(not(feature = "terminal"), allow(dead_code))]
+    completion: Option<String>,
+}
+
+#[cfg(feature = "terminal")]
+impl rustyline::hint::Hint for ReplHint {
+    fn display(&self) -> &str {
+        &self.display
+    }
+
+    fn completion(&self) -> Option<&str> {
+        self.completion.as_deref()
+    }
+}
+
+/// Closest position to `pos` at or before it that is a character boundary of `line`.
+///
+/// Frontends other than the terminal may send any byte offset as the cursor position.
+pub(crate) fn clamp_pos(line: &str, pos: usize) -> usize {
+    (0..=pos.min(line.len()))
+        .rev()
+        .find(|i| line.is_char_boundary(*i))
+        .unwrap_or(0)
+}
+
+impl Completion {
+    /// Hint with the rest of the command name, if there is only one matching name, followed
+    /// by placeholders of the arguments that have not been typed yet.
+    ///
+    /// Only the rest of the name is inserted when the hint is accepted.
+    pub(crate) fn hint(&self, line: &str, pos: usize) -> Option<ReplHint> {
+        if !self.with_hints || pos < line.len() {
+            return None;
+        }
+        let args = split_args(line.trim_start()).ok()?;
+        let new_arg = line.ends_with(char::is_whitespace);
+        let (name, rest) = match args.first() {
+            Some(name) if args.len() > 1 || new_arg => (name.to_string(), String::new()),
+            Some(prefix) => {
+                // avoid converting all the candidates to strings on every keystroke
+                let names = self.registry.names();
+                let mut candidates = names
+                    .predictive_search(prefix)
+                    .filter(|name| self.registry.is_offered(name));
+                match (candidates.next(), candidates.next()) {
+                    (Some(only), None) => (only.to_string(), only[prefix.len()..].to_string()),
+                    _ if self.registry.contains(prefix) => (prefix.to_string(), String::new()),
+                    _ => return None,
+                }
+            }
+            None => return None,
+        };
+        let name = self.registry.expand_abbreviation(&name).unwrap_or(name);
+        let cmds = self.registry.get(&name);
+        if !cmds.is_empty() && cmds.iter().all(|cmd| !cmd.hints) {
+            return None;
+        }
+        let placeholders = Self::placeholders(&cmds, &args[1..], new_arg);
+        let rest = match self.previous_value(&name, &cmds, &args[1..], new_arg) {
+            Some(value) => value,
+            None => rest,
+        };
+        let display = format!("{rest}{}", placeholders.unwrap_or_default());
+        let completion = (!rest.is_empty()).then_some(rest);
+        (!display.is_empty()).then_some(ReplHint {
+            display,
+            completion,
+        })
+    }
+
+    /// Rest of the most recent value used for the argument being typed, if any starts with
+    /// what has been typed so far.
+    fn previous_value(
+        &self,
+        name: &str,
+        cmds: &[Arc<Command>],
+        typed: &[Token],
+        new_arg: bool,
+    ) -> Option<String> {
+        let history = self.value_history.as_ref()?;
+        let (last, before) = typed.split_last().filter(|_| !new_arg)?;
+        let (index, prefix) = arg_position(cmds, before, last)?;
+        let value = history.matching(name, index, prefix).into_iter().next()?;
+        (value.len() > prefix.len()).then(|| value[prefix.len()..].to_string())
+    }
+
+    /// Placeholders like ` <X:i32> <Y:i32>` of arguments of `cmds` following `typed` ones,
+    /// from the first overload accepting them.
+    fn placeholders(cmds: &[Arc<Command>], typed: &[Token], new_arg: bool) -> Option<String> {
+        let named: Vec<&str> = typed
+            .iter()
+            .filter_map(|arg| named_key(cmds, arg))
+            .map(|(key, _)| key)
+            .collect();
+        let positional: Vec<&str> = typed
+            .iter()
+            .filter(|arg| named_key(cmds, arg).is_none())
+            .map(Token::as_str)
+            .collect();
+        // the argument under cursor may be incomplete, so it is not validated
+        let complete = match typed.last() {
+            Some(last) if !new_arg && named_key(cmds, last).is_none() => positional.len() - 1,
+            _ => positional.len(),
+        };
+        let cmd = cmds.iter().find_map(|cmd| {
+            let remaining: Vec<CommandArgInfo> = cmd
+                .args_info
+                .iter()
+                .filter(|info| !named.contains(&info.name.as_deref().unwrap_or_default()))
+                .cloned()
+                .collect();
+            let fits = remaining.len() >= positional.len()
+                && validate(&positional[..complete], &remaining[..complete]).is_ok();
+            fits.then_some(remaining)
+        })?;
+        let placeholders: Vec<String> = cmd[positional.len()..]
+            .iter()
+            .map(|info| match &info.name {
+                Some(name) => format!("<{name}:{}>", info.arg_type),
+                None => format!("<{}>", info.arg_type),
+            })
+            .collect();
+        if placeholders.is_empty() {
+            return None;
+        }
+        let separator = if new_arg { "" } else { " " };
+        Some(format!("{separator}{}", placeholders.join(" ")))
+    }
+
+    /// Complete command names, then arguments, then (if enabled) filenames.
+    pub(crate) fn complete(&self, line: &str, pos: usize) -> (usize, Vec<Candidate>) {
         if !self.with_completion {
-            return Ok((0, Vec::with_capacity(0)));
+            return (0, Vec::with_capacity(0));
+        }
+        if let Some(completion) = self.complete_command(line) {
+            return completion;
+        }
+        if self.completion_disabled(&line[..pos]) {
+            return (0, Vec::with_capacity(0));
         }
-        if let Some(completion) = self.complete_command(line, pos, ctx)? {
-            Ok(completion)
-        } else if let Some(completer) = self.filename_completer.as_ref() {
-            completer.complete(line, pos, ctx)
+        if let Some(completion) = self.complete_args(line, pos) {
+            return completion;
+        }
+        #[cfg(feature = "terminal")]
+        if let Some(fs) = self.file_system.as_deref() {
+            return self.complete_filename(fs, line, pos);
+        }
+        (0, Vec::with_capacity(0))
+    }
+
+    fn complete_command(&self, line: &str) -> Option<(usize, Vec<Candidate>)> {
+        // fails if there is an unmatched quote, so assume there are no arguments at all
+        let args = split_args(line).unwrap_or_else(|_e| Vec::with_capacity(0));
+        if args.len() != 1 {
+            return None;
+        }
+        let found = self
+            .registry
+            .offered_candidates(&args[0], self.completion_limit.map(|limit| limit.max(1)));
+        // complete one namespace segment at a time
+        let mut names: Vec<String> = found
+            .names
+            .iter()
+            .map(|name| next_segment(name, args[0].len()).to_string())
+            .collect();
+        names.dedup();
+        let mut candidates: Vec<_> = names
+            .into_iter()
+            .map(|c| Candidate {
+                display: c.clone(),
+                replacement: c,
+            })
+            .collect();
+        if found.remaining > 0 {
+            // completing with the common prefix still works as if all candidates were listed
+            candidates.push(Candidate {
+                display: format!("…and {} more", found.remaining),
+                replacement: found.common_prefix,
+            });
+        }
+        Some((whitespace_before(line), candidates))
+    }
+}
+
+impl Completion {
+    /// Name of the command `typed` as the first argument, if it can be unambiguously resolved,
+    /// possibly from a prefix or an abbreviation.
+    fn command_name(&self, typed: &str) -> Option<String> {
+        let found = self.registry.candidates(typed, Some(1));
+        match (
+            found.names.first(),
+            self.registry.expand_abbreviation(typed),
+        ) {
+            (Some(name), _) if name == typed => Some(name.clone()),
+            (_, Some(name)) => Some(name),
+            (Some(name), None) if found.remaining == 0 => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether the arguments of the command in `before` are not completed, see
+    /// [`Command::completion`].
+    fn completion_disabled(&self, before: &str) -> bool {
+        let Some(name) = split_args(before)
+            .ok()
+            .and_then(|args| self.command_name(args.first()?))
+        else {
+            return false;
+        };
+        let cmds = self.registry.get(&name);
+        !cmds.is_empty() && cmds.iter().all(|cmd| !cmd.completion)
+    }
+
+    /// Complete argument values using [`ArgCompleter`](crate::command::ArgCompleter)s
+    /// and argument names for the `key=value` syntax.
+    ///
+    /// Returns `None` when the command has no completer for the argument under cursor
+    /// or it found nothing, so that other completion methods can be tried.
+    pub(crate) fn complete_args(&self, line: &str, pos: usize) -> Option<(usize, Vec<Candidate>)> {
+        let before = &line[..pos];
+        let mut args = split_args(before).ok()?;
+        let new_arg = before.ends_with(char::is_whitespace);
+        if args.is_empty() || (args.len() == 1 && !new_arg) {
+            return None;
+        }
+        let prefix = if new_arg {
+            String::new()
+        } else {
+            args.pop().unwrap().to_string()
+        };
+
+        // only complete arguments of a command that can be unambiguously resolved
+        let name = &self.command_name(&args[0])?;
+        let cmds = self.registry.get(name);
+        let mut candidates = Vec::new();
+        if let Some((key, value)) = named_key(&cmds, &prefix) {
+            // cache key cannot collide with positional ones, which never look like `key=value`
+            let completers: Vec<_> = cmds
+                .iter()
+                .filter_map(|cmd| cmd.args_info[arg_index(cmd, key)?].completer.clone())
+                .collect();
+            let is_path = completers.iter().any(|completer| completer.is_path());
+            let values = self
+                .cache
+                .get_or_insert_with(name, 0, &prefix, || self.matching_values(completers, value));
+            let values = self.with_previous_values(values, name, &cmds, &args[1..], &prefix);
+            candidates.extend(values.into_iter().map(|value| Candidate {
+                replacement: format!("{key}={}", quote(&value, is_path)),
+                display: value,
+            }));
         } else {
-            Ok((0, Vec::with_capacity(0)))
+            let given: Vec<&str> = args[1..]
+                .iter()
+                .filter_map(|arg| named_key(&cmds, arg))
+                .map(|(key, _)| key)
+                .collect();
+            let index = args.len() - 1 - given.len();
+            let completers: Vec<_> = cmds
+                .iter()
+                .filter_map(|cmd| cmd.args_info.get(index)?.completer.clone())
+                .collect();
+            let is_path = completers.iter().any(|completer| completer.is_path());
+            let values = self.cache.get_or_insert_with(name, index, &prefix, || {
+                self.matching_values(completers, &prefix)
+            });
+            let values = self.with_previous_values(values, name, &cmds, &args[1..], &prefix);
+            if !prefix.is_empty() {
+                let mut keys: Vec<&str> = cmds
+                    .iter()
+                    .filter(|cmd| !cmd.raw_args)
+                    .flat_map(|cmd| cmd.args_info.iter().filter_map(|info| info.name.as_deref()))
+                    .filter(|key| starts_with(key, &prefix) && !given.contains(key))
+                    .collect();
+                keys.sort();
+                keys.dedup();
+                candidates.extend(keys.into_iter().map(|key| Candidate {
+                    display: format!("{key}="),
+                    replacement: format!("{key}="),
+                }));
+            }
+            candidates.extend(values.into_iter().map(|value| Candidate {
+                replacement: quote(&value, is_path).into_owned(),
+                display: value,
+            }));
+        }
+        if candidates.is_empty() {
+            return None;
         }
+
+        let start = before.trim_end_matches(|c: char| !c.is_whitespace()).len();
+        Some((start, candidates))
     }
 }
 
 impl Completion {
-    fn complete_command(
+    /// Complete the path under the cursor with the entries of `fs`, after expanding `~` and
+    /// variables in its directory and making it relative to the working directory, see
+    /// [`paths::complete`].
+    #[cfg(feature = "terminal")]
+    fn complete_filename(
         &self,
+        fs: &dyn FileSystem,
         line: &str,
-        _pos: usize,
-        _ctx: &rustyline::Context<'_>,
-    ) -> rustyline::Result<Option<(usize, Vec<<Self as Completer>::Candidate>)>> {
-        // fails if there is an unmatched quote, so assume there are no arguments at all
-        let args = split_args(line).unwrap_or_else(|_e| Vec::with_capacity(0));
-        let on_first = args.len() == 1;
-        let completions = if on_first {
-            let candidates = completion_candidates(&self.trie, &args[0])
-                .into_iter()
-                .map(|c| Pair {
-                    display: c.clone(),
-                    replacement: c,
-                })
-                .collect();
-            Some((whitespace_before(line), candidates))
-        } else {
-            None
+        pos: usize,
+    ) -> (usize, Vec<Candidate>) {
+        let before = &line[..pos];
+        // the path being typed may still miss its closing quote
+        let Some(args) = ["", "'", "\""].iter().find_map(|quote| {
+            let closed = format!("{before}{quote}");
+            let args = split_args(&closed).ok()?;
+            Some(args.into_iter().map(Token::into_owned).collect::<Vec<_>>())
+        }) else {
+            return (0, Vec::with_capacity(0));
+        };
+        let (start, prefix) = match args.last() {
+            Some(arg) if arg.span.end >= before.len() => (arg.span.start, arg.as_str()),
+            _ => (pos, ""),
         };
-        Ok(completions)
+        let paths = paths::complete(prefix, &*self.home, self.cwd().as_deref(), |prefix| {
+            fs::complete(fs, prefix, |_| true)
+        });
+        let candidates = paths
+            .into_iter()
+            .map(|path| Candidate {
+                replacement: paths::quote(&path).into_owned(),
+                display: path,
+            })
+            .collect();
+        (start, candidates)
     }
-}
 
-pub(crate) fn completion_candidates(trie: &Trie<u8>, prefix: &str) -> Vec<String> {
-    if prefix.is_empty() {
-        Vec::with_capacity(0)
-    } else {
-        trie.predictive_search(prefix)
+    /// Working directory of relative paths, if the REPL manages one.
+    fn cwd(&self) -> Option<PathBuf> {
+        self.working_dir.as_ref().map(|dir| dir.borrow().clone())
+    }
+
+    /// Sorted, unique values from all `completers` that start with `prefix`.
+    ///
+    /// Path completers complete the prefix with `~` and variables expanded, see
+    /// [`paths::complete`].
+    fn matching_values(&self, completers: Vec<Arc<dyn ArgCompleter>>, prefix: &str) -> Vec<String> {
+        let mut values: Vec<String> = completers
+            .into_iter()
+            .flat_map(|completer| match completer.is_path() {
+                true => paths::complete(prefix, &*self.home, self.cwd().as_deref(), |prefix| {
+                    completer.complete(prefix)
+                }),
+                false => completer.complete(prefix),
+            })
+            .filter(|value| starts_with(value, prefix))
+            .collect();
+        values.sort();
+        values.dedup();
+        values
+    }
+
+    /// Add values previously used for the argument being completed to `values`, most
+    /// recent first.
+    fn with_previous_values(
+        &self,
+        values: Vec<String>,
+        name: &str,
+        cmds: &[Arc<Command>],
+        before: &[Token],
+        arg: &str,
+    ) -> Vec<String> {
+        let previous = match (&self.value_history, arg_position(cmds, before, arg)) {
+            (Some(history), Some((index, prefix))) => history.matching(name, index, prefix),
+            _ => return values,
+        };
+        let mut all: Vec<String> = previous
             .into_iter()
-            .map(|bytes| String::from_utf8(bytes).unwrap())
+            .filter(|value| !values.contains(value))
+            .collect();
+        all.extend(values);
+        all
+    }
+}
+
+/// Part of `name` up to and including the first namespace separator after `typed` bytes,
+/// or the whole name if there is none.
+fn next_segment(name: &str, typed: usize) -> &str {
+    match name[typed..].find(NAMESPACE_SEPARATORS) {
+        Some(i) => &name[..typed + i + 1],
+        None => name,
+    }
+}
+
+/// Maximum number of values kept per argument in [`ValueHistory`].
+const VALUE_HISTORY_SIZE: usize = 20;
+
+/// Values previously given to `String` and `Custom` arguments, per command and argument.
+#[derive(Default)]
+pub(crate) struct ValueHistory {
+    /// Values by command name and argument index, most recent last.
+    values: RefCell<HashMap<(String, usize), Vec<String>>>,
+}
+
+impl ValueHistory {
+    /// Remember the values of `args` passed to command `name`.
+    pub(crate) fn record(&self, name: &str, cmds: &[Arc<Command>], args: &[&str]) {
+        let mut values = self.values.borrow_mut();
+        for (i, arg) in args.iter().enumerate() {
+            let Some((index, value)) = arg_position(cmds, &args[..i], arg) else {
+                continue;
+            };
+            let textual = cmds.iter().any(|cmd| {
+                cmd.args_info.get(index).is_some_and(|info| {
+                    matches!(
+                        info.arg_type,
+                        CommandArgType::String | CommandArgType::Custom
+                    )
+                })
+            });
+            if !textual || value.is_empty() {
+                continue;
+            }
+            let entry = values.entry((name.to_string(), index)).or_default();
+            entry.retain(|previous| previous != value);
+            if entry.len() == VALUE_HISTORY_SIZE {
+                entry.remove(0);
+            }
+            entry.push(value.to_string());
+        }
+    }
+
+    /// Values of argument `index` of command `name` starting with `prefix`, most recent first.
+    pub(crate) fn matching(&self, name: &str, index: usize, prefix: &str) -> Vec<String> {
+        let values = self.values.borrow();
+        let Some(entry) = values.get(&(name.to_string(), index)) else {
+            return Vec::new();
+        };
+        entry
+            .iter()
+            .rev()
+            .filter(|value| starts_with(value, prefix))
+            .cloned()
             .collect()
     }
 }
 
+/// Index of argument `arg` following `before`, together with its value without the key of
+/// the `key=value` syntax.
+///
+/// Positional arguments are counted as in [`Completion::complete_args`], skipping named ones.
+fn arg_position<'a>(
+    cmds: &[Arc<Command>],
+    before: &[impl AsRef<str>],
+    arg: &'a str,
+) -> Option<(usize, &'a str)> {
+    if let Some((key, value)) = named_key(cmds, arg) {
+        let index = cmds.iter().find_map(|cmd| arg_index(cmd, key))?;
+        return Some((index, value));
+    }
+    let positional = before
+        .iter()
+        .filter(|arg| named_key(cmds, arg.as_ref()).is_none())
+        .count();
+    Some((positional, arg))
+}
+
+fn arg_index(cmd: &Command, key: &str) -> Option<usize> {
+    cmd.args_info
+        .iter()
+        .position(|info| info.name.as_deref() == Some(key))
+}
+
+/// Split `arg` into key and value if it is a `key=value` argument of any of `cmds`.
+fn named_key<'a>(cmds: &[Arc<Command>], arg: &'a str) -> Option<(&'a str, &'a str)> {
+    let (key, value) = arg.split_once('=')?;
+    cmds.iter()
+        .any(|cmd| arg_index(cmd, key).is_some())
+        .then_some((key, value))
+}
+
+/// Quote a completed `value` for the command line, keeping `~` and variables of paths
+/// expandable.
+fn quote(value: &str, is_path: bool) -> Cow<'_, str> {
+    match is_path {
+        true => paths::quote(value),
+        false => shell_words::quote(value),
+    }
+}
+
+type CacheKey = (String, usize, String);
+
+/// Cache of argument completion results keyed by (command, argument index, prefix).
+///
+/// Entries expire after the configured time-to-live. Without TTL nothing is cached.
+pub(crate) struct CompletionCache {
+    ttl: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    entries: RefCell<HashMap<CacheKey, (Instant, Vec<String>)>>,
+}
+
+impl CompletionCache {
+    pub(crate) fn new(ttl: Option<Duration>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            ttl,
+            clock,
+            entries: Default::default(),
+        }
+    }
+
+    fn get_or_insert_with(
+        &self,
+        command: &str,
+        index: usize,
+        prefix: &str,
+        f: impl FnOnce() -> Vec<String>,
+    ) -> Vec<String> {
+        let ttl = match self.ttl {
+            Some(ttl) => ttl,
+            None => return f(),
+        };
+        let now = self.clock.now();
+        let key = (command.to_string(), index, prefix.to_string());
+        if let Some((created, values)) = self.entries.borrow().get(&key) {
+            if now.duration_since(*created) < ttl {
+                return values.clone();
+            }
+        }
+        let values = f();
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(|_, (created, _)| now.duration_since(*created) < ttl);
+        entries.insert(key, (now, values.clone()));
+        values
+    }
+
+    /// Drop cached results for given `command`, or all of them.
+    pub(crate) fn invalidate(&self, command: Option<&str>) {
+        match command {
+            Some(command) => self
+                .entries
+                .borrow_mut()
+                .retain(|(name, _, _), _| name != command),
+            None => self.entries.borrow_mut().clear(),
+        }
+    }
+}
+
+/// Length in bytes of the whitespace at the start of `line`.
 fn whitespace_before(line: &str) -> usize {
-    line.chars().take_while(|c| char::is_whitespace(*c)).count()
+    line.len() - line.trim_start().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+    use crate::command::{CommandArgInfo, CommandArgType, TrivialCommandHandler};
+    use crate::runtime::default_runtime;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn completion(ttl: Option<Duration>, queries: Arc<AtomicUsize>) -> Completion {
+        let hosts = CommandArgInfo::new_with_name(CommandArgType::String, "host").with_completer(
+            move |prefix: &str| {
+                queries.fetch_add(1, Ordering::SeqCst);
+                ["alpha", "beta", "bravo", "my host"]
+                    .iter()
+                    .filter(|h| h.starts_with(prefix))
+                    .map(|h| h.to_string())
+                    .collect()
+            },
+        );
+        let cmd = Command::new(
+            "Connect",
+            vec![hosts],
+            Box::new(TrivialCommandHandler::new()),
+        );
+        let add = Command::new(
+            "Add",
+            vec![
+                CommandArgInfo::new_with_name(CommandArgType::I32, "X"),
+                CommandArgInfo::new_with_name(CommandArgType::I32, "Y"),
+            ],
+            Box::new(TrivialCommandHandler::new()),
+        );
+        let cmds = vec![("connect".into(), cmd), ("add".into(), add)];
+        let registry = CommandRegistry::new(cmds, default_runtime());
+        Completion {
+            registry: Arc::new(registry.unwrap()),
+            with_hints: true,
+            with_completion: true,
+            completion_limit: None,
+            cache: Rc::new(CompletionCache::new(ttl, Arc::new(SystemClock::default()))),
+            value_history: None,
+            home: Rc::new(|| Some("/home/me".into())),
+            working_dir: None,
+            #[cfg(feature = "terminal")]
+            file_system: None,
+            #[cfg(feature = "terminal")]
+            theme: Default::default(),
+            #[cfg(feature = "terminal")]
+            color: true,
+            #[cfg(feature = "terminal")]
+            printer: None,
+            #[cfg(feature = "terminal")]
+            validator: None,
+        }
+    }
+
+    #[cfg(feature = "terminal")]
+    #[test]
+    fn input_validator() {
+        let mut completion = completion(None, Default::default());
+        assert!(matches!(
+            completion.validate_input("eval (1"),
+            ValidationResult::Valid(None)
+        ));
+        completion.validator = Some(Rc::new(|input: &str| {
+            let depth = input.matches('(').count() as isize - input.matches(')').count() as isize;
+            match depth {
+                0 => InputStatus::Complete,
+                1.. => InputStatus::Incomplete,
+                _ => InputStatus::Invalid("unbalanced ')'".into()),
+            }
+        }));
+        assert!(matches!(
+            completion.validate_input("eval (1 +"),
+            ValidationResult::Incomplete
+        ));
+        assert!(matches!(
+            completion.validate_input("eval (1 +\n 2)"),
+            ValidationResult::Valid(None)
+        ));
+        assert!(matches!(
+            completion.validate_input("eval 1)"),
+            ValidationResult::Invalid(Some(message)) if message == "unbalanced ')'"
+        ));
+        let args = split_args("eval (1 +\n 2)").unwrap();
+        assert_eq!(args.len(), 4);
+    }
+
+    fn replacements(completion: &Completion, line: &str) -> Option<(usize, Vec<String>)> {
+        completion
+            .complete_args(line, line.len())
+            .map(|(start, pairs)| (start, pairs.into_iter().map(|p| p.replacement).collect()))
+    }
+
+    #[test]
+    fn argument_completion() {
+        let completion = completion(None, Default::default());
+        assert_eq!(
+            replacements(&completion, "connect b"),
+            Some((8, vec!["beta".into(), "bravo".into()]))
+        );
+        assert_eq!(
+            replacements(&completion, "conn "),
+            Some((
+                5,
+                vec![
+                    "alpha".into(),
+                    "beta".into(),
+                    "bravo".into(),
+                    "'my host'".into()
+                ]
+            ))
+        );
+        assert_eq!(replacements(&completion, "connect x"), None);
+        assert_eq!(replacements(&completion, "connect alpha "), None);
+        assert_eq!(replacements(&completion, "connect"), None);
+    }
+
+    #[test]
+    fn argument_history() {
+        let mut completion = completion(None, Default::default());
+        let history = ValueHistory::default();
+        let connect = completion.registry.get("connect");
+        history.record("connect", &connect, &["gamma"]);
+        history.record("connect", &connect, &["host=beta"]);
+        history.record("add", &completion.registry.get("add"), &["1", "2"]);
+        completion.value_history = Some(history);
+
+        assert_eq!(
+            replacements(&completion, "connect "),
+            Some((
+                8,
+                vec![
+                    "gamma".into(),
+                    "alpha".into(),
+                    "beta".into(),
+                    "bravo".into(),
+                    "'my host'".into()
+                ]
+            ))
+        );
+        assert_eq!(
+            replacements(&completion, "connect host=b"),
+            Some((8, vec!["host=beta".into(), "host=bravo".into()]))
+        );
+        let hint = completion.hint("connect ga", 10).unwrap();
+        assert_eq!(hint.completion.as_deref(), Some("mma"));
+        assert_eq!(completion.hint("add 1", 5).unwrap().completion, None);
+    }
+
+    #[test]
+    fn namespace_completion() {
+        let completion = completion(None, Default::default());
+        for name in ["db.migrate", "db.seed", "db.cache.clear", "dbg"] {
+            let cmd = Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+            completion.registry.add(name, cmd).unwrap();
+        }
+        let names = |line: &str| {
+            completion
+                .complete_command(line)
+                .map(|(_, candidates)| candidates.into_iter().map(|c| c.replacement).collect())
+        };
+        assert_eq!(names("d"), Some(vec!["db.".to_string(), "dbg".into()]));
+        assert_eq!(
+            names("db."),
+            Some(vec![
+                "db.cache.".to_string(),
+                "db.migrate".into(),
+                "db.seed".into()
+            ])
+        );
+        assert_eq!(names("db.c"), Some(vec!["db.cache.".to_string()]));
+        assert_eq!(names("db.cache."), Some(vec!["db.cache.clear".to_string()]));
+    }
+
+    #[test]
+    fn unicode_hints() {
+        let completion = completion(None, Default::default());
+        // ideographic space is 3 bytes long
+        let line = "\u{3000}con";
+        let hint = completion.hint(line, line.len()).unwrap();
+        assert_eq!(hint.completion.as_deref(), Some("nect"));
+    }
+
+    #[test]
+    fn argument_hints() {
+        let completion = completion(None, Default::default());
+        let hint = |line: &str| completion.hint(line, line.len());
+        let display = |line: &str| hint(line).map(|h| h.display);
+        assert_eq!(display("add").as_deref(), Some(" <X:i32> <Y:i32>"));
+        assert_eq!(display("add ").as_deref(), Some("<X:i32> <Y:i32>"));
+        assert_eq!(display("add 1").as_deref(), Some(" <Y:i32>"));
+        assert_eq!(display("add 1 ").as_deref(), Some("<Y:i32>"));
+        assert_eq!(display("add Y=2 ").as_deref(), Some("<X:i32>"));
+        assert_eq!(display("add 1 2"), None);
+        assert_eq!(display("add x "), None);
+        assert_eq!(display("add 1 2 3"), None);
+        let partial = hint("ad").unwrap();
+        assert_eq!(partial.display, "d <X:i32> <Y:i32>");
+        assert_eq!(partial.completion.as_deref(), Some("d"));
+        assert_eq!(hint("add").unwrap().completion, None);
+        assert_eq!(display("co").as_deref(), Some("nnect <host:String>"));
+        assert_eq!(display("add 1 2 ").as_deref(), None);
+    }
+
+    #[test]
+    fn disabled_hints() {
+        let completion = completion(None, Default::default());
+        let cmd = Command::new("", vec![], Box::new(TrivialCommandHandler::new())).hints(false);
+        completion.registry.add("secret", cmd).unwrap();
+        assert!(completion.hint("se", 2).is_none());
+        assert!(completion.hint("secret", 6).is_none());
+        assert!(completion.hint("ad", 2).is_some());
+    }
+
+    #[test]
+    fn disabled_completion() {
+        let completion = completion(None, Default::default());
+        let text = CommandArgInfo::new(CommandArgType::String)
+            .with_completer(|_: &str| vec!["unexpected".to_string()]);
+        let cmd = Command::new("", vec![text], Box::new(TrivialCommandHandler::new()));
+        completion
+            .registry
+            .add("note", cmd.completion(false))
+            .unwrap();
+        let (_, names) = completion.complete("no", 2);
+        assert_eq!(names[0].replacement, "note");
+        assert_eq!(completion.complete("note u", 6), (0, vec![]));
+        assert_eq!(completion.complete("note some u", 11), (0, vec![]));
+        assert!(replacements(&completion, "connect b").is_some());
+    }
+
+    #[test]
+    fn named_argument_completion() {
+        let completion = completion(None, Default::default());
+        assert_eq!(
+            replacements(&completion, "connect h"),
+            Some((8, vec!["host=".into()]))
+        );
+        assert_eq!(
+            replacements(&completion, "connect host=b"),
+            Some((8, vec!["host=beta".into(), "host=bravo".into()]))
+        );
+        assert_eq!(
+            replacements(&completion, "connect host=m"),
+            Some((8, vec!["host='my host'".into()]))
+        );
+        assert_eq!(replacements(&completion, "connect host=alpha h"), None);
+        assert_eq!(replacements(&completion, "connect other=b"), None);
+    }
+
+    #[cfg(feature = "terminal")]
+    #[test]
+    fn filename_completion() {
+        use crate::fs::VirtualFileSystem;
+
+        let mut completion = completion(None, Default::default());
+        let fs = VirtualFileSystem::new()
+            .file("docs/a.txt")
+            .file("docs/my notes.txt")
+            .file("/home/me/.profile")
+            .file("/home/me/todo.txt")
+            .file("/srv/files/docs/b.txt");
+        completion.file_system = Some(Rc::new(fs));
+        let complete = |completion: &Completion, line: &str| {
+            let (start, candidates) = completion.complete(line, line.len());
+            let replacements: Vec<String> = candidates.into_iter().map(|c| c.replacement).collect();
+            (start, replacements)
+        };
+        assert_eq!(complete(&completion, "add 1 "), (6, vec!["docs/".into()]));
+        assert_eq!(
+            complete(&completion, "add 1 docs/"),
+            (6, vec!["docs/a.txt".into(), "'docs/my notes.txt'".into()])
+        );
+        assert_eq!(
+            complete(&completion, "add 1 'docs/my no"),
+            (6, vec!["'docs/my notes.txt'".into()])
+        );
+        assert_eq!(
+            complete(&completion, "add ~/"),
+            (4, vec!["~/todo.txt".into()])
+        );
+        completion.working_dir = Some(Rc::new(RefCell::new("/srv/files".into())));
+        assert_eq!(
+            complete(&completion, "add docs/"),
+            (4, vec!["docs/b.txt".into()])
+        );
+    }
+
+    #[test]
+    fn cached_argument_completion() {
+        let queries = Arc::new(AtomicUsize::new(0));
+        let completion = completion(Some(Duration::from_secs(3600)), queries.clone());
+        assert!(replacements(&completion, "connect b").is_some());
+        assert!(replacements(&completion, "connect b").is_some());
+        assert_eq!(queries.load(Ordering::SeqCst), 1);
+        assert!(replacements(&completion, "connect a").is_some());
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        completion.cache.invalidate(Some("other"));
+        assert!(replacements(&completion, "connect b").is_some());
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+        completion.cache.invalidate(Some("connect"));
+        assert!(replacements(&completion, "connect b").is_some());
+        assert_eq!(queries.load(Ordering::SeqCst), 3);
+        completion.cache.invalidate(None);
+        assert!(replacements(&completion, "connect b").is_some());
+        assert_eq!(queries.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn expired_argument_completion() {
+        let queries = Arc::new(AtomicUsize::new(0));
+        let completion = completion(Some(Duration::ZERO), queries.clone());
+        assert!(replacements(&completion, "connect b").is_some());
+        assert!(replacements(&completion, "connect b").is_some());
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+    }
 }
@@ -0,0 +1,286 @@
+//! Settings loaded from a TOML file, see [`ReplBuilder::from_config`].
+//!
+//! All the settings are optional:
+//!
+//! ```toml
+//! prompt = "db> "
+//! text-width = 100
+//! predict-commands = false
+//! verbosity = "quiet"
+//! correction = "prompt"
+//! confirm-destructive = "always"
+//! globbing = "error"
+//! variable-substitution = true
+//! pager = "less -R"
+//!
+//! [aliases]
+//! ls = "list --all"
+//!
+//! [snippets]
+//! deploy = "rollout $1 --env $2"
+//!
+//! [history]
+//! file = "/home/me/.my-tool-history"
+//! max-size = 1000
+//! ignore-dups = true
+//! ignore-space = false
+//! search = "prefix"
+//!
+//! [theme]
+//! hint = { color = "gray", italic = true }
+//! candidate = { bold = true }
+//! bracket = { color = "cyan", bold = true }
+//!
+//! [keymap]
+//! edit-mode = "vi"
+//! word-separators = "/."
+//! "ctrl-l" = "clear-screen"
+//! "f5" = "run:status"
+//! ```
+//!
+//! The keymap uses the names described in [`keymap`](crate::keymap). History, pager, theme
+//! and keymap settings are only available with the `terminal` feature.
+//!
+//! Requires the `config` feature.
+//!
+//! [`ReplBuilder::from_config`]: crate::repl::ReplBuilder::from_config
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+#[cfg(feature = "terminal")]
+use crate::keymap::Keymap;
+use crate::output::Verbosity;
+use crate::repl::{Confirmation, Correction, Globbing, ReplBuilder};
+#[cfg(feature = "terminal")]
+use crate::repl::{HistoryLimits, HistorySearch};
+#[cfg(feature = "terminal")]
+use crate::style::Theme;
+
+/// Error when loading a configuration file.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    /// File could not be read.
+    #[error("failed to read configuration '{0}': {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+    /// File is not valid.
+    #[error("invalid configuration: {0}")]
+    Parse(#[from] toml::de::Error),
+}
+
+/// Contents of a configuration file, see the [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// See [`ReplBuilder::prompt`].
+    pub prompt: Option<String>,
+    /// See [`ReplBuilder::text_width`].
+    pub text_width: Option<usize>,
+    /// See [`ReplBuilder::predict_commands`].
+    pub predict_commands: Option<bool>,
+    /// See [`ReplBuilder::verbosity`].
+    pub verbosity: Option<Verbosity>,
+    /// See [`ReplBuilder::correction`].
+    pub correction: Option<Correction>,
+    /// See [`ReplBuilder::confirm_destructive`].
+    pub confirm_destructive: Option<Confirmation>,
+    /// See [`ReplBuilder::globbing`].
+    pub globbing: Option<Globbing>,
+    /// See [`ReplBuilder::variable_substitution`].
+    pub variable_substitution: Option<bool>,
+    /// See [`ReplBuilder::alias`].
+    pub aliases: BTreeMap<String, String>,
+    /// See [`ReplBuilder::snippet`].
+    pub snippets: BTreeMap<String, String>,
+    /// History of the line editor.
+    #[cfg(feature = "terminal")]
+    pub history: HistoryConfig,
+    /// See [`ReplBuilder::pager`].
+    #[cfg(feature = "terminal")]
+    pub pager: Option<String>,
+    /// See [`ReplBuilder::theme`].
+    #[cfg(feature = "terminal")]
+    pub theme: Option<Theme>,
+    /// See [`ReplBuilder::keymap`].
+    #[cfg(feature = "terminal")]
+    pub keymap: Option<Keymap>,
+}
+
+/// History settings of [`Config`].
+#[cfg(feature = "terminal")]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HistoryConfig {
+    /// See [`ReplBuilder::history_file`].
+    pub file: Option<PathBuf>,
+    /// Maximum number of entries kept.
+    pub max_size: Option<usize>,
+    /// Do not add an entry equal to the previous one.
+    pub ignore_dups: Option<bool>,
+    /// Do not add lines starting with a space.
+    pub ignore_space: Option<bool>,
+    /// See [`ReplBuilder::history_search`].
+    pub search: Option<HistorySearch>,
+}
+
+impl Config {
+    /// Read configuration from the TOML file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        text.parse()
+    }
+
+    /// Builder with the settings present in the configuration, and defaults for the others.
+    pub fn builder(self) -> ReplBuilder {
+        let mut builder = ReplBuilder::default();
+        if let Some(prompt) = self.prompt {
+            builder = builder.prompt(prompt);
+        }
+        if let Some(text_width) = self.text_width {
+            builder = builder.text_width(text_width);
+        }
+        if let Some(predict_commands) = self.predict_commands {
+            builder = builder.predict_commands(predict_commands);
+        }
+        if let Some(verbosity) = self.verbosity {
+            builder = builder.verbosity(verbosity);
+        }
+        if let Some(correction) = self.correction {
+            builder = builder.correction(correction);
+        }
+        if let Some(confirm_destructive) = self.confirm_destructive {
+            builder = builder.confirm_destructive(confirm_destructive);
+        }
+        if let Some(globbing) = self.globbing {
+            builder = builder.globbing(globbing);
+        }
+        if let Some(variable_substitution) = self.variable_substitution {
+            builder = builder.variable_substitution(variable_substitution);
+        }
+        for (name, line) in &self.aliases {
+            builder = builder.alias(name, line);
+        }
+        for (name, template) in &self.snippets {
+            builder = builder.snippet(name, template);
+        }
+        #[cfg(feature = "terminal")]
+        {
+            builder = self.history.apply(builder);
+            if let Some(pager) = self.pager {
+                builder = builder.pager(pager);
+            }
+            if let Some(theme) = self.theme {
+                builder = builder.theme(theme);
+            }
+            if let Some(keymap) = self.keymap {
+                builder = builder.keymap(keymap);
+            }
+        }
+        builder
+    }
+}
+
+#[cfg(feature = "terminal")]
+impl HistoryConfig {
+    fn apply(self, builder: ReplBuilder) -> ReplBuilder {
+        let mut builder = builder.history_limits(HistoryLimits {
+            max_size: self.max_size,
+            ignore_dups: self.ignore_dups,
+            ignore_space: self.ignore_space,
+        });
+        if let Some(search) = self.search {
+            builder = builder.history_search(search);
+        }
+        match self.file {
+            Some(file) => builder.history_file(file),
+            None => builder,
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(toml::from_str(s)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let config: Config = r#"
+            prompt = "db> "
+            predict-commands = false
+            verbosity = "verbose"
+            confirm-destructive = "never"
+            globbing = "literal"
+            variable-substitution = true
+
+            [aliases]
+            ls = "list --all"
+
+            [snippets]
+            deploy = "rollout $1 --env $2"
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(config.prompt.as_deref(), Some("db> "));
+        assert_eq!(config.text_width, None);
+        assert_eq!(config.predict_commands, Some(false));
+        assert_eq!(config.verbosity, Some(Verbosity::Verbose));
+        assert_eq!(config.confirm_destructive, Some(Confirmation::Never));
+        assert_eq!(config.globbing, Some(Globbing::Literal));
+        assert_eq!(config.variable_substitution, Some(true));
+        assert_eq!(config.aliases["ls"], "list --all");
+        assert_eq!(config.snippets["deploy"], "rollout $1 --env $2");
+
+        assert!(matches!(
+            "promt = 'x'".parse::<Config>(),
+            Err(ConfigError::Parse(_))
+        ));
+        assert!(matches!(
+            Config::load("/nonexistent/repl.toml"),
+            Err(ConfigError::Io(..))
+        ));
+    }
+
+    #[cfg(feature = "terminal")]
+    #[test]
+    fn terminal_settings() {
+        use crate::keymap::parse_key;
+        use crate::style::{Color, Style};
+
+        let config: Config = r#"
+            [history]
+            max-size = 10
+            search = "prefix"
+
+            [theme]
+            hint = { color = "gray", italic = true }
+
+            [keymap]
+            edit-mode = "vi"
+            f5 = "run:status"
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(config.history.max_size, Some(10));
+        assert_eq!(config.history.file, None);
+        assert_eq!(config.history.search, Some(HistorySearch::Prefix));
+        let theme = config.theme.as_ref().unwrap();
+        assert_eq!(theme.hint, Style::new().italic().color(Color::Gray));
+        assert!(theme.candidate.is_plain());
+        let keymap = config.keymap.as_ref().unwrap();
+        let commands: Vec<_> = keymap.commands().collect();
+        assert_eq!(commands, vec![(parse_key("f5").unwrap(), "status")]);
+    }
+}
@@ -0,0 +1,268 @@
+//! Detection of command sets that build correctly but are confusing to use.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::command::CommandArgType;
+use crate::prefix::{common_prefix, starts_with};
+use crate::repl::RESERVED;
+
+/// Possible problem with the configured commands, see [`ReplBuilder::check`].
+///
+/// [`ReplBuilder::check`]: crate::repl::ReplBuilder::check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// Command name is a prefix of other names, so no abbreviation selects it and
+    /// it always has to be typed in full.
+    NameIsPrefix {
+        /// The shorter name.
+        name: String,
+        /// Names starting with `name`.
+        longer: Vec<String>,
+    },
+    /// Names starting with the same [`SHARED_PREFIX_LEN`] characters or more, so with
+    /// `predict_commands` they can only be abbreviated to something longer than `prefix`.
+    SharedPrefix {
+        /// Longest prefix common to all the `names`.
+        prefix: String,
+        /// Names sharing the prefix, in lexicographic order.
+        names: Vec<String>,
+    },
+    /// Overload that accepts a subset of arguments of another overload which is tried
+    /// first, so it only runs when the other one's handler rejects the arguments.
+    ShadowedOverload {
+        /// Command name.
+        name: String,
+        /// Signature of the shadowed overload.
+        overload: String,
+        /// Signature of the overload shadowing it.
+        shadowed_by: String,
+    },
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::NameIsPrefix { name, longer } => write!(
+                f,
+                "'{}' is a prefix of {}, it cannot be abbreviated",
+                name,
+                quoted(longer)
+            ),
+            Diagnostic::SharedPrefix { prefix, names } => write!(
+                f,
+                "{} share prefix '{}', it is not enough to select a command",
+                quoted(names),
+                prefix
+            ),
+            Diagnostic::ShadowedOverload {
+                name,
+                overload,
+                shadowed_by,
+            } => write!(
+                f,
+                "'{name} {overload}' only runs if '{name} {shadowed_by}' rejects its arguments"
+            ),
+        }
+    }
+}
+
+fn quoted(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("'{name}'"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Result of checking the configuration, see [`ReplBuilder::check`].
+///
+/// [`ReplBuilder::check`]: crate::repl::ReplBuilder::check
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// All problems found, grouped by kind.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    /// Returns true if no problems have been found.
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "{diagnostic}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Length in characters from which a prefix shared by several names is reported as
+/// [`Diagnostic::SharedPrefix`]. Shorter ones are common and cost only a key or two.
+pub const SHARED_PREFIX_LEN: usize = 3;
+
+/// Argument types of all overloads of each command, in registration order.
+pub(crate) type Overloads = BTreeMap<String, Vec<Vec<CommandArgType>>>;
+
+pub(crate) fn diagnose(commands: &Overloads, predict_commands: bool) -> Report {
+    let mut names: Vec<String> = commands.keys().cloned().collect();
+    names.extend(RESERVED.iter().map(|(name, _)| name.to_string()));
+    names.sort();
+    names.dedup();
+
+    // conflicts between built-in commands only are not actionable
+    let user_defined = |group: &[String]| group.iter().any(|name| commands.contains_key(name));
+
+    let mut diagnostics = Vec::new();
+    for (i, name) in names.iter().enumerate() {
+        let longer: Vec<String> = names[i + 1..]
+            .iter()
+            .take_while(|other| other.starts_with(name.as_str()))
+            .filter(|other| starts_with(other, name))
+            .cloned()
+            .collect();
+        if !longer.is_empty() && (commands.contains_key(name) || user_defined(&longer)) {
+            diagnostics.push(Diagnostic::NameIsPrefix {
+                name: name.clone(),
+                longer,
+            });
+        }
+    }
+
+    if predict_commands {
+        // sorted names with the same leading graphemes form contiguous groups
+        for group in names.chunk_by(|a, b| leading(a) == leading(b)) {
+            if let [first, .., last] = group {
+                if !user_defined(group) {
+                    continue;
+                }
+                diagnostics.push(Diagnostic::SharedPrefix {
+                    prefix: common_prefix(first, last).into(),
+                    names: group.to_vec(),
+                });
+            }
+        }
+    }
+
+    for (name, overloads) in commands {
+        for (i, overload) in overloads.iter().enumerate() {
+            let shadowing = overloads.iter().enumerate().find(|(j, other)| {
+                *j != i && covers(other, overload) && tried_before((*j, other), (i, overload))
+            });
+            if let Some((_, other)) = shadowing {
+                diagnostics.push(Diagnostic::ShadowedOverload {
+                    name: name.clone(),
+                    overload: signature(overload),
+                    shadowed_by: signature(other),
+                });
+            }
+        }
+    }
+
+    Report { diagnostics }
+}
+
+/// First [`SHARED_PREFIX_LEN`] graphemes of `name`, or all of it if it is shorter.
+fn leading(name: &str) -> &str {
+    name.grapheme_indices(true)
+        .nth(SHARED_PREFIX_LEN)
+        .map_or(name, |(i, _)| &name[..i])
+}
+
+/// Returns true if overload `a` accepts all the arguments accepted by `b`.
+fn covers(a: &[CommandArgType], b: &[CommandArgType]) -> bool {
+    use CommandArgType::*;
+    a.len() == b.len()
+        && a.iter().zip(b).all(|pair| {
+            matches!(
+                pair,
+                (String | Custom, _) | (F32, F32 | I32) | (I32, I32) | (Bytes, Bytes)
+            )
+        })
+}
+
+/// Returns true if overload `a` comes before `b` in dispatch order, see
+/// [`CommandRegistry::overloads`](crate::registry::CommandRegistry::overloads).
+fn tried_before(a: (usize, &[CommandArgType]), b: (usize, &[CommandArgType])) -> bool {
+    let specificity =
+        |types: &[CommandArgType]| -> u32 { types.iter().map(|t| t.specificity() as u32).sum() };
+    (specificity(a.1), b.0) > (specificity(b.1), a.0)
+}
+
+fn signature(types: &[CommandArgType]) -> String {
+    types
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use CommandArgType::*;
+
+    fn overloads(entries: &[(&str, &[&[CommandArgType]])]) -> Overloads {
+        entries
+            .iter()
+            .map(|(name, types)| (name.to_string(), types.iter().map(|t| t.to_vec()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn prefixes() {
+        let commands = overloads(&[("hello", &[&[]]), ("run", &[&[]]), ("running", &[&[]])]);
+        let report = diagnose(&commands, false);
+        assert_eq!(
+            report.diagnostics,
+            vec![Diagnostic::NameIsPrefix {
+                name: "run".into(),
+                longer: vec!["running".into()]
+            }]
+        );
+
+        let report = diagnose(&commands, true);
+        assert!(report.diagnostics.contains(&Diagnostic::SharedPrefix {
+            prefix: "hel".into(),
+            names: vec!["hello".into(), "help".into()]
+        }));
+        assert!(report.diagnostics.contains(&Diagnostic::SharedPrefix {
+            prefix: "run".into(),
+            names: vec!["run".into(), "running".into()]
+        }));
+        assert_eq!(report.diagnostics.len(), 3);
+
+        // a short shared prefix is not worth reporting
+        let commands = overloads(&[("hint", &[&[]]), ("hop", &[&[]]), ("quote", &[&[]])]);
+        assert!(diagnose(&commands, true).is_empty());
+    }
+
+    #[test]
+    fn shadowed_overloads() {
+        let commands = overloads(&[
+            ("set", &[&[String], &[Custom], &[I32], &[F32]]),
+            ("pair", &[&[I32, String], &[String, I32]]),
+        ]);
+        let report = diagnose(&commands, false);
+        assert_eq!(
+            report.diagnostics,
+            vec![
+                Diagnostic::NameIsPrefix {
+                    name: "set".into(),
+                    longer: vec!["set-option".into()]
+                },
+                Diagnostic::ShadowedOverload {
+                    name: "set".into(),
+                    overload: "String".into(),
+                    shadowed_by: "Custom".into()
+                }
+            ]
+        );
+        assert!(diagnose(&Overloads::new(), false).is_empty());
+    }
+}
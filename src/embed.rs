@@ -0,0 +1,579 @@
+//! Embedding the REPL in applications with their own input and output widgets.
+//!
+//! [`channels`] splits a REPL into a [`Driver`], which owns the commands and must be polled
+//! on the thread that built them, and a [`Frontend`] handle, which can be moved to e.g. a GUI
+//! thread. The frontend sends lines and receives the produced output as text chunks; no
+//! terminal is involved. It can also query the prompt and completions for its text widget.
+//!
+//! ```rust
+//! # use mini_async_repl::{embed, Repl};
+//! # async fn example() -> anyhow::Result<()> {
+//! let (driver, mut frontend) = embed::channels(Repl::builder())?;
+//! let gui = async move {
+//!     frontend.send_line("help");
+//!     frontend.close();
+//!     while let Some(chunk) = frontend.output().await {
+//!         print!("{chunk}");
+//!     }
+//! };
+//! let (result, ()) = futures::future::join(driver.run(), gui).await;
+//! result
+//! # }
+//! ```
+//!
+//! Closing or dropping the frontend ends the session, unless it has been built with
+//! [`EndOfInput::Wait`](crate::repl::EndOfInput::Wait). Then it waits for another frontend to
+//! be attached with an [`Attacher`], e.g. when a remote client reconnects, and sends it the
+//! output of later commands.
+//!
+//! Servers with several such sessions keep them in [`Sessions`], where clients detach by
+//! closing their frontend and reattach by name, like in tmux. Sessions keep their state,
+//! including commands run in the background, while no client is attached. Alerts concerning
+//! all clients can be [broadcast](Sessions::broadcast) to every attached frontend, while the
+//! output of commands only goes to the frontend of their session.
+//!
+//! With [`ReplBuilder::idle_timeout`], the session ends once no request has been received
+//! for that long. Frontends should call [`Frontend::activity`] on each keypress, so that a
+//! user typing a long line is not considered idle.
+//!
+//! Note that only the output written by the REPL itself (help, errors, usage) is sent to the
+//! frontend. Handlers printing directly to stdout are not captured.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt;
+
+use crate::clock;
+use crate::repl::{BuilderError, EndOfInput, LoopStatus, Repl, ReplBuilder};
+
+/// Completion candidate, see [`Repl::complete`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Text to show in the list of candidates.
+    pub display: String,
+    /// Text to replace the completed part of the line with.
+    pub replacement: String,
+}
+
+enum Request {
+    Line(String),
+    Activity,
+    Prompt(oneshot::Sender<String>),
+    Complete {
+        line: String,
+        pos: usize,
+        reply: oneshot::Sender<(usize, Vec<Candidate>)>,
+    },
+}
+
+/// Channels of a frontend, as seen by the driver.
+type Attachment = (
+    mpsc::UnboundedReceiver<Request>,
+    mpsc::UnboundedSender<String>,
+);
+
+/// Create the channels of a new frontend.
+fn frontend() -> (Frontend, Attachment) {
+    let (requests_tx, requests_rx) = mpsc::unbounded();
+    let (output_tx, output_rx) = mpsc::unbounded();
+    let frontend = Frontend {
+        requests: requests_tx,
+        output: output_rx,
+    };
+    (frontend, (requests_rx, output_tx))
+}
+
+/// Build a REPL from `builder` with its output redirected to the returned [`Frontend`].
+pub fn channels(builder: ReplBuilder) -> Result<(Driver, Frontend), BuilderError> {
+    let (frontend, (requests, output)) = frontend();
+    let output = Arc::new(Mutex::new(output));
+    let out: Box<dyn Write> = Box::new(ChannelWriter(output.clone()));
+    let repl = builder.out(out).build()?;
+    let (attach, attachments) = mpsc::unbounded();
+    let driver = Driver {
+        repl,
+        connection: Connection {
+            requests,
+            output,
+            attachments,
+        },
+        attach,
+    };
+    Ok((driver, frontend))
+}
+
+/// Side of [`channels`] executing the commands.
+pub struct Driver {
+    repl: Repl,
+    connection: Connection,
+    /// Sender of [`Attacher`]s, disconnected once the driver runs
+    attach: mpsc::UnboundedSender<Attachment>,
+}
+
+/// Output channel of the frontend attached to a [`Driver`], replaced when another one is
+/// attached.
+type Output = Arc<Mutex<mpsc::UnboundedSender<String>>>;
+
+/// Frontend attached to a [`Driver`].
+struct Connection {
+    requests: mpsc::UnboundedReceiver<Request>,
+    /// Output channel of the frontend, shared with the writer of the REPL
+    output: Output,
+    attachments: mpsc::UnboundedReceiver<Attachment>,
+}
+
+impl Connection {
+    /// Next request of the frontend. When it is closed, waits for another frontend to be
+    /// attached if the session ends with [`EndOfInput::Wait`].
+    ///
+    /// Returns `None` when the session ends.
+    async fn next(&mut self, end_of_input: EndOfInput) -> Option<Request> {
+        loop {
+            if let Some(request) = self.requests.next().await {
+                return Some(request);
+            }
+            if end_of_input == EndOfInput::Quit {
+                return None;
+            }
+            let (requests, output) = self.attachments.next().await?;
+            self.requests = requests;
+            *self.output.lock().unwrap_or_else(PoisonError::into_inner) = output;
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // attachers share the sender, so it is not dropped with the driver
+        let output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+        output.close_channel();
+    }
+}
+
+impl Driver {
+    /// Serve requests of the [`Frontend`] until it is closed or dropped, or a command quits.
+    ///
+    /// Returns critical errors of commands, like [`Repl::run`]. The output channel is closed
+    /// when this returns.
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        // frontends can only be attached with attachers handed out before
+        self.attach.disconnect();
+        loop {
+            let end_of_input = self.repl.end_of_input();
+            let request = match self.repl.idle_timeout() {
+                Some(idle_timeout) => {
+                    let next = self.connection.next(end_of_input);
+                    match clock::timeout(self.repl.clock(), idle_timeout, next).await {
+                        Some(request) => request,
+                        None => {
+                            if self.repl.expire_idle()? == LoopStatus::Break {
+                                break;
+                            }
+                            continue;
+                        }
+                    }
+                }
+                None => self.connection.next(end_of_input).await,
+            };
+            let Some(request) = request else {
+                break;
+            };
+            match request {
+                Request::Line(line) => {
+                    if self.repl.handle_lines(&line).await? == LoopStatus::Break {
+                        break;
+                    }
+                }
+                Request::Activity => {}
+                Request::Prompt(reply) => {
+                    let _ = reply.send(self.repl.prompt().to_string());
+                }
+                Request::Complete { line, pos, reply } => {
+                    let _ = reply.send(self.repl.complete(&line, pos));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The REPL being driven, e.g. to modify its commands.
+    pub fn repl(&mut self) -> &mut Repl {
+        &mut self.repl
+    }
+
+    /// Handle attaching frontends to the session once the current one is closed, see
+    /// [`EndOfInput::Wait`].
+    pub fn attacher(&self) -> Attacher {
+        Attacher {
+            attach: self.attach.clone(),
+            output: self.connection.output.clone(),
+        }
+    }
+}
+
+/// Handle attaching a new [`Frontend`] to a [`Driver`] whose frontend has been closed.
+///
+/// Can be sent to other threads, e.g. to the one accepting connections of remote clients.
+/// Once all attachers are dropped, a session built with [`EndOfInput::Wait`] ends when its
+/// frontend is closed.
+///
+/// It can also [print](Attacher::print) to the attached frontend, e.g. when a background job
+/// completes.
+#[derive(Clone)]
+pub struct Attacher {
+    attach: mpsc::UnboundedSender<Attachment>,
+    output: Output,
+}
+
+impl Attacher {
+    /// Attach a new frontend, receiving the output of the commands from now on.
+    ///
+    /// Requests of the frontend are served once the previous frontend is closed or
+    /// dropped. Returns `None` if the [`Driver`] has stopped.
+    pub fn attach(&self) -> Option<Frontend> {
+        let (frontend, attachment) = frontend();
+        self.attach.unbounded_send(attachment).ok()?;
+        Some(frontend)
+    }
+
+    /// Send `text` to the frontend currently attached, even while a command is running.
+    ///
+    /// Returns `false` if no frontend is attached, in which case the text is dropped.
+    pub fn print(&self, text: impl Into<String>) -> bool {
+        let output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+        output.unbounded_send(text.into()).is_ok()
+    }
+
+    /// Whether the [`Driver`] is still running, or has not started yet.
+    pub fn is_alive(&self) -> bool {
+        !self.attach.is_closed()
+    }
+}
+
+/// Error when opening a session in [`Sessions`].
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    /// Session with the same name is still alive.
+    #[error("session '{0}' already exists")]
+    Exists(String),
+    /// REPL of the session could not be built.
+    #[error(transparent)]
+    Builder(#[from] BuilderError),
+}
+
+/// Registry of named sessions that clients can detach from and reattach to, see the
+/// [module documentation](self).
+///
+/// The registry only holds [`Attacher`]s, so it can be kept on the thread accepting the
+/// clients, while the [`Driver`]s run where they have been built.
+#[derive(Clone, Default)]
+pub struct Sessions {
+    sessions: BTreeMap<String, Attacher>,
+}
+
+impl Sessions {
+    /// Empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a session named `name` from `builder`, with a first frontend attached.
+    ///
+    /// The session waits for another frontend when the attached one is closed, see
+    /// [`EndOfInput::Wait`], until it is [closed](Sessions::close) in the registry.
+    pub fn open(
+        &mut self,
+        name: impl Into<String>,
+        builder: ReplBuilder,
+    ) -> Result<(Driver, Frontend), SessionError> {
+        let name = name.into();
+        if self.sessions.get(&name).is_some_and(Attacher::is_alive) {
+            return Err(SessionError::Exists(name));
+        }
+        let (driver, frontend) = channels(builder.end_of_input(EndOfInput::Wait))?;
+        self.sessions.insert(name, driver.attacher());
+        Ok((driver, frontend))
+    }
+
+    /// Attach a new frontend to the session named `name`, or return `None` if there is no
+    /// such session alive.
+    pub fn attach(&mut self, name: &str) -> Option<Frontend> {
+        let frontend = self.sessions.get(name)?.attach();
+        if frontend.is_none() {
+            self.sessions.remove(name);
+        }
+        frontend
+    }
+
+    /// Names of the sessions still alive, in alphabetical order.
+    pub fn names(&mut self) -> Vec<&str> {
+        self.sessions.retain(|_, attacher| attacher.is_alive());
+        self.sessions.keys().map(String::as_str).collect()
+    }
+
+    /// Send `text` to the frontends attached to all sessions, e.g. alerts concerning every
+    /// client. Returns the number of frontends it has been sent to.
+    ///
+    /// Output of commands is only sent to the frontend of the session running them.
+    pub fn broadcast(&mut self, text: &str) -> usize {
+        self.sessions.retain(|_, attacher| attacher.is_alive());
+        self.sessions
+            .values()
+            .filter(|attacher| attacher.print(text))
+            .count()
+    }
+
+    /// Remove the session named `name` from the registry, so that it ends once its current
+    /// frontend is closed. Returns whether it was registered.
+    pub fn close(&mut self, name: &str) -> bool {
+        self.sessions.remove(name).is_some()
+    }
+}
+
+/// Side of [`channels`] used by the user interface.
+pub struct Frontend {
+    requests: mpsc::UnboundedSender<Request>,
+    output: mpsc::UnboundedReceiver<String>,
+}
+
+impl Frontend {
+    /// Queue a line of input as if it has been entered by the user. Text with multiple lines
+    /// is handled line by line, see [`Repl::handle_lines`].
+    ///
+    /// Returns `false` if the [`Driver`] has stopped.
+    pub fn send_line(&self, line: &str) -> bool {
+        self.requests
+            .unbounded_send(Request::Line(line.to_string()))
+            .is_ok()
+    }
+
+    /// Report input that is not a complete line yet, e.g. a keypress, restarting the
+    /// countdown of [`ReplBuilder::idle_timeout`].
+    ///
+    /// Returns `false` if the [`Driver`] has stopped.
+    pub fn activity(&self) -> bool {
+        self.requests.unbounded_send(Request::Activity).is_ok()
+    }
+
+    /// Current prompt, or `None` if the [`Driver`] has stopped.
+    pub async fn prompt(&self) -> Option<String> {
+        let (reply, response) = oneshot::channel();
+        self.requests.unbounded_send(Request::Prompt(reply)).ok()?;
+        response.await.ok()
+    }
+
+    /// Completions of `line` with cursor at byte `pos`, see [`Repl::complete`].
+    ///
+    /// Returns `None` if the [`Driver`] has stopped.
+    pub async fn complete(&self, line: &str, pos: usize) -> Option<(usize, Vec<Candidate>)> {
+        let (reply, response) = oneshot::channel();
+        let request = Request::Complete {
+            line: line.to_string(),
+            pos,
+            reply,
+        };
+        self.requests.unbounded_send(request).ok()?;
+        response.await.ok()
+    }
+
+    /// Wait for the next chunk of output, or `None` once the [`Driver`] has stopped
+    /// and all output has been received.
+    pub async fn output(&mut self) -> Option<String> {
+        self.output.next().await
+    }
+
+    /// Next chunk of output if one is available, without waiting.
+    ///
+    /// Useful for immediate mode GUIs polling once per frame.
+    pub fn try_output(&mut self) -> Option<String> {
+        self.output.try_recv().ok()
+    }
+
+    /// Stop sending input, letting [`Driver::run`] return once queued lines are handled.
+    ///
+    /// Sessions waiting for another frontend, see [`EndOfInput::Wait`], keep running and
+    /// this frontend is detached from them.
+    pub fn close(&self) {
+        self.requests.close_channel();
+    }
+}
+
+/// Writer sending each write as a chunk of text to the attached frontend.
+struct ChannelWriter(Output);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // the frontend may be gone, but then there is no one to report the error to
+        let _ = self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .unbounded_send(String::from_utf8_lossy(buf).into_owned());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Command, TrivialCommandHandler};
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn frontend_session() {
+        let builder = Repl::builder().prompt("app> ").add(
+            "hello",
+            Command::new("Say hello", vec![], Box::new(TrivialCommandHandler::new())),
+        );
+        let (driver, mut frontend) = channels(builder).unwrap();
+        let gui = async move {
+            assert_eq!(frontend.prompt().await.as_deref(), Some("app> "));
+            let (start, candidates) = frontend.complete("he", 2).await.unwrap();
+            assert_eq!(start, 0);
+            let replacements: Vec<_> = candidates.iter().map(|c| c.replacement.as_str()).collect();
+            assert_eq!(replacements, vec!["hello", "help"]);
+            assert!(frontend.send_line("help"));
+            assert!(frontend.send_line("nope"));
+            assert!(frontend.send_line("quit"));
+            let mut output = String::new();
+            while let Some(chunk) = frontend.output().await {
+                output.push_str(&chunk);
+            }
+            assert!(output.contains("Say hello"));
+            assert!(output.contains("Command not found: nope"));
+            assert!(!frontend.send_line("help"));
+            assert_eq!(frontend.prompt().await, None);
+        };
+        let (result, ()) = futures::future::join(driver.run(), gui).await;
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let builder = Repl::builder().idle_timeout(Duration::from_millis(10));
+        let (driver, mut frontend) = channels(builder).unwrap();
+        let gui = async move {
+            assert!(frontend.activity());
+            assert_eq!(
+                frontend.output().await.as_deref(),
+                Some("Session expired due to inactivity\n")
+            );
+        };
+        let (result, ()) = futures::future::join(driver.run(), gui).await;
+        result.unwrap();
+
+        let expired = Rc::new(Cell::new(0));
+        let count = expired.clone();
+        let builder = Repl::builder()
+            .idle_timeout(Duration::from_millis(10))
+            .on_idle(move || {
+                count.set(count.get() + 1);
+                if count.get() < 2 {
+                    LoopStatus::Continue
+                } else {
+                    LoopStatus::Break
+                }
+            });
+        let (driver, _frontend) = channels(builder).unwrap();
+        driver.run().await.unwrap();
+        assert_eq!(expired.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn reattach() {
+        let builder = Repl::builder().end_of_input(EndOfInput::Wait);
+        let (driver, mut frontend) = channels(builder).unwrap();
+        let attacher = driver.attacher();
+        let gui = async move {
+            assert!(frontend.send_line("help"));
+            let first = frontend.output().await.unwrap();
+            assert!(first.contains("quit"));
+            drop(frontend);
+
+            let mut frontend = attacher.attach().unwrap();
+            assert!(frontend.send_line("nope"));
+            assert!(frontend.send_line("quit"));
+            let mut output = String::new();
+            while let Some(chunk) = frontend.output().await {
+                output.push_str(&chunk);
+            }
+            assert!(output.contains("Command not found: nope"));
+            assert!(attacher.attach().is_none());
+        };
+        let (result, ()) = futures::future::join(driver.run(), gui).await;
+        result.unwrap();
+
+        // without attachers left, the session ends with its frontend
+        let builder = Repl::builder().end_of_input(EndOfInput::Wait);
+        let (driver, frontend) = channels(builder).unwrap();
+        drop(frontend);
+        driver.run().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn session_registry() {
+        let mut sessions = Sessions::new();
+        let (driver, frontend) = sessions.open("main", Repl::builder()).unwrap();
+        assert!(matches!(
+            sessions.open("main", Repl::builder()),
+            Err(SessionError::Exists(_))
+        ));
+        assert_eq!(sessions.names(), ["main"]);
+        assert!(sessions.attach("other").is_none());
+        frontend.close();
+
+        let client = async {
+            let mut frontend = sessions.attach("main").unwrap();
+            assert!(frontend.send_line("quit"));
+            while frontend.output().await.is_some() {}
+        };
+        let (result, ()) = futures::future::join(driver.run(), client).await;
+        result.unwrap();
+        assert!(sessions.attach("main").is_none());
+        assert!(sessions.names().is_empty());
+
+        // closed sessions end with their frontend and their name can be reused
+        let (driver, frontend) = sessions.open("main", Repl::builder()).unwrap();
+        assert!(sessions.close("main"));
+        drop(frontend);
+        driver.run().await.unwrap();
+        assert!(sessions.open("main", Repl::builder()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn broadcast() {
+        let mut sessions = Sessions::new();
+        let (first, mut a) = sessions.open("a", Repl::builder()).unwrap();
+        let (second, mut b) = sessions.open("b", Repl::builder()).unwrap();
+        let attacher = first.attacher();
+        assert_eq!(sessions.broadcast("alert\n"), 2);
+        assert!(a.send_line("nope"));
+        assert!(a.send_line("quit"));
+        sessions.close("b");
+        b.close();
+        let (first, second) = futures::future::join(first.run(), second.run()).await;
+        first.unwrap();
+        second.unwrap();
+
+        let mut output = String::new();
+        while let Some(chunk) = a.output().await {
+            output.push_str(&chunk);
+        }
+        assert!(output.starts_with("alert\n"));
+        assert!(output.contains("Command not found: nope"));
+        assert_eq!(b.output().await.as_deref(), Some("alert\n"));
+        assert_eq!(b.output().await, None);
+        assert!(!attacher.print("late"));
+        assert_eq!(sessions.broadcast("alert\n"), 0);
+        assert!(sessions.names().is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+//! Evaluation of input that is not a command, for calculator or scripting-style REPLs.
+//!
+//! With [`ReplBuilder::evaluator`], lines that do not start with the name of a command are
+//! passed to an [`Evaluator`] instead of being reported as unknown commands. Its result is
+//! printed and kept as `$_`, which can be used in the next expressions and as an argument
+//! of commands:
+//!
+//! ```rust
+//! # use mini_async_repl::Repl;
+//! # async fn main_() -> anyhow::Result<()> {
+//! let mut repl = Repl::builder()
+//!     .evaluator(|input: &str| {
+//!         let sum: i64 = input
+//!             .split('+')
+//!             .map(|term| term.trim().parse::<i64>())
+//!             .sum::<Result<_, _>>()?;
+//!         Ok(Some(sum.to_string()))
+//!     })
+//!     .build()?;
+//! repl.handle_line("1 + 2").await?; // prints 3
+//! repl.handle_line("$_ + 4").await?; // prints 7
+//! assert_eq!(repl.last_result(), Some("7"));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Commands and built-in commands keep precedence, so names of commands cannot be used as
+//! variables of the evaluated language. See `examples/calculator.rs` for a complete REPL.
+//!
+//! [`ReplBuilder::evaluator`]: crate::repl::ReplBuilder::evaluator
+
+use std::borrow::Cow;
+
+/// Name of the variable holding the last result.
+pub const LAST_RESULT: &str = "$_";
+
+/// Evaluator of the input that is not a command, see the [module documentation](self).
+///
+/// Any closure taking the input and returning the result is also an evaluator.
+pub trait Evaluator {
+    /// Evaluate `input`, with `$_` already replaced by the last result.
+    ///
+    /// Returns the text of the result, or `None` if there is nothing to print (e.g. after
+    /// an assignment), in which case `$_` is unchanged. Errors are reported like errors of
+    /// commands, including [`CriticalError`](crate::CriticalError)s.
+    fn eval(&mut self, input: &str) -> anyhow::Result<Option<String>>;
+}
+
+impl<F> Evaluator for F
+where
+    F: FnMut(&str) -> anyhow::Result<Option<String>>,
+{
+    fn eval(&mut self, input: &str) -> anyhow::Result<Option<String>> {
+        self(input)
+    }
+}
+
+/// Replace `$_` in `input` with `last`, if there is a last result.
+pub(crate) fn substitute<'a>(input: &'a str, last: Option<&str>) -> Cow<'a, str> {
+    match last {
+        Some(last) if input.contains(LAST_RESULT) => Cow::Owned(input.replace(LAST_RESULT, last)),
+        _ => Cow::Borrowed(input),
+    }
+}
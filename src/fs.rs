@@ -0,0 +1,187 @@
+//! Directory listings used to complete paths, see
+//! [`ReplBuilder::file_system`](crate::repl::ReplBuilder::file_system).
+//!
+//! Filename completion lists directories through a [`FileSystem`]. [`RealFileSystem`] is the
+//! one of the machine and is used by default, while [`VirtualFileSystem`] holds a fixed tree,
+//! so that completion can be tested without touching the disk. Sandboxed or remote sessions
+//! can implement the trait to offer the files of the system commands actually act on.
+//!
+//! ```rust
+//! use std::path::Path;
+//! use mini_async_repl::fs::{FileSystem, VirtualFileSystem};
+//!
+//! let fs = VirtualFileSystem::new()
+//!     .file("docs/notes.txt")
+//!     .dir("src");
+//! let names: Vec<String> = fs
+//!     .read_dir(Path::new("."))
+//!     .unwrap()
+//!     .into_iter()
+//!     .map(|entry| entry.name)
+//!     .collect();
+//! assert_eq!(names, ["docs", "src"]);
+//! ```
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR};
+
+use crate::paths;
+
+/// Entry of a directory listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// Name of the entry within its directory.
+    pub name: String,
+    /// Whether the entry is a directory, or a link to one.
+    pub is_dir: bool,
+}
+
+/// Source of directory listings, see [`fs`](self).
+pub trait FileSystem {
+    /// Entries of directory `dir`, in any order. Relative paths are relative to the current
+    /// directory, which is `.` when listing it.
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>>;
+}
+
+/// File system of the machine.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>> {
+        let entries = std::fs::read_dir(dir)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().into_string().ok()?;
+                // follows symbolic links
+                let is_dir = entry.path().is_dir();
+                Some(DirEntry { name, is_dir })
+            })
+            .collect();
+        Ok(entries)
+    }
+}
+
+/// Fixed tree of files and directories held in memory.
+///
+/// Relative paths are relative to the current directory, and absolute paths can be added
+/// as well, e.g. to complete `~/` with a [home directory](crate::repl::ReplBuilder::home_dir).
+#[derive(Debug, Default, Clone)]
+pub struct VirtualFileSystem {
+    /// Whether each path is a directory, by path without `.` components.
+    entries: BTreeMap<PathBuf, bool>,
+}
+
+impl VirtualFileSystem {
+    /// Empty tree.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add file `path`, and the directories containing it.
+    pub fn file(self, path: impl AsRef<Path>) -> Self {
+        self.add(path.as_ref(), false)
+    }
+
+    /// Add directory `path`, and the directories containing it.
+    pub fn dir(self, path: impl AsRef<Path>) -> Self {
+        self.add(path.as_ref(), true)
+    }
+
+    fn add(mut self, path: &Path, is_dir: bool) -> Self {
+        let path = normalize(path);
+        // the current and root directories have no parent and are not entries
+        for parent in path.ancestors().skip(1) {
+            if parent.parent().is_some() {
+                self.entries.insert(parent.to_path_buf(), true);
+            }
+        }
+        self.entries.insert(path, is_dir);
+        self
+    }
+}
+
+impl FileSystem for VirtualFileSystem {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>> {
+        let dir = normalize(dir);
+        let is_root = dir == Path::new("") || dir.parent().is_none();
+        if !is_root && self.entries.get(&dir) != Some(&true) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        let entries = self
+            .entries
+            .iter()
+            .filter(|(path, _)| path.parent() == Some(&dir))
+            .filter_map(|(path, is_dir)| {
+                let name = path.file_name()?.to_str()?.to_string();
+                Some(DirEntry {
+                    name,
+                    is_dir: *is_dir,
+                })
+            })
+            .collect();
+        Ok(entries)
+    }
+}
+
+/// `path` without its `.` components, so that `./docs/` and `docs` are the same.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// Paths starting with `prefix` in `fs`, sorted, with a trailing separator for directories
+/// so that completion can continue inside them. Only entries `accept`ed are offered, and
+/// hidden ones only when the prefix of their name starts with a dot.
+pub(crate) fn complete(
+    fs: &dyn FileSystem,
+    prefix: &str,
+    accept: impl Fn(&DirEntry) -> bool,
+) -> Vec<String> {
+    let (dir, file_prefix) = paths::split_dir(prefix);
+    let Ok(entries) = fs.read_dir(Path::new(if dir.is_empty() { "." } else { dir })) else {
+        return Vec::new();
+    };
+    let mut values: Vec<String> = entries
+        .into_iter()
+        .filter(|entry| {
+            entry.name.starts_with(file_prefix)
+                && (!entry.name.starts_with('.') || file_prefix.starts_with('.'))
+                && accept(entry)
+        })
+        .map(|entry| match entry.is_dir {
+            true => format!("{dir}{}{MAIN_SEPARATOR}", entry.name),
+            false => format!("{dir}{}", entry.name),
+        })
+        .collect();
+    values.sort();
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_file_system() {
+        let fs = VirtualFileSystem::new()
+            .file("docs/a.txt")
+            .file("./docs/.hidden")
+            .dir("docs/drafts")
+            .file("/srv/files/b.log");
+        let all = |_: &DirEntry| true;
+        assert_eq!(complete(&fs, "", all), ["docs/"]);
+        assert_eq!(complete(&fs, "docs/", all), ["docs/a.txt", "docs/drafts/"]);
+        assert_eq!(complete(&fs, "docs/.", all), ["docs/.hidden"]);
+        assert_eq!(
+            complete(&fs, "docs/", |entry| entry.is_dir),
+            ["docs/drafts/"]
+        );
+        assert_eq!(complete(&fs, "/srv/f", all), ["/srv/files/"]);
+        assert_eq!(complete(&fs, "/srv/files/", all), ["/srv/files/b.log"]);
+        assert!(complete(&fs, "missing/", all).is_empty());
+        assert!(fs.read_dir(Path::new("docs/a.txt")).is_err());
+    }
+}
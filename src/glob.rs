@@ -0,0 +1,173 @@
+//! Expansion of glob patterns in path arguments, see [`ReplBuilder::globbing`].
+//!
+//! Patterns use the shell syntax: `*` matches any characters, `?` one character and
+//! `[abc]`, `[a-z]` or `[!abc]` one character of a set. They do not match path separators,
+//! and only match names starting with a dot if the pattern does.
+//!
+//! [`ReplBuilder::globbing`]: crate::repl::ReplBuilder::globbing
+
+use std::path::{is_separator, Path, MAIN_SEPARATOR};
+
+/// Returns true if `arg` contains characters with a special meaning in patterns.
+pub(crate) fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+/// Paths matching `pattern`, sorted within each directory. Relative patterns are matched in
+/// `cwd` if given, and give relative paths.
+pub(crate) fn expand(pattern: &str, cwd: Option<&Path>) -> Vec<String> {
+    let components: Vec<&str> = pattern
+        .split(is_separator)
+        .filter(|component| !component.is_empty())
+        .collect();
+    // paths matched so far, each empty or ending with a separator
+    let base = match cwd {
+        Some(cwd) if Path::new(pattern).is_relative() && !pattern.starts_with(is_separator) => {
+            format!("{}{MAIN_SEPARATOR}", cwd.display())
+        }
+        _ => String::new(),
+    };
+    let mut prefixes = vec![if pattern.starts_with(is_separator) {
+        MAIN_SEPARATOR.to_string()
+    } else {
+        base.clone()
+    }];
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        let mut next = Vec::new();
+        for prefix in &prefixes {
+            let names = if is_pattern(component) {
+                matching_names(prefix, component)
+            } else {
+                vec![component.to_string()]
+            };
+            for name in names {
+                let path = format!("{prefix}{name}");
+                if last {
+                    if Path::new(&path).exists() {
+                        next.push(path);
+                    }
+                } else if Path::new(&path).is_dir() {
+                    next.push(format!("{path}{MAIN_SEPARATOR}"));
+                }
+            }
+        }
+        prefixes = next;
+    }
+    for path in &mut prefixes {
+        path.replace_range(..base.len(), "");
+    }
+    prefixes
+}
+
+/// Names of the entries of directory `dir` (the current one if empty) matching `pattern`.
+fn matching_names(dir: &str, pattern: &str) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.') || pattern.first() == Some(&'.'))
+        .filter(|name| matches(&pattern, &name.chars().collect::<Vec<_>>()))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Returns true if `word` matches the whole `pattern`, e.g. an argument rather than a path.
+pub(crate) fn matches_word(pattern: &str, word: &str) -> bool {
+    let chars = |text: &str| text.chars().collect::<Vec<_>>();
+    matches(&chars(pattern), &chars(word))
+}
+
+/// Returns true if `name` matches the whole `pattern`.
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|i| matches(rest, &name[i..])),
+        Some(('?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+        Some(('[', rest)) => match (class(rest), name.split_first()) {
+            (Some((set, len)), Some((c, name))) => set(*c) && matches(&rest[len..], name),
+            (Some(_), None) => false,
+            // without a closing bracket it is a regular character
+            (None, _) => name.first() == Some(&'[') && matches(rest, &name[1..]),
+        },
+        Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+    }
+}
+
+/// Set of characters at the start of `pattern`, just after `[`, with the length of the
+/// pattern it takes including the closing `]`.
+fn class(pattern: &[char]) -> Option<(impl Fn(char) -> bool + '_, usize)> {
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    let start = usize::from(negated);
+    // a closing bracket right at the start is part of the set
+    let end = start + 1 + pattern.get(start + 1..)?.iter().position(|&c| c == ']')?;
+    let set = &pattern[start..end];
+    let contains = move |c: char| {
+        let mut i = 0;
+        while i < set.len() {
+            if set.get(i + 1) == Some(&'-') && i + 2 < set.len() {
+                if (set[i]..=set[i + 2]).contains(&c) {
+                    return true;
+                }
+                i += 3;
+            } else {
+                if set[i] == c {
+                    return true;
+                }
+                i += 1;
+            }
+        }
+        false
+    };
+    Some((move |c| contains(c) != negated, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob_matches(pattern: &str, name: &str) -> bool {
+        let chars = |text: &str| text.chars().collect::<Vec<_>>();
+        matches(&chars(pattern), &chars(name))
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(glob_matches("*.log", "app.log"));
+        assert!(!glob_matches("*.log", "app.log.1"));
+        assert!(glob_matches("app.?", "app.1"));
+        assert!(glob_matches("[a-c]x[!0-9]", "bxy"));
+        assert!(!glob_matches("[a-c]x[!0-9]", "bx1"));
+        assert!(glob_matches("[]]", "]"));
+        assert!(glob_matches("[ab", "[ab"));
+        assert!(!is_pattern("app.log"));
+    }
+
+    #[test]
+    fn expand_paths() {
+        let root = std::env::temp_dir().join(format!("repl-glob-{}", std::process::id()));
+        for file in ["a/1.log", "a/2.log", "a/.hidden.log", "b/3.log", "b/3.txt"] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let root_str = root.to_str().unwrap();
+        let sep = MAIN_SEPARATOR;
+        let expanded = expand(&format!("{root_str}{sep}*{sep}*.log"), None);
+        let expected: Vec<String> = ["a/1.log", "a/2.log", "b/3.log"]
+            .iter()
+            .map(|file| format!("{root_str}{sep}{}", file.replace('/', &sep.to_string())))
+            .collect();
+        assert_eq!(expanded, expected);
+        assert!(expand(&format!("{root_str}{sep}*{sep}*.csv"), None).is_empty());
+        assert_eq!(expand(&format!("{root_str}{sep}a{sep}.h*"), None).len(), 1);
+        assert_eq!(
+            expand(&format!("b{sep}3.*"), Some(&root)),
+            [format!("b{sep}3.log"), format!("b{sep}3.txt")]
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+//! Highlighting of brackets and quotes in the input line, see [`Theme::bracket`] and
+//! [`Theme::unclosed_quote`].
+//!
+//! Quotes and escapes follow the syntax of command lines, see
+//! [`tokenize`](crate::tokenize::tokenize): brackets inside quotes or comments are not
+//! matched.
+
+use std::borrow::Cow;
+
+use crate::style::{Style, Theme};
+use crate::tokenize;
+
+/// Brackets and quotes of a line.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Scan {
+    /// Positions of matching brackets, opening first.
+    pairs: Vec<(usize, usize)>,
+    /// Position of the quote that is not closed at the end of the line.
+    unclosed_quote: Option<usize>,
+}
+
+/// Find the matching brackets and the unclosed quote of `line`.
+///
+/// With `literal_backslashes` a backslash is a regular character, not an escape.
+fn scan(line: &str, literal_backslashes: bool) -> Scan {
+    let mut scan = Scan::default();
+    let mut open: Vec<(usize, char)> = Vec::new();
+    let lexed = tokenize::lex(line, literal_backslashes, |i, c| match c {
+        '(' | '[' | '{' => open.push((i, c)),
+        ')' | ']' | '}' => {
+            let opening = match c {
+                ')' => '(',
+                ']' => '[',
+                _ => '{',
+            };
+            if open.last().is_some_and(|(_, last)| *last == opening) {
+                let (start, _) = open.pop().unwrap();
+                scan.pairs.push((start, i));
+            } else {
+                // a stray bracket does not match anything before it
+                open.clear();
+            }
+        }
+        _ => {}
+    });
+    if let Err(quote) = lexed {
+        scan.unclosed_quote = Some(quote.start);
+    }
+    scan
+}
+
+/// Bracket matching the one at `pos` or just before it, with both positions in order.
+fn matching_bracket(scan: &Scan, pos: usize) -> Option<(usize, usize)> {
+    let at = |pos| {
+        scan.pairs
+            .iter()
+            .copied()
+            .find(|(open, close)| *open == pos || *close == pos)
+    };
+    at(pos).or_else(|| pos.checked_sub(1).and_then(at))
+}
+
+/// Whether `theme` highlights anything, so that the line has to be redrawn on each edit.
+pub(crate) fn is_active(theme: &Theme) -> bool {
+    !theme.bracket.is_plain() || !theme.unclosed_quote.is_plain()
+}
+
+/// Style the brackets matching each other at the cursor and the text after an unclosed
+/// quote, or return `line` unchanged if there are none.
+pub(crate) fn highlight<'l>(line: &'l str, pos: usize, theme: &Theme) -> Cow<'l, str> {
+    if !is_active(theme) {
+        return Cow::Borrowed(line);
+    }
+    let scan = scan(line, cfg!(windows));
+    // byte ranges to style, in order and not overlapping since brackets are never quoted
+    let mut spans: Vec<(usize, usize, Style)> = Vec::new();
+    if let Some((open, close)) = matching_bracket(&scan, pos) {
+        spans.push((open, open + 1, theme.bracket));
+        spans.push((close, close + 1, theme.bracket));
+    }
+    if let Some(start) = scan.unclosed_quote {
+        spans.push((start, line.len(), theme.unclosed_quote));
+    }
+    spans.retain(|(_, _, style)| !style.is_plain());
+    if spans.is_empty() {
+        return Cow::Borrowed(line);
+    }
+    let mut styled = String::with_capacity(line.len() + 16 * spans.len());
+    let mut end = 0;
+    for (start, stop, style) in spans {
+        styled.push_str(&line[end..start]);
+        styled.push_str(&style.paint(&line[start..stop]));
+        end = stop;
+    }
+    styled.push_str(&line[end..]);
+    Cow::Owned(styled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::Color;
+
+    #[test]
+    fn brackets_and_quotes() {
+        let line = "eval (f [1] \"(\") # )";
+        let scan = scan(line, false);
+        assert_eq!(scan.pairs, vec![(8, 10), (5, 15)]);
+        assert_eq!(scan.unclosed_quote, None);
+        assert_eq!(matching_bracket(&scan, 5), Some((5, 15)));
+        assert_eq!(matching_bracket(&scan, 11), Some((8, 10)));
+        assert_eq!(matching_bracket(&scan, 2), None);
+
+        assert_eq!(super::scan("say 'it''s", false).unclosed_quote, Some(8));
+        assert_eq!(super::scan("say \"a\\\"", false).unclosed_quote, Some(4));
+        assert_eq!(super::scan("say \"a\\\"", true).unclosed_quote, None);
+        assert_eq!(super::scan("f ( ] )", false).pairs, vec![]);
+    }
+
+    #[test]
+    fn styles() {
+        let mut theme = Theme::default();
+        assert!(!is_active(&theme));
+        assert!(matches!(highlight("f (x)", 5, &theme), Cow::Borrowed(_)));
+        theme.bracket = Style::new().bold();
+        theme.unclosed_quote = Style::new().color(Color::Red);
+        assert_eq!(
+            highlight("f (x) 'y", 5, &theme),
+            "f \x1b[1m(\x1b[0mx\x1b[1m)\x1b[0m \x1b[31m'y\x1b[0m"
+        );
+        assert_eq!(highlight("f (x) y", 6, &theme), "f (x) y");
+    }
+}
@@ -0,0 +1,128 @@
+//! Encrypted history file, see [`ReplBuilder::history_cipher`].
+//!
+//! The crate does not implement any cipher itself. Applications implement [`HistoryCipher`]
+//! with an authenticated cipher (e.g. AES-GCM or ChaCha20-Poly1305) and their own key, and
+//! the REPL uses it to read the history file when building and to rewrite it after each
+//! entry.
+//!
+//! Requires the `terminal` feature.
+//!
+//! [`ReplBuilder::history_cipher`]: crate::repl::ReplBuilder::history_cipher
+
+use std::path::Path;
+
+/// Encryption of the history file, see the [module documentation](self).
+pub trait HistoryCipher {
+    /// Encrypt the contents of the history file.
+    fn encrypt(&self, plaintext: &[u8]) -> Vec<u8>;
+    /// Decrypt contents returned by [`HistoryCipher::encrypt`], or return `None` if they
+    /// cannot be authenticated, e.g. because the file is corrupted or the key has changed.
+    fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// Entries of the encrypted history file at `path`, oldest first.
+///
+/// A missing file, or one that cannot be decrypted, results in an empty history, which
+/// replaces the file when it is saved.
+pub(crate) fn load(path: &Path, cipher: &dyn HistoryCipher) -> Vec<String> {
+    let Ok(ciphertext) = std::fs::read(path) else {
+        return Vec::new();
+    };
+    cipher
+        .decrypt(&ciphertext)
+        .and_then(|plaintext| String::from_utf8(plaintext).ok())
+        .map(|text| text.lines().map(unescape).collect())
+        .unwrap_or_default()
+}
+
+/// Write `entries` to the history file at `path`, encrypted with `cipher`.
+///
+/// The file is replaced atomically, so an interrupted write does not lose the history.
+pub(crate) fn save<'a>(
+    path: &Path,
+    cipher: &dyn HistoryCipher,
+    entries: impl IntoIterator<Item = &'a str>,
+) -> std::io::Result<()> {
+    let text: String = entries
+        .into_iter()
+        .map(|entry| escape(entry) + "\n")
+        .collect();
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    std::fs::write(&partial, cipher.encrypt(text.as_bytes()))?;
+    std::fs::rename(&partial, path)
+}
+
+/// Escape line breaks so that each entry takes a single line.
+fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                entry.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                entry.push('\\');
+                chars.next();
+            }
+            _ => entry.push(c),
+        }
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Toy cipher with a checksum, standing in for an authenticated one.
+    struct XorCipher(u8);
+
+    impl HistoryCipher for XorCipher {
+        fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+            let sum = plaintext.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+            plaintext.iter().chain([&sum]).map(|b| b ^ self.0).collect()
+        }
+
+        fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+            let mut plaintext: Vec<u8> = ciphertext.iter().map(|b| b ^ self.0).collect();
+            let sum = plaintext.pop()?;
+            let valid = plaintext.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == sum;
+            valid.then_some(plaintext)
+        }
+    }
+
+    #[test]
+    fn encrypted_file() {
+        let path = std::env::temp_dir().join(format!("repl-history-{}.enc", std::process::id()));
+        let entries = ["status", "echo 'a\\b'", "multi\nline"];
+        save(&path, &XorCipher(0x5a), entries).unwrap();
+        let contents = std::fs::read(&path).unwrap();
+        assert!(!String::from_utf8_lossy(&contents).contains("status"));
+        assert_eq!(load(&path, &XorCipher(0x5a)), entries);
+        // wrong key
+        assert!(load(&path, &XorCipher(0x11)).is_empty());
+
+        let repl = crate::Repl::builder()
+            .history_file(&path)
+            .history_cipher(XorCipher(0x5a))
+            .build()
+            .unwrap();
+        let search = crate::repl::HistorySearch::Substring;
+        assert_eq!(
+            repl.history_search("", search),
+            ["multi\nline", "echo 'a\\b'", "status"]
+        );
+
+        std::fs::write(&path, b"garbage").unwrap();
+        assert!(load(&path, &XorCipher(0x5a)).is_empty());
+        std::fs::remove_file(&path).unwrap();
+        assert!(load(&path, &XorCipher(0x5a)).is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+//! Passing command arguments as a single JSON object or array.
+//!
+//! Commands opt in with [`Command::json_args`]. Then, besides the usual syntax, a line
+//! consisting of the command name followed by a JSON value is accepted:
+//!
+//! ```text
+//! > create-user {"name": "bo", "admin": true}
+//! > create-user ["bo", true]
+//! ```
+//!
+//! Object keys are matched to argument names and array items to arguments by position.
+//! Values are checked against the declared argument types (`i32` and `f32` need numbers,
+//! `String` needs a string, `Custom` accepts anything and receives strings as-is and other
+//! values as JSON text) and then passed to the handler like regular arguments, so existing
+//! handlers need no changes. The payload is taken verbatim from the line, without shell-like
+//! splitting, so it does not need to be quoted.
+//!
+//! Requires the `json` feature.
+
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::command::{ArgsError, Command, CommandArgInfo, CommandArgType};
+
+/// Split a line into the command name and a JSON payload, if it looks like one.
+pub(crate) fn split_payload(line: &str) -> Option<(&str, &str)> {
+    let (name, rest) = line.trim().split_once(char::is_whitespace)?;
+    let rest = rest.trim_start();
+    rest.starts_with(['{', '[']).then_some((name, rest))
+}
+
+/// Convert `payload` to arguments of the first overload accepting JSON that matches it.
+///
+/// Returns the error of the first JSON overload if none matches.
+pub(crate) fn expand(cmds: &[Arc<Command>], payload: &str) -> Result<Vec<String>, ArgsError> {
+    let value: Value = serde_json::from_str(payload)
+        .map_err(|err| ArgsError::Invalid(format!("invalid JSON: {err}")))?;
+    let mut first_err = None;
+    for cmd in cmds.iter().filter(|cmd| cmd.json_args) {
+        match to_args(&value, &cmd.args_info) {
+            Ok(args) => return Ok(args),
+            Err(err) => {
+                first_err.get_or_insert(err);
+            }
+        }
+    }
+    Err(first_err.unwrap_or(ArgsError::NoVariantFound))
+}
+
+/// Convert a JSON object or array to arguments described by `args_info`.
+pub fn to_args(value: &Value, args_info: &[CommandArgInfo]) -> Result<Vec<String>, ArgsError> {
+    match value {
+        Value::Array(items) => {
+            if items.len() != args_info.len() {
+                return Err(ArgsError::WrongNumberOfArguments {
+                    got: items.len(),
+                    expected: args_info.len(),
+                });
+            }
+            items
+                .iter()
+                .zip(args_info)
+                .map(|(item, info)| to_arg(item, info))
+                .collect()
+        }
+        Value::Object(fields) => {
+            let names: Vec<&str> = args_info
+                .iter()
+                .map(|info| {
+                    info.name.as_deref().ok_or_else(|| {
+                        ArgsError::Invalid("arguments have no names, use a JSON array".into())
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            if let Some(unknown) = fields.keys().find(|key| !names.contains(&key.as_str())) {
+                return Err(ArgsError::Invalid(format!("unknown field '{unknown}'")));
+            }
+            names
+                .iter()
+                .zip(args_info)
+                .map(|(name, info)| match fields.get(*name) {
+                    Some(field) => to_arg(field, info),
+                    None => Err(ArgsError::Invalid(format!("missing field '{name}'"))),
+                })
+                .collect()
+        }
+        _ => Err(ArgsError::Invalid("expected a JSON object or array".into())),
+    }
+}
+
+fn to_arg(value: &Value, info: &CommandArgInfo) -> Result<String, ArgsError> {
+    let arg = match (info.arg_type, value) {
+        (CommandArgType::I32, Value::Number(n)) => n
+            .as_i64()
+            .and_then(|n| i32::try_from(n).ok())
+            .map(|n| n.to_string()),
+        (CommandArgType::F32, Value::Number(n)) => Some(n.to_string()),
+        (CommandArgType::Bytes, Value::Number(n)) => n.as_u64().map(|n| n.to_string()),
+        (CommandArgType::Bytes, Value::String(s)) => Some(s.clone()),
+        (CommandArgType::String | CommandArgType::Custom, Value::String(s)) => Some(s.clone()),
+        (CommandArgType::Custom, other) => Some(other.to_string()),
+        _ => None,
+    };
+    arg.ok_or_else(|| ArgsError::WrongArgumentValue {
+        argument: value.to_string(),
+        error: format!("expected {}", info.arg_type),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn args_info() -> Vec<CommandArgInfo> {
+        vec![
+            CommandArgInfo::new_with_name(CommandArgType::String, "name"),
+            CommandArgInfo::new_with_name(CommandArgType::I32, "age"),
+            CommandArgInfo::new_with_name(CommandArgType::Custom, "admin"),
+        ]
+    }
+
+    #[test]
+    fn object_and_array() {
+        let expected = vec!["bo".to_string(), "30".into(), "true".into()];
+        let object = json!({"admin": true, "name": "bo", "age": 30});
+        assert_eq!(to_args(&object, &args_info()).unwrap(), expected);
+        let array = json!(["bo", 30, true]);
+        assert_eq!(to_args(&array, &args_info()).unwrap(), expected);
+    }
+
+    #[test]
+    fn schema_errors() {
+        let info = args_info();
+        let err = to_args(&json!({"name": "bo", "age": 30}), &info).unwrap_err();
+        assert!(matches!(err, ArgsError::Invalid(msg) if msg.contains("'admin'")));
+        let err = to_args(&json!({"name": "bo", "age": 30, "admin": 1, "x": 0}), &info);
+        assert!(matches!(err, Err(ArgsError::Invalid(msg)) if msg.contains("'x'")));
+        let err = to_args(&json!(["bo", 3.5, true]), &info).unwrap_err();
+        assert!(matches!(err, ArgsError::WrongArgumentValue { argument, .. } if argument == "3.5"));
+        let err = to_args(&json!([1, 2, 3]), &info).unwrap_err();
+        assert!(matches!(err, ArgsError::WrongArgumentValue { .. }));
+        let err = to_args(&json!(["bo"]), &info).unwrap_err();
+        assert!(matches!(
+            err,
+            ArgsError::WrongNumberOfArguments {
+                got: 1,
+                expected: 3
+            }
+        ));
+        assert!(to_args(&json!("bo"), &info).is_err());
+    }
+
+    #[test]
+    fn payload() {
+        assert_eq!(
+            split_payload(r#"  create {"a": "b c"} "#),
+            Some(("create", r#"{"a": "b c"}"#))
+        );
+        assert_eq!(split_payload("create [1, 2]"), Some(("create", "[1, 2]")));
+        assert_eq!(split_payload("create a [1, 2]"), None);
+        assert_eq!(split_payload("create"), None);
+    }
+}
@@ -0,0 +1,847 @@
+//! Key bindings of the line editor.
+//!
+//! A [`Keymap`] selects the edit mode and binds keys to editing actions. It is passed to
+//! [`ReplBuilder::keymap`](crate::repl::ReplBuilder::keymap) and can be written in a simple
+//! text format, so that end users can tweak the bindings:
+//!
+//! ```text
+//! # lines of `key = action`
+//! edit-mode = vi
+//! word-separators = /.
+//! ctrl-p = previous-history
+//! alt-b = backward-word
+//! f2 = insert:status --all
+//! f5 = run:refresh
+//! f6 = snippet:deploy
+//! ```
+//!
+//! Keys consist of optional `ctrl-`, `alt-` and `shift-` modifiers followed by a character
+//! or a key name: `f1`..`f24`, `up`, `down`, `left`, `right`, `home`, `end`, `page-up`,
+//! `page-down`, `insert`, `delete`, `backspace`, `tab`, `back-tab`, `enter` or `esc`.
+//! Actions are named after their readline counterparts (see [`ACTIONS`]), besides
+//! `insert:TEXT` which inserts `TEXT` and `run:LINE` which immediately runs `LINE` as if it
+//! has been entered, keeping the text typed so far for the next prompt, and `snippet:NAME`
+//! which inserts the template of [snippet](crate::repl::ReplBuilder::snippet) `NAME`, to be
+//! filled in before running it. `word-separators` sets the characters ending words besides
+//! whitespace, see [`Keymap::word_separators`]. With the `serde` feature, a [`Keymap`] is
+//! (de)serialized as a map of the same strings.
+//!
+//! Requires the `terminal` feature.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rustyline::{
+    Anchor, At, CharSearch, Cmd, ConditionalEventHandler, EditMode, Event, EventContext,
+    EventHandler, KeyCode, KeyEvent, Modifiers, Movement, RepeatCount, Word,
+};
+
+use crate::clock::Clock;
+use crate::snippet::{self, Snippets};
+
+/// Names of the editing actions that can be bound to keys.
+pub const ACTIONS: &[&str] = &[
+    "abort",
+    "accept-line",
+    "backward-char",
+    "backward-delete-char",
+    "backward-kill-line",
+    "backward-kill-word",
+    "backward-word",
+    "beginning-of-history",
+    "beginning-of-line",
+    "capitalize-word",
+    "clear-screen",
+    "complete",
+    "complete-backward",
+    "complete-hint",
+    "delete-char",
+    "downcase-word",
+    "end-of-file",
+    "end-of-history",
+    "end-of-line",
+    "forward-char",
+    "forward-search-history",
+    "forward-word",
+    "history-search-backward",
+    "history-search-forward",
+    "interrupt",
+    "kill-line",
+    "kill-word",
+    "newline",
+    "next-history",
+    "noop",
+    "previous-history",
+    "quoted-insert",
+    "reverse-search-history",
+    "suspend",
+    "transpose-chars",
+    "transpose-words",
+    "undo",
+    "upcase-word",
+    "yank",
+    "yank-pop",
+];
+
+const INSERT: &str = "insert:";
+const RUN: &str = "run:";
+const SNIPPET: &str = "snippet:";
+const EDIT_MODE: &str = "edit-mode";
+const WORD_SEPARATORS: &str = "word-separators";
+
+fn action(name: &str) -> Option<Cmd> {
+    if let Some(text) = name.strip_prefix(INSERT) {
+        return Some(Cmd::Insert(1, text.into()));
+    }
+    let cmd = match name {
+        "abort" => Cmd::Abort,
+        "accept-line" => Cmd::AcceptLine,
+        "backward-char" => Cmd::Move(Movement::BackwardChar(1)),
+        "backward-delete-char" => Cmd::Kill(Movement::BackwardChar(1)),
+        "backward-kill-line" => Cmd::Kill(Movement::BeginningOfLine),
+        "backward-kill-word" => Cmd::Kill(Movement::BackwardWord(1, Word::Emacs)),
+        "backward-word" => Cmd::Move(Movement::BackwardWord(1, Word::Emacs)),
+        "beginning-of-history" => Cmd::BeginningOfHistory,
+        "beginning-of-line" => Cmd::Move(Movement::BeginningOfLine),
+        "capitalize-word" => Cmd::CapitalizeWord,
+        "clear-screen" => Cmd::ClearScreen,
+        "complete" => Cmd::Complete,
+        "complete-backward" => Cmd::CompleteBackward,
+        "complete-hint" => Cmd::CompleteHint,
+        "delete-char" => Cmd::Kill(Movement::ForwardChar(1)),
+        "downcase-word" => Cmd::DowncaseWord,
+        "end-of-file" => Cmd::EndOfFile,
+        "end-of-history" => Cmd::EndOfHistory,
+        "end-of-line" => Cmd::Move(Movement::EndOfLine),
+        "forward-char" => Cmd::Move(Movement::ForwardChar(1)),
+        "forward-search-history" => Cmd::ForwardSearchHistory,
+        "forward-word" => Cmd::Move(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs)),
+        "history-search-backward" => Cmd::HistorySearchBackward,
+        "history-search-forward" => Cmd::HistorySearchForward,
+        "interrupt" => Cmd::Interrupt,
+        "kill-line" => Cmd::Kill(Movement::EndOfLine),
+        "kill-word" => Cmd::Kill(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs)),
+        "newline" => Cmd::Newline,
+        "next-history" => Cmd::NextHistory,
+        "noop" => Cmd::Noop,
+        "previous-history" => Cmd::PreviousHistory,
+        "quoted-insert" => Cmd::QuotedInsert,
+        "reverse-search-history" => Cmd::ReverseSearchHistory,
+        "suspend" => Cmd::Suspend,
+        "transpose-chars" => Cmd::TransposeChars,
+        "transpose-words" => Cmd::TransposeWords(1),
+        "undo" => Cmd::Undo(1),
+        "upcase-word" => Cmd::UpcaseWord,
+        "yank" => Cmd::Yank(1, Anchor::Before),
+        "yank-pop" => Cmd::YankPop,
+        _ => return None,
+    };
+    Some(cmd)
+}
+
+fn action_name(cmd: &Cmd) -> Option<String> {
+    if let Cmd::Insert(1, text) = cmd {
+        return Some(format!("{INSERT}{text}"));
+    }
+    ACTIONS
+        .iter()
+        .find(|name| action(name).as_ref() == Some(cmd))
+        .map(|name| name.to_string())
+}
+
+const KEYS: &[(&str, KeyCode)] = &[
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("page-up", KeyCode::PageUp),
+    ("page-down", KeyCode::PageDown),
+    ("insert", KeyCode::Insert),
+    ("delete", KeyCode::Delete),
+    ("backspace", KeyCode::Backspace),
+    ("tab", KeyCode::Tab),
+    ("back-tab", KeyCode::BackTab),
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+];
+
+const MODIFIERS: &[(&str, Modifiers)] = &[
+    ("ctrl-", Modifiers::CTRL),
+    ("alt-", Modifiers::ALT),
+    ("shift-", Modifiers::SHIFT),
+];
+
+/// Parse a key like `ctrl-x` or `f5`, see the [module documentation](self).
+pub fn parse_key(key: &str) -> Result<KeyEvent, KeymapError> {
+    let unknown = || KeymapError::UnknownKey(key.into());
+    let mut rest = key.trim();
+    let mut mods = Modifiers::NONE;
+    'outer: loop {
+        for (prefix, modifier) in MODIFIERS {
+            // a lone `-` after a modifier is the minus key
+            if let Some(tail) = rest.strip_prefix(prefix).filter(|tail| !tail.is_empty()) {
+                mods |= *modifier;
+                rest = tail;
+                continue 'outer;
+            }
+        }
+        break;
+    }
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match KEYS.iter().find(|(name, _)| *name == rest) {
+            Some((_, code)) => *code,
+            None => {
+                let n: u8 = rest
+                    .strip_prefix('f')
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| (1..=24).contains(n))
+                    .ok_or_else(unknown)?;
+                KeyCode::F(n)
+            }
+        },
+    };
+    Ok(KeyEvent::normalize(KeyEvent(code, mods)))
+}
+
+/// Format a key in the format accepted by [`parse_key`].
+pub fn key_name(key: KeyEvent) -> String {
+    let KeyEvent(code, mods) = key;
+    let mut name: String = MODIFIERS
+        .iter()
+        .filter(|(_, modifier)| mods.contains(*modifier))
+        .map(|(prefix, _)| *prefix)
+        .collect();
+    match code {
+        // ctrl-x is stored as ctrl-X
+        KeyCode::Char(c) if mods.contains(Modifiers::CTRL) => name.push(c.to_ascii_lowercase()),
+        KeyCode::Char(c) => name.push(c),
+        KeyCode::F(n) => name.push_str(&format!("f{n}")),
+        other => match KEYS.iter().find(|(_, code)| *code == other) {
+            Some((key, _)) => name.push_str(key),
+            None => name.push_str(&format!("{other:?}").to_lowercase()),
+        },
+    }
+    name
+}
+
+/// Error when parsing a [`Keymap`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum KeymapError {
+    /// Key could not be parsed.
+    #[error("unknown key '{0}'")]
+    UnknownKey(String),
+    /// Action is not one of [`ACTIONS`], `insert:TEXT`, `run:LINE` nor `snippet:NAME`.
+    #[error("unknown action '{0}'")]
+    UnknownAction(String),
+    /// Edit mode is neither `emacs` nor `vi`.
+    #[error("unknown edit mode '{0}', expected 'emacs' or 'vi'")]
+    UnknownEditMode(String),
+    /// Line is not in the `key = action` form.
+    #[error("expected 'key = action', got '{0}'")]
+    Syntax(String),
+}
+
+/// Parse edit mode name, `emacs` or `vi`.
+pub(crate) fn parse_edit_mode(name: &str) -> Result<EditMode, KeymapError> {
+    match name {
+        "emacs" => Ok(EditMode::Emacs),
+        "vi" => Ok(EditMode::Vi),
+        _ => Err(KeymapError::UnknownEditMode(name.into())),
+    }
+}
+
+/// Edit mode and key bindings of the line editor, see the [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Keymap {
+    edit_mode: Option<EditMode>,
+    word_separators: Option<String>,
+    bindings: Vec<(KeyEvent, Binding)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Binding {
+    Edit(Cmd),
+    Run(String),
+    Snippet(String),
+}
+
+impl Keymap {
+    /// Keymap with the defaults of the line editor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `mode` instead of the one from [`ReplBuilder::editor_config`](crate::repl::ReplBuilder::editor_config).
+    pub fn edit_mode(mut self, mode: EditMode) -> Self {
+        self.edit_mode = Some(mode);
+        self
+    }
+
+    /// End words at `separators` besides whitespace when moving over or deleting words, e.g.
+    /// with `alt-b`, `alt-d` or `ctrl-w`, instead of the line editor's defaults.
+    ///
+    /// By default `ctrl-w` deletes up to whitespace, and the other word actions stop at
+    /// anything but letters and digits. With `"/"` both take `web-1.prod` as one word of
+    /// `ssh web-1.prod/logs`, and with `""` words only end at whitespace. Applies to the
+    /// default keys of the emacs mode and to keys bound to word actions.
+    pub fn word_separators(mut self, separators: &str) -> Self {
+        self.word_separators = Some(separators.into());
+        self
+    }
+
+    /// Bind `key` to `cmd`, replacing previous bindings of `key`.
+    ///
+    /// Only actions listed in [`ACTIONS`] and text insertion are kept when the keymap is
+    /// formatted or serialized.
+    pub fn bind(self, key: KeyEvent, cmd: Cmd) -> Self {
+        self.with_binding(key, Binding::Edit(cmd))
+    }
+
+    /// Bind `key` to run command `line`, replacing previous bindings of `key`.
+    pub fn run(self, key: KeyEvent, line: &str) -> Self {
+        self.with_binding(key, Binding::Run(line.into()))
+    }
+
+    /// Bind `key` to insert the template of snippet `name`, replacing previous bindings of
+    /// `key`.
+    pub fn snippet(self, key: KeyEvent, name: &str) -> Self {
+        self.with_binding(key, Binding::Snippet(name.into()))
+    }
+
+    fn with_binding(mut self, key: KeyEvent, binding: Binding) -> Self {
+        let key = KeyEvent::normalize(key);
+        self.bindings.retain(|(bound, _)| *bound != key);
+        self.bindings.push((key, binding));
+        self
+    }
+
+    /// Build a keymap from `(key, action)` pairs, where key `edit-mode` selects the mode and
+    /// `word-separators` sets [`Keymap::word_separators`].
+    pub fn from_pairs<K, A>(pairs: impl IntoIterator<Item = (K, A)>) -> Result<Self, KeymapError>
+    where
+        K: AsRef<str>,
+        A: AsRef<str>,
+    {
+        let mut keymap = Self::new();
+        for (key, value) in pairs {
+            let (key, value) = (key.as_ref().trim(), value.as_ref().trim());
+            if key == EDIT_MODE {
+                keymap = keymap.edit_mode(parse_edit_mode(value)?);
+            } else if key == WORD_SEPARATORS {
+                keymap = keymap.word_separators(value);
+            } else if let Some(line) = value.strip_prefix(RUN) {
+                keymap = keymap.run(parse_key(key)?, line);
+            } else if let Some(name) = value.strip_prefix(SNIPPET) {
+                keymap = keymap.snippet(parse_key(key)?, name);
+            } else {
+                let cmd = action(value).ok_or_else(|| KeymapError::UnknownAction(value.into()))?;
+                keymap = keymap.bind(parse_key(key)?, cmd);
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// `(key, action)` pairs in the format accepted by [`Keymap::from_pairs`].
+    pub fn to_pairs(&self) -> Vec<(String, String)> {
+        let mode = self.edit_mode.map(|mode| {
+            let name = if matches!(mode, EditMode::Vi) {
+                "vi"
+            } else {
+                "emacs"
+            };
+            (EDIT_MODE.to_string(), name.to_string())
+        });
+        let separators = self
+            .word_separators
+            .as_ref()
+            .map(|separators| (WORD_SEPARATORS.to_string(), separators.clone()));
+        let bindings = self.bindings.iter().filter_map(|(key, binding)| {
+            let action = match binding {
+                Binding::Edit(cmd) => action_name(cmd)?,
+                Binding::Run(line) => format!("{RUN}{line}"),
+                Binding::Snippet(name) => format!("{SNIPPET}{name}"),
+            };
+            Some((key_name(*key), action))
+        });
+        mode.into_iter().chain(separators).chain(bindings).collect()
+    }
+
+    /// Apply edit mode and editing actions to `editor`, see [`Keymap::commands`] and
+    /// [`Keymap::snippets`] for the rest.
+    ///
+    /// With `watch`, the bound keys also track input for
+    /// [`ReplBuilder::idle_timeout`](crate::repl::ReplBuilder::idle_timeout), see [`watched`].
+    pub(crate) fn apply<H: rustyline::Helper>(
+        &self,
+        editor: &mut rustyline::Editor<H>,
+        watch: Option<&IdleWatch>,
+    ) {
+        use rustyline::config::Configurer;
+        if let Some(mode) = self.edit_mode {
+            editor.set_edit_mode(mode);
+        }
+        for (key, binding) in &self.bindings {
+            if let Binding::Edit(cmd) = binding {
+                match &self.word_separators {
+                    Some(separators) if is_word_action(cmd) => {
+                        let handler = WordAction {
+                            cmd: cmd.clone(),
+                            separators: separators.clone(),
+                            emacs_only: false,
+                        };
+                        let handler = EventHandler::Conditional(Box::new(handler));
+                        editor.bind_sequence(*key, watched(handler, watch));
+                    }
+                    _ => {
+                        editor.bind_sequence(*key, watched(cmd.clone(), watch));
+                    }
+                }
+            }
+        }
+        let Some(separators) = &self.word_separators else {
+            return;
+        };
+        for (key, cmd) in emacs_word_keys() {
+            if self.bindings.iter().all(|(bound, _)| *bound != key) {
+                let handler = WordAction {
+                    cmd,
+                    separators: separators.clone(),
+                    emacs_only: true,
+                };
+                let handler = EventHandler::Conditional(Box::new(handler));
+                editor.bind_sequence(key, watched(handler, watch));
+            }
+        }
+    }
+
+    /// Keys bound to command lines.
+    pub(crate) fn commands(&self) -> impl Iterator<Item = (KeyEvent, &str)> {
+        self.bindings
+            .iter()
+            .filter_map(|(key, binding)| match binding {
+                Binding::Run(line) => Some((*key, line.as_str())),
+                _ => None,
+            })
+    }
+
+    /// Keys bound to snippets.
+    pub(crate) fn snippets(&self) -> impl Iterator<Item = (KeyEvent, &str)> {
+        self.bindings
+            .iter()
+            .filter_map(|(key, binding)| match binding {
+                Binding::Snippet(name) => Some((*key, name.as_str())),
+                _ => None,
+            })
+    }
+}
+
+/// Default keys of the emacs mode bound to word actions.
+fn emacs_word_keys() -> [(KeyEvent, Cmd); 7] {
+    let backward = Movement::BackwardWord(1, Word::Emacs);
+    let forward = Movement::ForwardWord(1, At::AfterEnd, Word::Emacs);
+    [
+        (
+            KeyEvent::ctrl('W'),
+            Cmd::Kill(Movement::BackwardWord(1, Word::Big)),
+        ),
+        (KeyEvent::alt('b'), Cmd::Move(backward.clone())),
+        (KeyEvent::alt('f'), Cmd::Move(forward.clone())),
+        (KeyEvent::alt('d'), Cmd::Kill(forward.clone())),
+        (
+            KeyEvent(KeyCode::Backspace, Modifiers::ALT),
+            Cmd::Kill(backward.clone()),
+        ),
+        (
+            KeyEvent(KeyCode::Left, Modifiers::CTRL),
+            Cmd::Move(backward),
+        ),
+        (
+            KeyEvent(KeyCode::Right, Modifiers::CTRL),
+            Cmd::Move(forward),
+        ),
+    ]
+}
+
+/// Whether `cmd` moves over or deletes words backward, or forward to their end.
+fn is_word_action(cmd: &Cmd) -> bool {
+    matches!(
+        cmd,
+        Cmd::Move(movement) | Cmd::Kill(movement)
+            if matches!(
+                movement,
+                Movement::BackwardWord(..) | Movement::ForwardWord(_, At::AfterEnd, _)
+            )
+    )
+}
+
+/// Position in `line` after moving `n` words from `pos`, where words are runs of characters
+/// other than whitespace and `separators`.
+fn word_boundary(line: &str, pos: usize, n: RepeatCount, forward: bool, separators: &str) -> usize {
+    let in_word = |c: char| !c.is_whitespace() && !separators.contains(c);
+    let mut pos = pos;
+    for _ in 0..n {
+        if forward {
+            let rest = &line[pos..];
+            let start = rest.find(in_word).unwrap_or(rest.len());
+            let len = rest[start..]
+                .find(|c| !in_word(c))
+                .unwrap_or(rest.len() - start);
+            pos += start + len;
+        } else {
+            let before = &line[..pos];
+            let end = before.rfind(in_word).map_or(0, |i| i + 1);
+            pos = before[..end].rfind(|c| !in_word(c)).map_or(0, |i| {
+                i + before[i..].chars().next().map_or(0, char::len_utf8)
+            });
+        }
+    }
+    pos
+}
+
+/// `cmd` moving over or deleting `n` words from `pos` in `line` (see [`word_boundary`]),
+/// as the movements of the line editor that fill its kill ring, which are searches of the
+/// character next to the boundary.
+fn word_action(cmd: &Cmd, line: &str, pos: usize, n: RepeatCount, separators: &str) -> Option<Cmd> {
+    let (movement, kill) = match cmd {
+        Cmd::Move(movement) => (movement, false),
+        Cmd::Kill(movement) => (movement, true),
+        _ => return None,
+    };
+    let forward = match movement {
+        Movement::BackwardWord(..) => false,
+        Movement::ForwardWord(_, At::AfterEnd, _) => true,
+        _ => return None,
+    };
+    let target = word_boundary(line, pos, n, forward, separators);
+    let movement = if target == pos {
+        return Some(Cmd::Noop);
+    } else if forward && target == line.len() {
+        Movement::EndOfBuffer
+    } else if forward {
+        // the search starts after the character at the cursor
+        let c = line[target..].chars().next()?;
+        let start = pos + line[pos..].chars().next()?.len_utf8();
+        let count = line[start..=target].matches(c).count();
+        match kill {
+            true => Movement::ViCharSearch(count, CharSearch::ForwardBefore(c)),
+            false => Movement::ViCharSearch(count, CharSearch::Forward(c)),
+        }
+    } else if target == 0 {
+        Movement::BeginningOfBuffer
+    } else {
+        let c = line[..target].chars().next_back()?;
+        let count = line[target - c.len_utf8()..pos].matches(c).count();
+        Movement::ViCharSearch(count, CharSearch::BackwardAfter(c))
+    };
+    Some(if kill {
+        Cmd::Kill(movement)
+    } else {
+        Cmd::Move(movement)
+    })
+}
+
+/// Handler of a key bound to a word action, with words ended by
+/// [`Keymap::word_separators`].
+struct WordAction {
+    cmd: Cmd,
+    separators: String,
+    /// Whether this is a default key of the emacs mode, left to the vi mode.
+    emacs_only: bool,
+}
+
+impl ConditionalEventHandler for WordAction {
+    fn handle(&self, _: &Event, n: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        if self.emacs_only && ctx.mode() == EditMode::Vi {
+            return None;
+        }
+        word_action(&self.cmd, ctx.line(), ctx.pos(), n, &self.separators)
+    }
+}
+
+/// Handler of a key bound to a command line.
+///
+/// The current line is accepted with `line` left in `pending`, to be run instead of it.
+pub(crate) struct RunCommand {
+    pub(crate) line: String,
+    pub(crate) pending: Arc<Mutex<Option<String>>>,
+}
+
+impl ConditionalEventHandler for RunCommand {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, _: &EventContext) -> Option<Cmd> {
+        *self.pending.lock().unwrap() = Some(self.line.clone());
+        Some(Cmd::AcceptLine)
+    }
+}
+
+/// Handler of a key bound to a snippet, inserting its template if it still exists.
+pub(crate) struct InsertSnippet {
+    pub(crate) name: String,
+    pub(crate) snippets: Snippets,
+}
+
+impl ConditionalEventHandler for InsertSnippet {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, _: &EventContext) -> Option<Cmd> {
+        let template = snippet::get(&self.snippets, &self.name)?;
+        Some(Cmd::Insert(1, template))
+    }
+}
+
+/// Handler of keys without a binding of their own, tracking input for
+/// [`ReplBuilder::idle_timeout`](crate::repl::ReplBuilder::idle_timeout). Keys with a
+/// binding never reach it, so their handlers are [`watched`] too.
+///
+/// The first key pressed after the timeout interrupts the line with `expired` set, instead
+/// of being handled. Nothing happens before that key: the read itself is never interrupted.
+#[derive(Clone)]
+pub(crate) struct IdleWatch {
+    pub(crate) timeout: Duration,
+    pub(crate) last_input: Arc<Mutex<Instant>>,
+    pub(crate) expired: Arc<AtomicBool>,
+    pub(crate) clock: Arc<dyn Clock>,
+}
+
+impl IdleWatch {
+    /// Record a key pressed, returning the command interrupting the line if the session
+    /// has been idle for too long.
+    fn input(&self) -> Option<Cmd> {
+        let mut last_input = self.last_input.lock().unwrap();
+        let now = self.clock.now();
+        if now.saturating_duration_since(*last_input) >= self.timeout {
+            self.expired.store(true, Ordering::Relaxed);
+            return Some(Cmd::Interrupt);
+        }
+        *last_input = now;
+        None
+    }
+}
+
+impl ConditionalEventHandler for IdleWatch {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, _: &EventContext) -> Option<Cmd> {
+        self.input()
+    }
+}
+
+/// Handler of a bound key, which the [`IdleWatch`] does not see, recording the input before
+/// running the handler of the binding.
+struct Watched {
+    watch: IdleWatch,
+    handler: EventHandler,
+}
+
+impl ConditionalEventHandler for Watched {
+    fn handle(
+        &self,
+        evt: &Event,
+        n: RepeatCount,
+        positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        self.watch.input().or_else(|| match &self.handler {
+            EventHandler::Simple(cmd) => Some(cmd.clone()),
+            EventHandler::Conditional(handler) => handler.handle(evt, n, positive, ctx),
+        })
+    }
+}
+
+/// Binding of a key to `handler`, also recording input with `watch` if there is one.
+pub(crate) fn watched(handler: impl Into<EventHandler>, watch: Option<&IdleWatch>) -> EventHandler {
+    match watch {
+        Some(watch) => EventHandler::Conditional(Box::new(Watched {
+            watch: watch.clone(),
+            handler: handler.into(),
+        })),
+        None => handler.into(),
+    }
+}
+
+impl FromStr for Keymap {
+    type Err = KeymapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pairs = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                // `=` itself can be bound (`alt-= = undo`) and inserted (`f2 = insert:a=b`)
+                line.split_once(" = ")
+                    .or_else(|| line.rsplit_once('='))
+                    .ok_or_else(|| KeymapError::Syntax(line.into()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_pairs(pairs)
+    }
+}
+
+impl Display for Keymap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (key, action) in self.to_pairs() {
+            writeln!(f, "{key} = {action}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Keymap {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.to_pairs())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Keymap {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pairs = std::collections::BTreeMap::<String, String>::deserialize(deserializer)?;
+        Self::from_pairs(pairs).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        let ctrl_p = KeyEvent(KeyCode::Char('P'), Modifiers::CTRL);
+        assert_eq!(parse_key("ctrl-p"), Ok(ctrl_p));
+        assert_eq!(parse_key("ctrl-P"), Ok(ctrl_p));
+        assert_eq!(
+            parse_key("f5"),
+            Ok(KeyEvent(KeyCode::F(5), Modifiers::NONE))
+        );
+        assert_eq!(
+            parse_key("alt-shift-page-up"),
+            Ok(KeyEvent(KeyCode::PageUp, Modifiers::ALT_SHIFT))
+        );
+        assert_eq!(
+            parse_key("alt--"),
+            Ok(KeyEvent(KeyCode::Char('-'), Modifiers::ALT))
+        );
+        assert!(parse_key("hyper-x").is_err());
+        assert!(parse_key("f25").is_err());
+        for key in ["ctrl-p", "f5", "alt-shift-page-up", "alt--", "x"] {
+            assert_eq!(key_name(parse_key(key).unwrap()), key);
+        }
+    }
+
+    #[test]
+    fn idle_watch() {
+        let clock = crate::clock::ManualClock::new();
+        let watch = IdleWatch {
+            timeout: Duration::from_secs(10),
+            last_input: Arc::new(Mutex::new(clock.now())),
+            expired: Default::default(),
+            clock: Arc::new(clock.clone()),
+        };
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(watch.input(), None);
+        // each key starts the countdown again
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(watch.input(), None);
+        assert!(!watch.expired.load(Ordering::Relaxed));
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(watch.input(), Some(Cmd::Interrupt));
+        assert!(watch.expired.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn word_separators() {
+        let line = "ssh web-1.prod/logs  x";
+        let boundary =
+            |pos, n, forward, separators| word_boundary(line, pos, n, forward, separators);
+        assert_eq!(boundary(line.len(), 1, false, ""), 21);
+        assert_eq!(boundary(21, 1, false, "/"), 15);
+        assert_eq!(boundary(19, 1, false, "/"), 15);
+        assert_eq!(boundary(19, 2, false, "/"), 4);
+        assert_eq!(boundary(19, 1, false, "/.-"), 15);
+        assert_eq!(boundary(14, 1, false, "/.-"), 10);
+        assert_eq!(boundary(0, 1, true, "/"), 3);
+        assert_eq!(boundary(3, 1, true, "/"), 14);
+        assert_eq!(boundary(14, 2, true, "/"), line.len());
+
+        let kill_back = Cmd::Kill(Movement::BackwardWord(1, Word::Big));
+        assert_eq!(
+            word_action(&kill_back, line, 19, 1, "/"),
+            Some(Cmd::Kill(Movement::ViCharSearch(
+                1,
+                CharSearch::BackwardAfter('/')
+            )))
+        );
+        assert_eq!(
+            word_action(&kill_back, line, 2, 1, "/"),
+            Some(Cmd::Kill(Movement::BeginningOfBuffer))
+        );
+        let forward = Movement::ForwardWord(1, At::AfterEnd, Word::Emacs);
+        assert_eq!(
+            word_action(&Cmd::Kill(forward.clone()), line, 4, 1, "."),
+            Some(Cmd::Kill(Movement::ViCharSearch(
+                1,
+                CharSearch::ForwardBefore('.')
+            )))
+        );
+        assert_eq!(
+            word_action(&Cmd::Move(forward), line, 17, 1, ""),
+            Some(Cmd::Move(Movement::ViCharSearch(
+                1,
+                CharSearch::Forward(' ')
+            )))
+        );
+        assert_eq!(word_action(&kill_back, line, 0, 1, ""), Some(Cmd::Noop));
+        assert_eq!(word_action(&Cmd::Undo(1), line, 4, 1, ""), None);
+
+        let keymap: Keymap = "word-separators = /.\nalt-b = backward-word"
+            .parse()
+            .unwrap();
+        assert_eq!(keymap.word_separators.as_deref(), Some("/."));
+        assert_eq!(keymap.to_string().parse::<Keymap>(), Ok(keymap));
+        let keymap: Keymap = "word-separators =".parse().unwrap();
+        assert_eq!(keymap.word_separators.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn text_format() {
+        let text = "# my bindings\nedit-mode = vi\nctrl-p = previous-history\n\nalt-= = undo\nf2 = insert:x=1\nf5 = run:add 1 2\nf6 = snippet:deploy\n";
+        let keymap: Keymap = text.parse().unwrap();
+        assert_eq!(keymap.edit_mode, Some(EditMode::Vi));
+        assert_eq!(keymap.bindings.len(), 5);
+        assert_eq!(keymap.bindings[1].1, Binding::Edit(Cmd::Undo(1)));
+        let f5 = parse_key("f5").unwrap();
+        assert_eq!(keymap.commands().collect::<Vec<_>>(), vec![(f5, "add 1 2")]);
+        let f6 = parse_key("f6").unwrap();
+        assert_eq!(keymap.snippets().collect::<Vec<_>>(), vec![(f6, "deploy")]);
+        assert_eq!(keymap.to_string().parse::<Keymap>(), Ok(keymap));
+
+        assert_eq!(
+            "ctrl-p previous-history".parse::<Keymap>(),
+            Err(KeymapError::Syntax("ctrl-p previous-history".into()))
+        );
+        assert_eq!(
+            "ctrl-p = fly".parse::<Keymap>(),
+            Err(KeymapError::UnknownAction("fly".into()))
+        );
+        assert!("edit-mode = nano".parse::<Keymap>().is_err());
+        assert!(ACTIONS.iter().all(|name| action(name).is_some()));
+    }
+
+    #[cfg(all(feature = "serde", feature = "json"))]
+    #[test]
+    fn serialization() {
+        let json = r#"{"ctrl-p":"previous-history","edit-mode":"vi"}"#;
+        let keymap: Keymap = serde_json::from_str(json).unwrap();
+        assert_eq!(keymap.edit_mode, Some(EditMode::Vi));
+        let value = serde_json::to_value(&keymap).unwrap();
+        assert_eq!(
+            value,
+            serde_json::from_str::<serde_json::Value>(json).unwrap()
+        );
+        assert!(serde_json::from_str::<Keymap>(r#"{"ctrl-p":"fly"}"#).is_err());
+    }
+}
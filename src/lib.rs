@@ -1,8 +1,60 @@
 // #![deny(missing_docs)]
 
+pub mod bytes;
+#[cfg(feature = "clap")]
+pub mod clap_args;
+pub mod clock;
 pub mod command;
 mod completion;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod diagnostics;
+pub mod embed;
+pub mod eval;
+pub mod fs;
+mod glob;
+#[cfg(feature = "terminal")]
+mod highlight;
+#[cfg(feature = "terminal")]
+pub mod history;
+#[cfg(feature = "json")]
+pub mod json_args;
+#[cfg(feature = "terminal")]
+pub mod keymap;
+pub mod locale;
+#[cfg(feature = "log")]
+pub mod logger;
+mod man;
+pub mod output;
+mod paths;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+mod prefix;
+#[cfg(feature = "terminal")]
+pub mod printer;
+pub mod prompt;
+#[cfg(feature = "proptest")]
+pub mod proptest_args;
+pub mod provider;
+pub mod registry;
 pub mod repl;
+pub mod runtime;
+pub mod script;
+#[cfg(feature = "serde")]
+pub mod serde_args;
+pub mod shell;
+mod snippet;
+#[cfg(feature = "config")]
+pub mod state;
+pub mod stats;
+#[cfg(feature = "terminal")]
+pub mod style;
+mod subst;
+mod tokenize;
+pub mod tutorial;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub use anyhow;
 
@@ -0,0 +1,166 @@
+//! Conventions of the language of the operator for numbers, see
+//! [`ReplBuilder::locale`](crate::repl::ReplBuilder::locale).
+//!
+//! With a [`Locale`] using a decimal comma, `f32` arguments can be typed as `1,5` and are
+//! passed to handlers as `1.5`, and the durations printed by the REPL (e.g. with
+//! [`ReplBuilder::slow_command`](crate::repl::ReplBuilder::slow_command)) read `1,5s`.
+//! Handlers can format their own values the same way with
+//! [`CommandContext::locale`](crate::command::CommandContext::locale):
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use mini_async_repl::locale::Locale;
+//!
+//! let locale: Locale = "de-DE".parse().unwrap();
+//! assert_eq!(locale.parse_f32("1,5"), Ok(1.5));
+//! assert_eq!(locale.format_duration(Duration::from_millis(1500)), "1,5s");
+//! assert_eq!(locale.format_size(1536), "1,5 KiB");
+//! ```
+
+use std::num::ParseFloatError;
+use std::str::FromStr;
+use std::time::Duration;
+
+use thiserror;
+
+/// Languages writing numbers with a decimal comma.
+const DECIMAL_COMMA: &[&str] = &[
+    "af", "be", "bg", "bs", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fr", "gl", "hr",
+    "hu", "id", "is", "it", "kk", "lt", "lv", "mk", "nb", "nl", "nn", "no", "pl", "pt", "ro", "ru",
+    "sk", "sl", "sq", "sr", "sv", "tr", "uk", "vi",
+];
+
+/// Units of [`Locale::format_size`].
+const SIZE_UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+/// How numbers are written, see [`locale`](self). Defaults to [`Locale::C`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Locale {
+    decimal_separator: char,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::C
+    }
+}
+
+impl Locale {
+    /// Numbers written like in Rust, with a decimal point.
+    pub const C: Locale = Locale::new('.');
+
+    /// Locale writing numbers with `decimal_separator`, e.g. `,`.
+    pub const fn new(decimal_separator: char) -> Self {
+        Self { decimal_separator }
+    }
+
+    /// Separator of the integer and fractional parts of numbers.
+    pub fn decimal_separator(&self) -> char {
+        self.decimal_separator
+    }
+
+    /// Parse `value` written with the decimal separator of the locale, or a point.
+    pub fn parse_f32(&self, value: &str) -> Result<f32, ParseFloatError> {
+        match self.delocalize(value) {
+            Some(value) => value.parse(),
+            None => value.parse(),
+        }
+    }
+
+    /// `value` with its decimal separator replaced by a point, if it is a number written
+    /// with the separator of the locale.
+    pub(crate) fn delocalize(&self, value: &str) -> Option<String> {
+        if self.decimal_separator == '.' || value.contains('.') {
+            return None;
+        }
+        let replaced = value.replacen(self.decimal_separator, ".", 1);
+        replaced.parse::<f32>().is_ok().then_some(replaced)
+    }
+
+    /// `value` with `precision` decimals and the decimal separator of the locale.
+    pub fn format_decimal(&self, value: f64, precision: usize) -> String {
+        let formatted = format!("{value:.precision$}");
+        match self.decimal_separator {
+            '.' => formatted,
+            separator => formatted.replacen('.', &separator.to_string(), 1),
+        }
+    }
+
+    /// `duration` in seconds with one decimal, e.g. `1.5s`.
+    pub fn format_duration(&self, duration: Duration) -> String {
+        format!("{}s", self.format_decimal(duration.as_secs_f64(), 1))
+    }
+
+    /// Size of `bytes` in binary units, with one decimal above 1024 bytes, e.g. `512 B` or
+    /// `1.5 KiB`.
+    pub fn format_size(&self, bytes: u64) -> String {
+        let mut size = bytes as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < SIZE_UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        match unit {
+            0 => format!("{bytes} B"),
+            _ => format!("{} {}", self.format_decimal(size, 1), SIZE_UNITS[unit]),
+        }
+    }
+}
+
+/// Error of parsing a [`Locale`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid locale '{0}'")]
+pub struct LocaleError(String);
+
+impl FromStr for Locale {
+    type Err = LocaleError;
+
+    /// Locale of a language tag or of the value of `LANG`, e.g. `fr`, `de-CH` or
+    /// `pt_BR.UTF-8`, by language. `C` and `POSIX` are [`Locale::C`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s.split(['-', '_', '.', '@']).next().unwrap_or_default();
+        if matches!(language, "C" | "POSIX") {
+            return Ok(Self::C);
+        }
+        if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic())
+        {
+            return Err(LocaleError(s.into()));
+        }
+        let language = language.to_ascii_lowercase();
+        match DECIMAL_COMMA.contains(&language.as_str()) {
+            true => Ok(Self::new(',')),
+            false => Ok(Self::C),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        let german: Locale = "de_DE.UTF-8".parse().unwrap();
+        assert_eq!(german, Locale::new(','));
+        assert_eq!("en-US".parse(), Ok(Locale::C));
+        assert_eq!("C".parse(), Ok(Locale::C));
+        assert!("1,5".parse::<Locale>().is_err());
+        assert!("".parse::<Locale>().is_err());
+
+        assert_eq!(german.delocalize("-1,25"), Some("-1.25".to_string()));
+        assert_eq!(german.delocalize("1.25"), None);
+        assert_eq!(german.delocalize("1,2,3"), None);
+        assert_eq!(Locale::C.delocalize("1,5"), None);
+        assert_eq!(german.parse_f32("2.5"), Ok(2.5));
+        assert!(Locale::C.parse_f32("2,5").is_err());
+
+        assert_eq!(german.format_decimal(2.0 / 3.0, 2), "0,67");
+        assert_eq!(
+            Locale::C.format_duration(Duration::from_millis(300)),
+            "0.3s"
+        );
+        assert_eq!(german.format_size(512), "512 B");
+        assert_eq!(german.format_size(5 << 30), "5,0 GiB");
+        assert_eq!(Locale::C.format_size(u64::MAX), "16777216.0 TiB");
+    }
+}
@@ -0,0 +1,135 @@
+//! [`log`] backend printing records above the prompt.
+//!
+//! Log records written to the terminal while the user is typing a line mess it up. A
+//! [`Logger`] prints them with the [`Printer`] of the REPL instead, coloring their level:
+//!
+//! ```rust,no_run
+//! # use mini_async_repl::{logger::Logger, Repl};
+//! let repl = Repl::builder().build()?;
+//! Logger::new(repl.printer())
+//!     .level(log::LevelFilter::Debug)
+//!     .init()?;
+//! log::info!("connected");
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Requires the `log` feature.
+
+use ::log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::printer::Printer;
+use crate::style::{Color, Style};
+
+/// Logger printing above the prompt, see the [module documentation](self).
+pub struct Logger {
+    printer: Printer,
+    level: LevelFilter,
+    /// Styles of the level names, from [`Level::Error`] to [`Level::Trace`].
+    styles: [Style; 5],
+    color: bool,
+}
+
+impl Logger {
+    /// Logger printing records of level [`Level::Info`] and above with `printer`, see
+    /// [`Repl::printer`](crate::Repl::printer).
+    ///
+    /// Levels are colored if the line editor writes to a terminal.
+    pub fn new(printer: Printer) -> Self {
+        let color = printer.is_terminal();
+        Self {
+            printer,
+            level: LevelFilter::Info,
+            styles: [
+                Style::new().bold().color(Color::Red),
+                Style::new().color(Color::Yellow),
+                Style::new().color(Color::Green),
+                Style::new().color(Color::Blue),
+                Style::new().color(Color::Gray),
+            ],
+            color,
+        }
+    }
+
+    /// Most verbose level printed.
+    pub fn level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Style of the name of `level`.
+    pub fn style(mut self, level: Level, style: Style) -> Self {
+        self.styles[level as usize - 1] = style;
+        self
+    }
+
+    /// Whether to style the level names.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Install the logger for the [`log`] macros, see [`log::set_logger`].
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        ::log::set_max_level(self.level);
+        ::log::set_boxed_logger(Box::new(self))
+    }
+
+    fn format(&self, record: &Record) -> String {
+        let level = format!("{:<5}", record.level());
+        let level = if self.color {
+            self.styles[record.level() as usize - 1].paint(&level)
+        } else {
+            level
+        };
+        format!("{level} {}: {}", record.target(), record.args())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            // there is nowhere to report the error to
+            let _ = self.printer.print(&self.format(record));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let logger = Logger::new(Printer::new(true))
+            .level(LevelFilter::Warn)
+            .color(true)
+            .style(Level::Warn, Style::new().underline());
+        let record = |level| {
+            Record::builder()
+                .level(level)
+                .target("app::net")
+                .args(format_args!("timed out"))
+                .build()
+        };
+        assert_eq!(
+            logger.format(&record(Level::Warn)),
+            "\x1b[4mWARN \x1b[0m app::net: timed out"
+        );
+        assert_eq!(
+            logger.format(&record(Level::Error)),
+            "\x1b[1;31mERROR\x1b[0m app::net: timed out"
+        );
+        assert!(!logger.enabled(record(Level::Info).metadata()));
+        let logger = logger.color(false);
+        assert_eq!(
+            logger.format(&record(Level::Info)),
+            "INFO  app::net: timed out"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Manual pages in `roff` format, see [`Repl::help_man`](crate::Repl::help_man).
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::registry::{CommandInfo, OverloadInfo};
+
+/// Text escaped for `roff`: backslashes and hyphens are escaped, and lines starting with a
+/// control character are protected with `\&`.
+fn escape(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', r"\e").replace('-', r"\-");
+            match line.starts_with(['.', '\'']) {
+                true => format!(r"\&{line}"),
+                false => line,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Paragraph tagged with `tag` (already escaped) describing an entry.
+fn entry(out: &mut String, tag: &str, description: &str) {
+    out.push_str(".TP\n");
+    let _ = writeln!(out, "{tag}");
+    if !description.is_empty() {
+        let _ = writeln!(out, "{}", escape(description));
+    }
+}
+
+/// Entry of an overload of command `name`, e.g. `\fBmove\fR \fIx:i32\fR`.
+fn overload(out: &mut String, name: &str, overload: &OverloadInfo) {
+    let mut tag = format!(r"\fB{}\fR", escape(name));
+    for arg in &overload.args {
+        let _ = write!(tag, r" \fI{}\fR", escape(&arg.to_string()));
+    }
+    let mut description = overload.description.clone();
+    if let Some(note) = &overload.deprecated {
+        let _ = write!(description, "\nDeprecated: {note}");
+    }
+    if let Some(url) = &overload.docs_url {
+        let _ = write!(description, "\nDocs: {url}");
+    }
+    entry(out, &tag, description.trim_start());
+}
+
+/// Manual page of program `name` in `section`, with `description`, the visible `commands`
+/// grouped by category and the built-in ones with their descriptions.
+pub(crate) fn render(
+    name: &str,
+    section: u8,
+    description: &str,
+    commands: &[CommandInfo],
+    builtins: &[(String, String)],
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        ".TH \"{}\" \"{section}\"",
+        escape(&name.to_uppercase())
+    );
+    out.push_str(".SH NAME\n");
+    match description.lines().next().filter(|line| !line.is_empty()) {
+        Some(summary) => {
+            let _ = writeln!(out, r"{} \- {}", escape(name), escape(summary));
+        }
+        None => {
+            let _ = writeln!(out, "{}", escape(name));
+        }
+    }
+    if !description.is_empty() {
+        out.push_str(".SH DESCRIPTION\n");
+        // blank lines separate paragraphs
+        let _ = writeln!(out, "{}", escape(description).replace("\n\n", "\n.PP\n"));
+    }
+
+    // commands without a category come first, without a subsection
+    let mut categories: BTreeMap<Option<&str>, Vec<(&str, &OverloadInfo)>> = BTreeMap::new();
+    for info in commands {
+        for o in info.overloads.iter().filter(|o| !o.hidden) {
+            categories
+                .entry(o.category.as_deref())
+                .or_default()
+                .push((&info.name, o));
+        }
+    }
+    if !categories.is_empty() {
+        out.push_str(".SH COMMANDS\n");
+    }
+    for (category, overloads) in categories {
+        if let Some(category) = category {
+            let _ = writeln!(out, ".SS {}", escape(category));
+        }
+        for (name, o) in overloads {
+            overload(&mut out, name, o);
+        }
+    }
+
+    if !builtins.is_empty() {
+        out.push_str(".SH BUILT-IN COMMANDS\n");
+        for (name, description) in builtins {
+            entry(&mut out, &format!(r"\fB{}\fR", escape(name)), description);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Command, CommandArgInfo, CommandArgType, TrivialCommandHandler};
+    use crate::registry::CommandRegistry;
+    use crate::runtime::default_runtime;
+
+    #[test]
+    fn man_page() {
+        let command = |description: &str, args| {
+            Command::new(description, args, Box::new(TrivialCommandHandler::new()))
+        };
+        let x = CommandArgInfo::new_with_name(CommandArgType::I32, "x");
+        let cmds = vec![
+            ("move".to_string(), command("Move to X", vec![x])),
+            (
+                "ls-files".to_string(),
+                command(".hidden files too", vec![]).category("files"),
+            ),
+            ("secret".to_string(), command("", vec![]).hidden(true)),
+        ];
+        let infos = CommandRegistry::new(cmds, default_runtime())
+            .unwrap()
+            .infos();
+        let builtins = [("quit".to_string(), "Quit repl".to_string())];
+        let page = render(
+            "my-tool",
+            1,
+            "Tool for C:\\ drives\n\nMore.",
+            &infos,
+            &builtins,
+        );
+        assert_eq!(
+            page,
+            [
+                r#".TH "MY\-TOOL" "1""#,
+                ".SH NAME",
+                r"my\-tool \- Tool for C:\e drives",
+                ".SH DESCRIPTION",
+                r"Tool for C:\e drives",
+                ".PP",
+                "More.",
+                ".SH COMMANDS",
+                ".TP",
+                r"\fBmove\fR \fIx:i32\fR",
+                "Move to X",
+                ".SS files",
+                ".TP",
+                r"\fBls\-files\fR",
+                r"\&.hidden files too",
+                ".SH BUILT-IN COMMANDS",
+                ".TP",
+                r"\fBquit\fR",
+                "Quit repl",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,587 @@
+//! Destination of the REPL output, see [`ReplBuilder::out`].
+//!
+//! Help, errors and output written by handlers through
+//! [`CommandContext::out`](crate::command::CommandContext::out) all go through the same sink.
+//! Before each prompt the sink is flushed and, if the last output did not end with a new line,
+//! one is added, so the line editor does not overwrite it when drawing the prompt.
+//!
+//! With [`ReplBuilder::json_output`] everything written while handling one line is collected
+//! into a single JSON object instead, written as one line once the input has been handled:
+//!
+//! ```text
+//! {"command":"add","status":"ok","output":"3\n","error":null,"data":[]}
+//! ```
+//!
+//! `status` is `"ok"`, `"error"` or `"quit"`, `command` is the name of the command that has
+//! been run, if any, and `data` holds the values handlers passed to
+//! [`CommandContext::emit`](crate::command::CommandContext::emit).
+//!
+//! With a [pager](crate::repl::ReplBuilder::pager), the output of a command taller than the
+//! terminal is shown through it. Adding `--no-pager` at the end of a command line prints its
+//! output directly.
+//!
+//! A command line ending with `| tee FILE` also writes the output of that command to `FILE`,
+//! replacing its contents, or appending to it with `| tee -a FILE`.
+//!
+//! [`ReplBuilder::out`]: crate::repl::ReplBuilder::out
+//! [`ReplBuilder::json_output`]: crate::repl::ReplBuilder::json_output
+
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+
+use std::str::FromStr;
+#[cfg(feature = "terminal")]
+use unicode_width::UnicodeWidthStr;
+
+use crate::locale::Locale;
+use crate::tokenize::Token;
+
+/// Where the REPL writes its output.
+///
+/// With [`Output::Stdout`] or [`Output::Stderr`] the line editor uses the same stream by
+/// default, see [`ReplBuilder::editor_config`](crate::repl::ReplBuilder::editor_config).
+pub enum Output {
+    Stdout,
+    Stderr,
+    /// Any other writer, e.g. a buffer or a network connection.
+    Writer(Box<dyn Write>),
+    /// Output discarded without being formatted for a terminal, e.g. for benchmarks, see
+    /// [`Repl::replay`](crate::Repl::replay).
+    Null,
+}
+
+impl From<Box<dyn Write>> for Output {
+    fn from(writer: Box<dyn Write>) -> Self {
+        Output::Writer(writer)
+    }
+}
+
+impl From<std::io::Stdout> for Output {
+    fn from(_: std::io::Stdout) -> Self {
+        Output::Stdout
+    }
+}
+
+impl From<std::io::Stderr> for Output {
+    fn from(_: std::io::Stderr) -> Self {
+        Output::Stderr
+    }
+}
+
+impl Output {
+    /// Stream of the line editor matching this output, if it is one of the standard streams.
+    #[cfg(feature = "terminal")]
+    pub(crate) fn stream(&self) -> Option<rustyline::OutputStreamType> {
+        match self {
+            Output::Stdout => Some(rustyline::OutputStreamType::Stdout),
+            Output::Stderr => Some(rustyline::OutputStreamType::Stderr),
+            Output::Writer(_) | Output::Null => None,
+        }
+    }
+
+    /// Whether this is a standard stream writing to a terminal, where [`hyperlink`]s can be
+    /// shown.
+    pub(crate) fn supports_hyperlinks(&self) -> bool {
+        let terminal = match self {
+            Output::Stdout => std::io::stdout().is_terminal(),
+            Output::Stderr => std::io::stderr().is_terminal(),
+            Output::Writer(_) | Output::Null => false,
+        };
+        terminal && std::env::var("TERM").map_or(true, |term| term != "dumb")
+    }
+}
+
+/// `url` as an OSC 8 hyperlink. Terminals not supporting them ignore the escape sequences
+/// and show the plain URL.
+pub(crate) fn hyperlink(url: &str) -> String {
+    format!("\x1b]8;;{url}\x1b\\{url}\x1b]8;;\x1b\\")
+}
+
+/// How much the REPL prints, see [`ReplBuilder::verbosity`].
+///
+/// Handlers can follow the same setting through
+/// [`CommandContext::verbosity`](crate::command::CommandContext::verbosity).
+///
+/// [`ReplBuilder::verbosity`]: crate::repl::ReplBuilder::verbosity
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Verbosity {
+    /// Only results and errors, without candidate lists or usage after an error.
+    Quiet,
+    #[default]
+    Normal,
+    /// Additional diagnostics, e.g. the causes of errors.
+    Verbose,
+}
+
+/// Error when parsing [`Verbosity`].
+#[derive(Debug, thiserror::Error)]
+#[error("invalid verbosity '{0}', expected quiet, normal or verbose")]
+pub struct ParseVerbosityError(String);
+
+impl FromStr for Verbosity {
+    type Err = ParseVerbosityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "quiet" => Ok(Verbosity::Quiet),
+            "normal" => Ok(Verbosity::Normal),
+            "verbose" => Ok(Verbosity::Verbose),
+            _ => Err(ParseVerbosityError(s.into())),
+        }
+    }
+}
+
+impl Display for Verbosity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Verbosity::Quiet => "quiet",
+            Verbosity::Normal => "normal",
+            Verbosity::Verbose => "verbose",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Suffixes of a command line changing where its output goes.
+pub(crate) struct Redirection {
+    pub(crate) tee: Option<Tee>,
+    #[cfg(feature = "terminal")]
+    pub(crate) no_pager: bool,
+}
+
+impl Redirection {
+    /// Remove `--no-pager` and `| tee [-a] FILE` suffixes from the arguments of a command line.
+    ///
+    /// Quoted arguments are never suffixes, so that e.g. `grep '|'` keeps its argument.
+    pub(crate) fn split(args: &mut Vec<Token>) -> Self {
+        #[cfg(feature = "terminal")]
+        let mut no_pager = take_last(args, "--no-pager");
+        let tee = Tee::split(args);
+        #[cfg(feature = "terminal")]
+        {
+            no_pager |= take_last(args, "--no-pager");
+        }
+        Self {
+            tee,
+            #[cfg(feature = "terminal")]
+            no_pager,
+        }
+    }
+}
+
+#[cfg(feature = "terminal")]
+fn take_last(args: &mut Vec<Token>, flag: &str) -> bool {
+    let found = args.len() > 1 && args.last().is_some_and(|arg| is_syntax(arg, flag));
+    if found {
+        args.pop();
+    }
+    found
+}
+
+/// Returns true if `arg` is the unquoted word `word`.
+fn is_syntax(arg: &Token, word: &str) -> bool {
+    !arg.quoted && arg.as_str() == word
+}
+
+/// File receiving a copy of the output of a single command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Tee {
+    pub(crate) path: PathBuf,
+    append: bool,
+}
+
+impl Tee {
+    /// Remove a trailing `| tee [-a] FILE` from the arguments of a command line.
+    pub(crate) fn split(args: &mut Vec<Token>) -> Option<Tee> {
+        let pipe = args
+            .iter()
+            .rposition(|arg| is_syntax(arg, "|"))
+            .filter(|&i| i > 0)?;
+        let tee = match &args[pipe + 1..] {
+            [tee, path] if is_syntax(tee, "tee") => Tee {
+                path: path.as_str().into(),
+                append: false,
+            },
+            [tee, flag, path] if is_syntax(tee, "tee") && is_syntax(flag, "-a") => Tee {
+                path: path.as_str().into(),
+                append: true,
+            },
+            _ => return None,
+        };
+        args.truncate(pipe);
+        Some(tee)
+    }
+
+    pub(crate) fn write(&self, output: &str) -> std::io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(self.append)
+            .truncate(!self.append)
+            .open(&self.path)?;
+        file.write_all(output.as_bytes())
+    }
+}
+
+/// Number of terminal rows taken by `text` on a terminal `columns` wide.
+#[cfg(feature = "terminal")]
+pub(crate) fn screen_lines(text: &str, columns: usize) -> usize {
+    let columns = columns.max(1);
+    text.lines()
+        .map(|line| line.width().div_ceil(columns).max(1))
+        .sum()
+}
+
+/// Show `text` through the pager `command`, waiting for it to exit.
+#[cfg(feature = "terminal")]
+pub(crate) fn page(command: &str, text: &str) -> std::io::Result<()> {
+    let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg);
+    let words = shell_words::split(command).map_err(|e| invalid(e.to_string()))?;
+    let (program, args) = words
+        .split_first()
+        .ok_or_else(|| invalid("empty pager command".into()))?;
+    let mut child = std::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // the pager may exit before reading everything
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// [`Output`] keeping track of whether the last write ended a line.
+pub(crate) struct Sink {
+    output: Output,
+    line_start: bool,
+    pub(crate) verbosity: Verbosity,
+    pub(crate) locale: Locale,
+    /// Output held back until [`Sink::release`].
+    #[cfg(feature = "terminal")]
+    held: Option<Vec<u8>>,
+    /// Copies of the output since each unfinished [`Sink::start_capture`].
+    captures: Vec<Vec<u8>>,
+    /// Errors reported since [`Sink::record_errors`], if it has been called.
+    errors: Option<Vec<String>>,
+    /// Response being collected, in JSON mode.
+    #[cfg(feature = "json")]
+    response: Option<Response>,
+}
+
+/// Output of a single line of input in JSON mode.
+#[cfg(feature = "json")]
+#[derive(Default)]
+struct Response {
+    command: Option<String>,
+    output: Vec<u8>,
+    error: Option<String>,
+    data: Vec<serde_json::Value>,
+}
+
+impl Sink {
+    pub(crate) fn new(output: Output) -> Self {
+        Self {
+            output,
+            line_start: true,
+            verbosity: Verbosity::Normal,
+            locale: Locale::default(),
+            #[cfg(feature = "terminal")]
+            held: None,
+            captures: Vec::new(),
+            errors: None,
+            #[cfg(feature = "json")]
+            response: None,
+        }
+    }
+
+    /// Collect output into JSON responses, see [`Sink::end_response`].
+    #[cfg(feature = "json")]
+    pub(crate) fn json(mut self) -> Self {
+        self.response = Some(Response::default());
+        self
+    }
+
+    pub(crate) fn is_json(&self) -> bool {
+        #[cfg(feature = "json")]
+        return self.response.is_some();
+        #[cfg(not(feature = "json"))]
+        false
+    }
+
+    /// Keep a copy of everything written from now on, until the matching
+    /// [`Sink::take_capture`]. Captures can be nested.
+    pub(crate) fn start_capture(&mut self) {
+        self.captures.push(Vec::new());
+    }
+
+    /// Stop the last capture and return the output written since it has been started.
+    pub(crate) fn take_capture(&mut self) -> Option<String> {
+        self.captures
+            .pop()
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    pub(crate) fn output(&self) -> &Output {
+        &self.output
+    }
+
+    /// Write to `output` from now on, returning the previous one.
+    pub(crate) fn replace_output(&mut self, output: Output) -> Output {
+        std::mem::replace(&mut self.output, output)
+    }
+
+    /// Keep the output from now on instead of writing it, until [`Sink::release`].
+    #[cfg(feature = "terminal")]
+    pub(crate) fn hold(&mut self) {
+        self.held = Some(Vec::new());
+    }
+
+    /// Stop holding output and return what has been held back.
+    #[cfg(feature = "terminal")]
+    pub(crate) fn release(&mut self) -> Option<String> {
+        self.held
+            .take()
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Keep the errors reported from now on, until [`Sink::take_errors`].
+    pub(crate) fn record_errors(&mut self) {
+        self.errors = Some(Vec::new());
+    }
+
+    /// Stop keeping errors and return the ones reported since [`Sink::record_errors`].
+    pub(crate) fn take_errors(&mut self) -> Vec<String> {
+        self.errors.take().unwrap_or_default()
+    }
+
+    /// Keep `err` if errors are recorded, without printing it, for errors printed otherwise.
+    pub(crate) fn record_error(&mut self, err: impl Display) {
+        if let Some(errors) = &mut self.errors {
+            errors.push(err.to_string());
+        }
+    }
+
+    /// Report an error, printed as `Error: {err}` or stored as the error of the response.
+    pub(crate) fn error(&mut self, err: impl Display) -> std::io::Result<()> {
+        self.record_error(&err);
+        #[cfg(feature = "json")]
+        if let Some(response) = &mut self.response {
+            response.error = Some(err.to_string());
+            return Ok(());
+        }
+        writeln!(self, "Error: {err}")
+    }
+
+    /// Set the name of the command the current response belongs to.
+    #[cfg(feature = "json")]
+    pub(crate) fn set_command(&mut self, name: &str) {
+        if let Some(response) = &mut self.response {
+            response.command = Some(name.to_string());
+        }
+    }
+
+    /// Add a value to the current response, or print it as a line of JSON in text mode.
+    #[cfg(feature = "json")]
+    pub(crate) fn emit(&mut self, value: serde_json::Value) -> std::io::Result<()> {
+        match &mut self.response {
+            Some(response) => {
+                response.data.push(value);
+                Ok(())
+            }
+            None => writeln!(self, "{value}"),
+        }
+    }
+
+    /// Write the current response as a line of JSON and start a new one. Nothing is written
+    /// if there was no command, output nor error, e.g. for an empty line.
+    #[cfg(feature = "json")]
+    pub(crate) fn end_response(&mut self, quit: bool) -> std::io::Result<()> {
+        let Some(response) = self.response.replace(Response::default()) else {
+            return Ok(());
+        };
+        if response.command.is_none() && response.output.is_empty() && response.error.is_none() {
+            return Ok(());
+        }
+        let status = match (&response.error, quit) {
+            (Some(_), _) => "error",
+            (None, true) => "quit",
+            (None, false) => "ok",
+        };
+        let line = serde_json::json!({
+            "command": response.command,
+            "status": status,
+            "output": String::from_utf8_lossy(&response.output),
+            "error": response.error,
+            "data": response.data,
+        });
+        let line = format!("{line}\n");
+        match &mut self.output {
+            Output::Stdout => std::io::stdout().write_all(line.as_bytes())?,
+            Output::Stderr => std::io::stderr().write_all(line.as_bytes())?,
+            Output::Writer(writer) => writer.write_all(line.as_bytes())?,
+            Output::Null => {}
+        }
+        self.flush()
+    }
+
+    /// End the current line, if any, and flush, so the next prompt starts on a new line.
+    pub(crate) fn finish_line(&mut self) -> std::io::Result<()> {
+        if !self.line_start {
+            self.write_all(b"\n")?;
+        }
+        self.flush()
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        #[cfg(feature = "json")]
+        if let Some(response) = &mut self.response {
+            response.output.extend_from_slice(buf);
+            for capture in &mut self.captures {
+                capture.extend_from_slice(buf);
+            }
+            return Ok(buf.len());
+        }
+        #[cfg(feature = "terminal")]
+        if let Some(held) = &mut self.held {
+            held.extend_from_slice(buf);
+            for capture in &mut self.captures {
+                capture.extend_from_slice(buf);
+            }
+            return Ok(buf.len());
+        }
+        let written = match &mut self.output {
+            Output::Stdout => std::io::stdout().write(buf)?,
+            Output::Stderr => std::io::stderr().write(buf)?,
+            Output::Writer(writer) => writer.write(buf)?,
+            Output::Null => buf.len(),
+        };
+        for capture in &mut self.captures {
+            capture.extend_from_slice(&buf[..written]);
+        }
+        if let Some(last) = buf[..written].last() {
+            self.line_start = *last == b'\n';
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.output {
+            Output::Stdout => std::io::stdout().flush(),
+            Output::Stderr => std::io::stderr().flush(),
+            Output::Writer(writer) => writer.flush(),
+            Output::Null => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize::tokenize;
+
+    #[test]
+    fn parse_verbosity() {
+        for verbosity in [Verbosity::Quiet, Verbosity::Normal, Verbosity::Verbose] {
+            assert_eq!(
+                verbosity.to_string().parse::<Verbosity>().unwrap(),
+                verbosity
+            );
+        }
+        assert!("loud".parse::<Verbosity>().is_err());
+        assert!(Verbosity::Quiet < Verbosity::Verbose);
+    }
+
+    #[test]
+    fn hyperlinks() {
+        assert_eq!(
+            hyperlink("https://example.com"),
+            "\x1b]8;;https://example.com\x1b\\https://example.com\x1b]8;;\x1b\\"
+        );
+        assert!(!Output::Writer(Box::new(Vec::new())).supports_hyperlinks());
+    }
+
+    #[test]
+    fn split_tee() {
+        let split = |line: &str| {
+            let mut args = tokenize(line, false).unwrap();
+            let tee = Tee::split(&mut args);
+            let args: Vec<String> = args.iter().map(Token::to_string).collect();
+            (args, tee)
+        };
+        assert_eq!(
+            split("list --all"),
+            (vec!["list".into(), "--all".into()], None)
+        );
+        let (args, tee) = split("list | tee 'out file.txt'");
+        assert_eq!(args, vec!["list".to_string()]);
+        assert_eq!(
+            tee,
+            Some(Tee {
+                path: "out file.txt".into(),
+                append: false
+            })
+        );
+        assert!(split("list | tee -a log").1.unwrap().append);
+        assert_eq!(split("echo | tee").1, None);
+        assert_eq!(split("| tee log").1, None);
+        // quoted words are arguments
+        for line in [
+            "grep '|' tee log",
+            r#"echo a "|" tee log"#,
+            r"echo \| tee log",
+        ] {
+            assert_eq!(split(line).1, None);
+        }
+        assert_eq!(split("echo | 'tee' log").1, None);
+        assert_eq!(split(r#"echo | tee "-a" log"#).1, None);
+    }
+
+    #[cfg(feature = "terminal")]
+    #[test]
+    fn redirection() {
+        let mut args = tokenize("log --no-pager", false).unwrap();
+        assert!(Redirection::split(&mut args).no_pager);
+        assert_eq!(args.len(), 1);
+        let mut args = tokenize("log --no-pager | tee out", false).unwrap();
+        let redirection = Redirection::split(&mut args);
+        assert!(redirection.no_pager && redirection.tee.is_some());
+        assert_eq!(args.len(), 1);
+        let mut args = tokenize("--no-pager", false).unwrap();
+        assert!(!Redirection::split(&mut args).no_pager);
+        let mut args = tokenize("grep '--no-pager'", false).unwrap();
+        assert!(!Redirection::split(&mut args).no_pager);
+        assert_eq!(args.len(), 2);
+    }
+
+    #[cfg(feature = "terminal")]
+    #[test]
+    fn lines_on_screen() {
+        assert_eq!(screen_lines("", 80), 0);
+        assert_eq!(screen_lines("a\n\nb\n", 80), 3);
+        assert_eq!(screen_lines(&"x".repeat(81), 80), 2);
+        assert_eq!(screen_lines("日本語", 4), 2);
+    }
+
+    #[cfg(feature = "terminal")]
+    #[test]
+    fn finish_line() {
+        let mut sink = Sink::new(Output::Writer(Box::new(Vec::new())));
+        sink.finish_line().unwrap();
+        assert!(sink.line_start);
+        write!(sink, "progress").unwrap();
+        assert!(!sink.line_start);
+        sink.finish_line().unwrap();
+        assert!(sink.line_start);
+    }
+}
@@ -0,0 +1,216 @@
+//! Expansion of `~` and environment variables in path arguments, see
+//! [`ReplBuilder::home_dir`].
+//!
+//! Like in shells, a leading `~` (alone or followed by a separator) is the home directory,
+//! and `$NAME` or `${NAME}` is the value of environment variable `NAME`, see
+//! [`subst`](crate::subst) for the other substitutions. Variables that are not set are left
+//! as they are.
+//!
+//! [`ReplBuilder::home_dir`]: crate::repl::ReplBuilder::home_dir
+
+use std::borrow::Cow;
+use std::path::{is_separator, Component, Path, PathBuf, MAIN_SEPARATOR};
+
+use crate::subst;
+
+/// Resolves the home directory, see
+/// [`ReplBuilder::home_dir`](crate::repl::ReplBuilder::home_dir).
+pub(crate) type HomeResolver = dyn Fn() -> Option<PathBuf>;
+
+/// Home directory of the user running the REPL, from `HOME` (`USERPROFILE` on Windows).
+pub(crate) fn default_home() -> Option<PathBuf> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var_os(var)
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Replace a leading `~` of `path` with the home directory given by `home`.
+pub(crate) fn expand_tilde<'a>(path: &'a str, home: &HomeResolver) -> Cow<'a, str> {
+    let Some(rest) = path.strip_prefix('~') else {
+        return Cow::Borrowed(path);
+    };
+    if !rest.is_empty() && !rest.starts_with(is_separator) {
+        // `~user` is not supported
+        return Cow::Borrowed(path);
+    }
+    match home().as_ref().and_then(|home| home.to_str()) {
+        Some(home) => Cow::Owned(format!("{home}{rest}")),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// Expand `~` and environment variables in `path`, leaving variables as they are if their
+/// expressions are not valid.
+pub(crate) fn expand<'a>(path: &'a str, home: &HomeResolver) -> Cow<'a, str> {
+    fn variables(path: &str) -> Cow<'_, str> {
+        subst::substitute(path, &subst::env).unwrap_or(Cow::Borrowed(path))
+    }
+    match expand_tilde(path, home) {
+        Cow::Borrowed(path) => variables(path),
+        Cow::Owned(path) => Cow::Owned(variables(&path).into_owned()),
+    }
+}
+
+/// `path` relative to `dir`, with `.` and `..` components removed without following
+/// symbolic links, like the `cd` of shells does.
+pub(crate) fn resolve(dir: &Path, path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in dir.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match resolved.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    resolved.pop();
+                }
+                // the parent of the root is the root
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => resolved.push(component),
+            },
+            other => resolved.push(other),
+        }
+    }
+    resolved
+}
+
+/// Directory part of `prefix` up to and including the last separator, and the rest.
+pub(crate) fn split_dir(prefix: &str) -> (&str, &str) {
+    match prefix.rfind(is_separator) {
+        Some(i) => prefix.split_at(i + 1),
+        None => ("", prefix),
+    }
+}
+
+/// Whether `path` has variables and needs no quoting otherwise, so that it can be given
+/// as it is on the command line and still have its variables expanded.
+fn is_expandable(path: &str) -> bool {
+    path.contains('$') && matches!(shell_words::quote(&path.replace('$', "")), Cow::Borrowed(_))
+}
+
+/// Directory part of a path with `~` and variables expanded, and relative to `cwd` if it
+/// is relative and there is one.
+pub(crate) fn anchor<'a>(dir: &'a str, home: &HomeResolver, cwd: Option<&Path>) -> Cow<'a, str> {
+    let expanded = expand(dir, home);
+    match cwd {
+        Some(cwd) if Path::new(expanded.as_ref()).is_relative() => {
+            let cwd = cwd.display().to_string();
+            let cwd = cwd.trim_end_matches(is_separator);
+            Cow::Owned(format!("{cwd}{MAIN_SEPARATOR}{expanded}"))
+        }
+        _ => expanded,
+    }
+}
+
+/// Complete `prefix` with `complete` after expanding its directory part and making it
+/// relative to `cwd` (see [`anchor`]), giving back the completed paths as typed, e.g.
+/// `~/notes.txt` rather than `/home/me/notes.txt`.
+///
+/// Paths with variables that could not be given back as typed without quoting them are
+/// given expanded.
+pub(crate) fn complete(
+    prefix: &str,
+    home: &HomeResolver,
+    cwd: Option<&Path>,
+    complete: impl FnOnce(&str) -> Vec<String>,
+) -> Vec<String> {
+    let (dir, file) = split_dir(prefix);
+    let anchored = anchor(dir, home, cwd);
+    if anchored == dir {
+        return complete(prefix);
+    }
+    complete(&format!("{anchored}{file}"))
+        .into_iter()
+        .map(|path| match path.strip_prefix(anchored.as_ref()) {
+            Some(rest) => {
+                let typed = format!("{dir}{rest}");
+                match !dir.contains('$') || is_expandable(&typed) {
+                    true => typed,
+                    false => path,
+                }
+            }
+            None => path,
+        })
+        .collect()
+}
+
+/// Quote a path given by [`complete`] for the command line, leaving its leading `~` and
+/// its variables unquoted so that they are expanded when the command runs.
+pub(crate) fn quote(path: &str) -> Cow<'_, str> {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(is_separator) => {
+            Cow::Owned(format!("~{}", shell_words::quote(rest)))
+        }
+        _ if is_expandable(path) => Cow::Borrowed(path),
+        _ => shell_words::quote(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expansion() {
+        let home = || Some(PathBuf::from("/home/me"));
+        assert_eq!(expand_tilde("~", &home), "/home/me");
+        assert_eq!(expand_tilde("~/a.txt", &home), "/home/me/a.txt");
+        assert_eq!(expand_tilde("~me/a.txt", &home), "~me/a.txt");
+        assert_eq!(expand_tilde("a/~", &home), "a/~");
+        assert_eq!(expand_tilde("~/a.txt", &|| None), "~/a.txt");
+
+        let dir = env!("CARGO_MANIFEST_DIR");
+        assert_eq!(
+            expand("$CARGO_MANIFEST_DIR/src", &home),
+            format!("{dir}/src")
+        );
+        assert_eq!(expand("~/$((1 +))", &home), "/home/me/$((1 +))");
+
+        let cwd = Path::new("/srv/files");
+        assert_eq!(
+            resolve(cwd, Path::new("../logs/./a")),
+            Path::new("/srv/logs/a")
+        );
+        assert_eq!(resolve(cwd, Path::new("/tmp/..")), Path::new("/"));
+        assert_eq!(
+            resolve(Path::new("a"), Path::new("../../..")),
+            Path::new("../..")
+        );
+        assert_eq!(resolve(Path::new("/"), Path::new("..")), Path::new("/"));
+    }
+
+    #[test]
+    fn completion_as_typed() {
+        let home = || Some(PathBuf::from("/home/me"));
+        let completed = complete("~/no", &home, None, |prefix| {
+            assert_eq!(prefix, "/home/me/no");
+            vec!["/home/me/notes/".to_string(), "/tmp/other".to_string()]
+        });
+        assert_eq!(completed, vec!["~/notes/", "/tmp/other"]);
+        assert_eq!(
+            complete("no", &home, None, |_| vec!["notes".into()]),
+            vec!["notes"]
+        );
+        let cwd = Path::new("/srv/files");
+        let completed = complete("docs/no", &home, Some(cwd), |prefix| {
+            assert_eq!(prefix, "/srv/files/docs/no");
+            vec!["/srv/files/docs/notes.txt".into()]
+        });
+        assert_eq!(completed, vec!["docs/notes.txt"]);
+
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let completed = complete("$CARGO_MANIFEST_DIR/s", &home, None, |prefix| {
+            vec![format!("{prefix}rc/"), format!("{prefix}ome file")]
+        });
+        assert_eq!(
+            completed,
+            vec![
+                "$CARGO_MANIFEST_DIR/src/".to_string(),
+                format!("{dir}/some file")
+            ]
+        );
+
+        assert_eq!(quote("~/my notes"), "~'/my notes'");
+        assert_eq!(quote("$HOME/notes"), "$HOME/notes");
+        assert_eq!(quote("my notes"), "'my notes'");
+    }
+}
@@ -0,0 +1,270 @@
+//! Command providers loaded from shared libraries at runtime.
+//!
+//! A plugin is a shared library accompanied by a TOML manifest describing its commands:
+//!
+//! ```toml
+//! # path relative to the manifest
+//! library = "libgreet.so"
+//!
+//! [[commands]]
+//! name = "greet"
+//! description = "Greet someone"
+//! symbol = "greet"
+//! args = [{ name = "who", type = "String" }]
+//! ```
+//!
+//! Argument types are the names used in help messages: `i32`, `f32`, `bytes`, `String` or
+//! `Custom`.
+//! Each command is implemented by a function exported with C ABI:
+//!
+//! ```c
+//! int32_t greet(size_t argc, const char *const *argv);
+//! ```
+//!
+//! Arguments are validated against the manifest before the call and passed as
+//! NUL-terminated UTF-8 strings. The function returns 0 to continue, 1 to quit the REPL
+//! and any other value to report an error.
+//!
+//! Requires the `plugins` feature.
+
+use std::ffi::{c_char, CString};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use libloading::Library;
+use serde::Deserialize;
+
+use crate::command::{
+    validate, ArgsError, Command, CommandArgInfo, CommandArgType, CommandStatus, SendExecuteCommand,
+};
+use crate::provider::CommandProvider;
+
+type PluginFn = unsafe extern "C" fn(usize, *const *const c_char) -> i32;
+
+/// Error when loading a plugin.
+#[derive(Debug, thiserror::Error)]
+pub enum PluginError {
+    /// Manifest could not be read.
+    #[error("failed to read plugin manifest '{0}': {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+    /// Manifest is not valid.
+    #[error("invalid plugin manifest: {0}")]
+    Manifest(#[from] toml::de::Error),
+    /// Argument type in the manifest is not one of the known types.
+    #[error("unknown argument type '{0}'")]
+    UnknownArgType(String),
+    /// Library could not be loaded or does not export a command symbol.
+    #[error("failed to load plugin library: {0}")]
+    Library(#[from] libloading::Error),
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    library: PathBuf,
+    #[serde(default)]
+    commands: Vec<CommandManifest>,
+}
+
+#[derive(Deserialize)]
+struct CommandManifest {
+    name: String,
+    #[serde(default)]
+    description: String,
+    symbol: String,
+    #[serde(default)]
+    args: Vec<ArgManifest>,
+}
+
+#[derive(Deserialize)]
+struct ArgManifest {
+    name: Option<String>,
+    #[serde(rename = "type")]
+    arg_type: String,
+}
+
+impl Manifest {
+    fn parse(text: &str) -> Result<Self, PluginError> {
+        let manifest: Manifest = toml::from_str(text)?;
+        for cmd in &manifest.commands {
+            for arg in &cmd.args {
+                parse_arg_type(&arg.arg_type)?;
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+fn parse_arg_type(name: &str) -> Result<CommandArgType, PluginError> {
+    [
+        CommandArgType::I32,
+        CommandArgType::F32,
+        CommandArgType::Bytes,
+        CommandArgType::String,
+        CommandArgType::Custom,
+    ]
+    .into_iter()
+    .find(|t| t.to_string() == name)
+    .ok_or_else(|| PluginError::UnknownArgType(name.into()))
+}
+
+struct PluginCommand {
+    description: String,
+    args_info: Vec<CommandArgInfo>,
+    function: PluginFn,
+}
+
+/// Commands loaded from a shared library, see the [module documentation](self).
+///
+/// Add it to a REPL with [`ReplBuilder::add_provider`](crate::repl::ReplBuilder::add_provider).
+/// The library stays loaded as long as the plugin or any of its commands exist.
+pub struct Plugin {
+    library: Arc<Library>,
+    commands: Vec<(String, Arc<PluginCommand>)>,
+}
+
+impl Plugin {
+    /// Load the plugin described by the manifest at `path`.
+    ///
+    /// All command symbols are resolved here, so a missing one is reported immediately.
+    ///
+    /// # Safety
+    ///
+    /// Loading a library runs its initialization code, and the exported functions must
+    /// match the signature described in the [module documentation](self). Both cannot be
+    /// verified, so the library has to be trusted.
+    pub unsafe fn load(path: impl AsRef<Path>) -> Result<Self, PluginError> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).map_err(|e| PluginError::Io(path.to_path_buf(), e))?;
+        let manifest = Manifest::parse(&text)?;
+        let library_path = path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(&manifest.library);
+        let library = Arc::new(Library::new(library_path)?);
+
+        let mut commands = Vec::new();
+        for cmd in manifest.commands {
+            let function = *library.get::<PluginFn>(cmd.symbol.as_bytes())?;
+            let args_info = cmd
+                .args
+                .iter()
+                .map(|arg| {
+                    let arg_type = parse_arg_type(&arg.arg_type)?;
+                    Ok(match &arg.name {
+                        Some(name) => CommandArgInfo::new_with_name(arg_type, name),
+                        None => CommandArgInfo::new(arg_type),
+                    })
+                })
+                .collect::<Result<_, PluginError>>()?;
+            let command = PluginCommand {
+                description: cmd.description,
+                args_info,
+                function,
+            };
+            commands.push((cmd.name, Arc::new(command)));
+        }
+        Ok(Self { library, commands })
+    }
+}
+
+impl CommandProvider for Plugin {
+    fn commands(&self) -> Vec<(String, Command)> {
+        self.commands
+            .iter()
+            .map(|(name, cmd)| {
+                let handler = PluginCommandHandler {
+                    _library: self.library.clone(),
+                    command: cmd.clone(),
+                };
+                let command =
+                    Command::new_send(&cmd.description, cmd.args_info.clone(), Box::new(handler));
+                (name.clone(), command)
+            })
+            .collect()
+    }
+}
+
+struct PluginCommandHandler {
+    // keeps the library loaded while the function pointer is in use
+    _library: Arc<Library>,
+    command: Arc<PluginCommand>,
+}
+
+impl PluginCommandHandler {
+    fn call(&self, args: &[&str], args_info: &[CommandArgInfo]) -> anyhow::Result<CommandStatus> {
+        validate(args, args_info)?;
+        let args = args
+            .iter()
+            .map(|arg| {
+                CString::new(*arg).map_err(|e| ArgsError::WrongArgumentValue {
+                    argument: arg.to_string(),
+                    error: e.to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let argv: Vec<*const c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
+        // SAFETY: the signature has been promised by the caller of `Plugin::load`, strings
+        // outlive the call and the library is kept loaded by `_library`
+        let code = unsafe { (self.command.function)(argv.len(), argv.as_ptr()) };
+        match code {
+            0 => Ok(CommandStatus::Done),
+            1 => Ok(CommandStatus::Quit),
+            code => Err(anyhow::anyhow!("plugin command failed with code {code}")),
+        }
+    }
+}
+
+impl SendExecuteCommand for PluginCommandHandler {
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + Send + 'a>> {
+        let result = self.call(args, args_info);
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest() {
+        let manifest = Manifest::parse(
+            r#"
+            library = "libgreet.so"
+
+            [[commands]]
+            name = "greet"
+            symbol = "greet"
+            args = [{ name = "who", type = "String" }, { type = "i32" }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(manifest.library, Path::new("libgreet.so"));
+        assert_eq!(manifest.commands[0].name, "greet");
+        assert_eq!(manifest.commands[0].args[1].arg_type, "i32");
+
+        let result = Manifest::parse(
+            r#"
+            library = "libgreet.so"
+            commands = [{ name = "x", symbol = "x", args = [{ type = "u8" }] }]
+            "#,
+        );
+        assert!(matches!(result, Err(PluginError::UnknownArgType(t)) if t == "u8"));
+        assert!(matches!(
+            Manifest::parse("commands = []"),
+            Err(PluginError::Manifest(_))
+        ));
+    }
+
+    #[test]
+    fn missing_manifest() {
+        let result = unsafe { Plugin::load("/nonexistent/plugin.toml") };
+        assert!(matches!(result, Err(PluginError::Io(..))));
+    }
+}
@@ -0,0 +1,151 @@
+//! Ordered set of names supporting fast prefix lookup.
+
+use std::collections::BTreeSet;
+use std::ops::Bound;
+
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
+
+/// Set of command names used for prefix search (prediction, hints and completion).
+///
+/// Names are kept sorted, so all names with a given prefix form a contiguous range
+/// that can be found in `O(log n)` and iterated lazily. Unlike a prebuilt trie,
+/// names can be inserted and removed one at a time, which keeps dynamic command
+/// registration cheap even for very large command sets.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct PrefixSet {
+    names: BTreeSet<String>,
+}
+
+impl PrefixSet {
+    pub(crate) fn insert(&mut self, name: &str) -> bool {
+        self.names.insert(name.to_string())
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        self.names.remove(name)
+    }
+
+    /// Iterate over all names starting with `prefix`, in lexicographic order.
+    ///
+    /// See [`starts_with`] for how names are matched.
+    pub(crate) fn predictive_search<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        self.names
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |name| name.starts_with(prefix))
+            .filter(move |name| is_boundary(name, prefix.len()))
+            .map(String::as_str)
+    }
+}
+
+/// Returns true if `s` starts with `prefix` ending at a grapheme boundary, so that e.g.
+/// `e` does not match `é` written as `e` followed by a combining accent.
+pub(crate) fn starts_with(s: &str, prefix: &str) -> bool {
+    s.starts_with(prefix) && is_boundary(s, prefix.len())
+}
+
+fn is_boundary(s: &str, index: usize) -> bool {
+    match s[index..].chars().next() {
+        // fast path, an ASCII character other than LF (which may follow CR) starts a grapheme
+        Some(c) if c.is_ascii() && c != '\n' => true,
+        None => true,
+        Some(_) => GraphemeCursor::new(index, s.len(), true)
+            .is_boundary(s, 0)
+            .unwrap_or(true),
+    }
+}
+
+/// Longest common prefix of `a` and `b` consisting of whole graphemes.
+pub(crate) fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let len = a
+        .grapheme_indices(true)
+        .zip(b.graphemes(true))
+        .find(|((_, x), y)| x != y)
+        .map_or(a.len().min(b.len()), |((i, _), _)| i);
+    &a[..len]
+}
+
+/// Number of grapheme insertions, deletions, substitutions and transpositions of adjacent
+/// graphemes needed to turn `a` into `b`.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<&str> = a.graphemes(true).collect();
+    let b: Vec<&str> = b.graphemes(true).collect();
+    // distances[i][j] between the first i graphemes of a and the first j graphemes of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn predictive_search() {
+        let mut set = PrefixSet::default();
+        for name in ["move", "make", "mo", "quit", "m"] {
+            set.insert(name);
+        }
+        let found: Vec<_> = set.predictive_search("mo").collect();
+        assert_eq!(found, vec!["mo", "move"]);
+        let found: Vec<_> = set.predictive_search("m").collect();
+        assert_eq!(found, vec!["m", "make", "mo", "move"]);
+        assert_eq!(set.predictive_search("x").count(), 0);
+    }
+
+    #[test]
+    fn graphemes() {
+        let mut set = PrefixSet::default();
+        // "é" as "e" followed by a combining acute accent
+        for name in ["e\u{301}cole", "etat", "żółw", "żaba"] {
+            set.insert(name);
+        }
+        let found: Vec<_> = set.predictive_search("e").collect();
+        assert_eq!(found, vec!["etat"]);
+        assert_eq!(set.predictive_search("e\u{301}").count(), 1);
+        assert_eq!(set.predictive_search("ż").count(), 2);
+        assert_eq!(common_prefix("żółw", "żaba"), "ż");
+        assert_eq!(common_prefix("e\u{301}cole", "etat"), "");
+        assert_eq!(common_prefix("move", "mo"), "mo");
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("status", "status"), 0);
+        assert_eq!(edit_distance("stauts", "status"), 1);
+        assert_eq!(edit_distance("stat", "status"), 2);
+        assert_eq!(edit_distance("", "ls"), 2);
+        assert_eq!(edit_distance("żółw", "zółw"), 1);
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let mut set = PrefixSet::default();
+        for i in 0..10_000 {
+            set.insert(&format!("cmd-{i}"));
+        }
+        assert_eq!(set.predictive_search("cmd-999").count(), 11);
+        assert!(set.remove("cmd-9990"));
+        assert!(!set.remove("cmd-9990"));
+        assert_eq!(set.predictive_search("cmd-999").count(), 10);
+    }
+}
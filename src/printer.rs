@@ -0,0 +1,190 @@
+//! Printing above the prompt, see [`Repl::printer`].
+//!
+//! Text written to the terminal by other threads or tasks while the user is typing a line
+//! gets mixed with the prompt and the typed text. A [`Printer`] pauses the prompt instead:
+//! it erases the prompt line, lets the application print a block of text and redraws the
+//! prompt with the typed text below it. Blocks printed with printers of the same REPL do not
+//! interleave.
+//!
+//! ```rust,no_run
+//! # use mini_async_repl::Repl;
+//! # use std::io::Write;
+//! let repl = Repl::builder().build()?;
+//! let printer = repl.printer();
+//! std::thread::spawn(move || {
+//!     printer.print("job 1 done").unwrap();
+//!     // or write several lines at once
+//!     let mut block = printer.suspend();
+//!     writeln!(block, "job 2 done")?;
+//!     writeln!(block, "job 3 done")
+//! });
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Prompts and lines spanning several terminal rows are not fully erased.
+//!
+//! Requires the `terminal` feature.
+//!
+//! [`Repl::printer`]: crate::Repl::printer
+
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use unicode_width::UnicodeWidthStr;
+
+/// Prompt and line being edited.
+#[derive(Default)]
+struct Input {
+    /// Prompt shown, or `None` while no line is being read.
+    prompt: Option<String>,
+    line: String,
+    /// Byte position of the cursor in the line.
+    pos: usize,
+}
+
+/// Handle printing above the prompt of a REPL, see the [module documentation](self).
+#[derive(Clone)]
+pub struct Printer {
+    input: Arc<Mutex<Input>>,
+    /// Whether the line editor writes to stderr instead of stdout.
+    stderr: bool,
+}
+
+impl Printer {
+    pub(crate) fn new(stderr: bool) -> Self {
+        Self {
+            input: Default::default(),
+            stderr,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Input> {
+        self.input.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether the line editor writes to a terminal.
+    pub fn is_terminal(&self) -> bool {
+        if self.stderr {
+            std::io::stderr().is_terminal()
+        } else {
+            std::io::stdout().is_terminal()
+        }
+    }
+
+    /// Record that `prompt` is shown with `line` typed, unless the line editor does not write
+    /// to a terminal.
+    pub(crate) fn show_prompt(&self, prompt: &str, line: &str) {
+        if self.is_terminal() {
+            *self.lock() = Input {
+                prompt: Some(prompt.to_string()),
+                line: line.to_string(),
+                pos: line.len(),
+            };
+        }
+    }
+
+    /// Record that the line has been edited.
+    pub(crate) fn edit(&self, line: &str, pos: usize) {
+        let mut input = self.lock();
+        if input.prompt.is_some() && (input.line != line || input.pos != pos) {
+            input.line = line.to_string();
+            input.pos = pos;
+        }
+    }
+
+    /// Record that no line is being read anymore.
+    pub(crate) fn hide_prompt(&self) {
+        *self.lock() = Input::default();
+    }
+
+    /// Erase the prompt until the returned block is dropped, so that it can be written to.
+    ///
+    /// Other printers of the same REPL wait until then.
+    pub fn suspend(&self) -> Suspended<'_> {
+        let mut block = Suspended {
+            input: self.lock(),
+            stderr: self.stderr,
+        };
+        if block.input.prompt.is_some() {
+            // go to the start of the line and clear the screen below
+            let _ = block.write_all(b"\r\x1b[J");
+        }
+        block
+    }
+
+    /// Print `text` above the prompt, adding a line break if it does not end with one.
+    pub fn print(&self, text: &str) -> std::io::Result<()> {
+        let mut block = self.suspend();
+        block.write_all(text.as_bytes())?;
+        if !text.ends_with('\n') {
+            block.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Block of output written while the prompt is erased, see [`Printer::suspend`].
+///
+/// Writes go to the stream of the line editor. The prompt is redrawn when it is dropped.
+pub struct Suspended<'a> {
+    input: MutexGuard<'a, Input>,
+    stderr: bool,
+}
+
+impl Write for Suspended<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.stderr {
+            std::io::stderr().write(buf)
+        } else {
+            std::io::stdout().write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.stderr {
+            std::io::stderr().flush()
+        } else {
+            std::io::stdout().flush()
+        }
+    }
+}
+
+impl Drop for Suspended<'_> {
+    fn drop(&mut self) {
+        let Some(prompt) = self.input.prompt.clone() else {
+            let _ = self.flush();
+            return;
+        };
+        let line = std::mem::take(&mut self.input.line);
+        let after_cursor = line.get(self.input.pos..).unwrap_or_default().width();
+        let mut redraw = format!("{prompt}{line}");
+        if after_cursor > 0 {
+            redraw.push_str(&format!("\x1b[{after_cursor}D"));
+        }
+        let _ = self.write_all(redraw.as_bytes());
+        let _ = self.flush();
+        self.input.line = line;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_input() {
+        let printer = Printer::new(true);
+        printer.edit("ignored", 1);
+        assert!(printer.lock().line.is_empty());
+        *printer.lock() = Input {
+            prompt: Some("> ".into()),
+            line: "typed".into(),
+            pos: 5,
+        };
+        printer.edit("typed more", 5);
+        assert_eq!(printer.lock().line, "typed more");
+        assert_eq!(printer.lock().pos, 5);
+        printer.hide_prompt();
+        assert!(printer.lock().prompt.is_none());
+    }
+}
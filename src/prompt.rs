@@ -0,0 +1,127 @@
+//! Placeholders of the prompt, see [`ReplBuilder::prompt`].
+//!
+//! The prompt can contain placeholders replaced before each line is read, so that it shows
+//! the state of the session when relevant:
+//!
+//! - `{mode}`: the mode set with [`Repl::set_mode`] in brackets, e.g. `[cfg]`,
+//! - `{jobs}`: the number of background jobs tracked with [`Repl::jobs`], e.g. `(2 jobs)`,
+//! - `{continuation}`: `...` when reading the continuation of a line ending with a
+//!   backslash.
+//!
+//! Placeholders are replaced with nothing when not relevant, e.g. `{mode}db{continuation}> `
+//! shows `db> `, `[cfg]db> ` or `db...> `. Other text in braces is shown as is.
+//!
+//! [`ReplBuilder::prompt`]: crate::repl::ReplBuilder::prompt
+//! [`Repl::set_mode`]: crate::Repl::set_mode
+//! [`Repl::jobs`]: crate::Repl::jobs
+
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Counter of the background jobs of a session, shown by the `{jobs}` placeholder.
+///
+/// Clones share the same count, so it can be moved to the tasks running the jobs.
+#[derive(Debug, Clone, Default)]
+pub struct Jobs(Arc<AtomicUsize>);
+
+impl Jobs {
+    /// Count a job as running until the returned guard is dropped.
+    pub fn start(&self) -> Job {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Job(self.0.clone())
+    }
+
+    /// Number of jobs running.
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Running job, see [`Jobs::start`].
+#[derive(Debug)]
+pub struct Job(Arc<AtomicUsize>);
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// State shown by the placeholders.
+pub(crate) struct PromptState<'a> {
+    pub(crate) mode: Option<&'a str>,
+    pub(crate) jobs: usize,
+    pub(crate) continuation: bool,
+}
+
+/// Replace the placeholders of `template`, see the [module documentation](self).
+pub(crate) fn render<'a>(template: &'a str, state: &PromptState) -> Cow<'a, str> {
+    if !template.contains('{') {
+        return Cow::Borrowed(template);
+    }
+    let mode = state.mode.map(|mode| format!("[{mode}]"));
+    let jobs = match state.jobs {
+        0 => String::new(),
+        1 => "(1 job)".to_string(),
+        n => format!("({n} jobs)"),
+    };
+    let continuation = if state.continuation { "..." } else { "" };
+    Cow::Owned(
+        template
+            .replace("{mode}", mode.as_deref().unwrap_or_default())
+            .replace("{jobs}", &jobs)
+            .replace("{continuation}", continuation),
+    )
+}
+
+/// Whether `line` ends with a backslash escaping the line break, so that the command goes on
+/// in the next line.
+#[cfg(feature = "terminal")]
+pub(crate) fn continues(line: &str) -> bool {
+    // backslashes are not escapes on Windows, see `split_args`
+    let trailing = line.len() - line.trim_end_matches('\\').len();
+    !cfg!(windows) && trailing % 2 == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders() {
+        let state = |mode, jobs, continuation| PromptState {
+            mode,
+            jobs,
+            continuation,
+        };
+        let template = "{mode}db{jobs}{continuation}> ";
+        assert_eq!(render(template, &state(None, 0, false)), "db> ");
+        assert_eq!(
+            render(template, &state(Some("cfg"), 1, false)),
+            "[cfg]db(1 job)> "
+        );
+        assert_eq!(render(template, &state(None, 2, true)), "db(2 jobs)...> ");
+        assert_eq!(render("{other}> ", &state(None, 0, true)), "{other}> ");
+        assert!(matches!(
+            render("> ", &state(None, 0, false)),
+            Cow::Borrowed(_)
+        ));
+
+        let jobs = Jobs::default();
+        let job = jobs.start();
+        let other = jobs.clone().start();
+        assert_eq!(jobs.count(), 2);
+        drop((job, other));
+        assert_eq!(jobs.count(), 0);
+    }
+
+    #[cfg(all(feature = "terminal", not(windows)))]
+    #[test]
+    fn continuation() {
+        assert!(continues("say a \\"));
+        assert!(!continues("say a \\\\"));
+        assert!(continues("say a \\\\\\"));
+        assert!(!continues("say a"));
+    }
+}
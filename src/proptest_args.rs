@@ -0,0 +1,157 @@
+//! [`proptest`] strategies generating arguments of commands, to check that handlers do not
+//! panic on any arguments the validation lets through.
+//!
+//! [`valid_args`] generates one value of the declared type per argument, any `i32` or
+//! `f32` (including `NaN` and infinities), any size in bytes and any string, and [`invalid_args`] arguments
+//! rejected by the type checks, with a wrong number of arguments or a number that does not
+//! parse. [`accepts`] filters out arguments rejected by the
+//! [validators](crate::command::Command::validator) of a command:
+//!
+//! ```rust
+//! use mini_async_repl::command::{Command, CommandArgInfo, CommandArgType, TrivialCommandHandler};
+//! use mini_async_repl::proptest_args::{accepts, valid_args};
+//! use proptest::prelude::*;
+//!
+//! let args_info = vec![
+//!     CommandArgInfo::new_with_name(CommandArgType::I32, "start"),
+//!     CommandArgInfo::new_with_name(CommandArgType::I32, "end"),
+//! ];
+//! let range = || {
+//!     Command::new("Range", args_info.clone(), Box::new(TrivialCommandHandler::new()))
+//!         .check_args(|start: i32, end: i32| start <= end, "START must not be after END")
+//! };
+//! proptest!(|(args in valid_args(&args_info))| {
+//!     let args: Vec<&str> = args.iter().map(String::as_str).collect();
+//!     let mut range = range();
+//!     prop_assume!(accepts(&range, &args));
+//!     let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+//!     runtime.block_on(range.execute(&args)).unwrap();
+//! });
+//! ```
+//!
+//! Requires the `proptest` feature.
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::select;
+use proptest::strategy::{BoxedStrategy, Union};
+
+use crate::bytes;
+use crate::command::{Command, CommandArgInfo, CommandArgType};
+
+/// Values of arguments of type `arg_type`, as they would be typed.
+pub fn arg_value(arg_type: CommandArgType) -> BoxedStrategy<String> {
+    match arg_type {
+        CommandArgType::I32 => any::<i32>().prop_map(|n| n.to_string()).boxed(),
+        CommandArgType::F32 => any::<f32>().prop_map(|x| x.to_string()).boxed(),
+        CommandArgType::Bytes => prop_oneof![
+            any::<u64>().prop_map(|n| n.to_string()),
+            any::<u64>().prop_map(bytes::format),
+        ]
+        .boxed(),
+        CommandArgType::String | CommandArgType::Custom => any::<String>().boxed(),
+    }
+}
+
+/// Arguments passing the type checks of a command taking `args_info`.
+pub fn valid_args(args_info: &[CommandArgInfo]) -> BoxedStrategy<Vec<String>> {
+    let values: Vec<_> = args_info
+        .iter()
+        .map(|info| arg_value(info.arg_type))
+        .collect();
+    values.boxed()
+}
+
+/// Values that do not parse as `arg_type`, for numeric types.
+fn not_a_number(arg_type: CommandArgType) -> BoxedStrategy<String> {
+    any::<String>()
+        .prop_filter("value parses", move |value| match arg_type {
+            CommandArgType::I32 => value.parse::<i32>().is_err(),
+            CommandArgType::Bytes => bytes::parse(value).is_err(),
+            _ => value.parse::<f32>().is_err(),
+        })
+        .boxed()
+}
+
+/// Arguments failing the type checks of a command taking `args_info`: too few or too many
+/// of them, or a numeric argument that does not parse.
+pub fn invalid_args(args_info: &[CommandArgInfo]) -> BoxedStrategy<Vec<String>> {
+    let len = args_info.len();
+    let mut strategies = vec![vec(any::<String>(), 0..=len + 2)
+        .prop_filter("right number of arguments", move |args| args.len() != len)
+        .boxed()];
+    let numeric: Vec<(usize, CommandArgType)> = args_info
+        .iter()
+        .map(|info| info.arg_type)
+        .enumerate()
+        .filter(|(_, arg_type)| {
+            matches!(
+                arg_type,
+                CommandArgType::I32 | CommandArgType::F32 | CommandArgType::Bytes
+            )
+        })
+        .collect();
+    if !numeric.is_empty() {
+        let bad_value = select(numeric)
+            .prop_flat_map(|(index, arg_type)| (Just(index), not_a_number(arg_type)));
+        let strategy = (valid_args(args_info), bad_value).prop_map(|(mut args, (index, bad))| {
+            args[index] = bad;
+            args
+        });
+        strategies.push(strategy.boxed());
+    }
+    Union::new(strategies).boxed()
+}
+
+/// Returns true if `cmd` accepts `args`: they pass the type checks and the validators of
+/// the command, so its handler is called with them.
+pub fn accepts(cmd: &Command, args: &[&str]) -> bool {
+    cmd.validate_args(args).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{validate, TrivialCommandHandler};
+
+    fn args_info() -> Vec<CommandArgInfo> {
+        [
+            CommandArgType::I32,
+            CommandArgType::F32,
+            CommandArgType::Bytes,
+            CommandArgType::String,
+            CommandArgType::Custom,
+        ]
+        .into_iter()
+        .map(CommandArgInfo::new)
+        .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn generated_args(valid in valid_args(&args_info()), invalid in invalid_args(&args_info())) {
+            let valid: Vec<&str> = valid.iter().map(String::as_str).collect();
+            prop_assert!(validate(&valid, &args_info()).is_ok());
+            let invalid: Vec<&str> = invalid.iter().map(String::as_str).collect();
+            prop_assert!(validate(&invalid, &args_info()).is_err());
+        }
+
+        #[test]
+        fn no_args(invalid in invalid_args(&[])) {
+            prop_assert!(!invalid.is_empty());
+        }
+    }
+
+    #[test]
+    fn accepted_args() {
+        let cmd = Command::new(
+            "Range",
+            vec![CommandArgInfo::new(CommandArgType::I32); 2],
+            Box::new(TrivialCommandHandler::new()),
+        )
+        .check_args(|start: i32, end: i32| start <= end, "start after end");
+        assert!(accepts(&cmd, &["1", "2"]));
+        assert!(!accepts(&cmd, &["2", "1"]));
+        assert!(!accepts(&cmd, &["x", "1"]));
+    }
+}
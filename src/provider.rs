@@ -0,0 +1,27 @@
+//! Reusable packs of commands.
+
+use crate::command::Command;
+
+/// Source of a group of related commands that can be added to a REPL with one call.
+///
+/// This allows distributing commands (e.g. filesystem or debugging commands) as a library
+/// and composing them with [`ReplBuilder::add_provider`](crate::repl::ReplBuilder::add_provider)
+/// or [`CommandRegistry::add_provider`](crate::registry::CommandRegistry::add_provider).
+/// Commands are created on each call, so the same provider can populate multiple registries,
+/// and it can provide different commands when queried again by
+/// [`CommandRegistry::reload_providers`](crate::registry::CommandRegistry::reload_providers).
+///
+/// Any closure returning the commands is also a provider.
+pub trait CommandProvider: Send + Sync {
+    /// Names and commands to add. Multiple entries with the same name become overloads.
+    fn commands(&self) -> Vec<(String, Command)>;
+}
+
+impl<F> CommandProvider for F
+where
+    F: Fn() -> Vec<(String, Command)> + Send + Sync,
+{
+    fn commands(&self) -> Vec<(String, Command)> {
+        self()
+    }
+}
@@ -0,0 +1,935 @@
+//! Command registry that can be shared between REPL sessions.
+//!
+//! Command names can be grouped into namespaces with `.` or `:` separators, e.g.
+//! `db.migrate` and `db.seed` are in namespace `db`. Completion offers one segment at a time
+//! and `help db` lists the commands of the namespace. A name cannot be both a command and a
+//! namespace.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::Duration;
+
+use futures::lock::Mutex as AsyncMutex;
+
+use crate::clock::Clock;
+use crate::command::{
+    bind_named, start_cooldown, ArgsError, Command, CommandArgInfo, CommandStatus, SessionChange,
+};
+use crate::output::Sink;
+use crate::prefix::{common_prefix, PrefixSet};
+use crate::provider::CommandProvider;
+use crate::repl::{split_args, BuilderError, RESERVED};
+use crate::runtime::Runtime;
+
+/// Provider with the names of the commands it added.
+pub(crate) type Provided = (Arc<dyn CommandProvider>, Vec<String>);
+
+/// Error of running command `name` during its [cooldown](Command::cooldown), with the
+/// `remaining` time rounded up to seconds.
+pub(crate) fn cooldown_error(name: &str, remaining: Duration) -> anyhow::Error {
+    let secs = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+    anyhow::anyhow!("'{name}' was run recently, try again in {secs}s")
+}
+
+/// Characters separating namespaces in command names.
+pub(crate) const NAMESPACE_SEPARATORS: [char; 2] = ['.', ':'];
+
+/// Namespaces containing command `name`, from the outermost, e.g. `a` and `a.b` for `a.b.c`.
+fn namespaces(name: &str) -> impl Iterator<Item = &str> {
+    name.match_indices(NAMESPACE_SEPARATORS)
+        .map(move |(i, _)| &name[..i])
+}
+
+/// Returns true if command `name` is in `namespace`, directly or in a nested namespace.
+pub(crate) fn in_namespace(name: &str, namespace: &str) -> bool {
+    name.strip_prefix(namespace)
+        .is_some_and(|rest| rest.starts_with(NAMESPACE_SEPARATORS))
+}
+
+/// Returns true if the separators of `name` are valid: each segment is non-empty and the
+/// same separator is used throughout.
+fn valid_namespaces(name: &str) -> bool {
+    let mut separators = name.matches(NAMESPACE_SEPARATORS);
+    let first = separators.next();
+    separators.all(|sep| Some(sep) == first)
+        && name
+            .split(NAMESPACE_SEPARATORS)
+            .all(|segment| !segment.is_empty())
+}
+
+/// Command names matching a prefix, see [`CommandRegistry::candidates`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Candidates {
+    /// Matching names in lexicographic order, at most `limit` of them.
+    pub names: Vec<String>,
+    /// Number of matching names that did not fit within the limit.
+    pub remaining: usize,
+    /// Longest prefix shared by all matching names, including the remaining ones.
+    pub common_prefix: String,
+}
+
+/// Metadata of a command with all its overloads, see [`CommandRegistry::infos`].
+#[derive(Clone)]
+pub struct CommandInfo {
+    /// Name used to call the command.
+    pub name: String,
+    /// Abbreviations of the name, see [`Command::abbreviation`].
+    pub abbreviations: Vec<String>,
+    /// Overloads in the order they have been added.
+    pub overloads: Vec<OverloadInfo>,
+}
+
+/// Metadata of a single command overload.
+#[derive(Clone)]
+pub struct OverloadInfo {
+    /// Names and types of arguments.
+    pub args: Vec<CommandArgInfo>,
+    /// Description shown in help.
+    pub description: String,
+    /// Category set with [`Command::category`].
+    pub category: Option<String>,
+    /// Set with [`Command::hidden`].
+    pub hidden: bool,
+    /// Deprecation note set with [`Command::deprecated`].
+    pub deprecated: Option<String>,
+    /// Link to the documentation set with [`Command::docs_url`].
+    pub docs_url: Option<String>,
+}
+
+impl OverloadInfo {
+    /// Arguments formatted as in usage messages, e.g. `x:i32 y:i32`.
+    pub fn signature(&self) -> String {
+        self.args
+            .iter()
+            .map(|info| info.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl From<&Command> for OverloadInfo {
+    fn from(cmd: &Command) -> Self {
+        Self {
+            args: cmd.args_info.clone(),
+            description: cmd.description.clone(),
+            category: cmd.category.clone(),
+            hidden: cmd.hidden,
+            deprecated: cmd.deprecated.clone(),
+            docs_url: cmd.docs_url.clone(),
+        }
+    }
+}
+
+/// Command overload together with arguments bound to it, see [`CommandRegistry::resolve`].
+pub(crate) type BoundOverload<'a> = (Arc<Command>, Vec<&'a str>);
+
+/// All the commands of a REPL together with the structures used for name lookup.
+///
+/// Registry is created by [`ReplBuilder::build`](crate::repl::ReplBuilder::build) or,
+/// when it should be shared between multiple sessions, by
+/// [`ReplBuilder::build_registry`](crate::repl::ReplBuilder::build_registry).
+/// Each session is a separate [`Repl`](crate::Repl) with its own editor, prompt and
+/// output, built by passing the registry to
+/// [`ReplBuilder::registry`](crate::repl::ReplBuilder::registry). The registry is
+/// [`Send`] and [`Sync`], so sessions can run on different threads, e.g. one per remote
+/// connection.
+///
+/// Commands can be added and removed at runtime with [`CommandRegistry::add`] and
+/// [`CommandRegistry::remove`]; the changes are visible to all sessions sharing the
+/// registry starting with the next line they handle. Command handlers are locked for
+/// the duration of their execution, so when two sessions run the same command
+/// concurrently, the second one waits until the first one finishes. The same applies to
+/// different commands of a [mutex group](Command::mutex_group).
+///
+/// Providers added with [`CommandRegistry::register_provider`] (or
+/// [`ReplBuilder::add_provider`](crate::repl::ReplBuilder::add_provider)) are kept, so that
+/// [`CommandRegistry::reload_providers`] can query them again, e.g. to pick up new plugins.
+pub struct CommandRegistry {
+    /// Current commands, replaced as a whole on changes so that lookups work on a
+    /// consistent snapshot without holding the lock.
+    state: RwLock<Arc<State>>,
+    generation: AtomicU64,
+    runtime: Arc<dyn Runtime>,
+    /// Providers that can be reloaded.
+    providers: Mutex<Vec<Provided>>,
+}
+
+/// Commands of a registry together with the structures used for name lookup.
+#[derive(Clone, Default)]
+struct State {
+    commands: HashMap<String, Vec<Arc<Command>>>,
+    names: PrefixSet,
+    /// Full command names by abbreviation, see [`Command::abbreviation`].
+    abbreviations: HashMap<String, String>,
+    /// Locks of mutex groups by name, see [`Command::mutex_group`].
+    groups: HashMap<String, Arc<AsyncMutex<()>>>,
+}
+
+impl State {
+    /// Remove command `name` with all of its overloads, if it exists.
+    fn remove(&mut self, name: &str) {
+        self.commands.remove(name);
+        self.names.remove(name);
+        self.abbreviations.retain(|_, full| full != name);
+    }
+}
+
+/// Part of the commands of a registry at some point, unaffected by later changes.
+pub(crate) struct Snapshot<T> {
+    state: Arc<State>,
+    part: fn(&State) -> &T,
+}
+
+impl<T> Deref for Snapshot<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        (self.part)(&self.state)
+    }
+}
+
+impl CommandRegistry {
+    pub(crate) fn new(
+        entries: Vec<(String, Command)>,
+        runtime: Arc<dyn Runtime>,
+    ) -> Result<Self, BuilderError> {
+        let mut names = PrefixSet::default();
+        for (name, _) in RESERVED.iter() {
+            names.insert(name);
+        }
+        let registry = Self {
+            state: RwLock::new(Arc::new(State {
+                names,
+                ..Default::default()
+            })),
+            generation: AtomicU64::new(0),
+            runtime,
+            providers: Default::default(),
+        };
+        for (name, cmd) in entries {
+            registry.add(&name, cmd)?;
+        }
+        Ok(registry)
+    }
+
+    /// Add a command with given `name`, as an overload if the name already exists.
+    ///
+    /// See [`CommandRegistry::overloads`] for the rules used to choose between overloads.
+    ///
+    /// Returns the same errors as [`ReplBuilder::build`](crate::repl::ReplBuilder::build).
+    pub fn add(&self, name: &str, cmd: Command) -> Result<(), BuilderError> {
+        self.insert(name, cmd).map(drop)
+    }
+
+    /// Like [`CommandRegistry::add`], returning the added overload so that exactly this one
+    /// can be removed later with [`CommandRegistry::remove_overload`].
+    pub(crate) fn insert(&self, name: &str, cmd: Command) -> Result<Arc<Command>, BuilderError> {
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        // copies the commands only while a snapshot of them is in use
+        let cmd = self.insert_into(Arc::make_mut(&mut state), name, cmd)?;
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        Ok(cmd)
+    }
+
+    /// Add `cmd` to the commands of `state`, see [`CommandRegistry::insert`].
+    fn insert_into(
+        &self,
+        state: &mut State,
+        name: &str,
+        mut cmd: Command,
+    ) -> Result<Arc<Command>, BuilderError> {
+        let args = split_args(name).map_err(|_e| BuilderError::InvalidName(name.into()))?;
+        if args.len() != 1 || name.is_empty() || !valid_namespaces(name) {
+            return Err(BuilderError::InvalidName(name.into()));
+        } else if RESERVED.iter().any(|(n, _)| *n == name) {
+            return Err(BuilderError::ReservedName(name.into()));
+        }
+        let State {
+            commands,
+            names,
+            abbreviations,
+            groups,
+        } = state;
+        if abbreviations.contains_key(name) {
+            return Err(BuilderError::AbbreviationConflict(name.into()));
+        }
+        if commands.keys().any(|other| in_namespace(other, name))
+            || namespaces(name).any(|namespace| commands.contains_key(namespace))
+        {
+            return Err(BuilderError::NamespaceConflict(name.into()));
+        }
+        for abbreviation in &cmd.abbreviations {
+            let taken = abbreviation.is_empty()
+                || abbreviation == name
+                || RESERVED.iter().any(|(n, _)| n == abbreviation)
+                || commands.contains_key(abbreviation)
+                || abbreviations
+                    .get(abbreviation)
+                    .is_some_and(|full| full != name);
+            if taken {
+                return Err(BuilderError::AbbreviationConflict(abbreviation.clone()));
+            }
+        }
+        let cmds = commands.entry(name.into()).or_default();
+        if cmds
+            .iter()
+            .any(|c| c.arg_types() == cmd.arg_types() && c.raw_args == cmd.raw_args)
+        {
+            return Err(BuilderError::DuplicateCommands(name.into()));
+        }
+        cmd.set_runtime(&self.runtime);
+        if let Some(group) = &mut cmd.mutex_group {
+            group.lock = groups.entry(group.name.clone()).or_default().clone();
+        }
+        for abbreviation in &cmd.abbreviations {
+            abbreviations.insert(abbreviation.clone(), name.into());
+        }
+        let cmd = Arc::new(cmd);
+        cmds.push(cmd.clone());
+        names.insert(name);
+        Ok(cmd)
+    }
+
+    /// Add all commands from a [`CommandProvider`].
+    ///
+    /// Stops at the first command that cannot be added; commands added before it are kept.
+    pub fn add_provider<P: CommandProvider + ?Sized>(
+        &self,
+        provider: &P,
+    ) -> Result<(), BuilderError> {
+        for (name, cmd) in provider.commands() {
+            self.add(&name, cmd)?;
+        }
+        Ok(())
+    }
+
+    /// Add all commands from `provider` and keep it, so that they can be replaced with the
+    /// ones it provides later by [`CommandRegistry::reload_providers`].
+    ///
+    /// Nothing is added if any of the commands cannot be added.
+    pub fn register_provider<P: CommandProvider + 'static>(
+        &self,
+        provider: P,
+    ) -> Result<(), BuilderError> {
+        let provider: Arc<dyn CommandProvider> = Arc::new(provider);
+        let mut providers = self.providers();
+        let entries = provider.commands();
+        let names = self.update(|state| self.add_all(state, entries))?;
+        providers.push((provider, names));
+        Ok(())
+    }
+
+    /// Track `provider`, whose commands named `names` have already been added.
+    pub(crate) fn track_provider(&self, provider: Arc<dyn CommandProvider>, names: Vec<String>) {
+        self.providers().push((provider, names));
+    }
+
+    fn providers(&self) -> std::sync::MutexGuard<'_, Vec<Provided>> {
+        self.providers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Query the registered providers again, replacing the commands they added before with
+    /// the ones they provide now.
+    ///
+    /// The commands are swapped all at once: if any of them cannot be added, the error is
+    /// returned and the registry is left unchanged. Executions that are already running
+    /// are not affected.
+    pub fn reload_providers(&self) -> Result<(), BuilderError> {
+        let mut current = self.providers();
+        // queried before locking the commands, which providers may look at
+        let entries: Vec<_> = current
+            .iter()
+            .map(|(provider, _)| provider.commands())
+            .collect();
+        let names = self.update(|state| {
+            for (_, names) in current.iter() {
+                for name in names {
+                    state.remove(name);
+                }
+            }
+            entries
+                .into_iter()
+                .map(|entries| self.add_all(state, entries))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        for ((_, old), new) in current.iter_mut().zip(names) {
+            *old = new;
+        }
+        Ok(())
+    }
+
+    /// Apply `change` to a copy of the commands and replace them with it if it succeeds.
+    ///
+    /// The lock is held throughout, so that commands added or removed concurrently are not
+    /// lost.
+    fn update<R>(
+        &self,
+        change: impl FnOnce(&mut State) -> Result<R, BuilderError>,
+    ) -> Result<R, BuilderError> {
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        let mut next = State::clone(&state);
+        let result = change(&mut next)?;
+        *state = Arc::new(next);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        Ok(result)
+    }
+
+    /// Current commands.
+    fn state(&self) -> Arc<State> {
+        self.state
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Add `entries` to `state`, returning their distinct names.
+    fn add_all(
+        &self,
+        state: &mut State,
+        entries: Vec<(String, Command)>,
+    ) -> Result<Vec<String>, BuilderError> {
+        let mut names = Vec::new();
+        for (name, cmd) in entries {
+            self.insert_into(state, &name, cmd)?;
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    /// Remove command with given `name` (all of its overloads).
+    ///
+    /// Returns false if there was no such command. Executions that are already
+    /// running are not affected.
+    pub fn remove(&self, name: &str) -> bool {
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        if !state.commands.contains_key(name) {
+            return false;
+        }
+        Arc::make_mut(&mut state).remove(name);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Remove overload `cmd` of command `name` added by [`CommandRegistry::insert`], keeping
+    /// the other overloads of the name.
+    ///
+    /// Returns false if it has already been removed, e.g. together with the whole command.
+    pub(crate) fn remove_overload(&self, name: &str, cmd: &Arc<Command>) -> bool {
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        let found = state
+            .commands
+            .get(name)
+            .is_some_and(|cmds| cmds.iter().any(|c| Arc::ptr_eq(c, cmd)));
+        if !found {
+            return false;
+        }
+        let state = Arc::make_mut(&mut state);
+        let cmds = state.commands.get_mut(name).expect("command exists");
+        cmds.retain(|c| !Arc::ptr_eq(c, cmd));
+        if cmds.is_empty() {
+            state.commands.remove(name);
+            state.names.remove(name);
+        }
+        let kept = state.commands.get(name);
+        state.abbreviations.retain(|abbreviation, full| {
+            full != name
+                || kept
+                    .is_some_and(|cmds| cmds.iter().any(|c| c.abbreviations.contains(abbreviation)))
+        });
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Returns true if there is a command with given `name` (excluding [`RESERVED`] commands).
+    pub fn contains(&self, name: &str) -> bool {
+        self.state().commands.contains_key(name)
+    }
+
+    /// Number of distinct command names (excluding [`RESERVED`] commands).
+    pub fn len(&self) -> usize {
+        self.state().commands.len()
+    }
+
+    /// Returns true if no commands have been added.
+    pub fn is_empty(&self) -> bool {
+        self.state().commands.is_empty()
+    }
+
+    /// Names of commands (including [`RESERVED`] ones) starting with `prefix`.
+    ///
+    /// At most `limit` names are collected; the rest are only counted, so this stays
+    /// cheap even with thousands of matching commands. Empty prefix matches nothing.
+    pub fn candidates(&self, prefix: &str, limit: Option<usize>) -> Candidates {
+        self.matching(prefix, limit, |_| true)
+    }
+
+    /// Like [`CommandRegistry::candidates`], but without commands hidden from completion
+    /// by their [preconditions](Command::precondition).
+    pub(crate) fn offered_candidates(&self, prefix: &str, limit: Option<usize>) -> Candidates {
+        self.matching(prefix, limit, |name| self.is_offered(name))
+    }
+
+    /// Names starting with `prefix` for which `filter` returns true, see
+    /// [`CommandRegistry::candidates`].
+    fn matching(
+        &self,
+        prefix: &str,
+        limit: Option<usize>,
+        filter: impl Fn(&str) -> bool,
+    ) -> Candidates {
+        let mut candidates = Candidates::default();
+        if prefix.is_empty() {
+            return candidates;
+        }
+        let names = self.names();
+        let limit = limit.unwrap_or(usize::MAX);
+        // names are sorted, so the common prefix of all is the one of the first and the last
+        let mut first_last: Option<(&str, &str)> = None;
+        for name in names.predictive_search(prefix).filter(|name| filter(name)) {
+            first_last = Some((first_last.map_or(name, |(first, _)| first), name));
+            if candidates.names.len() < limit {
+                candidates.names.push(name.into());
+            } else {
+                candidates.remaining += 1;
+            }
+        }
+        if let Some((first, last)) = first_last {
+            candidates.common_prefix = common_prefix(first, last).into();
+        }
+        candidates
+    }
+
+    /// Whether command `name` is completed, i.e. unless all of its overloads are hidden by
+    /// their [preconditions](Command::precondition). [`RESERVED`] commands always are.
+    pub(crate) fn is_offered(&self, name: &str) -> bool {
+        match self.state().commands.get(name) {
+            Some(cmds) => cmds.iter().any(|cmd| cmd.is_offered()),
+            None => true,
+        }
+    }
+
+    /// Explanation of the failed [precondition](Command::precondition) of command `name`,
+    /// if none of its overloads can run.
+    pub(crate) fn unavailable(&self, name: &str) -> Option<String> {
+        let state = self.state();
+        let cmds = state.commands.get(name)?;
+        let mut unmet = cmds.iter().map(|cmd| cmd.unmet_precondition());
+        let first = unmet.next()??;
+        unmet
+            .all(|precondition| precondition.is_some())
+            .then(|| first.explanation().to_string())
+    }
+
+    /// Counter incremented on every change, used to invalidate derived data (e.g. help).
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Metadata of all commands (excluding [`RESERVED`] ones), sorted by name.
+    pub fn infos(&self) -> Vec<CommandInfo> {
+        let mut infos: Vec<_> = self
+            .state()
+            .commands
+            .iter()
+            .map(|(name, cmds)| CommandInfo {
+                name: name.clone(),
+                abbreviations: self.abbreviations_of(name),
+                overloads: cmds
+                    .iter()
+                    .map(|cmd| OverloadInfo::from(cmd.as_ref()))
+                    .collect(),
+            })
+            .collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+
+    /// Overloads of command `name` that accept `args`, in the order they should be tried.
+    ///
+    /// Resolution rules:
+    /// 1. Arguments given as `key=value` are moved to the place of the argument named `key`
+    ///    (see [`bind_named`]) for each overload separately.
+    /// 2. Only overloads with the same number of arguments as given and whose
+    ///    [`i32`](crate::command::CommandArgType::I32),
+    ///    [`f32`](crate::command::CommandArgType::F32) and
+    ///    [`bytes`](crate::command::CommandArgType::Bytes) arguments parse, and whose
+    ///    [validators](Command::validator) accept the arguments, are considered.
+    /// 3. The most specific overload comes first. Each argument contributes to specificity
+    ///    depending on its type: `i32` > `f32` = `bytes` > `Custom` > `String`, summed over all
+    ///    arguments.
+    /// 4. Overloads with equal specificity keep the order in which they have been added.
+    /// 5. Overloads parsing their own arguments (see [`Command::raw_args`]) accept any
+    ///    arguments and come after all the others.
+    ///
+    /// The REPL runs the first overload and, only if its handler fails with [`ArgsError`]
+    /// (e.g. a `Custom` argument could not be parsed), falls back to the next one.
+    /// Adding an overload can therefore only take over the inputs for which it is more
+    /// specific than the existing ones.
+    ///
+    /// If no overload accepts `args`, returns the validation error of the first overload
+    /// with a matching number of arguments, or of the first overload otherwise.
+    pub fn overloads(&self, name: &str, args: &[&str]) -> Result<Vec<Arc<Command>>, ArgsError> {
+        let overloads = self.resolve(name, args)?;
+        Ok(overloads.into_iter().map(|(cmd, _)| cmd).collect())
+    }
+
+    /// Same as [`CommandRegistry::overloads`], together with the arguments bound for each.
+    pub(crate) fn resolve<'a>(
+        &self,
+        name: &str,
+        args: &[&'a str],
+    ) -> Result<Vec<BoundOverload<'a>>, ArgsError> {
+        let cmds = self.get(name);
+        let mut accepted = Vec::new();
+        let mut first_err = None;
+        let mut arity_err = None;
+        for cmd in cmds {
+            if cmd.raw_args {
+                accepted.push((cmd, args.to_vec()));
+                continue;
+            }
+            match bind_named(args, &cmd.args_info)
+                .and_then(|bound| cmd.validate_args(&bound).map(|_| bound))
+            {
+                Ok(bound) => accepted.push((cmd, bound)),
+                Err(
+                    err @ (ArgsError::WrongNumberOfArguments { .. }
+                    | ArgsError::UnexpectedToken { .. }),
+                ) => {
+                    arity_err.get_or_insert(err);
+                }
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        if accepted.is_empty() {
+            // an overload with the right arity explains the failure better than the others
+            return Err(first_err.or(arity_err).unwrap_or(ArgsError::NoVariantFound));
+        }
+        // stable sort keeps registration order among equally specific overloads
+        accepted.sort_by_key(|(cmd, _)| {
+            let specificity: u32 = cmd
+                .args_info
+                .iter()
+                .map(|info| info.arg_type.specificity() as u32)
+                .sum();
+            (cmd.raw_args, std::cmp::Reverse(specificity))
+        });
+        Ok(accepted)
+    }
+
+    /// Run the overload of command `name` accepting `args`, writing to `out`, for both the
+    /// lines of a REPL and of a batch.
+    ///
+    /// Overloads are tried in the order of [`CommandRegistry::resolve`], skipping the ones
+    /// whose [preconditions](Command::precondition) do not hold or whose handler rejects
+    /// the arguments. Deprecated overloads print a warning, transient errors are retried
+    /// (see [`Command::retry`]) and the [cooldown](Command::cooldown) of the command starts
+    /// when an overload runs.
+    pub(crate) async fn execute(
+        &self,
+        name: &str,
+        args: &[&str],
+        out: &mut Sink,
+        cwd: Option<&Path>,
+        changes: &mut Vec<SessionChange>,
+        clock: &Arc<dyn Clock>,
+    ) -> anyhow::Result<CommandStatus> {
+        let cmds = self.get(name);
+        let mut cooldown = None;
+        let mut last_arg_err = None;
+        for (cmd, args) in self.resolve(name, args)?.iter() {
+            // overloads accepting the arguments may still be unavailable
+            if let Some(precondition) = cmd.unmet_precondition() {
+                last_arg_err = Some(anyhow::anyhow!("{}", precondition.explanation()));
+                continue;
+            }
+            if cooldown.is_none() {
+                cooldown = Some(
+                    start_cooldown(&cmds, &**clock)
+                        .map_err(|remaining| cooldown_error(name, remaining))?,
+                );
+            }
+            if let Some(note) = &cmd.deprecated {
+                writeln!(out, "Warning: '{name}' is deprecated: {note}")?;
+            }
+            match cmd.execute_retrying(args, out, cwd, changes, clock).await {
+                Err(err) if err.is::<ArgsError>() => last_arg_err = Some(err),
+                other => return other,
+            }
+        }
+        if let Some(cooldown) = cooldown.flatten() {
+            cooldown.cancel();
+        }
+        Err(last_arg_err.unwrap_or_else(|| anyhow::anyhow!("no matching overload")))
+    }
+
+    /// Full name of the command abbreviated as `abbreviation`.
+    pub fn expand_abbreviation(&self, abbreviation: &str) -> Option<String> {
+        self.state().abbreviations.get(abbreviation).cloned()
+    }
+
+    /// Abbreviations of command `name`, sorted.
+    pub(crate) fn abbreviations_of(&self, name: &str) -> Vec<String> {
+        let mut found: Vec<String> = self
+            .state()
+            .abbreviations
+            .iter()
+            .filter(|(_, full)| *full == name)
+            .map(|(abbreviation, _)| abbreviation.clone())
+            .collect();
+        found.sort();
+        found
+    }
+
+    /// All overloads of command `name`.
+    pub(crate) fn get(&self, name: &str) -> Vec<Arc<Command>> {
+        self.state().commands.get(name).cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn commands(&self) -> Snapshot<HashMap<String, Vec<Arc<Command>>>> {
+        Snapshot {
+            state: self.state(),
+            part: |state| &state.commands,
+        }
+    }
+
+    /// Names of all commands, including [`RESERVED`] ones.
+    pub(crate) fn names(&self) -> Snapshot<PrefixSet> {
+        Snapshot {
+            state: self.state(),
+            part: |state| &state.names,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandArgInfo, CommandArgType, TrivialCommandHandler};
+    use crate::runtime::default_runtime;
+
+    fn command() -> Command {
+        Command::new("", vec![], Box::new(TrivialCommandHandler::new()))
+    }
+
+    #[test]
+    fn reload_keeps_concurrent_changes() {
+        let registry = Arc::new(CommandRegistry::new(vec![], default_runtime()).unwrap());
+        let session: Arc<Mutex<Option<Arc<CommandRegistry>>>> = Default::default();
+        let other = session.clone();
+        let provider = move || {
+            // another session adds a command while the provider is queried
+            if let Some(registry) = other.lock().unwrap().as_ref() {
+                registry.add("added", command()).unwrap();
+            }
+            vec![("provided".to_string(), command())]
+        };
+        registry.register_provider(provider).unwrap();
+        *session.lock().unwrap() = Some(registry.clone());
+        registry.reload_providers().unwrap();
+        assert!(registry.contains("added"));
+        assert!(registry.contains("provided"));
+        session.lock().unwrap().take();
+    }
+
+    #[test]
+    fn dynamic_registration() {
+        let registry = CommandRegistry::new(vec![], default_runtime()).unwrap();
+        assert!(registry.is_empty());
+        registry.add("move", command()).unwrap();
+        registry.add("make", command()).unwrap();
+        assert!(matches!(
+            registry.add("make", command()),
+            Err(BuilderError::DuplicateCommands(_))
+        ));
+        assert!(matches!(
+            registry.add("help", command()),
+            Err(BuilderError::ReservedName(_))
+        ));
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.candidates("m", None).names, vec!["make", "move"]);
+
+        assert!(registry.remove("make"));
+        assert!(!registry.remove("make"));
+        assert!(!registry.contains("make"));
+        assert_eq!(registry.candidates("m", None).names, vec!["move"]);
+        assert_eq!(registry.candidates("h", None).names, vec!["help"]);
+    }
+
+    #[test]
+    fn abbreviations() {
+        let registry = CommandRegistry::new(vec![], default_runtime()).unwrap();
+        registry
+            .add("configure", command().abbreviation("cfg"))
+            .unwrap();
+        registry.add("status", command()).unwrap();
+        assert_eq!(
+            registry.expand_abbreviation("cfg").as_deref(),
+            Some("configure")
+        );
+        // abbreviations are not matched by prefix
+        assert!(registry.candidates("cf", None).names.is_empty());
+        for (name, cmd) in [
+            ("cfg", command()),
+            ("clear", command().abbreviation("cfg")),
+            ("stop", command().abbreviation("status")),
+            ("stop", command().abbreviation("help")),
+        ] {
+            assert!(matches!(
+                registry.add(name, cmd),
+                Err(BuilderError::AbbreviationConflict(_))
+            ));
+        }
+        assert_eq!(registry.infos()[0].abbreviations, vec!["cfg"]);
+        assert!(registry.remove("configure"));
+        assert_eq!(registry.expand_abbreviation("cfg"), None);
+    }
+
+    #[test]
+    fn namespace_conflicts() {
+        let registry = CommandRegistry::new(vec![], default_runtime()).unwrap();
+        registry.add("db.migrate", command()).unwrap();
+        registry.add("db.cache.clear", command()).unwrap();
+        registry.add("cache:clear", command()).unwrap();
+        for name in ["db..x", ".x", "db.", "db.x:y"] {
+            assert!(matches!(
+                registry.add(name, command()),
+                Err(BuilderError::InvalidName(_))
+            ));
+        }
+        for name in ["db", "db.cache", "db.migrate.up", "cache:clear:all"] {
+            assert!(matches!(
+                registry.add(name, command()),
+                Err(BuilderError::NamespaceConflict(_))
+            ));
+        }
+        assert!(in_namespace("db.cache.clear", "db"));
+        assert!(!in_namespace("dbg", "db"));
+        assert!(!in_namespace("db", "db"));
+    }
+
+    #[test]
+    fn bounded_candidates() {
+        let registry = CommandRegistry::new(vec![], default_runtime()).unwrap();
+        for i in 0..1000 {
+            registry
+                .add(&format!("endpoint-{i:04}"), command())
+                .unwrap();
+        }
+        let candidates = registry.candidates("endpoint-00", Some(3));
+        assert_eq!(
+            candidates.names,
+            vec!["endpoint-0000", "endpoint-0001", "endpoint-0002"]
+        );
+        assert_eq!(candidates.remaining, 97);
+        assert_eq!(candidates.common_prefix, "endpoint-00");
+
+        let candidates = registry.candidates("end", Some(0));
+        assert!(candidates.names.is_empty());
+        assert_eq!(candidates.remaining, 1000);
+        assert_eq!(candidates.common_prefix, "endpoint-0");
+
+        let candidates = registry.candidates("endpoint-0999", Some(3));
+        assert_eq!(candidates.names, vec!["endpoint-0999"]);
+        assert_eq!(candidates.common_prefix, "endpoint-0999");
+        assert_eq!(registry.candidates("", None), Candidates::default());
+    }
+
+    #[test]
+    fn overload_resolution() {
+        let registry = CommandRegistry::new(vec![], default_runtime()).unwrap();
+        let overload = |desc: &str, types: &[CommandArgType]| {
+            let args_info = types.iter().map(|t| CommandArgInfo::new(*t)).collect();
+            Command::new(desc, args_info, Box::new(TrivialCommandHandler::new()))
+        };
+        use CommandArgType::*;
+        registry.add("set", overload("string", &[String])).unwrap();
+        registry.add("set", overload("float", &[F32])).unwrap();
+        registry.add("set", overload("custom", &[Custom])).unwrap();
+        registry
+            .add("set", overload("pair", &[String, String]))
+            .unwrap();
+
+        let order = |args: &[&str]| -> Vec<std::string::String> {
+            let cmds = registry.overloads("set", args).unwrap();
+            cmds.iter().map(|cmd| cmd.description.clone()).collect()
+        };
+        assert_eq!(order(&["1.5"]), vec!["float", "custom", "string"]);
+        assert_eq!(order(&["x"]), vec!["custom", "string"]);
+        assert_eq!(order(&["x", "y"]), vec!["pair"]);
+
+        // a more specific overload takes over only the inputs it accepts
+        registry.add("set", overload("int", &[I32])).unwrap();
+        assert_eq!(order(&["1"]), vec!["int", "float", "custom", "string"]);
+        assert_eq!(order(&["1.5"]), vec!["float", "custom", "string"]);
+
+        // raw overloads accept anything, but only as a last resort
+        registry
+            .add("set", overload("raw", &[String]).raw_args())
+            .unwrap();
+        assert_eq!(order(&["1.5"]), vec!["float", "custom", "string", "raw"]);
+        assert_eq!(order(&["x", "y", "z"]), vec!["raw"]);
+
+        registry.add("num", overload("int", &[I32])).unwrap();
+        registry.add("num", overload("pair", &[I32, I32])).unwrap();
+        assert!(matches!(
+            registry.overloads("num", &["x"]),
+            Err(ArgsError::WrongArgumentValue { .. })
+        ));
+        assert!(matches!(
+            registry.overloads("num", &[]),
+            Err(ArgsError::WrongNumberOfArguments { expected: 1, .. })
+        ));
+
+        // overloads whose validators reject the arguments are skipped
+        let digit = |args: &[&str], _: &[CommandArgInfo]| match args[0].parse::<i32>() {
+            Ok(0..=9) => Ok(()),
+            _ => Err(ArgsError::Invalid("not a digit".into())),
+        };
+        registry
+            .add("digit", overload("int", &[I32]).validator(digit))
+            .unwrap();
+        registry
+            .add("digit", overload("string", &[String]))
+            .unwrap();
+        let digits = |args: &[&str]| -> Vec<std::string::String> {
+            let cmds = registry.overloads("digit", args).unwrap();
+            cmds.iter().map(|cmd| cmd.description.clone()).collect()
+        };
+        assert_eq!(digits(&["7"]), vec!["int", "string"]);
+        assert_eq!(digits(&["17"]), vec!["string"]);
+    }
+
+    #[test]
+    fn introspection() {
+        let registry = CommandRegistry::new(vec![], default_runtime()).unwrap();
+        let arg = CommandArgInfo::new_with_name(CommandArgType::I32, "n");
+        let cmd = Command::new("Go", vec![arg], Box::new(TrivialCommandHandler::new()));
+        registry.add("go", cmd.category("moves")).unwrap();
+        registry
+            .add("go", command().deprecated("use 'go 1'"))
+            .unwrap();
+        registry.add("debug", command().hidden(true)).unwrap();
+
+        let infos = registry.infos();
+        let names: Vec<_> = infos.iter().map(|info| info.name.as_str()).collect();
+        assert_eq!(names, vec!["debug", "go"]);
+        assert!(infos[0].overloads[0].hidden);
+        let go = &infos[1].overloads;
+        assert_eq!(go[0].signature(), "n:i32");
+        assert_eq!(go[0].description, "Go");
+        assert_eq!(go[0].category.as_deref(), Some("moves"));
+        assert_eq!(go[1].signature(), "");
+        assert_eq!(go[1].deprecated.as_deref(), Some("use 'go 1'"));
+    }
+}
@@ -1,25 +1,122 @@
 //! Main REPL logic.
 
-use std::{collections::HashMap, io::Write, rc::Rc};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "terminal")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    io::Write,
+    ops::Range,
+    rc::Rc,
+    sync::{Arc, PoisonError},
+    time::{Duration, Instant},
+};
 
-use rustyline::{self, completion::FilenameCompleter, error::ReadlineError};
+use futures::stream::{self, StreamExt};
+#[cfg(feature = "terminal")]
+use rustyline::{self, error::ReadlineError};
 use shell_words;
 use textwrap;
 use thiserror;
-use trie_rs::{Trie, TrieBuilder};
+use unicode_width::UnicodeWidthStr;
 
-use crate::command::{ArgsError, Command, CommandStatus, CriticalError};
-use crate::completion::{completion_candidates, Completion};
+use crate::clock::{Clock, SystemClock};
+use crate::command::{
+    ArgsError, Command, CommandArgInfo, CommandArgType, CommandStatus, CriticalError, Scope,
+    SessionChange,
+};
+#[cfg(feature = "terminal")]
+use crate::completion::InputValidator;
+use crate::completion::{clamp_pos, Completion, CompletionCache, ValueHistory};
+#[cfg(feature = "config")]
+use crate::config::{Config, ConfigError};
+use crate::diagnostics::{diagnose, Overloads, Report};
+use crate::embed::Candidate;
+use crate::eval::{self, Evaluator};
+#[cfg(feature = "terminal")]
+use crate::fs::{FileSystem, RealFileSystem};
+use crate::glob;
+#[cfg(feature = "terminal")]
+use crate::history::{self, HistoryCipher};
+#[cfg(feature = "terminal")]
+use crate::keymap::{key_name, parse_key, watched, IdleWatch, InsertSnippet, Keymap, RunCommand};
+use crate::locale::Locale;
+#[cfg(feature = "terminal")]
+use crate::output::{page, screen_lines};
+use crate::output::{Output, Redirection, Sink, Verbosity};
+use crate::paths::{self, HomeResolver};
+use crate::prefix::edit_distance;
+#[cfg(feature = "terminal")]
+use crate::printer::Printer;
+use crate::prompt::{self, Jobs, PromptState};
+use crate::provider::CommandProvider;
+use crate::registry::{cooldown_error, in_namespace, CommandInfo, CommandRegistry, Provided};
+use crate::runtime::{default_runtime, Runtime};
+use crate::script::{BatchReport, BatchResult, Demo, Replay, ScriptFailure, ScriptReport};
+use crate::shell::Shell;
+use crate::snippet::{self, Snippets};
+#[cfg(feature = "config")]
+use crate::state::{SessionState, StateError};
+use crate::stats::{self, Stats};
+#[cfg(feature = "terminal")]
+use crate::style::Theme;
+use crate::subst;
+use crate::tokenize::{tokenize, Token};
+use crate::tutorial::Tutorial;
+#[cfg(feature = "terminal")]
+use rustyline::{Cmd, Event, EventHandler, KeyEvent};
 
 /// Reserved command names. These commands are always added to REPL.
-pub const RESERVED: &[(&str, &str)] = &[("help", "Show this help message"), ("quit", "Quit repl")];
+pub const RESERVED: &[(&str, &str)] = &[
+    (
+        "help",
+        "Show this help message, or the help of a command, namespace or category",
+    ),
+    ("quit", "Quit repl"),
+    #[cfg(feature = "terminal")]
+    ("bind", "List key bindings, bind KEY LINE or unbind KEY"),
+    ("set-option", "List options or set option NAME to VALUE"),
+    (
+        "show-output",
+        "Print again the output of the N-th last command",
+    ),
+];
+
+/// Diagnostic command added with [`ReplBuilder::status_command`]. Names starting with a
+/// namespace separator cannot be taken by other commands.
+const STATUS: (&str, &str) = (":status", "Show statistics of the session");
+
+/// Commands added with [`ReplBuilder::working_dir`], unless there are commands with the same
+/// names.
+const DIR_COMMANDS: &[(&str, &str)] = &[
+    (
+        "cd",
+        "Change the current directory to DIR, or to the home directory, or - for the previous one",
+    ),
+    ("pwd", "Print the current directory"),
+];
+
+/// Category listed in help for commands without one, see [`ReplBuilder::help_contents`].
+const UNCATEGORIZED: &str = "uncategorized";
+
+/// Command added with [`ReplBuilder::snippet_command`], unless there is a command with the
+/// same name.
+const SNIPPET: (&str, &str) = (
+    "snippet",
+    "List snippets, show snippet NAME, define NAME = LINE or remove it with -d NAME",
+);
 
 /// Read-eval-print loop.
 ///
 /// REPL is ment do be constructed using the builder pattern via [`Repl::builder()`].
-/// Commands are added during building and currently cannot be added/removed/modified
-/// after [`Repl`] has been built. This is because the names are used to generate Trie
-/// with all the names for fast name lookup and completion.
+/// Commands are usually added during building, but can also be added or removed
+/// later through [`Repl::registry`].
+///
+/// Multiple sessions (e.g. a local one and one per remote connection) can share the same
+/// commands by building a [`CommandRegistry`] once with [`ReplBuilder::build_registry`]
+/// and passing it to each session's builder with [`ReplBuilder::registry`].
 ///
 /// [`Repl`] can be used in two ways: one can use the [`Repl::run`] method directly to just
 /// start the evaluation loop, or [`Repl::next`] can be used to get back control between
@@ -27,12 +124,137 @@ pub const RESERVED: &[(&str, &str)] = &[("help", "Show this help message"), ("qu
 pub struct Repl {
     description: String,
     prompt: String,
+    /// Mode shown in the prompt, see [`Repl::set_mode`].
+    mode: Option<String>,
+    /// Overloads added with [`Repl::add_temporary`], with their names and how long they exist.
+    temporary: Vec<(String, Arc<Command>, Scope)>,
+    /// Tutorial in progress with the index of its current step, see
+    /// [`Repl::start_tutorial`].
+    tutorial: Option<(Tutorial, usize)>,
+    /// Whether input comes from [`Repl::fuzz_line`].
+    #[cfg(feature = "fuzz")]
+    fuzzing: bool,
+    jobs: Jobs,
+    /// When the session started, if [`ReplBuilder::status_command`] shows the uptime
+    started: Option<Instant>,
+    stats: Stats,
     text_width: usize,
-    commands: HashMap<String, Vec<Command>>,
-    trie: Rc<Trie<u8>>,
+    registry: Arc<CommandRegistry>,
+    #[cfg(feature = "terminal")]
     editor: rustyline::Editor<Completion>,
-    out: Box<dyn Write>,
+    /// Keys bound to command lines, see [`Repl::bind_command`].
+    #[cfg(feature = "terminal")]
+    key_commands: Vec<(KeyEvent, String)>,
+    /// Command line of the key pressed while reading the current line.
+    #[cfg(feature = "terminal")]
+    pending_command: Arc<std::sync::Mutex<Option<String>>>,
+    /// Text typed before a key bound to a command was pressed, restored on the next prompt.
+    #[cfg(feature = "terminal")]
+    pending_input: Option<String>,
+    #[cfg(feature = "terminal")]
+    confirm_paste: bool,
+    #[cfg(feature = "terminal")]
+    history_file: Option<PathBuf>,
+    #[cfg(feature = "terminal")]
+    history_cipher: Option<Box<dyn HistoryCipher>>,
+    /// Command showing long output, see [`ReplBuilder::pager`].
+    #[cfg(feature = "terminal")]
+    pager: Option<String>,
+    /// Command lines of aliases, see [`ReplBuilder::alias`].
+    aliases: BTreeMap<String, String>,
+    /// Command line templates of snippets, see [`ReplBuilder::snippet`].
+    snippets: Snippets,
+    snippet_command: bool,
+    #[cfg(not(feature = "terminal"))]
+    completion: Completion,
+    out: Sink,
+    correction: Correction,
+    globbing: Globbing,
+    variable_substitution: bool,
+    /// Home directory of `~` in path arguments, see [`ReplBuilder::home_dir`].
+    home_dir: Rc<HomeResolver>,
+    /// Current directory, see [`ReplBuilder::working_dir`].
+    working_dir: Option<Rc<RefCell<PathBuf>>>,
+    /// Directory before the last `cd`.
+    previous_dir: Option<PathBuf>,
+    confirm_destructive: Confirmation,
+    /// Candidates listed for the previous line, which can be selected by number.
+    numbered_candidates: Option<NumberedCandidates>,
+    /// Output of the last commands, most recent first, see [`ReplBuilder::output_history`].
+    output_history: VecDeque<String>,
+    output_history_size: usize,
+    status_command: bool,
+    idle_timeout: Option<Duration>,
+    end_of_input: EndOfInput,
+    /// Called when the session is idle, see [`ReplBuilder::on_idle`].
+    on_idle: Option<Box<dyn FnMut() -> LoopStatus>>,
+    slow_command: Option<Duration>,
+    /// Called after slow commands, see [`ReplBuilder::on_slow_command`].
+    on_slow_command: Option<Box<SlowCommandHook>>,
+    /// Called with critical errors, see [`ReplBuilder::on_critical_error`].
+    on_critical_error: Option<Box<CriticalErrorHook>>,
+    /// Called when the session restarts, see [`ReplBuilder::on_restart`].
+    on_restart: Option<Box<RestartHook>>,
+    /// Checks of command lines before they run, see [`ReplBuilder::lint`].
+    lints: Vec<Box<LintHook>>,
+    /// Evaluator of lines that are not commands, see [`ReplBuilder::evaluator`].
+    evaluator: Option<Box<dyn Evaluator>>,
+    /// Last result of the evaluator, see [`Repl::last_result`].
+    last_result: Option<String>,
+    /// Time of the last key pressed, see [`IdleWatch`].
+    #[cfg(feature = "terminal")]
+    last_input: Arc<std::sync::Mutex<Instant>>,
+    /// Whether the line was interrupted because the session has been idle.
+    #[cfg(feature = "terminal")]
+    idle_expired: Arc<AtomicBool>,
+    /// Tracking of input by bound keys, with [`ReplBuilder::idle_timeout`].
+    #[cfg(feature = "terminal")]
+    idle_watch: Option<IdleWatch>,
+    /// Printer of lines above the prompt, see [`Repl::printer`].
+    #[cfg(feature = "terminal")]
+    printer: Printer,
     predict_commands: bool,
+    completion_limit: Option<usize>,
+    completion_cache: Rc<CompletionCache>,
+    help_contents: Option<usize>,
+    /// Last rendered help, see [`Repl::help`].
+    help_cache: RefCell<Option<RenderedHelp>>,
+    runtime: Arc<dyn Runtime>,
+    clock: Arc<dyn Clock>,
+}
+
+/// Outcome of [`Repl::check_command`].
+enum Check {
+    /// Help of the command, requested with `-h` or `--help`.
+    Help(String),
+    /// The command can run, with the warnings of the lints.
+    Run(Vec<String>),
+}
+
+/// Help text together with everything it depends on, see [`Repl::help`].
+struct RenderedHelp {
+    /// Registry generation of the listed commands.
+    generation: u64,
+    text_width: usize,
+    /// Aliases, snippets and built-in commands, which depend on the session.
+    other: Vec<(String, String)>,
+    text: String,
+}
+
+/// Commands listed for an ambiguous prefix, with the arguments given after it.
+struct NumberedCandidates {
+    names: Vec<String>,
+    args: Vec<String>,
+}
+
+impl NumberedCandidates {
+    /// Command line running the candidate numbered `line`, if it is one of the numbers.
+    fn select(self, line: &str) -> Option<String> {
+        let index = line.trim().parse::<usize>().ok()?.checked_sub(1)?;
+        let name = self.names.into_iter().nth(index)?;
+        let words = std::iter::once(name).chain(self.args);
+        Some(shell_words::join(words))
+    }
 }
 
 /// State of the REPL after command execution.
@@ -57,17 +279,86 @@ pub enum LoopStatus {
 /// ```
 pub struct ReplBuilder {
     commands: Vec<(String, Command)>,
+    /// Providers of some of the commands, with their names, see [`ReplBuilder::add_provider`].
+    providers: Vec<Provided>,
+    registry: Option<Arc<CommandRegistry>>,
     description: String,
     prompt: String,
     text_width: usize,
-    editor_config: rustyline::config::Config,
-    out: Box<dyn Write>,
+    #[cfg(feature = "terminal")]
+    editor_config: Option<rustyline::config::Config>,
+    /// History settings applied over `editor_config`, see [`ReplBuilder::history_limits`].
+    #[cfg(feature = "terminal")]
+    history_limits: HistoryLimits,
+    #[cfg(feature = "terminal")]
+    keymap: Keymap,
+    #[cfg(feature = "terminal")]
+    history_search: HistorySearch,
+    #[cfg(feature = "terminal")]
+    theme: Theme,
+    #[cfg(feature = "terminal")]
+    confirm_paste: bool,
+    #[cfg(feature = "terminal")]
+    history_file: Option<PathBuf>,
+    #[cfg(feature = "terminal")]
+    history_cipher: Option<Box<dyn HistoryCipher>>,
+    #[cfg(feature = "terminal")]
+    pager: Option<String>,
+    aliases: BTreeMap<String, String>,
+    snippets: BTreeMap<String, String>,
+    env_prefix: Option<String>,
+    out: Output,
+    #[cfg(feature = "json")]
+    json_output: bool,
+    locale: Locale,
+    verbosity: Verbosity,
+    output_history: usize,
+    status_command: bool,
+    snippet_command: bool,
     with_hints: bool,
     with_completion: bool,
+    argument_history: bool,
+    correction: Correction,
+    globbing: Globbing,
+    variable_substitution: bool,
+    #[cfg(feature = "terminal")]
+    file_system: Rc<dyn FileSystem>,
+    home_dir: Rc<HomeResolver>,
+    working_dir: Option<PathBuf>,
+    confirm_destructive: Confirmation,
+    idle_timeout: Option<Duration>,
+    end_of_input: EndOfInput,
+    on_idle: Option<Box<dyn FnMut() -> LoopStatus>>,
+    slow_command: Option<Duration>,
+    on_slow_command: Option<Box<SlowCommandHook>>,
+    on_critical_error: Option<Box<CriticalErrorHook>>,
+    on_restart: Option<Box<RestartHook>>,
+    lints: Vec<Box<LintHook>>,
+    evaluator: Option<Box<dyn Evaluator>>,
+    #[cfg(feature = "terminal")]
     with_filename_completion: bool,
+    #[cfg(feature = "terminal")]
+    input_validator: Option<Rc<InputValidator>>,
     predict_commands: bool,
+    completion_limit: Option<usize>,
+    completion_cache_ttl: Option<Duration>,
+    help_contents: Option<usize>,
+    runtime: Arc<dyn Runtime>,
+    clock: Option<Arc<dyn Clock>>,
 }
 
+/// Hook called with the name and duration of slow commands.
+type SlowCommandHook = dyn FnMut(&str, Duration);
+
+/// Hook deciding what to do with critical errors.
+type CriticalErrorHook = dyn FnMut(&CriticalError) -> CriticalAction;
+
+/// Hook reloading the session, e.g. its configuration or commands.
+type RestartHook = dyn FnMut(&mut Repl) -> anyhow::Result<()>;
+
+/// Hook checking the name and arguments of commands about to run.
+type LintHook = dyn Fn(&str, &[&str]) -> Option<String>;
+
 /// Error when building REPL.
 #[derive(Debug, thiserror::Error)]
 pub enum BuilderError {
@@ -80,10 +371,225 @@ pub enum BuilderError {
     /// Command name is one of [`RESERVED`] names.
     #[error("'{0}' is a reserved command name")]
     ReservedName(String),
+    /// Commands have been added to a builder using a shared [`CommandRegistry`].
+    #[error("cannot add command '{0}' when using a shared registry")]
+    SharedRegistry(String),
+    /// No commands have been added, see [`ReplBuilder::build_strict`].
+    #[error("no commands have been added")]
+    NoCommands,
+    /// Filename completion is enabled but no command takes a `String` or `Custom` argument,
+    /// see [`ReplBuilder::build_strict`].
+    #[error("filename completion is enabled but no command takes a string argument")]
+    UnusedFilenameCompletion,
+    /// Completion cache is enabled but no argument has a completer,
+    /// see [`ReplBuilder::build_strict`].
+    #[error("completion cache is enabled but no argument has a completer")]
+    UnusedCompletionCache,
+    /// [`ReplBuilder::out`] is a standard stream other than the one the line editor uses,
+    /// see [`ReplBuilder::editor_config`].
+    #[error("REPL output and line editor use different standard streams")]
+    OutputStreamMismatch,
+    /// Environment variable has a value that is not valid, see [`ReplBuilder::env_prefix`].
+    #[error("invalid value '{1}' of environment variable {0}")]
+    InvalidEnvVar(String, String),
+    /// Command name is also a namespace of other commands, or one of its namespaces is a
+    /// command name, see [`registry`](crate::registry).
+    #[error("'{0}' conflicts with a namespace of the same name")]
+    NamespaceConflict(String),
+    /// Abbreviation is already a command name or abbreviation, see
+    /// [`Command::abbreviation`].
+    #[error("abbreviation '{0}' is already used")]
+    AbbreviationConflict(String),
+    /// Alias has the same name as a command, see [`ReplBuilder::alias`].
+    #[error("alias '{0}' has the same name as a command")]
+    AliasConflict(String),
+    /// Snippet has the same name as a command or an alias, see [`ReplBuilder::snippet`].
+    #[error("snippet '{0}' has the same name as a command or alias")]
+    SnippetConflict(String),
+}
+
+/// Error when changing an option with [`Repl::set_option`].
+#[derive(Debug, thiserror::Error)]
+pub enum OptionError {
+    /// There is no option with this name.
+    #[error("unknown option '{0}'")]
+    UnknownOption(String),
+    /// Value cannot be parsed as the type of the option.
+    #[error("invalid value '{value}' for option '{name}'")]
+    InvalidValue {
+        /// Option name.
+        name: String,
+        /// Value given.
+        value: String,
+    },
+}
+
+/// When to confirm running [destructive](Command::destructive) commands, see
+/// [`ReplBuilder::confirm_destructive`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Confirmation {
+    /// Always ask the user. Without a terminal to ask on, the command is not run.
+    Always,
+    /// Ask the user unless `--yes` is given as an argument.
+    #[default]
+    UnlessYes,
+    /// Run without asking.
+    Never,
+}
+
+/// Expansion of glob patterns in path arguments, see [`ReplBuilder::globbing`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Globbing {
+    /// Pass patterns as they are.
+    #[default]
+    Off,
+    /// Expand patterns, passing the ones matching no files as they are.
+    Literal,
+    /// Expand patterns, failing if one matches no files.
+    Error,
+}
+
+/// What to do when an unknown command is close to exactly one known command, see
+/// [`ReplBuilder::correction`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Correction {
+    /// Report the command as not found.
+    #[default]
+    Off,
+    /// Ask whether to run the known command instead. Without a terminal to ask on, the
+    /// command is only suggested.
+    Prompt,
+    /// Run the known command, printing which one.
+    Auto,
+}
+
+/// Split a line into arguments using shell-like syntax.
+///
+/// On Windows backslash is not an escape character, so that paths like `C:\Users\me` can
+/// be entered without quoting.
+pub(crate) fn split_args(line: &str) -> Result<Vec<Token<'_>>, shell_words::ParseError> {
+    tokenize(line, cfg!(windows))
+}
+
+/// Command line the arguments of a command have been read from, used to point at an
+/// invalid one.
+struct SourceLine<'a> {
+    line: &'a str,
+    /// Spans of the arguments (without the command name) in `line`
+    spans: Vec<Range<usize>>,
+}
+
+/// Editor configuration used by default, see [`ReplBuilder::editor_config`].
+#[cfg(feature = "terminal")]
+fn default_editor_config(stream: rustyline::OutputStreamType) -> rustyline::config::Config {
+    rustyline::config::Config::builder()
+        .output_stream(stream)
+        .completion_type(rustyline::CompletionType::List)
+        .build()
+}
+
+/// What a session does when its input source ends, see [`ReplBuilder::end_of_input`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum EndOfInput {
+    /// End the session.
+    #[default]
+    Quit,
+    /// Keep the session and wait for input from another source.
+    Wait,
+}
+
+/// What to do with a critical error, see [`ReplBuilder::on_critical_error`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum CriticalAction {
+    /// Return the error, ending the session.
+    #[default]
+    Terminate,
+    /// Print the error and restart the session, see [`Repl::restart`].
+    Restart,
+    /// Print the error like any other error and continue.
+    Print,
+}
+
+/// Whether a line typed in the terminal is ready to be handled, see
+/// [`ReplBuilder::input_validator`].
+#[cfg(feature = "terminal")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InputStatus {
+    /// Handle the input when Enter is pressed.
+    Complete,
+    /// Start a new line of the input when Enter is pressed.
+    Incomplete,
+    /// Keep editing the input when Enter is pressed, showing the message.
+    Invalid(String),
+}
+
+/// How entries of the line editor history are matched, see [`ReplBuilder::history_search`]
+/// and [`Repl::history_search`].
+#[cfg(feature = "terminal")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum HistorySearch {
+    /// Entries containing the text anywhere. `Ctrl-R` and `Ctrl-S` search incrementally as
+    /// the text is typed.
+    #[default]
+    Substring,
+    /// Entries starting with the text. `Ctrl-R` and `Ctrl-S` recall the previous or next
+    /// entry starting with the text before the cursor.
+    Prefix,
+}
+
+/// History settings of the line editor that are not left to its configuration.
+#[cfg(feature = "terminal")]
+#[derive(Default)]
+pub(crate) struct HistoryLimits {
+    pub(crate) max_size: Option<usize>,
+    pub(crate) ignore_dups: Option<bool>,
+    pub(crate) ignore_space: Option<bool>,
 }
 
-pub(crate) fn split_args(line: &str) -> Result<Vec<String>, shell_words::ParseError> {
-    shell_words::split(line)
+#[cfg(feature = "terminal")]
+impl HistoryLimits {
+    fn apply(&self, editor: &mut rustyline::Editor<Completion>) {
+        use rustyline::config::Configurer;
+        if let Some(max_size) = self.max_size {
+            editor.set_max_history_size(max_size);
+        }
+        if let Some(ignore_dups) = self.ignore_dups {
+            editor.set_history_ignore_dups(ignore_dups);
+        }
+        if let Some(ignore_space) = self.ignore_space {
+            editor.set_history_ignore_space(ignore_space);
+        }
+    }
 }
 
 impl Default for ReplBuilder {
@@ -93,15 +599,67 @@ impl Default for ReplBuilder {
             text_width: 80,
             description: Default::default(),
             commands: Default::default(),
-            out: Box::new(std::io::stderr()),
-            editor_config: rustyline::config::Config::builder()
-                .output_stream(rustyline::OutputStreamType::Stderr) // NOTE: cannot specify `out`
-                .completion_type(rustyline::CompletionType::List)
-                .build(),
+            providers: Default::default(),
+            registry: None,
+            out: Output::Stderr,
+            #[cfg(feature = "json")]
+            json_output: false,
+            locale: Locale::C,
+            verbosity: Verbosity::Normal,
+            output_history: 0,
+            status_command: false,
+            snippet_command: false,
+            #[cfg(feature = "terminal")]
+            editor_config: None,
+            #[cfg(feature = "terminal")]
+            history_limits: HistoryLimits::default(),
             with_hints: true,
             with_completion: true,
+            argument_history: false,
+            correction: Correction::Off,
+            globbing: Globbing::Off,
+            variable_substitution: false,
+            #[cfg(feature = "terminal")]
+            file_system: Rc::new(RealFileSystem),
+            home_dir: Rc::new(paths::default_home),
+            working_dir: None,
+            confirm_destructive: Confirmation::UnlessYes,
+            idle_timeout: None,
+            end_of_input: EndOfInput::Quit,
+            on_idle: None,
+            slow_command: None,
+            on_slow_command: None,
+            on_critical_error: None,
+            on_restart: None,
+            lints: Vec::new(),
+            evaluator: None,
+            #[cfg(feature = "terminal")]
             with_filename_completion: false,
+            #[cfg(feature = "terminal")]
+            input_validator: None,
+            #[cfg(feature = "terminal")]
+            keymap: Keymap::default(),
+            #[cfg(feature = "terminal")]
+            history_search: HistorySearch::default(),
+            #[cfg(feature = "terminal")]
+            theme: Theme::default(),
+            #[cfg(feature = "terminal")]
+            confirm_paste: false,
+            #[cfg(feature = "terminal")]
+            history_file: None,
+            #[cfg(feature = "terminal")]
+            history_cipher: None,
+            #[cfg(feature = "terminal")]
+            pager: None,
+            aliases: BTreeMap::new(),
+            snippets: BTreeMap::new(),
+            env_prefix: None,
             predict_commands: true,
+            completion_limit: None,
+            completion_cache_ttl: None,
+            help_contents: None,
+            runtime: default_runtime(),
+            clock: None,
         }
     }
 }
@@ -122,18 +680,85 @@ impl ReplBuilder {
     setters! {
         /// Repl description shown in [`Repl::help`]. Defaults to an empty string.
         description: String
-        /// Prompt string, defaults to `"> "`.
+        /// Prompt string, defaults to `"> "`. It can contain placeholders showing the state
+        /// of the session, e.g. `{mode}`, see [`prompt`](crate::prompt).
         prompt: String
         /// Width of the text used when wrapping the help message. Defaults to 80.
         text_width: usize
-        /// Configuration for [`rustyline`]. Some sane defaults are used.
-        editor_config: rustyline::config::Config
-        /// Where to print REPL output. By default [`std::io::Stderr`] is used.
+        /// Configuration for [`rustyline`]. Some sane defaults are used, with the editor
+        /// writing to the same stream as [`ReplBuilder::out`] if it is stdout or stderr.
+        ///
+        /// Building fails with [`BuilderError::OutputStreamMismatch`] if the configuration
+        /// uses a different standard stream than [`ReplBuilder::out`].
+        #[cfg(feature = "terminal")]
+        editor_config: Option<rustyline::config::Config>
+        /// Edit mode and key bindings, applied over [`ReplBuilder::editor_config`].
+        /// Defaults to the bindings of [`rustyline`].
+        #[cfg(feature = "terminal")]
+        keymap: Keymap
+        /// Behavior of `Ctrl-R` and `Ctrl-S`, see [`HistorySearch`]. Bindings of these keys
+        /// in [`ReplBuilder::keymap`] take precedence.
+        #[cfg(feature = "terminal")]
+        history_search: HistorySearch
+        /// Styles of hints and completion candidates. Defaults to no styling.
+        #[cfg(feature = "terminal")]
+        theme: Theme
+        /// List the commands and ask for confirmation before running multiple pasted lines.
+        /// Defaults to `false`.
+        ///
+        /// With bracketed paste (see [`rustyline::config::Builder::bracketed_paste`], enabled
+        /// by default) pasted lines are inserted into the input as a whole and run in order,
+        /// one command per line, once the input is accepted. Without it, the terminal sends
+        /// each pasted line break as if Enter was pressed.
+        #[cfg(feature = "terminal")]
+        confirm_paste: bool
+        /// Where to print REPL output: `std::io::stdout()`, `std::io::stderr()` or any
+        /// `Box<dyn Write>`, see [`Output`]. By default [`std::io::Stderr`] is used.
+        ///
+        /// Help, errors and the output of handlers written through
+        /// [`CommandContext::out`](crate::command::CommandContext::out) all go here, see
+        /// [`output`](crate::output). Note that [`rustyline`] will always use
+        /// [`std::io::Stderr`] or [`std::io::Stdout`], so when using another writer, the
+        /// prompt and the output go to different places.
+        out: Output
+        /// Write one JSON object per line of input instead of plain text, so that other
+        /// programs can drive the REPL over pipes. Defaults to `false`.
+        ///
+        /// The format is described in [`output`](crate::output). Responses are written to
+        /// [`ReplBuilder::out`], so use `std::io::stdout()` to get them on stdout.
+        ///
+        /// Requires the `json` feature.
+        #[cfg(feature = "json")]
+        json_output: bool
+        /// How numbers are written by the operator, see [`locale`](crate::locale). Defaults
+        /// to [`Locale::C`].
+        ///
+        /// Arguments of type `f32` can be typed with the decimal separator of the locale,
+        /// and are passed to handlers and validators with a point. Durations printed by the
+        /// REPL use it as well, and handlers can format their values with
+        /// [`CommandContext::locale`].
+        locale: Locale
+        /// How much to print besides command output. Defaults to [`Verbosity::Normal`].
+        ///
+        /// In quiet mode the candidates for an unknown command and the usage after invalid
+        /// arguments are not printed, in verbose mode errors include their causes. Can be
+        /// changed at runtime with `set-option verbosity VALUE`.
+        verbosity: Verbosity
+        /// Keep the output of the last `output_history` commands, so it can be printed again
+        /// with the built-in `show-output N` command or read with [`Repl::recent_output`].
+        /// Defaults to `0`, which disables the history.
+        output_history: usize
+        /// Add the `:status` command, printing statistics of the session: commands run,
+        /// errors, background jobs, history size, uptime and mode. Defaults to `false`.
         ///
-        /// Note that [`rustyline`] will always use [`std::io::Stderr`] or [`std::io::Stdout`].
-        /// These must be configured in [`ReplBuilder::editor_config`], and currently there seems to be no way
-        /// to use other output stream for [`rustyline`] (which probably also makes little sense).
-        out: Box<dyn Write>
+        /// Useful when the REPL is embedded in a long-running process.
+        status_command: bool
+        /// Add the `snippet` command, listing, showing, defining and removing
+        /// [snippets](ReplBuilder::snippet) at runtime. Defaults to `false`.
+        ///
+        /// `snippet deploy = rollout $1 --env $2` defines a snippet, `snippet deploy` shows
+        /// it and `snippet -d deploy` removes it. Use [`Repl::save_state`] to keep them.
+        snippet_command: bool
         /// Print command hints. Defaults to `true`.
         ///
         /// Hints will show the end of a command if there is only one avaliable.
@@ -151,10 +776,57 @@ impl ReplBuilder {
         /// > m|
         /// ```
         /// then no hints will be shown.
+        ///
+        /// Once the command name is known, placeholders of the arguments not typed yet are
+        /// shown as well, e.g. `> add 1| <Y:i32>`. Accepting the hint only completes the name.
         with_hints: bool
         /// Use completion. Defaults to `true`.
         with_completion: bool
+        /// Suggest values previously given to `String` and `Custom` arguments of the same
+        /// command, both as completions and hints. Defaults to `false`.
+        ///
+        /// Values are remembered per argument for the duration of the session, once the
+        /// command succeeds.
+        argument_history: bool
+        /// Handling of mistyped commands within a small edit distance of exactly one known
+        /// command, e.g. `stauts` for `status`. Defaults to [`Correction::Off`].
+        ///
+        /// With [`Correction::Prompt`] the user is asked `Did you mean 'status'? [Y/n]`.
+        correction: Correction
+        /// When to ask for confirmation before running commands marked as
+        /// [destructive](Command::destructive). Defaults to [`Confirmation::UnlessYes`].
+        ///
+        /// The user is asked `Run destructive command 'drop'? [y/N]`. An argument `--yes` is
+        /// never passed to destructive commands; without a terminal to ask on, they only run
+        /// if it is given, or never with [`Confirmation::Always`].
+        confirm_destructive: Confirmation
+        /// Expansion of glob patterns (e.g. `*.log`) given to path arguments, i.e. arguments
+        /// with a completer such as [`PathCompleter`](crate::command::PathCompleter), before
+        /// they are validated. Defaults to [`Globbing::Off`].
+        ///
+        /// Quoted or escaped patterns are passed as they are, like in shells. A pattern
+        /// matching several files becomes several arguments, so it usually makes sense for
+        /// commands with [`Command::raw_args`]. Patterns use the shell syntax (`*`, `?` and
+        /// sets such as `[a-z]`), and only match names starting with a dot if they do.
+        globbing: Globbing
+        /// Substitute environment variables and expressions in all arguments, not only in
+        /// path arguments (see [`ReplBuilder::home_dir`]). Defaults to `false`.
+        ///
+        /// Besides `$NAME` and `${NAME}`, simple expressions spare scripts an external shell:
+        /// `${NAME:-default}`, `${#NAME}` (length), `${NAME^^}` and `${NAME,,}` (upper and
+        /// lower case), and integer arithmetic such as `$((N + 1))`, in which unset variables
+        /// are `0`. Variables that are not set are left as they are, while invalid
+        /// expressions are reported as errors. Like in shells, nothing is substituted in
+        /// single quotes or after `\$`.
+        variable_substitution: bool
         /// Add filename completion, besides command completion. Defaults to `false`.
+        ///
+        /// Files are offered for any argument without a completer of its own. To complete
+        /// paths only for some arguments, set a
+        /// [`PathCompleter`](crate::command::PathCompleter) on them instead. A leading `~`
+        /// and environment variables are replaced with their values when completing, see
+        /// [`ReplBuilder::home_dir`].
+        #[cfg(feature = "terminal")]
         with_filename_completion: bool
         /// Execute commands when entering incomplete names. Defaults to `true`.
         ///
@@ -163,331 +835,4895 @@ impl ReplBuilder {
         /// For example, with commands `"make"` and "`move`", entering just `mo` will resolve
         /// to `move` and the command will be executed, but entering `m` will result in an error.
         predict_commands: bool
+        /// Maximum number of command names listed as candidates. Defaults to no limit.
+        ///
+        /// Applies to TAB-completion and to the candidates printed for an ambiguous
+        /// command name. Names above the limit are summarized as "…and N more",
+        /// which keeps the REPL responsive with thousands of (e.g. generated) commands.
+        completion_limit: Option<usize>
+        /// List the categories of commands (see [`Command::category`]) instead of the
+        /// commands themselves in help when there are more than `help_contents` of them.
+        /// Defaults to no limit.
+        ///
+        /// `help CATEGORY` then lists the commands of a category, and `help --all` all of
+        /// them. Commands without a category are in category `uncategorized`.
+        help_contents: Option<usize>
+        /// How long to cache results of argument completers. Defaults to no caching.
+        ///
+        /// Results are cached per command, argument index and typed prefix, so repeated
+        /// TAB presses do not query a slow data source each time. Use
+        /// [`Repl::invalidate_completions`] to drop cached results earlier.
+        completion_cache_ttl: Option<Duration>
+        /// End sessions driven through [`embed`](crate::embed) after this long without input.
+        /// Disabled by default.
+        ///
+        /// Any key pressed resets the countdown, not only entered lines, and time spent
+        /// running commands does not count. Use [`ReplBuilder::on_idle`] to do something else
+        /// than ending the session.
+        ///
+        /// The timeout is not enforced by [`Repl::run`]: the line editor cannot be interrupted
+        /// while it waits for a key, so a terminal session stays open however long it is idle.
+        /// Only the next key pressed notices that the time ran out, and ends the session
+        /// instead of being handled.
+        idle_timeout: Option<Duration>
+        /// What a session driven through [`embed`](crate::embed) does when its frontend is
+        /// closed. Defaults to [`EndOfInput::Quit`].
+        ///
+        /// With [`EndOfInput::Wait`] the session is kept until another frontend attaches,
+        /// e.g. when a remote client reconnects, see [`Attacher`](crate::embed::Attacher).
+        /// [`Repl::run`] always ends when the terminal input ends.
+        end_of_input: EndOfInput
+        /// Print how long commands took when it exceeds this, e.g. `took 12.3s`, so that
+        /// expensive operations get noticed. Disabled by default.
+        ///
+        /// The line is not printed with [`Verbosity::Quiet`]. Use
+        /// [`ReplBuilder::on_slow_command`] to e.g. log or collect such commands.
+        slow_command: Option<Duration>
     }
 
-    /// Add a command with given `name`. Use along with the [`command!`] macro.
-    pub fn add(mut self, name: &str, cmd: Command) -> Self {
-        self.commands.push((name.into(), cmd));
+    /// Call `hook` instead of ending the session when [`ReplBuilder::idle_timeout`] runs out.
+    /// The session ends if it returns [`LoopStatus::Break`], otherwise the countdown starts
+    /// again.
+    pub fn on_idle(mut self, hook: impl FnMut() -> LoopStatus + 'static) -> Self {
+        self.on_idle = Some(Box::new(hook));
         self
     }
 
-    /// Finalize the configuration and return the REPL or error.
-    pub fn build(self) -> Result<Repl, BuilderError> {
-        let mut commands: HashMap<String, Vec<Command>> = HashMap::new();
-        let mut trie = TrieBuilder::new();
-        for (name, cmd) in self.commands {
-            let cmds = commands.entry(name.clone()).or_default();
-            let args = split_args(&name).map_err(|_e| BuilderError::InvalidName(name.clone()))?;
-            if args.len() != 1 || name.is_empty() {
-                return Err(BuilderError::InvalidName(name));
-            } else if RESERVED.iter().any(|(n, _)| *n == name) {
-                return Err(BuilderError::ReservedName(name));
-            } else if cmds.iter().any(|c| c.arg_types() == cmd.arg_types()) {
-                return Err(BuilderError::DuplicateCommands(name));
-            }
-            cmds.push(cmd);
-            trie.push(name);
-        }
-        for (name, _) in RESERVED.iter() {
-            trie.push(name);
-        }
-
-        let trie = Rc::new(trie.build());
-        let helper = Completion {
-            trie: trie.clone(),
-            with_hints: self.with_hints,
-            with_completion: self.with_completion,
-            filename_completer: if self.with_filename_completion {
-                Some(FilenameCompleter::new())
-            } else {
-                None
-            },
-        };
-        let mut editor = rustyline::Editor::with_config(self.editor_config);
-        editor.set_helper(Some(helper));
+    /// Call `hook` with the name of the command and its duration after each command taking
+    /// longer than [`ReplBuilder::slow_command`].
+    pub fn on_slow_command(mut self, hook: impl FnMut(&str, Duration) + 'static) -> Self {
+        self.on_slow_command = Some(Box::new(hook));
+        self
+    }
 
-        Ok(Repl {
-            description: self.description,
-            prompt: self.prompt,
-            text_width: self.text_width,
-            commands,
-            trie,
-            editor,
-            out: self.out,
-            predict_commands: self.predict_commands,
-        })
+    /// Call `hook` with the [`CriticalError`]s returned by commands, to decide whether they
+    /// end the session, see [`CriticalAction`].
+    ///
+    /// Without a hook critical errors are returned from [`Repl::run`], so this is useful when
+    /// the REPL must keep running, e.g. in a production console.
+    pub fn on_critical_error(
+        mut self,
+        hook: impl FnMut(&CriticalError) -> CriticalAction + 'static,
+    ) -> Self {
+        self.on_critical_error = Some(Box::new(hook));
+        self
     }
-}
 
-impl Repl {
-    /// Start [`ReplBuilder`] with default values.
-    pub fn builder() -> ReplBuilder {
-        ReplBuilder::default()
+    /// Call `hook` when the session restarts, see [`Repl::restart`], e.g. to reload a
+    /// configuration file or to add the commands of providers again through
+    /// [`Repl::registry`].
+    pub fn on_restart(
+        mut self,
+        hook: impl FnMut(&mut Repl) -> anyhow::Result<()> + 'static,
+    ) -> Self {
+        self.on_restart = Some(Box::new(hook));
+        self
     }
 
-    fn format_help_entries(&self, entries: &[(String, String)]) -> String {
-        if entries.is_empty() {
-            return String::new();
-        }
-        let width = entries
-            .iter()
-            .map(|(sig, _)| sig)
-            .max_by_key(|sig| sig.len())
-            .unwrap()
-            .len();
-        entries
-            .iter()
-            .map(|(sig, desc)| {
-                let indent = " ".repeat(width + 2 + 2);
-                let opts = textwrap::Options::new(self.text_width)
-                    .initial_indent("")
-                    .subsequent_indent(&indent);
-                let line = format!("  {sig:width$}  {desc}");
-                textwrap::fill(&line, opts)
-            })
-            .fold(String::new(), |mut out, next| {
-                out.push('\n');
-                out.push_str(&next);
-                out
-            })
+    /// Call `hook` with the name and arguments of each command about to run, printing the
+    /// warning it returns as `Warning: ...` before the command runs, e.g. "--force without
+    /// --backup discards the current data".
+    ///
+    /// Lints never stop commands from running, see [`Command::destructive`] for that. Several
+    /// lints can be added, and run in the order they were added. Built-in commands are not
+    /// checked.
+    pub fn lint(mut self, hook: impl Fn(&str, &[&str]) -> Option<String> + 'static) -> Self {
+        self.lints.push(Box::new(hook));
+        self
     }
 
-    /// Returns formatted help message.
-    pub fn help(&self) -> String {
-        let mut names: Vec<_> = self.commands.keys().collect();
-        names.sort();
+    /// Pass lines that do not start with the name of a command to `evaluator`, printing
+    /// the results, see [`eval`](crate::eval).
+    ///
+    /// Lines that cannot be split into arguments (e.g. with an unclosed quote) are passed
+    /// too. Unknown commands are then never reported nor corrected.
+    pub fn evaluator(mut self, evaluator: impl Evaluator + 'static) -> Self {
+        self.evaluator = Some(Box::new(evaluator));
+        self
+    }
 
-        let signature =
-            |name: &String, args_info: &Vec<String>| format!("{} {}", name, args_info.join(" "));
-        let user: Vec<_> = self
-            .commands
-            .iter()
-            .flat_map(|(name, cmds)| {
-                cmds.iter()
-                    .map(move |cmd| (signature(name, &cmd.arg_types()), cmd.description.clone()))
-            })
-            .collect();
+    /// Async runtime used for features needing runtime services (blocking tasks, timers).
+    ///
+    /// Defaults to [`default_runtime`], which depends on the enabled crate features.
+    pub fn runtime<R: Runtime + 'static>(mut self, runtime: R) -> Self {
+        self.runtime = Arc::new(runtime);
+        self
+    }
 
-        let other: Vec<_> = RESERVED
-            .iter()
-            .map(|(name, desc)| ((*name).to_string(), desc.to_string()))
-            .collect();
+    /// Source of time for command cooldowns, the idle timeout, retry delays, demo pauses
+    /// and the completion cache, also given to commands through [`CommandContext::clock`].
+    ///
+    /// Defaults to a [`SystemClock`] with the timers of the [runtime](ReplBuilder::runtime).
+    /// Tests can pass a [`ManualClock`](crate::clock::ManualClock) and advance it instead
+    /// of sleeping.
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
 
-        let msg = format!(
-            r#"
-{}
+    /// Builder with settings read from a TOML file, see [`config`](crate::config).
+    ///
+    /// Settings missing from the file keep their defaults. Values set on the returned builder
+    /// take precedence over the file, e.g. a later call to [`ReplBuilder::prompt`] replaces
+    /// the prompt from the file. History settings from the file are applied over
+    /// [`ReplBuilder::editor_config`].
+    #[cfg(feature = "config")]
+    pub fn from_config(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Ok(Config::load(path)?.builder())
+    }
 
-Available commands:
-{}
+    /// Override history settings of [`ReplBuilder::editor_config`], used for configuration
+    /// files.
+    #[cfg(all(feature = "terminal", feature = "config"))]
+    pub(crate) fn history_limits(mut self, limits: HistoryLimits) -> Self {
+        self.history_limits = limits;
+        self
+    }
 
-Other commands:
-{}
-        "#,
-            self.description,
-            self.format_help_entries(&user),
-            self.format_help_entries(&other)
-        );
-        msg.trim().into()
+    /// Load history from `path` when building, and append each entered line to it.
+    ///
+    /// A missing file is created on the first entry. Errors when writing are ignored.
+    #[cfg(feature = "terminal")]
+    pub fn history_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.history_file = Some(path.into());
+        self
     }
 
-    async fn handle_line(&mut self, line: &str) -> anyhow::Result<LoopStatus> {
-        // if there is any parsing error just continue to next input
-        let args = match split_args(line) {
-            Err(err) => {
-                writeln!(&mut self.out, "Error: {err}")?;
-                return Ok(LoopStatus::Continue);
-            }
-            Ok(args) => args,
+    /// Encrypt the [history file](ReplBuilder::history_file) with `cipher`, see
+    /// [`history`](crate::history).
+    ///
+    /// The whole file is rewritten after each entry. If it cannot be decrypted when building,
+    /// e.g. because it is corrupted, the session starts with an empty history.
+    #[cfg(feature = "terminal")]
+    pub fn history_cipher(mut self, cipher: impl HistoryCipher + 'static) -> Self {
+        self.history_cipher = Some(Box::new(cipher));
+        self
+    }
+
+    /// Source of the directory listings of
+    /// [filename completion](ReplBuilder::with_filename_completion). Defaults to
+    /// [`RealFileSystem`].
+    ///
+    /// Tests can pass a [`VirtualFileSystem`](crate::fs::VirtualFileSystem) to check
+    /// completion without creating files, and sandboxed or remote sessions can list the
+    /// files their commands act on.
+    #[cfg(feature = "terminal")]
+    pub fn file_system(mut self, fs: impl FileSystem + 'static) -> Self {
+        self.file_system = Rc::new(fs);
+        self
+    }
+
+    /// Resolve the home directory, to which a leading `~` of path arguments expands, with
+    /// `resolver`. Defaults to the `HOME` environment variable (`USERPROFILE` on Windows).
+    ///
+    /// Path arguments are arguments with a completer such as
+    /// [`PathCompleter`](crate::command::PathCompleter). Like in shells, `~` and environment
+    /// variables (`$NAME` or `${NAME}`, see [`ReplBuilder::variable_substitution`]) in them
+    /// are expanded before they are validated, unless quoted (variables only in single
+    /// quotes), and are kept as typed when completing paths. A resolver can e.g. give
+    /// the home of a remote user in server modes, or a temporary directory in tests. When it
+    /// returns `None`, `~` is left as it is.
+    pub fn home_dir(mut self, resolver: impl Fn() -> Option<PathBuf> + 'static) -> Self {
+        self.home_dir = Rc::new(resolver);
+        self
+    }
+
+    /// Manage a current directory for the session, starting at `dir`, for file-manager-like
+    /// REPLs. Disabled by default.
+    ///
+    /// It is separate from the current directory of the process, so that sessions (e.g. one
+    /// per remote connection) can each have their own. Built-in commands `cd` and `pwd`
+    /// change and show it, unless there are commands with the same names. Commands get it
+    /// with [`CommandContext::cwd`], relative paths are completed and glob patterns are
+    /// expanded in it, and [`Repl::set_working_dir`] changes it.
+    pub fn working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.working_dir = Some(dir.into());
+        self
+    }
+
+    /// Decide with `validator` whether pressing Enter runs the line typed in the terminal or
+    /// starts a new line of the same input, e.g. while parentheses are unbalanced in an
+    /// expression REPL, see [`InputStatus`].
+    ///
+    /// Input spanning several lines is then handled as a single command, with line breaks
+    /// separating arguments like spaces, instead of one command per pasted line.
+    #[cfg(feature = "terminal")]
+    pub fn input_validator(mut self, validator: impl Fn(&str) -> InputStatus + 'static) -> Self {
+        self.input_validator = Some(Rc::new(validator));
+        self
+    }
+
+    /// Show the output of commands that does not fit in the terminal through `command`,
+    /// e.g. `"less -R"`. Disabled by default.
+    ///
+    /// Output is only paged when [`ReplBuilder::out`] is the terminal. Ending a command line
+    /// with `--no-pager` prints its output directly, and `set-option pager off` disables the
+    /// pager. Output of paged commands is shown once they finish.
+    #[cfg(feature = "terminal")]
+    pub fn pager(mut self, command: impl Into<String>) -> Self {
+        self.pager = Some(command.into());
+        self
+    }
+
+    /// Let environment variables starting with `prefix` and `_` override the configuration
+    /// when building, so deployed tools can be tuned without code changes:
+    ///
+    /// - `<PREFIX>_PROMPT` replaces the prompt,
+    /// - `<PREFIX>_HISTFILE` sets [`ReplBuilder::history_file`],
+    /// - `<PREFIX>_EDIT_MODE` (`emacs` or `vi`) sets the edit mode of [`ReplBuilder::keymap`].
+    ///
+    /// Independently of the prefix, and also when none is set, a non-empty `NO_COLOR`
+    /// variable disables [`ReplBuilder::theme`], see <https://no-color.org>. Variables that
+    /// are not set leave the configuration unchanged.
+    pub fn env_prefix(mut self, prefix: &str) -> Self {
+        self.env_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Apply the overrides described in [`ReplBuilder::env_prefix`], reading variables
+    /// with `env`.
+    fn env_overrides(mut self, env: impl Fn(&str) -> Option<String>) -> Result<Self, BuilderError> {
+        #[cfg(feature = "terminal")]
+        if env("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            self.theme = Theme::default();
+        }
+        let Some(prefix) = self.env_prefix.clone() else {
+            return Ok(self);
         };
-        let prefix = &args[0];
-        let mut candidates = completion_candidates(&self.trie, prefix);
-        let exact = !candidates.is_empty() && &candidates[0] == prefix;
-        let can_take_first = !candidates.is_empty() && (exact || self.predict_commands);
-        if !can_take_first {
-            writeln!(&mut self.out, "Command not found: {prefix}")?;
-            if candidates.len() > 1 || (!self.predict_commands && !exact) {
-                candidates.sort();
-                writeln!(&mut self.out, "Candidates:\n  {}", candidates.join("\n  "))?;
-            }
-            writeln!(&mut self.out, "Use 'help' to see available commands.")?;
-            Ok(LoopStatus::Continue)
-        } else {
-            let name = &candidates[0];
-            let tail: Vec<_> = args[1..].iter().map(String::as_str).collect();
-            match self.handle_command(name, &tail).await {
-                Ok(CommandStatus::Done) => Ok(LoopStatus::Continue),
-                Ok(CommandStatus::Quit) => Ok(LoopStatus::Break),
-                Err(err) if err.downcast_ref::<CriticalError>().is_some() => Err(err),
-                Err(err) => {
-                    // other errors are handled here
-                    writeln!(&mut self.out, "Error: {err}")?;
-                    if err.is::<ArgsError>() {
-                        // in case of ArgsError we know it could not have been a reserved command
-                        let cmds = self.commands.get_mut(name).unwrap();
-                        writeln!(&mut self.out, "Usage:")?;
-                        for cmd in cmds.iter() {
-                            writeln!(
-                                &mut self.out,
-                                "  {} {}",
-                                name,
-                                cmd.args_info
-                                    .clone()
-                                    .into_iter()
-                                    .map(|info| info.to_string())
-                                    .collect::<Vec<_>>()
-                                    .join(" ")
-                            )?;
-                        }
-                    }
-                    Ok(LoopStatus::Continue)
-                }
+        let var = |name: &str| {
+            let name = format!("{prefix}_{name}");
+            env(&name).map(|value| (name, value))
+        };
+        if let Some((_, prompt)) = var("PROMPT") {
+            self.prompt = prompt;
+        }
+        #[cfg(feature = "terminal")]
+        {
+            if let Some((_, path)) = var("HISTFILE").filter(|(_, path)| !path.is_empty()) {
+                self.history_file = Some(path.into());
+            }
+            if let Some((name, mode)) = var("EDIT_MODE") {
+                let mode = crate::keymap::parse_edit_mode(&mode)
+                    .map_err(|_e| BuilderError::InvalidEnvVar(name, mode))?;
+                self.keymap = std::mem::take(&mut self.keymap).edit_mode(mode);
             }
         }
+        Ok(self)
     }
 
-    /// Run a single REPL iteration and return whether this is the last one or not.
-    pub async fn next(&mut self) -> anyhow::Result<LoopStatus> {
-        match self.editor.readline(&self.prompt) {
-            Ok(line) => {
-                if !line.trim().is_empty() {
-                    self.editor.add_history_entry(line.trim());
-                    self.handle_line(&line).await
-                } else {
-                    Ok(LoopStatus::Continue)
-                }
+    /// Make `name` run command `line`, with any arguments typed after `name` appended.
+    ///
+    /// Unlike command names, aliases are never abbreviated. Adding an alias with the same
+    /// name again replaces it.
+    pub fn alias(mut self, name: &str, line: &str) -> Self {
+        self.aliases.insert(name.into(), line.into());
+        self
+    }
+
+    /// Make `name` run command line `template`, with `$1` to `$9` replaced by the arguments
+    /// typed after `name` and `$@` by all of them, e.g. `rollout $1 --env $2`.
+    ///
+    /// Unlike aliases, snippets take exactly the arguments they use, unless they use `$@`.
+    /// The template can also be inserted by a key, to be filled in before running it, see
+    /// `snippet:NAME` in [`keymap`](crate::keymap). Adding a snippet with the same name
+    /// again replaces it.
+    pub fn snippet(mut self, name: &str, template: &str) -> Self {
+        self.snippets.insert(name.into(), template.into());
+        self
+    }
+
+    /// Add a command with given `name`. Use along with the [`command!`] macro.
+    pub fn add(mut self, name: &str, cmd: Command) -> Self {
+        self.commands.push((name.into(), cmd));
+        self
+    }
+
+    /// Add all commands from a [`CommandProvider`].
+    ///
+    /// The provider is kept, so that its commands can be replaced with the ones it provides
+    /// later with [`Repl::reload_providers`].
+    pub fn add_provider<P: CommandProvider + 'static>(mut self, provider: P) -> Self {
+        let provider: Arc<dyn CommandProvider> = Arc::new(provider);
+        let commands = provider.commands();
+        let mut names: Vec<String> = Vec::new();
+        for (name, _) in &commands {
+            if !names.contains(name) {
+                names.push(name.clone());
             }
-            Err(ReadlineError::Interrupted) => {
-                writeln!(&mut self.out, "CTRL-C")?;
-                Ok(LoopStatus::Break)
+        }
+        self.commands.extend(commands);
+        self.providers.push((provider, names));
+        self
+    }
+
+    /// Use commands from an already built [`CommandRegistry`].
+    ///
+    /// This allows to create multiple sessions sharing the same commands (and any state
+    /// captured by their handlers) without registering them again. Commands cannot be
+    /// added with [`ReplBuilder::add`] when using a shared registry.
+    pub fn registry(mut self, registry: Arc<CommandRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Build only the [`CommandRegistry`] with commands added so far.
+    ///
+    /// Other settings are ignored, they should be specified for each session instead.
+    pub fn build_registry(self) -> Result<Arc<CommandRegistry>, BuilderError> {
+        make_registry(self.commands, self.providers, self.registry, &self.runtime)
+    }
+
+    /// Look for commands that would make the REPL confusing to use, without building it.
+    ///
+    /// Reports names that are prefixes of other names, names sharing a prefix (only when
+    /// [`ReplBuilder::predict_commands`] is enabled) and overloads shadowed by other
+    /// overloads. Errors that make building fail are not part of the report.
+    pub fn check(&self) -> Report {
+        let mut overloads = Overloads::new();
+        if let Some(registry) = &self.registry {
+            for (name, cmds) in registry.commands().iter() {
+                // overloads parsing their own arguments cannot be analyzed
+                let types = cmds
+                    .iter()
+                    .filter(|cmd| !cmd.raw_args)
+                    .map(|cmd| cmd.arg_type_list())
+                    .collect();
+                overloads.insert(name.clone(), types);
             }
-            Err(ReadlineError::Eof) => Ok(LoopStatus::Break),
-            // TODO: not sure if these should be propagated or handler here
-            Err(err) => {
-                writeln!(&mut self.out, "Error: {err:?}")?;
-                Ok(LoopStatus::Continue)
+        }
+        for (name, cmd) in &self.commands {
+            let types = overloads.entry(name.clone()).or_default();
+            if !cmd.raw_args {
+                types.push(cmd.arg_type_list());
             }
         }
+        diagnose(&overloads, self.predict_commands)
     }
 
-    async fn handle_command(&mut self, name: &str, args: &[&str]) -> anyhow::Result<CommandStatus> {
-        match name {
-            "help" => {
-                let help = self.help();
-                writeln!(&mut self.out, "{help}")?;
-                Ok(CommandStatus::Done)
-            }
-            "quit" => Ok(CommandStatus::Quit),
-            _ => {
-                // find_command must have returned correct name
+    /// Same as [`ReplBuilder::build`] but also returns the result of [`ReplBuilder::check`].
+    pub fn build_with_report(self) -> Result<(Repl, Report), BuilderError> {
+        let report = self.check();
+        Ok((self.build()?, report))
+    }
 
-                // if all commands are not possible to call because of argument error
-                // return the last argument one as our result
-                let mut last_arg_err = None;
-                let cmds = self.commands.get_mut(name).unwrap();
-                for cmd in cmds.iter_mut() {
-                    match cmd.execute(args).await {
-                        Err(e) => {
-                            if !e.is::<ArgsError>() {
-                                return Err(e);
-                            } else {
-                                last_arg_err = Some(Err(e));
-                            }
-                        }
-                        other => return other,
-                    }
-                }
-                // last_arg_err should always have at least a value here
-                last_arg_err.unwrap()
+    /// Same as [`ReplBuilder::build`] but also rejects configurations that are most likely
+    /// a mistake:
+    /// * no commands (besides the [`RESERVED`] ones),
+    /// * filename completion enabled while no command takes a `String` or `Custom` argument,
+    /// * completion cache enabled while no argument has a completer.
+    pub fn build_strict(self) -> Result<Repl, BuilderError> {
+        if !self.any_command(|_| true) {
+            return Err(BuilderError::NoCommands);
+        }
+        #[cfg(feature = "terminal")]
+        {
+            let string_arg = |info: &CommandArgInfo| {
+                matches!(
+                    info.arg_type,
+                    CommandArgType::String | CommandArgType::Custom
+                )
+            };
+            if self.with_filename_completion
+                && !self.any_command(|cmd| cmd.args_info.iter().any(string_arg))
+            {
+                return Err(BuilderError::UnusedFilenameCompletion);
             }
         }
+        let completer = |info: &CommandArgInfo| info.completer.is_some();
+        if self.completion_cache_ttl.is_some()
+            && !self.any_command(|cmd| cmd.args_info.iter().any(completer))
+        {
+            return Err(BuilderError::UnusedCompletionCache);
+        }
+        self.build()
     }
 
-    /// Run the evaluation loop until [`LoopStatus::Break`] is received.
-    pub async fn run(&mut self) -> anyhow::Result<()> {
-        while self.next().await? == LoopStatus::Continue {}
-        Ok(())
+    fn any_command(&self, pred: impl Fn(&Command) -> bool) -> bool {
+        let shared = self.registry.as_ref().is_some_and(|registry| {
+            registry
+                .commands()
+                .values()
+                .any(|cmds| cmds.iter().any(|cmd| pred(cmd)))
+        });
+        shared || self.commands.iter().any(|(_, cmd)| pred(cmd))
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::command::{CommandArgInfo, CommandArgType, ExecuteCommand, TrivialCommandHandler};
-    use std::future::Future;
-    use std::pin::Pin;
 
-    #[test]
-    fn builder_duplicate() {
-        let command_x_1 = Command::new("Command X", vec![], Box::new(TrivialCommandHandler::new()));
+    /// Finalize the configuration and return the REPL or error.
+    pub fn build(self) -> Result<Repl, BuilderError> {
+        self.env_overrides(|name| std::env::var(name).ok())?
+            .assemble()
+    }
+
+    fn assemble(self) -> Result<Repl, BuilderError> {
+        let registry = make_registry(self.commands, self.providers, self.registry, &self.runtime)?;
+        for name in self.aliases.keys() {
+            check_alias(&registry, name)?;
+        }
+        for name in self.snippets.keys() {
+            check_snippet(&registry, &self.aliases, name)?;
+        }
+        let snippets: Snippets = Arc::new(std::sync::Mutex::new(self.snippets));
+        let clock = self
+            .clock
+            .unwrap_or_else(|| Arc::new(SystemClock::new(self.runtime.clone())));
+        let completion_cache = Rc::new(CompletionCache::new(
+            self.completion_cache_ttl,
+            clock.clone(),
+        ));
+        let working_dir = self.working_dir.map(|dir| Rc::new(RefCell::new(dir)));
+        let completion = Completion {
+            registry: registry.clone(),
+            with_hints: self.with_hints,
+            with_completion: self.with_completion,
+            completion_limit: self.completion_limit,
+            cache: completion_cache.clone(),
+            value_history: self.argument_history.then(ValueHistory::default),
+            home: self.home_dir.clone(),
+            working_dir: working_dir.clone(),
+            #[cfg(feature = "terminal")]
+            file_system: self
+                .with_filename_completion
+                .then_some(self.file_system.clone()),
+            #[cfg(feature = "terminal")]
+            theme: self.theme,
+            #[cfg(feature = "terminal")]
+            color: true,
+            #[cfg(feature = "terminal")]
+            printer: None,
+            #[cfg(feature = "terminal")]
+            validator: self.input_validator,
+        };
+        #[cfg(feature = "terminal")]
+        let key_commands: Vec<_> = self
+            .keymap
+            .commands()
+            .map(|(key, line)| (key, line.to_string()))
+            .collect();
+        #[cfg(feature = "terminal")]
+        let (last_input, idle_expired) = (
+            Arc::new(std::sync::Mutex::new(clock.now())),
+            Arc::new(AtomicBool::new(false)),
+        );
+        #[cfg(feature = "terminal")]
+        let idle_watch = self.idle_timeout.map(|timeout| IdleWatch {
+            timeout,
+            last_input: last_input.clone(),
+            expired: idle_expired.clone(),
+            clock: clock.clone(),
+        });
+        #[cfg(feature = "terminal")]
+        let printer;
+        #[cfg(feature = "terminal")]
+        let editor = {
+            let stream = self.out.stream();
+            let config = match self.editor_config {
+                Some(config) if stream.is_some_and(|s| s != config.output_stream()) => {
+                    return Err(BuilderError::OutputStreamMismatch);
+                }
+                Some(config) => config,
+                None => {
+                    default_editor_config(stream.unwrap_or(rustyline::OutputStreamType::Stderr))
+                }
+            };
+            printer = Printer::new(config.output_stream() == rustyline::OutputStreamType::Stderr);
+            let mut editor = rustyline::Editor::with_config(config);
+            editor.set_helper(Some(Completion {
+                printer: Some(printer.clone()),
+                ..completion
+            }));
+            self.history_limits.apply(&mut editor);
+            match (&self.history_file, &self.history_cipher) {
+                (Some(path), Some(cipher)) => {
+                    for entry in history::load(path, cipher.as_ref()) {
+                        editor.add_history_entry(entry);
+                    }
+                }
+                (Some(path), None) => {
+                    // the file is created on the first entry
+                    let _ = editor.load_history(path);
+                }
+                (None, _) => {}
+            }
+            if self.history_search == HistorySearch::Prefix {
+                let watch = idle_watch.as_ref();
+                editor.bind_sequence(
+                    KeyEvent::ctrl('R'),
+                    watched(Cmd::HistorySearchBackward, watch),
+                );
+                editor.bind_sequence(
+                    KeyEvent::ctrl('S'),
+                    watched(Cmd::HistorySearchForward, watch),
+                );
+            }
+            self.keymap.apply(&mut editor, idle_watch.as_ref());
+            for (key, name) in self.keymap.snippets() {
+                let handler = InsertSnippet {
+                    name: name.into(),
+                    snippets: snippets.clone(),
+                };
+                let handler = EventHandler::Conditional(Box::new(handler));
+                editor.bind_sequence(key, watched(handler, idle_watch.as_ref()));
+            }
+            if let Some(watch) = &idle_watch {
+                let watch = EventHandler::Conditional(Box::new(watch.clone()));
+                editor.bind_sequence(Event::Any, watch);
+            }
+            editor
+        };
+
+        let mut out = Sink::new(self.out);
+        out.verbosity = self.verbosity;
+        out.locale = self.locale;
+        #[cfg(feature = "json")]
+        let out = if self.json_output { out.json() } else { out };
+        #[allow(unused_mut)]
+        let mut repl = Repl {
+            description: self.description,
+            prompt: self.prompt,
+            mode: None,
+            temporary: Vec::new(),
+            tutorial: None,
+            #[cfg(feature = "fuzz")]
+            fuzzing: false,
+            jobs: Jobs::default(),
+            started: self.status_command.then(|| clock.now()),
+            stats: Stats::default(),
+            text_width: self.text_width,
+            registry,
+            #[cfg(feature = "terminal")]
+            editor,
+            #[cfg(feature = "terminal")]
+            key_commands: Vec::new(),
+            #[cfg(feature = "terminal")]
+            pending_command: Default::default(),
+            #[cfg(feature = "terminal")]
+            pending_input: None,
+            #[cfg(feature = "terminal")]
+            confirm_paste: self.confirm_paste,
+            #[cfg(feature = "terminal")]
+            history_file: self.history_file,
+            #[cfg(feature = "terminal")]
+            history_cipher: self.history_cipher,
+            #[cfg(feature = "terminal")]
+            pager: self.pager,
+            aliases: self.aliases,
+            snippets,
+            snippet_command: self.snippet_command,
+            correction: self.correction,
+            globbing: self.globbing,
+            variable_substitution: self.variable_substitution,
+            home_dir: self.home_dir,
+            working_dir,
+            previous_dir: None,
+            confirm_destructive: self.confirm_destructive,
+            numbered_candidates: None,
+            output_history: VecDeque::new(),
+            output_history_size: self.output_history,
+            status_command: self.status_command,
+            idle_timeout: self.idle_timeout,
+            end_of_input: self.end_of_input,
+            on_idle: self.on_idle,
+            slow_command: self.slow_command,
+            on_slow_command: self.on_slow_command,
+            on_critical_error: self.on_critical_error,
+            on_restart: self.on_restart,
+            lints: self.lints,
+            evaluator: self.evaluator,
+            last_result: None,
+            #[cfg(feature = "terminal")]
+            last_input,
+            #[cfg(feature = "terminal")]
+            idle_expired,
+            #[cfg(feature = "terminal")]
+            idle_watch,
+            #[cfg(feature = "terminal")]
+            printer,
+            #[cfg(not(feature = "terminal"))]
+            completion,
+            out,
+            predict_commands: self.predict_commands,
+            completion_limit: self.completion_limit,
+            completion_cache,
+            help_contents: self.help_contents,
+            help_cache: RefCell::new(None),
+            runtime: self.runtime,
+            clock,
+        };
+        #[cfg(feature = "terminal")]
+        for (key, line) in key_commands {
+            repl.bind_command(key, &line);
+        }
+        Ok(repl)
+    }
+}
+
+/// `duration` rounded to seconds, e.g. `1h 02m 03s`.
+fn format_uptime(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes:02}m {secs:02}s")
+    } else if minutes > 0 {
+        format!("{minutes}m {secs:02}s")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// Check that `name` can be used for an alias of a command in `registry`.
+fn check_alias(registry: &CommandRegistry, name: &str) -> Result<(), BuilderError> {
+    let args = split_args(name).map_err(|_e| BuilderError::InvalidName(name.into()))?;
+    if args.len() != 1 || name.is_empty() {
+        return Err(BuilderError::InvalidName(name.into()));
+    } else if RESERVED.iter().any(|(n, _)| *n == name) {
+        return Err(BuilderError::ReservedName(name.into()));
+    } else if registry.contains(name) {
+        return Err(BuilderError::AliasConflict(name.into()));
+    }
+    Ok(())
+}
+
+/// Check that `name` can be used for a snippet besides the commands in `registry` and
+/// `aliases`.
+fn check_snippet(
+    registry: &CommandRegistry,
+    aliases: &BTreeMap<String, String>,
+    name: &str,
+) -> Result<(), BuilderError> {
+    match check_alias(registry, name) {
+        Err(BuilderError::AliasConflict(_)) => Err(BuilderError::SnippetConflict(name.into())),
+        Err(err) => Err(err),
+        Ok(()) if aliases.contains_key(name) => Err(BuilderError::SnippetConflict(name.into())),
+        Ok(()) => Ok(()),
+    }
+}
+
+fn make_registry(
+    commands: Vec<(String, Command)>,
+    providers: Vec<Provided>,
+    shared: Option<Arc<CommandRegistry>>,
+    runtime: &Arc<dyn Runtime>,
+) -> Result<Arc<CommandRegistry>, BuilderError> {
+    if let Some(registry) = shared {
+        return match commands.into_iter().next() {
+            Some((name, _)) => Err(BuilderError::SharedRegistry(name)),
+            None => Ok(registry),
+        };
+    }
+    let registry = CommandRegistry::new(commands, runtime.clone())?;
+    for (provider, names) in providers {
+        registry.track_provider(provider, names);
+    }
+    Ok(Arc::new(registry))
+}
+
+impl Repl {
+    /// Start [`ReplBuilder`] with default values.
+    pub fn builder() -> ReplBuilder {
+        ReplBuilder::default()
+    }
+
+    /// Commands available in this REPL.
+    ///
+    /// Can be used to add or remove commands at runtime, or to create more sessions sharing them.
+    pub fn registry(&self) -> &Arc<CommandRegistry> {
+        &self.registry
+    }
+
+    /// Metadata of all commands (excluding [`RESERVED`] ones), sorted by name.
+    ///
+    /// Allows building custom UIs or documentation on top of the registered commands.
+    pub fn commands(&self) -> impl Iterator<Item = CommandInfo> {
+        self.registry.infos().into_iter()
+    }
+
+    /// Query the providers of commands again, replacing their commands with the ones they
+    /// provide now, e.g. to pick up new plugins, see
+    /// [`CommandRegistry::reload_providers`].
+    ///
+    /// The change is visible to all sessions sharing the registry.
+    pub fn reload_providers(&self) -> Result<(), BuilderError> {
+        self.registry.reload_providers()?;
+        self.invalidate_completions(None);
+        Ok(())
+    }
+
+    /// Drop cached argument completion results for `command` (or all commands if `None`).
+    ///
+    /// See [`ReplBuilder::completion_cache_ttl`].
+    pub fn invalidate_completions(&self, command: Option<&str>) {
+        self.completion_cache.invalidate(command);
+    }
+
+    /// Restart the session without leaving [`Repl::run`], as when a command returns
+    /// [`CommandStatus::Restart`].
+    ///
+    /// The mode, the candidates listed for an ambiguous prefix and cached completions are
+    /// cleared, and then [`ReplBuilder::on_restart`] is called. History, statistics and
+    /// background jobs are kept.
+    pub fn restart(&mut self) -> anyhow::Result<()> {
+        self.set_mode(None);
+        self.numbered_candidates = None;
+        self.invalidate_completions(None);
+        let Some(mut hook) = self.on_restart.take() else {
+            return Ok(());
+        };
+        let result = hook(self);
+        self.on_restart = Some(hook);
+        result
+    }
+
+    /// Change the width used for wrapping help text, e.g. after the terminal has been resized.
+    pub fn set_text_width(&mut self, text_width: usize) {
+        self.text_width = text_width;
+    }
+
+    /// Current verbosity, see [`ReplBuilder::verbosity`].
+    pub fn verbosity(&self) -> Verbosity {
+        self.out.verbosity
+    }
+
+    /// Change the verbosity of the REPL and of handlers following it.
+    pub fn set_verbosity(&mut self, verbosity: Verbosity) {
+        self.out.verbosity = verbosity;
+    }
+
+    /// Current locale, see [`ReplBuilder::locale`].
+    pub fn locale(&self) -> Locale {
+        self.out.locale
+    }
+
+    /// Change the locale of the REPL and of handlers following it.
+    pub fn set_locale(&mut self, locale: Locale) {
+        self.out.locale = locale;
+    }
+
+    /// Current values of the options that can be changed with [`Repl::set_option`].
+    pub fn options(&self) -> Vec<(&'static str, String)> {
+        #[allow(unused_mut)]
+        let mut options = vec![
+            ("prompt", self.prompt.clone()),
+            ("predict-commands", self.predict_commands.to_string()),
+            ("text-width", self.text_width.to_string()),
+            ("verbosity", self.verbosity().to_string()),
+        ];
+        #[cfg(feature = "terminal")]
+        options.push(("color", self.completion().color.to_string()));
+        #[cfg(feature = "terminal")]
+        options.push(("pager", self.pager.as_deref().unwrap_or("off").to_string()));
+        options
+    }
+
+    /// Change option `name`, taking effect from the next prompt. Options are:
+    ///
+    /// - `prompt`, see [`ReplBuilder::prompt`],
+    /// - `predict-commands` (`true` or `false`), see [`ReplBuilder::predict_commands`],
+    /// - `text-width`, see [`Repl::set_text_width`],
+    /// - `verbosity` (`quiet`, `normal` or `verbose`), see [`ReplBuilder::verbosity`],
+    /// - `color` (`true` or `false`), whether to apply [`ReplBuilder::theme`],
+    /// - `pager`, the pager command, `off` to disable it or `on` to use `$PAGER` or `less -R`,
+    ///   see [`ReplBuilder::pager`].
+    ///
+    /// The built-in `set-option NAME VALUE` command calls this method, and `set-option`
+    /// without arguments lists [`Repl::options`].
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), OptionError> {
+        let invalid = || OptionError::InvalidValue {
+            name: name.into(),
+            value: value.into(),
+        };
+        match name {
+            "prompt" => self.prompt = value.into(),
+            "predict-commands" => self.predict_commands = value.parse().map_err(|_e| invalid())?,
+            "text-width" => self.set_text_width(value.parse().map_err(|_e| invalid())?),
+            "verbosity" => self.set_verbosity(value.parse().map_err(|_e| invalid())?),
+            #[cfg(feature = "terminal")]
+            "color" => {
+                let color = value.parse().map_err(|_e| invalid())?;
+                if let Some(completion) = self.editor.helper_mut() {
+                    completion.color = color;
+                }
+            }
+            #[cfg(feature = "terminal")]
+            "pager" => {
+                self.pager = match value {
+                    "off" => None,
+                    "on" => Some(std::env::var("PAGER").unwrap_or_else(|_e| "less -R".into())),
+                    command => Some(command.into()),
+                }
+            }
+            _ => return Err(OptionError::UnknownOption(name.into())),
+        }
+        Ok(())
+    }
+
+    fn set_option_builtin(&mut self, args: &[&str]) -> anyhow::Result<()> {
+        match args {
+            [] => {
+                let entries: Vec<_> = self
+                    .options()
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), shell_words::quote(&value).into()))
+                    .collect();
+                let table = self.format_help_entries(&entries);
+                writeln!(&mut self.out, "{}", table.trim_start_matches('\n'))?;
+            }
+            [name, value] => self.set_option(name, value)?,
+            _ => anyhow::bail!("usage: set-option [NAME VALUE]"),
+        }
+        Ok(())
+    }
+
+    /// Prompt shown before each line of input, with its placeholders replaced, see
+    /// [`prompt`](crate::prompt).
+    pub fn prompt(&self) -> Cow<'_, str> {
+        self.render_prompt(false)
+    }
+
+    fn render_prompt(&self, continuation: bool) -> Cow<'_, str> {
+        let state = PromptState {
+            mode: self.mode.as_deref(),
+            jobs: self.jobs.count(),
+            continuation,
+        };
+        prompt::render(&self.prompt, &state)
+    }
+
+    /// Show `mode` in the prompt with the `{mode}` placeholder, e.g. while commands apply to
+    /// a part of the application. `None` hides it.
+    ///
+    /// Changing the mode removes the temporary commands added with [`Scope::Mode`], see
+    /// [`Repl::add_temporary`].
+    pub fn set_mode(&mut self, mode: Option<&str>) {
+        if self.mode.as_deref() != mode {
+            self.remove_temporary(Scope::Mode);
+        }
+        self.mode = mode.map(str::to_string);
+    }
+
+    /// Add command `name` until `scope` ends: until the mode changes (see
+    /// [`Repl::set_mode`]) or until the session ends, e.g. `disconnect` and the commands of
+    /// a connection after `connect`.
+    ///
+    /// Handlers can add them with [`CommandContext::add_temporary`]. Temporary commands are
+    /// added to the [registry](Repl::registry) as overloads, also when it is shared with
+    /// other sessions, and only this overload is removed when the scope ends: other overloads
+    /// of the name, permanent or added by other sessions, are kept. They can be removed
+    /// earlier with [`CommandRegistry::remove`].
+    ///
+    /// [`CommandContext::add_temporary`]: crate::command::CommandContext::add_temporary
+    pub fn add_temporary(
+        &mut self,
+        name: &str,
+        cmd: Command,
+        scope: Scope,
+    ) -> Result<(), BuilderError> {
+        let cmd = self.registry.insert(name, cmd)?;
+        self.temporary.push((name.to_string(), cmd, scope));
+        Ok(())
+    }
+
+    /// Remove the temporary commands of `scope` that still exist.
+    fn remove_temporary(&mut self, scope: Scope) {
+        let registry = &self.registry;
+        self.temporary.retain(|(name, cmd, temporary)| {
+            if *temporary == scope {
+                registry.remove_overload(name, cmd);
+            }
+            *temporary != scope
+        });
+    }
+
+    /// Apply the changes requested by a handler through its
+    /// [`CommandContext`](crate::command::CommandContext).
+    fn apply_changes(&mut self, changes: Vec<SessionChange>) -> std::io::Result<()> {
+        for change in changes {
+            match change {
+                SessionChange::Mode(mode) => self.set_mode(mode.as_deref()),
+                SessionChange::Temporary { name, cmd, scope } => {
+                    if let Err(err) = self.add_temporary(&name, *cmd, scope) {
+                        self.out.error(err)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Guide the user through `tutorial`, printing the instruction of its first step.
+    ///
+    /// Each step then waits for a command line matching the expected one: other commands
+    /// are not run and the expected line is shown instead, while built-in commands such as
+    /// `help` still work. The tutorial advances once the expected command succeeds, and
+    /// ends after the last step or with [`Repl::stop_tutorial`]. Replaces a tutorial in
+    /// progress.
+    pub fn start_tutorial(&mut self, tutorial: Tutorial) -> std::io::Result<()> {
+        let instruction = tutorial.instruction(0);
+        self.tutorial = (!tutorial.is_empty()).then_some((tutorial, 0));
+        if let Some(instruction) = instruction {
+            writeln!(&mut self.out, "{instruction}")?;
+        }
+        Ok(())
+    }
+
+    /// End the tutorial in progress, returning it.
+    pub fn stop_tutorial(&mut self) -> Option<Tutorial> {
+        self.tutorial.take().map(|(tutorial, _)| tutorial)
+    }
+
+    /// Index (starting at 0) of the current step of the tutorial in progress, if any.
+    pub fn tutorial_step(&self) -> Option<usize> {
+        self.tutorial.as_ref().map(|(_, step)| *step)
+    }
+
+    /// Move to the next step of the tutorial in progress, printing its instruction or the
+    /// final message after the last step.
+    fn advance_tutorial(&mut self) -> std::io::Result<()> {
+        let Some((tutorial, step)) = &mut self.tutorial else {
+            return Ok(());
+        };
+        *step += 1;
+        match tutorial.instruction(*step) {
+            Some(instruction) => writeln!(&mut self.out, "{instruction}"),
+            None => {
+                let message = tutorial.finished_message().to_string();
+                self.tutorial = None;
+                writeln!(&mut self.out, "{message}")
+            }
+        }
+    }
+
+    /// Mode shown in the prompt, see [`Repl::set_mode`].
+    pub fn mode(&self) -> Option<&str> {
+        self.mode.as_deref()
+    }
+
+    /// Current directory of the session, if it manages one, see [`ReplBuilder::working_dir`].
+    pub fn working_dir(&self) -> Option<PathBuf> {
+        self.working_dir.as_ref().map(|dir| dir.borrow().clone())
+    }
+
+    /// Change the current directory of the session to `dir`, relative to the current one,
+    /// like the `cd` command. Fails if `dir` is not a directory or the session does not
+    /// manage one, see [`ReplBuilder::working_dir`].
+    pub fn set_working_dir(&mut self, dir: impl AsRef<Path>) -> anyhow::Result<()> {
+        let resolved = self.resolve_working_dir(dir.as_ref())?;
+        if let Some(working_dir) = &self.working_dir {
+            self.previous_dir = Some(working_dir.replace(resolved));
+        }
+        // completions of relative paths are now in another directory
+        self.completion().cache.invalidate(None);
+        Ok(())
+    }
+
+    /// Directory that [`Repl::set_working_dir`] would change to.
+    fn resolve_working_dir(&self, dir: &Path) -> anyhow::Result<PathBuf> {
+        let Some(working_dir) = &self.working_dir else {
+            anyhow::bail!("the current directory is not managed by the REPL");
+        };
+        let resolved = paths::resolve(&working_dir.borrow(), dir);
+        if !resolved.is_dir() {
+            anyhow::bail!("not a directory: {}", dir.display());
+        }
+        Ok(resolved)
+    }
+
+    /// Last result printed by [`ReplBuilder::evaluator`], available as `$_`.
+    pub fn last_result(&self) -> Option<&str> {
+        self.last_result.as_deref()
+    }
+
+    /// Counter of background jobs shown in the prompt with the `{jobs}` placeholder.
+    ///
+    /// Tasks running in the background can hold a [`Job`](crate::prompt::Job) while they
+    /// run, so that the prompt shows how many there are.
+    pub fn jobs(&self) -> Jobs {
+        self.jobs.clone()
+    }
+
+    /// Completions of `line` with cursor at byte `pos`, as offered on TAB.
+    ///
+    /// Returns the position from which the line is to be replaced and the candidates. This
+    /// allows frontends other than the terminal to offer the same completions. A `pos` past
+    /// the end of the line or inside a character is moved back to the previous character.
+    pub fn complete(&self, line: &str, pos: usize) -> (usize, Vec<Candidate>) {
+        self.completion().complete(line, clamp_pos(line, pos))
+    }
+
+    /// Hint shown after the cursor at byte `pos` of `line`, see [`ReplBuilder::with_hints`].
+    pub fn hint(&self, line: &str, pos: usize) -> Option<String> {
+        let pos = clamp_pos(line, pos);
+        self.completion().hint(line, pos).map(|hint| hint.display)
+    }
+
+    #[cfg(feature = "terminal")]
+    fn completion(&self) -> &Completion {
+        self.editor.helper().expect("helper is set when building")
+    }
+
+    #[cfg(not(feature = "terminal"))]
+    fn completion(&self) -> &Completion {
+        &self.completion
+    }
+
+    /// Async runtime configured with [`ReplBuilder::runtime`].
+    pub fn runtime(&self) -> &dyn Runtime {
+        self.runtime.as_ref()
+    }
+
+    /// Source of time configured with [`ReplBuilder::clock`].
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    fn format_help_entries(&self, entries: &[(String, String)]) -> String {
+        if entries.is_empty() {
+            return String::new();
+        }
+        // display width, as names may contain wide or combining characters
+        let width = entries.iter().map(|(sig, _)| sig.width()).max().unwrap();
+        entries
+            .iter()
+            .map(|(sig, desc)| {
+                let indent = " ".repeat(width + 2 + 2);
+                let opts = textwrap::Options::new(self.text_width)
+                    .initial_indent("")
+                    .subsequent_indent(&indent);
+                let padding = " ".repeat(width - sig.width());
+                let line = format!("  {sig}{padding}  {desc}");
+                textwrap::fill(&line, opts)
+            })
+            .fold(String::new(), |mut out, next| {
+                out.push('\n');
+                out.push_str(&next);
+                out
+            })
+    }
+
+    /// Detailed help of command `name` with usage of each overload, or `None` if there is
+    /// no such command.
+    ///
+    /// This is printed when a command is called with `-h` or `--help` as its first argument.
+    /// Links to the documentation (see [`Command::docs_url`]) are terminal hyperlinks when
+    /// the output is a terminal.
+    pub fn command_help(&self, name: &str) -> Option<String> {
+        let cmds = self.registry.get(name);
+        if cmds.is_empty() {
+            return None;
+        }
+        let opts = textwrap::Options::new(self.text_width)
+            .initial_indent("  ")
+            .subsequent_indent("  ");
+        let hyperlinks = !self.out.is_json() && self.out.output().supports_hyperlinks();
+        let sections: Vec<_> = cmds
+            .iter()
+            .map(|cmd| {
+                let args: Vec<_> = cmd.args_info.iter().map(|info| info.to_string()).collect();
+                let mut section = format!("Usage: {} {}", name, args.join(" "))
+                    .trim_end()
+                    .to_string();
+                let mut notes = vec![cmd.description.clone()];
+                if let Some(category) = &cmd.category {
+                    notes.push(format!("Category: {category}"));
+                }
+                if let Some(note) = &cmd.deprecated {
+                    notes.push(format!("Deprecated: {note}"));
+                }
+                if let Some(cooldown) = cmd.cooldown {
+                    notes.push(format!("Can be run at most once every {cooldown:?}"));
+                }
+                for note in notes.iter().filter(|note| !note.is_empty()) {
+                    section.push('\n');
+                    section.push_str(&textwrap::fill(note, &opts));
+                }
+                // links are not wrapped, so that they can still be opened
+                if let Some(url) = &cmd.docs_url {
+                    let link = match hyperlinks {
+                        true => crate::output::hyperlink(url),
+                        false => url.clone(),
+                    };
+                    section.push_str(&format!("\n  Docs: {link}"));
+                }
+                section
+            })
+            .collect();
+        Some(sections.join("\n\n"))
+    }
+
+    /// Returns formatted help message.
+    ///
+    /// With more commands than [`ReplBuilder::help_contents`], it lists their categories
+    /// instead of the commands. Otherwise the text is rendered on first use and cached until
+    /// anything it shows changes: the commands in the registry, the aliases, snippets and
+    /// other built-in commands of the session, or the text width.
+    pub fn help(&self) -> String {
+        match self.help_contents {
+            Some(limit) if self.registry.len() > limit => self.render_contents(),
+            _ => self.full_help(),
+        }
+    }
+
+    /// Help listing all commands, printed by `help --all`.
+    fn full_help(&self) -> String {
+        let generation = self.registry.generation();
+        let other = self.other_help_entries();
+        if let Some(rendered) = &*self.help_cache.borrow() {
+            if rendered.generation == generation
+                && rendered.text_width == self.text_width
+                && rendered.other == other
+            {
+                return rendered.text.clone();
+            }
+        }
+        let text = self.render_help(&other);
+        *self.help_cache.borrow_mut() = Some(RenderedHelp {
+            generation,
+            text_width: self.text_width,
+            other,
+            text: text.clone(),
+        });
+        text
+    }
+
+    /// Help entries of the visible commands whose name and overload satisfy `filter`.
+    fn help_entries(&self, filter: impl Fn(&str, &Command) -> bool) -> Vec<(String, String)> {
+        let signature =
+            |name: &String, args_info: &Vec<String>| format!("{} {}", name, args_info.join(" "));
+        let commands = self.registry.commands();
+        commands
+            .iter()
+            .flat_map(|(key, cmds)| {
+                let abbreviations = self.registry.abbreviations_of(key);
+                let name = if abbreviations.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{key} ({})", abbreviations.join(", "))
+                };
+                let filter = &filter;
+                cmds.iter()
+                    .filter(move |cmd| !cmd.hidden && filter(key, cmd))
+                    .map(move |cmd| {
+                        let description = match &cmd.deprecated {
+                            Some(note) => format!("{} (deprecated: {})", cmd.description, note),
+                            None => cmd.description.clone(),
+                        };
+                        (signature(&name, &cmd.arg_types()), description)
+                    })
+            })
+            .collect()
+    }
+
+    /// Help of the commands in `namespace`, or `None` if there are none.
+    fn namespace_help(&self, namespace: &str) -> Option<String> {
+        let entries = self.help_entries(|name, _| in_namespace(name, namespace));
+        if entries.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "Commands in '{namespace}':\n{}",
+            self.format_help_entries(&entries)
+        ))
+    }
+
+    /// Help of the commands in `category`, or `None` if there are none, see
+    /// [`ReplBuilder::help_contents`].
+    fn category_help(&self, category: &str) -> Option<String> {
+        let mut entries = self
+            .help_entries(|_, cmd| cmd.category.as_deref().unwrap_or(UNCATEGORIZED) == category);
+        if entries.is_empty() {
+            return None;
+        }
+        entries.sort();
+        Some(format!(
+            "Commands in category '{category}':\n{}",
+            self.format_help_entries(&entries)
+        ))
+    }
+
+    /// Help listing the categories of commands with their number of commands, see
+    /// [`ReplBuilder::help_contents`].
+    fn render_contents(&self) -> String {
+        let mut categories: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        let commands = self.registry.commands();
+        for (name, cmds) in commands.iter() {
+            for cmd in cmds.iter().filter(|cmd| !cmd.hidden) {
+                let category = cmd.category.as_deref().unwrap_or(UNCATEGORIZED);
+                categories.entry(category).or_default().insert(name);
+            }
+        }
+        let entries: Vec<_> = categories
+            .into_iter()
+            .map(|(category, names)| {
+                let count = match names.len() {
+                    1 => "1 command".to_string(),
+                    n => format!("{n} commands"),
+                };
+                (category.to_string(), count)
+            })
+            .collect();
+        let msg = format!(
+            r#"
+{}
+
+Categories:
+{}
+
+Other commands:
+{}
+
+Use 'help CATEGORY' to list the commands of a category, or 'help --all' to list all of them.
+        "#,
+            self.description,
+            self.format_help_entries(&entries),
+            self.format_help_entries(&self.other_help_entries())
+        );
+        msg.trim().into()
+    }
+
+    /// Help entries of the built-in commands, aliases and snippets.
+    fn other_help_entries(&self) -> Vec<(String, String)> {
+        let mut other: Vec<_> = RESERVED
+            .iter()
+            .map(|(name, desc)| ((*name).to_string(), desc.to_string()))
+            .collect();
+        if self.status_command {
+            other.push((STATUS.0.to_string(), STATUS.1.to_string()));
+        }
+        for (name, desc) in DIR_COMMANDS {
+            if self.is_dir_command(name) {
+                other.push((name.to_string(), desc.to_string()));
+            }
+        }
+        other.extend(
+            self.aliases
+                .iter()
+                .map(|(name, line)| (name.clone(), format!("Alias of '{line}'"))),
+        );
+        if self.is_snippet_command(SNIPPET.0) {
+            other.push((SNIPPET.0.to_string(), SNIPPET.1.to_string()));
+        }
+        other.extend(
+            self.snippets()
+                .into_iter()
+                .map(|(name, template)| (name, format!("Snippet '{template}'"))),
+        );
+        other
+    }
+
+    fn render_help(&self, other: &[(String, String)]) -> String {
+        let user = self.help_entries(|_, _| true);
+        let msg = format!(
+            r#"
+{}
+
+Available commands:
+{}
+
+Other commands:
+{}
+        "#,
+            self.description,
+            self.format_help_entries(&user),
+            self.format_help_entries(other)
+        );
+        msg.trim().into()
+    }
+
+    /// Handle a single line of input as if it has been entered by the user.
+    ///
+    /// This can be used to drive a session from a source other than the terminal,
+    /// e.g. a remote connection.
+    pub async fn handle_line(&mut self, line: &str) -> anyhow::Result<LoopStatus> {
+        let status = self.handle_input(line).await;
+        #[cfg(feature = "json")]
+        if self.out.is_json() {
+            if let Err(err) = &status {
+                self.out.error(err)?;
+            }
+            self.out
+                .end_response(matches!(status, Ok(LoopStatus::Break)))?;
+        }
+        status
+    }
+
+    async fn handle_input(&mut self, line: &str) -> anyhow::Result<LoopStatus> {
+        // lines read from scripts or pipes may end with CRLF
+        let line = line.trim_end_matches(['\r', '\n']);
+        let selected = self
+            .numbered_candidates
+            .take()
+            .and_then(|candidates| candidates.select(line));
+        let line = selected.as_deref().unwrap_or(line);
+
+        #[cfg(feature = "json")]
+        if let Some((prefix, payload)) = crate::json_args::split_payload(line) {
+            let cmds = self
+                .resolve(prefix)
+                .map(|name| (self.registry.get(&name), name));
+            if let Some((cmds, name)) = cmds.filter(|(cmds, _)| cmds.iter().any(|c| c.json_args)) {
+                return match crate::json_args::expand(&cmds, payload) {
+                    Ok(args) => {
+                        let tail: Vec<_> = args.iter().map(String::as_str).collect();
+                        self.dispatch(&name, &tail, None).await
+                    }
+                    Err(err) => self.report_error(&name, &[], err.into(), None),
+                };
+            }
+        }
+
+        // if there is any parsing error just continue to next input
+        let args = match split_args(line) {
+            Err(_) if self.evaluator.is_some() => return self.evaluate(line),
+            Err(err) => {
+                self.out.error(err)?;
+                return Ok(LoopStatus::Continue);
+            }
+            Ok(args) if args.is_empty() => return Ok(LoopStatus::Continue),
+            Ok(args) => match self.expand_alias(args) {
+                Ok(args) => args,
+                Err(err) => {
+                    self.out.error(err)?;
+                    return Ok(LoopStatus::Continue);
+                }
+            },
+        };
+        let mut args = args;
+        let redirection = Redirection::split(&mut args);
+        let mut args = match self.expand_snippet(args) {
+            Ok(args) => args,
+            Err(err) => {
+                self.out.error(err)?;
+                return Ok(LoopStatus::Continue);
+            }
+        };
+        if let Some(last) = &self.last_result {
+            for arg in args.iter_mut().skip(1) {
+                if arg.as_str() == eval::LAST_RESULT {
+                    arg.text = Cow::Owned(last.clone());
+                }
+            }
+        }
+        let prefix = &args[0];
+        let mut resolved = self.resolve(prefix);
+        if resolved.is_none() && self.evaluator.is_some() {
+            return self.evaluate(line);
+        }
+        let mut suggestion = None;
+        if resolved.is_none() && self.correction != Correction::Off {
+            if let Some(known) = self.correction_for(prefix) {
+                match self.correction {
+                    Correction::Auto => {
+                        writeln!(&mut self.out, "Running '{known}' instead of '{prefix}'")?;
+                        resolved = Some(known);
+                    }
+                    #[cfg(feature = "terminal")]
+                    Correction::Prompt if self.interactive() => {
+                        let question = format!("Did you mean '{known}'? [Y/n] ");
+                        if self.confirm(&question, true)? {
+                            resolved = Some(known);
+                        }
+                    }
+                    _ => suggestion = Some(known),
+                }
+            }
+        }
+        match resolved {
+            Some(name) => {
+                if self.verbosity() == Verbosity::Verbose && name != prefix.as_str() {
+                    writeln!(&mut self.out, "Running '{name}'")?;
+                }
+                let args = match self.expand_args(&name, line, args.split_off(1)) {
+                    Ok(args) => args,
+                    Err(err) => return self.report_error(&name, &[], err, None),
+                };
+                let tail: Vec<_> = args.iter().map(Token::as_str).collect();
+                let source = SourceLine {
+                    line,
+                    spans: args.iter().map(|arg| arg.span.clone()).collect(),
+                };
+                let expected = match &self.tutorial {
+                    Some((tutorial, step)) => tutorial.matches(*step, &name, &tail),
+                    None => false,
+                };
+                if let Some((tutorial, step)) = self.tutorial.as_ref().filter(|_| !expected) {
+                    // built-in commands can still be used, e.g. to get help
+                    if self.registry.contains(&name) {
+                        let expected = tutorial.expected(*step).to_string();
+                        writeln!(&mut self.out, "This step expects: {expected}")?;
+                        return Ok(LoopStatus::Continue);
+                    }
+                }
+                let errors = self.stats.errors;
+                let status = self.run_command(&name, &tail, redirection, &source).await;
+                if expected && self.stats.errors == errors {
+                    self.advance_tutorial()?;
+                }
+                status
+            }
+            None => {
+                let candidates = self
+                    .registry
+                    .candidates(prefix, self.completion_limit.map(|limit| limit.max(1)));
+                if self.out.is_json() {
+                    self.out
+                        .error(format_args!("command not found: {prefix}"))?;
+                } else {
+                    writeln!(&mut self.out, "Command not found: {prefix}")?;
+                    self.out
+                        .record_error(format_args!("command not found: {prefix}"));
+                }
+                if let Some(known) = suggestion {
+                    writeln!(&mut self.out, "Did you mean '{known}'?")?;
+                }
+                if self.verbosity() == Verbosity::Quiet {
+                    return Ok(LoopStatus::Continue);
+                }
+                if !candidates.names.is_empty() {
+                    writeln!(&mut self.out, "Candidates:")?;
+                    for (i, name) in candidates.names.iter().enumerate() {
+                        writeln!(&mut self.out, "  {}) {name}", i + 1)?;
+                    }
+                    if candidates.remaining > 0 {
+                        writeln!(&mut self.out, "  …and {} more", candidates.remaining)?;
+                    }
+                    writeln!(&mut self.out, "Enter a number to run one of them.")?;
+                    self.numbered_candidates = Some(NumberedCandidates {
+                        names: candidates.names,
+                        args: args[1..].iter().map(|arg| arg.to_string()).collect(),
+                    });
+                }
+                writeln!(&mut self.out, "Use 'help' to see available commands.")?;
+                Ok(LoopStatus::Continue)
+            }
+        }
+    }
+
+    /// `args` with their first one replaced by the command line of the alias it names, if
+    /// it is an alias.
+    fn expand_alias<'a>(&self, args: Vec<Token<'a>>) -> anyhow::Result<Vec<Token<'a>>> {
+        let Some(line) = self.aliases.get(args[0].as_str()) else {
+            return Ok(args);
+        };
+        match split_args(line) {
+            Ok(expanded) if !expanded.is_empty() => {
+                // the expanded arguments are not in the line, they point to the alias
+                let span = &args[0].span;
+                let expanded: Vec<Token> = expanded
+                    .into_iter()
+                    .map(|token| Token {
+                        span: span.clone(),
+                        ..token.into_owned()
+                    })
+                    .collect();
+                Ok(expanded
+                    .into_iter()
+                    .chain(args.into_iter().skip(1))
+                    .collect())
+            }
+            _ => anyhow::bail!("invalid alias '{}'", args[0]),
+        }
+    }
+
+    /// `args` replaced by the expanded template of the snippet named by the first one, if it
+    /// is a snippet.
+    fn expand_snippet<'a>(&self, args: Vec<Token<'a>>) -> anyhow::Result<Vec<Token<'a>>> {
+        let Some(template) = snippet::get(&self.snippets, args[0].as_str()) else {
+            return Ok(args);
+        };
+        match snippet::expand(&template, &args[1..], args[0].span.clone()) {
+            Ok(expanded) if !expanded.is_empty() => Ok(expanded),
+            Ok(_) => anyhow::bail!("invalid snippet '{}'", args[0]),
+            Err(err) => anyhow::bail!("snippet '{}': {err}", args[0]),
+        }
+    }
+
+    /// Handle each line of `text` in order, as if they have been entered one after another.
+    ///
+    /// Stops after a command quits the REPL or returns a critical error, unless
+    /// [`ReplBuilder::on_critical_error`] decides otherwise.
+    pub async fn handle_lines(&mut self, text: &str) -> anyhow::Result<LoopStatus> {
+        for line in text.lines() {
+            if self.handle_line(line).await? == LoopStatus::Break {
+                return Ok(LoopStatus::Break);
+            }
+        }
+        Ok(LoopStatus::Continue)
+    }
+
+    /// Handle each line of `text` in order like [`Repl::handle_lines`], returning the lines
+    /// that failed besides printing their errors, see [`script`](crate::script).
+    ///
+    /// Stops after a command quits the REPL, and fails with critical errors like
+    /// [`Repl::handle_lines`].
+    pub async fn run_script(&mut self, text: &str) -> anyhow::Result<ScriptReport> {
+        let mut report = ScriptReport::default();
+        for (i, line) in text.lines().enumerate() {
+            self.out.record_errors();
+            let status = self.handle_line(line).await;
+            let errors = self.out.take_errors();
+            report.lines += 1;
+            if !errors.is_empty() {
+                report.failures.push(ScriptFailure {
+                    line: i + 1,
+                    command: line.trim().to_string(),
+                    error: errors.join("\n"),
+                });
+            }
+            if status? == LoopStatus::Break {
+                report.quit = true;
+                break;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Handle arbitrary `data` as input, so that applications can fuzz their commands, e.g.
+    /// with `cargo fuzz`:
+    /// ```rust,ignore
+    /// fuzz_target!(|data: &[u8]| {
+    ///     let mut repl = build_repl();
+    ///     let _ = runtime.block_on(repl.fuzz_line(data));
+    /// });
+    /// ```
+    /// Invalid UTF-8 is replaced, then each line is completed and hinted at its end and
+    /// handled like with [`Repl::handle_line`], until a command quits. Nothing is asked to
+    /// the user, the output is discarded and `| tee FILE` redirections are ignored, so that
+    /// runs are deterministic and do not write files. The REPL itself must not panic
+    /// whatever the input; panics of handlers are up to them.
+    ///
+    /// Requires the `fuzz` feature.
+    #[cfg(feature = "fuzz")]
+    pub async fn fuzz_line(&mut self, data: &[u8]) -> anyhow::Result<LoopStatus> {
+        let text = String::from_utf8_lossy(data);
+        let output = self.out.replace_output(Output::Null);
+        self.fuzzing = true;
+        let mut status = Ok(LoopStatus::Continue);
+        for line in text.lines() {
+            self.complete(line, line.len());
+            self.hint(line, line.len());
+            status = self.handle_line(line).await;
+            if !matches!(status, Ok(LoopStatus::Continue)) {
+                break;
+            }
+        }
+        self.fuzzing = false;
+        self.out.replace_output(output);
+        status
+    }
+
+    /// Replay the lines of `replay` as fast as possible, without asking anything or printing,
+    /// to benchmark the completion, hints, validation and dispatch of an application's
+    /// commands, e.g. with `criterion`:
+    /// ```rust
+    /// # use mini_async_repl::{Repl, runtime::block_on, script::Replay};
+    /// # fn bench(mut repl: Repl) {
+    /// let replay = Replay::new(["help", "status --verbose"]).hints(false);
+    /// // b.iter(|| ...)
+    /// block_on(repl.replay(&replay)).unwrap();
+    /// # }
+    /// ```
+    /// Each line is completed and hinted at its end and handled like with
+    /// [`Repl::handle_line`], as enabled by `replay`, until a command quits. The output is
+    /// discarded as with [`Output::Null`], so that terminal output does not dominate the
+    /// measurements.
+    pub async fn replay(&mut self, replay: &Replay) -> anyhow::Result<LoopStatus> {
+        let output = self.out.replace_output(Output::Null);
+        let mut status = Ok(LoopStatus::Continue);
+        for line in &replay.lines {
+            if replay.completion {
+                std::hint::black_box(self.complete(line, line.len()));
+            }
+            if replay.hints {
+                std::hint::black_box(self.hint(line, line.len()));
+            }
+            if replay.dispatch {
+                status = self.handle_line(line).await;
+                if !matches!(status, Ok(LoopStatus::Continue)) {
+                    break;
+                }
+            }
+        }
+        self.out.replace_output(output);
+        status
+    }
+
+    /// Play the lines of `text` as a demo: show the prompt, type each line one character at
+    /// a time with the delays of `demo` and run it, e.g. to record the terminal.
+    ///
+    /// The prompt and the typed lines are written to [`ReplBuilder::out`], as the line
+    /// editor is not used. Stops after a command quits the REPL, and fails with critical
+    /// errors like [`Repl::handle_lines`].
+    pub async fn play_demo(&mut self, text: &str, demo: &Demo) -> anyhow::Result<LoopStatus> {
+        for line in text.lines() {
+            let prompt = self.prompt().into_owned();
+            self.out.finish_line()?;
+            write!(&mut self.out, "{prompt}")?;
+            self.out.flush()?;
+            for c in line.chars() {
+                self.pause(demo.keystroke).await;
+                write!(&mut self.out, "{c}")?;
+                self.out.flush()?;
+            }
+            self.pause(demo.before_enter).await;
+            writeln!(&mut self.out)?;
+            if self.handle_line(line).await? == LoopStatus::Break {
+                return Ok(LoopStatus::Break);
+            }
+            self.out.flush()?;
+            self.pause(demo.after_command).await;
+        }
+        Ok(LoopStatus::Continue)
+    }
+
+    async fn pause(&self, delay: Duration) {
+        if !delay.is_zero() {
+            self.clock.sleep(delay).await;
+        }
+    }
+
+    /// Run independent command `lines` concurrently, at most `parallelism` at a time, and
+    /// return their output and errors in the order of the lines, see [`script`](crate::script).
+    ///
+    /// Lines run on the current task, so handlers awaiting e.g. network requests overlap,
+    /// while lines running the same command still wait for each other, as a command has a
+    /// single handler taking `&mut self`, whatever its kind: this includes
+    /// [`Send`](Command::new_send) and [heavy](Command::new_heavy) handlers. Commands run as
+    /// on the lines of the REPL, with help for `-h`, [retries](Command::retry) and
+    /// [cooldowns](Command::cooldown), and aliases, snippets and arguments are expanded as
+    /// usual, but built-in commands and redirections are not available,
+    /// [destructive](Command::destructive) commands are never confirmed and warnings go to
+    /// the output of their line. Commands quitting or restarting the session have no effect.
+    pub async fn run_batch<S: AsRef<str>>(
+        &mut self,
+        lines: &[S],
+        parallelism: usize,
+    ) -> BatchReport {
+        let this = &*self;
+        let results: Vec<(Option<String>, BatchResult, Vec<SessionChange>)> = stream::iter(lines)
+            .map(|line| this.batch_line(line.as_ref()))
+            .buffered(parallelism.max(1))
+            .collect()
+            .await;
+        let mut report = BatchReport::default();
+        for (name, result, changes) in results {
+            if let Some(name) = name {
+                self.stats
+                    .record(&name, result.duration, result.error.is_some());
+            }
+            // the output of the batch is in its report
+            let _ = self.apply_changes(changes);
+            report.results.push(result);
+        }
+        report
+    }
+
+    /// Run a line of [`Repl::run_batch`] with its own output, returning the name of the
+    /// command it ran, if any.
+    async fn batch_line(&self, line: &str) -> (Option<String>, BatchResult, Vec<SessionChange>) {
+        let mut out = Sink::new(Output::Writer(Box::new(std::io::sink())));
+        out.verbosity = self.verbosity();
+        out.locale = self.locale();
+        out.start_capture();
+        let mut name = None;
+        let mut changes = Vec::new();
+        let start = stats::now(&*self.clock);
+        let result = self
+            .run_batch_line(line, &mut out, &mut name, &mut changes)
+            .await;
+        let result = BatchResult {
+            command: line.trim().to_string(),
+            output: out.take_capture().unwrap_or_default(),
+            error: result.err().map(|err| match self.verbosity() {
+                Verbosity::Verbose => format!("{err:#}"),
+                _ => err.to_string(),
+            }),
+            duration: stats::since(&*self.clock, start),
+        };
+        (name, result, changes)
+    }
+
+    async fn run_batch_line(
+        &self,
+        line: &str,
+        out: &mut Sink,
+        command: &mut Option<String>,
+        changes: &mut Vec<SessionChange>,
+    ) -> anyhow::Result<()> {
+        let args = split_args(line)?;
+        if args.is_empty() {
+            return Ok(());
+        }
+        let mut args = self.expand_snippet(self.expand_alias(args)?)?;
+        let name = self
+            .resolve(&args[0])
+            .ok_or_else(|| anyhow::anyhow!("command not found: {}", args[0]))?;
+        if !self.registry.contains(&name) {
+            anyhow::bail!("built-in command '{name}' cannot run in a batch");
+        }
+        *command = Some(name.clone());
+        let args = self.expand_args(&name, line, args.split_off(1))?;
+        let mut args: Vec<&str> = args.iter().map(Token::as_str).collect();
+        match self.check_command(&name, &args)? {
+            Check::Help(help) => {
+                writeln!(out, "{help}")?;
+                return Ok(());
+            }
+            Check::Run(warnings) => {
+                for warning in warnings {
+                    writeln!(out, "Warning: {warning}")?;
+                }
+            }
+        }
+        if self.registry.get(&name).iter().any(|cmd| cmd.destructive)
+            && self.needs_confirmation(&mut args)
+        {
+            anyhow::bail!("'{name}' is destructive and cannot be confirmed in a batch");
+        }
+        let cwd = self.working_dir();
+        self.registry
+            .execute(&name, &args, out, cwd.as_deref(), changes, &self.clock)
+            .await
+            .map(|_status| ())
+    }
+
+    /// Checks before running command `name` of the registry with `args`, for both the
+    /// lines of the REPL and of a batch: its help is shown for `-h`, and it must not be in
+    /// its [cooldown](Command::cooldown) or [unavailable](Command::precondition).
+    fn check_command(&self, name: &str, args: &[&str]) -> anyhow::Result<Check> {
+        // commands parsing their own arguments may handle help flags themselves
+        let help_flag = matches!(args.first(), Some(&"-h" | &"--help"));
+        let cmds = self.registry.get(name);
+        if help_flag && !cmds.iter().any(|cmd| cmd.raw_args) {
+            if let Some(help) = self.command_help(name) {
+                return Ok(Check::Help(help));
+            }
+        }
+        // the cooldown starts atomically when the command runs, this only fails early
+        if let Some(remaining) = cmds
+            .iter()
+            .filter_map(|cmd| cmd.cooldown_remaining(&*self.clock))
+            .max()
+        {
+            return Err(cooldown_error(name, remaining));
+        }
+        if let Some(explanation) = self.registry.unavailable(name) {
+            anyhow::bail!("{explanation}");
+        }
+        let warnings = self.lints.iter().filter_map(|lint| lint(name, args));
+        Ok(Check::Run(warnings.collect()))
+    }
+
+    /// Ask a yes or no `question`, with `default` as the answer to an empty line.
+    #[cfg(feature = "terminal")]
+    fn confirm(&mut self, question: &str, default: bool) -> anyhow::Result<bool> {
+        self.out.finish_line()?;
+        let answer = match self.editor.readline(question) {
+            Ok(answer) => answer,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(match answer.trim() {
+            "" => default,
+            answer => matches!(answer, "y" | "Y" | "yes"),
+        })
+    }
+
+    /// Whether the user can be asked questions, i.e. input comes from a terminal and output
+    /// goes to it.
+    #[cfg(feature = "terminal")]
+    fn interactive(&self) -> bool {
+        use std::io::IsTerminal;
+        !self.out.is_json()
+            && self.out.output().stream().is_some()
+            && std::io::stdin().is_terminal()
+    }
+
+    /// Whether running a destructive command with `args` needs a confirmation under
+    /// [`ReplBuilder::confirm_destructive`], for both the lines of the REPL and of a batch.
+    /// Removes `--yes` from `args`.
+    fn needs_confirmation(&self, args: &mut Vec<&str>) -> bool {
+        let yes = match args.iter().position(|arg| *arg == "--yes") {
+            Some(index) => {
+                args.remove(index);
+                true
+            }
+            None => false,
+        };
+        match self.confirm_destructive {
+            Confirmation::Always => true,
+            Confirmation::UnlessYes => !yes,
+            Confirmation::Never => false,
+        }
+    }
+
+    /// Whether destructive command `name` may run, asking the user if needed, see
+    /// [`ReplBuilder::confirm_destructive`]. Removes `--yes` from `args`.
+    fn allow_destructive(&mut self, name: &str, args: &mut Vec<&str>) -> anyhow::Result<bool> {
+        if !self.needs_confirmation(args) {
+            return Ok(true);
+        }
+        #[cfg(feature = "terminal")]
+        if self.interactive() {
+            return self.confirm(&format!("Run destructive command '{name}'? [y/N] "), false);
+        }
+        match self.confirm_destructive {
+            Confirmation::UnlessYes => {
+                anyhow::bail!("'{name}' is destructive, add --yes to run it without confirmation")
+            }
+            _ => anyhow::bail!("'{name}' is destructive and can only be confirmed on a terminal"),
+        }
+    }
+
+    /// Known command within a small edit distance of `name`, if there is exactly one.
+    fn correction_for(&self, name: &str) -> Option<String> {
+        let max_distance = (name.chars().count() / 3).clamp(1, 2);
+        let commands = self.registry.commands();
+        let visible = commands
+            .iter()
+            .filter(|(_, cmds)| cmds.iter().any(|cmd| !cmd.hidden))
+            .map(|(known, _)| known.as_str());
+        let mut close = visible
+            .chain(RESERVED.iter().map(|(known, _)| *known))
+            .filter(|known| edit_distance(name, known) <= max_distance);
+        match (close.next(), close.next()) {
+            (Some(only), None) => Some(only.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Whether input is checked by [`ReplBuilder::input_validator`].
+    #[cfg(feature = "terminal")]
+    fn validates_input(&self) -> bool {
+        self.editor
+            .helper()
+            .is_some_and(|helper| helper.validator.is_some())
+    }
+
+    /// Run multiple lines inserted with bracketed paste, see [`ReplBuilder::confirm_paste`].
+    #[cfg(feature = "terminal")]
+    async fn handle_paste(&mut self, text: &str) -> anyhow::Result<LoopStatus> {
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        if self.confirm_paste && lines.len() > 1 {
+            writeln!(&mut self.out, "Pasted {} commands:", lines.len())?;
+            for line in &lines {
+                writeln!(&mut self.out, "  {line}")?;
+            }
+            if !self.confirm("Run them? [y/N] ", false)? {
+                writeln!(&mut self.out, "Cancelled")?;
+                return Ok(LoopStatus::Continue);
+            }
+        }
+        for line in &lines {
+            self.add_history_entry(line);
+        }
+        self.handle_lines(&lines.join("\n")).await
+    }
+
+    /// Expand `~`, variables and glob patterns given to path arguments of command `name`,
+    /// and variables given to other arguments with [`ReplBuilder::variable_substitution`],
+    /// see also [`ReplBuilder::home_dir`] and [`ReplBuilder::globbing`]. Files matching a
+    /// pattern take the span of the pattern.
+    fn expand_args<'a>(
+        &self,
+        name: &str,
+        line: &str,
+        args: Vec<Token<'a>>,
+    ) -> anyhow::Result<Vec<Token<'a>>> {
+        let cmds = self.registry.get(name);
+        let mut expanded = Vec::with_capacity(args.len());
+        for (index, mut arg) in args.into_iter().enumerate() {
+            if let Some(number) = self.delocalize(&cmds, index, &arg) {
+                arg.text = Cow::Owned(number);
+            }
+            let is_path = cmds.iter().any(|cmd| cmd.is_path_arg(index, &arg));
+            if !is_path && !self.variable_substitution {
+                expanded.push(arg);
+                continue;
+            }
+            let raw = line.get(arg.span.clone()).unwrap_or_default();
+            // quoted or escaped arguments are not in the line as they are
+            let verbatim = raw == arg.as_str();
+            if is_path && raw.starts_with('~') {
+                if let Cow::Owned(path) = paths::expand_tilde(&arg, &*self.home_dir) {
+                    arg.text = Cow::Owned(path);
+                }
+            }
+            if subst::substitutes(raw) {
+                if let Cow::Owned(text) = subst::substitute(&arg, &subst::env)? {
+                    arg.text = Cow::Owned(text);
+                }
+            }
+            if !is_path || self.globbing == Globbing::Off || !verbatim || !glob::is_pattern(&arg) {
+                expanded.push(arg);
+                continue;
+            }
+            let cwd = self.working_dir();
+            let files = glob::expand(&arg, cwd.as_deref());
+            if files.is_empty() {
+                if self.globbing == Globbing::Error {
+                    anyhow::bail!("no files match '{arg}'");
+                }
+                expanded.push(arg);
+                continue;
+            }
+            expanded.extend(files.into_iter().map(|file| Token {
+                text: Cow::Owned(file),
+                span: arg.span.clone(),
+                quoted: true,
+            }));
+        }
+        Ok(expanded)
+    }
+
+    /// `arg`, given at `index` to a decimal argument of one of `cmds`, with the decimal
+    /// separator of the [locale](ReplBuilder::locale) replaced by a point, if it has one.
+    fn delocalize(&self, cmds: &[Arc<Command>], index: usize, arg: &str) -> Option<String> {
+        let locale = self.locale();
+        if locale == Locale::C {
+            return None;
+        }
+        let (key, value) = match arg.split_once('=') {
+            Some((key, value)) if cmds.iter().any(|cmd| cmd.named_arg(key).is_some()) => {
+                (Some(key), value)
+            }
+            _ => (None, arg),
+        };
+        let is_decimal = cmds.iter().any(|cmd| {
+            let info = match key {
+                Some(key) => cmd.named_arg(key),
+                None => cmd.positional_arg(index),
+            };
+            info.is_some_and(|info| info.arg_type == CommandArgType::F32)
+        });
+        let number = locale.delocalize(value).filter(|_| is_decimal)?;
+        Some(match key {
+            Some(key) => format!("{key}={number}"),
+            None => number,
+        })
+    }
+
+    /// Evaluate `line` with [`ReplBuilder::evaluator`], printing the result and keeping it
+    /// as `$_`.
+    fn evaluate(&mut self, line: &str) -> anyhow::Result<LoopStatus> {
+        let input = eval::substitute(line.trim(), self.last_result.as_deref()).into_owned();
+        let Some(evaluator) = &mut self.evaluator else {
+            return Ok(LoopStatus::Continue);
+        };
+        match evaluator.eval(&input) {
+            Ok(Some(value)) => {
+                writeln!(&mut self.out, "{value}")?;
+                self.last_result = Some(value);
+                Ok(LoopStatus::Continue)
+            }
+            Ok(None) => Ok(LoopStatus::Continue),
+            Err(err) => self.report_error("", &[], err, None),
+        }
+    }
+
+    /// Name of the command selected by `prefix`: exact match or, if
+    /// [`ReplBuilder::predict_commands`] is enabled, the only name starting with it.
+    fn resolve(&self, prefix: &str) -> Option<String> {
+        let candidates = self.registry.candidates(prefix, Some(1));
+        let total = candidates.names.len() + candidates.remaining;
+        let exact = candidates.names.first().map(String::as_str) == Some(prefix);
+        if (self.status_command && prefix == STATUS.0)
+            || self.is_dir_command(prefix)
+            || self.is_snippet_command(prefix)
+        {
+            Some(prefix.to_string())
+        } else if exact {
+            candidates.names.into_iter().next()
+        } else if let Some(name) = self.registry.expand_abbreviation(prefix) {
+            Some(name)
+        } else if total == 1 && self.predict_commands {
+            candidates.names.into_iter().next()
+        } else {
+            None
+        }
+    }
+
+    /// Dispatch the command, sending its output to the pager and to a file if requested.
+    async fn run_command(
+        &mut self,
+        name: &str,
+        args: &[&str],
+        redirection: Redirection,
+        source: &SourceLine<'_>,
+    ) -> anyhow::Result<LoopStatus> {
+        #[cfg(feature = "terminal")]
+        let paged = !redirection.no_pager
+            && self.pager.is_some()
+            && !self.out.is_json()
+            && self.out.output().stream().is_some();
+        #[cfg(feature = "terminal")]
+        if paged {
+            self.out.hold();
+        }
+        #[cfg(feature = "fuzz")]
+        let redirection = Redirection {
+            tee: redirection.tee.filter(|_| !self.fuzzing),
+            ..redirection
+        };
+        if redirection.tee.is_some() {
+            self.out.start_capture();
+        }
+        let status = self.dispatch(name, args, Some(source)).await;
+        let tee_result = match redirection.tee {
+            Some(tee) => {
+                let output = self.out.take_capture().unwrap_or_default();
+                tee.write(&output).map_err(|err| (tee.path, err))
+            }
+            None => Ok(()),
+        };
+        #[cfg(feature = "terminal")]
+        if let Some(output) = self.out.release() {
+            self.show_paged(&output)?;
+        }
+        if let Err((path, err)) = tee_result {
+            self.out
+                .error(format_args!("cannot write '{}': {err}", path.display()))?;
+        }
+        status
+    }
+
+    /// Print output held back for the pager, through the pager if it does not fit in the
+    /// terminal.
+    #[cfg(feature = "terminal")]
+    fn show_paged(&mut self, output: &str) -> std::io::Result<()> {
+        let overflows = self
+            .editor
+            .dimensions()
+            .is_some_and(|(columns, rows)| screen_lines(output, columns) >= rows);
+        let Some(pager) = self.pager.as_deref().filter(|_| overflows) else {
+            return self.out.write_all(output.as_bytes());
+        };
+        if let Err(err) = page(pager, output) {
+            let pager = pager.to_string();
+            self.out.write_all(output.as_bytes())?;
+            self.out
+                .error(format_args!("cannot run pager '{pager}': {err}"))?;
+        }
+        Ok(())
+    }
+
+    async fn dispatch(
+        &mut self,
+        name: &str,
+        args: &[&str],
+        source: Option<&SourceLine<'_>>,
+    ) -> anyhow::Result<LoopStatus> {
+        #[cfg(feature = "json")]
+        self.out.set_command(name);
+        let record = self.output_history_size > 0 && name != "show-output";
+        if record {
+            self.out.start_capture();
+        }
+        let start = stats::now(&*self.clock);
+        let result = self.handle_command(name, args).await;
+        let duration = stats::since(&*self.clock, start);
+        self.stats.record(name, duration, result.is_err());
+        self.report_slow(name, duration)?;
+        let status = match result {
+            Ok(status) => {
+                if let Some(history) = &self.completion().value_history {
+                    history.record(name, &self.registry.get(name), args);
+                }
+                match status {
+                    CommandStatus::Done => Ok(LoopStatus::Continue),
+                    CommandStatus::Quit => Ok(LoopStatus::Break),
+                    CommandStatus::Restart => match self.restart() {
+                        Ok(()) => Ok(LoopStatus::Continue),
+                        Err(err) => self.report_error(name, args, err, None),
+                    },
+                }
+            }
+            Err(err) => self.report_error(name, args, err, source),
+        };
+        if let Some(output) = record.then(|| self.out.take_capture()).flatten() {
+            self.output_history.truncate(self.output_history_size - 1);
+            self.output_history.push_front(output);
+        }
+        status
+    }
+
+    /// Statistics of the commands run since the session started or
+    /// [`Repl::reset_stats`] was called, see [`stats`](crate::stats).
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Start gathering statistics from scratch, returning the ones gathered so far, e.g. to
+    /// export them periodically.
+    pub fn reset_stats(&mut self) -> Stats {
+        std::mem::take(&mut self.stats)
+    }
+
+    /// Report a command that took `duration`, if longer than [`ReplBuilder::slow_command`].
+    fn report_slow(&mut self, name: &str, duration: Duration) -> std::io::Result<()> {
+        if self
+            .slow_command
+            .is_none_or(|threshold| duration <= threshold)
+        {
+            return Ok(());
+        }
+        if self.verbosity() != Verbosity::Quiet {
+            self.out.finish_line()?;
+            let took = self.locale().format_duration(duration);
+            writeln!(self.out, "took {took}")?;
+        }
+        if let Some(hook) = &mut self.on_slow_command {
+            hook(name, duration);
+        }
+        Ok(())
+    }
+
+    /// Current state of the session, see [`state`](crate::state).
+    #[cfg(feature = "config")]
+    pub fn state(&self) -> SessionState {
+        SessionState {
+            mode: self.mode.clone(),
+            working_dir: self.working_dir(),
+            options: self
+                .options()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            aliases: self.aliases.clone(),
+            snippets: self.snippets(),
+            #[cfg(feature = "terminal")]
+            bindings: self
+                .key_commands()
+                .map(|(key, line)| (key_name(key), line.to_string()))
+                .collect(),
+            #[cfg(feature = "terminal")]
+            history: self.editor.history().iter().cloned().collect(),
+        }
+    }
+
+    /// Restore a state returned by [`Repl::state`]. Options, the mode and the current directory
+    /// are set and aliases, snippets and key bindings are added over the current ones, while
+    /// the history is replaced.
+    ///
+    /// Nothing is restored if any entry cannot be, e.g. an alias with the name of a command
+    /// added since the state was saved.
+    #[cfg(feature = "config")]
+    pub fn restore_state(&mut self, state: SessionState) -> Result<(), StateError> {
+        let working_dir = match &state.working_dir {
+            Some(dir) => Some(
+                self.resolve_working_dir(dir)
+                    .map_err(StateError::WorkingDir)?,
+            ),
+            None => None,
+        };
+        for name in state.aliases.keys() {
+            check_alias(&self.registry, name)?;
+        }
+        let mut aliases = self.aliases.clone();
+        aliases.extend(state.aliases);
+        for name in state.snippets.keys() {
+            check_snippet(&self.registry, &aliases, name)?;
+        }
+        #[cfg(feature = "terminal")]
+        let bindings = state
+            .bindings
+            .iter()
+            .map(|(key, line)| Ok((parse_key(key)?, line)))
+            .collect::<Result<Vec<_>, crate::keymap::KeymapError>>()?;
+        // options are checked by setting them, so they are set back if one is invalid
+        let previous = self.options();
+        for (name, value) in &state.options {
+            if let Err(err) = self.set_option(name, value) {
+                for (name, value) in &previous {
+                    let _ = self.set_option(name, value);
+                }
+                return Err(err.into());
+            }
+        }
+
+        self.set_mode(state.mode.as_deref());
+        if let Some(dir) = working_dir {
+            self.set_working_dir(dir).map_err(StateError::WorkingDir)?;
+        }
+        self.aliases = aliases;
+        self.snippets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(state.snippets);
+        #[cfg(feature = "terminal")]
+        {
+            for (key, line) in bindings {
+                self.bind_command(key, line);
+            }
+            self.editor.clear_history();
+            for entry in state.history {
+                self.editor.add_history_entry(entry);
+            }
+        }
+        Ok(())
+    }
+
+    /// Save [`Repl::state`] to `path`, see [`state`](crate::state).
+    #[cfg(feature = "config")]
+    pub fn save_state(&self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        self.state().save(path)
+    }
+
+    /// Restore the state saved to `path` with [`Repl::save_state`], see
+    /// [`Repl::restore_state`].
+    #[cfg(feature = "config")]
+    pub fn load_state(&mut self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        self.restore_state(SessionState::load(path)?)
+    }
+
+    /// Time without input after which the session ends, see [`ReplBuilder::idle_timeout`] for
+    /// the limits of the terminal.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    /// Handle printing above the prompt from other threads or tasks, e.g. log messages or
+    /// notifications of background jobs, see [`printer`](crate::printer).
+    #[cfg(feature = "terminal")]
+    pub fn printer(&self) -> Printer {
+        self.printer.clone()
+    }
+
+    /// What the session does when its input ends, see [`ReplBuilder::end_of_input`].
+    pub fn end_of_input(&self) -> EndOfInput {
+        self.end_of_input
+    }
+
+    /// Run [`ReplBuilder::on_idle`], or end the session, once the idle timeout ran out.
+    pub(crate) fn expire_idle(&mut self) -> std::io::Result<LoopStatus> {
+        if let Some(hook) = &mut self.on_idle {
+            return Ok(hook());
+        }
+        writeln!(&mut self.out, "Session expired due to inactivity")?;
+        Ok(LoopStatus::Break)
+    }
+
+    /// Output of the `n`-th last command, starting from 1, if it is still kept in the
+    /// history, see [`ReplBuilder::output_history`].
+    pub fn recent_output(&self, n: usize) -> Option<&str> {
+        let index = n.checked_sub(1)?;
+        self.output_history.get(index).map(String::as_str)
+    }
+
+    fn status_builtin(&mut self, args: &[&str]) -> anyhow::Result<()> {
+        if !args.is_empty() {
+            anyhow::bail!("usage: {}", STATUS.0);
+        }
+        #[allow(unused_mut)]
+        let mut lines = vec![
+            ("Commands run", self.stats.commands.to_string()),
+            ("Errors", self.stats.errors.to_string()),
+            ("Background jobs", self.jobs.count().to_string()),
+        ];
+        #[cfg(feature = "terminal")]
+        lines.push(("History entries", self.editor.history().len().to_string()));
+        if let Some(started) = self.started {
+            lines.push((
+                "Uptime",
+                format_uptime(self.clock.now().saturating_duration_since(started)),
+            ));
+        }
+        lines.push(("Mode", self.mode.clone().unwrap_or_else(|| "none".into())));
+        for (name, value) in lines {
+            writeln!(&mut self.out, "{:<16} {value}", format!("{name}:"))?;
+        }
+        Ok(())
+    }
+
+    /// Whether `name` is one of the [`DIR_COMMANDS`] and is not taken by a command.
+    fn is_dir_command(&self, name: &str) -> bool {
+        self.working_dir.is_some()
+            && DIR_COMMANDS.iter().any(|(known, _)| *known == name)
+            && !self.registry.contains(name)
+    }
+
+    /// Whether `name` is the [`SNIPPET`] command and is not taken by a command.
+    fn is_snippet_command(&self, name: &str) -> bool {
+        self.snippet_command && name == SNIPPET.0 && !self.registry.contains(name)
+    }
+
+    /// Command line templates of the snippets by name, see [`ReplBuilder::snippet`].
+    pub fn snippets(&self) -> BTreeMap<String, String> {
+        let snippets = self.snippets.lock().unwrap_or_else(PoisonError::into_inner);
+        snippets.clone()
+    }
+
+    /// Add or replace snippet `name`, see [`ReplBuilder::snippet`].
+    pub fn add_snippet(&mut self, name: &str, template: &str) -> Result<(), BuilderError> {
+        check_snippet(&self.registry, &self.aliases, name)?;
+        let mut snippets = self.snippets.lock().unwrap_or_else(PoisonError::into_inner);
+        snippets.insert(name.into(), template.into());
+        Ok(())
+    }
+
+    /// Remove snippet `name`, returning false if there was none.
+    pub fn remove_snippet(&mut self, name: &str) -> bool {
+        let mut snippets = self.snippets.lock().unwrap_or_else(PoisonError::into_inner);
+        snippets.remove(name).is_some()
+    }
+
+    fn snippet_builtin(&mut self, args: &[&str]) -> anyhow::Result<()> {
+        match args {
+            [] => {
+                let entries: Vec<_> = self.snippets().into_iter().collect();
+                if entries.is_empty() {
+                    writeln!(&mut self.out, "No snippets")?;
+                } else {
+                    for (name, template) in entries {
+                        writeln!(&mut self.out, "{name} = {template}")?;
+                    }
+                }
+            }
+            ["-d" | "--delete", name] => {
+                if !self.remove_snippet(name) {
+                    anyhow::bail!("no snippet '{name}'");
+                }
+            }
+            [name] => {
+                let template = snippet::get(&self.snippets, name)
+                    .ok_or_else(|| anyhow::anyhow!("no snippet '{name}'"))?;
+                writeln!(&mut self.out, "{template}")?;
+            }
+            [name, "=", words @ ..] if !words.is_empty() => {
+                self.add_snippet(name, &snippet::join(words))?;
+            }
+            _ => anyhow::bail!("usage: snippet [NAME [= LINE] | -d NAME]"),
+        }
+        Ok(())
+    }
+
+    fn cd_builtin(&mut self, args: &[&str]) -> anyhow::Result<()> {
+        let dir = match args {
+            [] => (self.home_dir)().ok_or_else(|| anyhow::anyhow!("no home directory"))?,
+            ["-"] => {
+                let previous = self
+                    .previous_dir
+                    .clone()
+                    .ok_or_else(|| anyhow::anyhow!("no previous directory"))?;
+                writeln!(&mut self.out, "{}", previous.display())?;
+                previous
+            }
+            [dir] => PathBuf::from(paths::expand(dir, &*self.home_dir).as_ref()),
+            _ => anyhow::bail!("usage: cd [DIR]"),
+        };
+        self.set_working_dir(dir)
+    }
+
+    fn show_output_builtin(&mut self, args: &[&str]) -> anyhow::Result<()> {
+        let n = match args {
+            [] => 1,
+            [n] => n
+                .parse()
+                .map_err(|_e| anyhow::anyhow!("invalid number '{n}'"))?,
+            _ => anyhow::bail!("usage: show-output [N]"),
+        };
+        if self.output_history_size == 0 {
+            anyhow::bail!("output history is disabled");
+        }
+        match self.recent_output(n) {
+            Some(output) => {
+                let output = output.to_string();
+                self.out.write_all(output.as_bytes())?;
+                Ok(())
+            }
+            None => anyhow::bail!("no output of command {n}"),
+        }
+    }
+
+    fn report_error(
+        &mut self,
+        name: &str,
+        args: &[&str],
+        err: anyhow::Error,
+        source: Option<&SourceLine<'_>>,
+    ) -> anyhow::Result<LoopStatus> {
+        let mut restart = false;
+        if let Some(critical) = err.downcast_ref::<CriticalError>() {
+            let action = match &mut self.on_critical_error {
+                Some(hook) => hook(critical),
+                None => CriticalAction::Terminate,
+            };
+            match action {
+                CriticalAction::Terminate => return Err(err),
+                CriticalAction::Restart => restart = true,
+                CriticalAction::Print => {}
+            }
+        }
+        // other errors are handled here
+        match self.verbosity() {
+            Verbosity::Verbose => self.out.error(format_args!("{err:#}"))?,
+            _ => self.out.error(&err)?,
+        }
+        if restart {
+            if let Err(err) = self.restart() {
+                self.out.error(&err)?;
+            }
+        }
+        if let Some(args_err) = err.downcast_ref::<ArgsError>() {
+            if self.verbosity() != Verbosity::Quiet {
+                if let Some(source) = source.filter(|_| !self.out.is_json()) {
+                    self.print_caret(name, args, args_err, source)?;
+                }
+                self.print_usage(name, args)?;
+            }
+        }
+        Ok(LoopStatus::Continue)
+    }
+
+    /// Print the command line with a caret under the argument `err` is about, if there is
+    /// one, e.g.:
+    ///
+    /// ```text
+    ///   add 1 x
+    ///         ^ expected i32
+    /// ```
+    fn print_caret(
+        &mut self,
+        name: &str,
+        args: &[&str],
+        err: &ArgsError,
+        source: &SourceLine,
+    ) -> std::io::Result<()> {
+        let index = match err {
+            ArgsError::WrongArgumentValue { argument, .. } => args
+                .iter()
+                .position(|arg| arg == argument)
+                // the value of a named argument
+                .or_else(|| {
+                    args.iter()
+                        .position(|arg| arg.split_once('=').is_some_and(|(_, v)| v == argument))
+                }),
+            ArgsError::UnexpectedToken { token, expected } => args
+                .iter()
+                .enumerate()
+                .skip(*expected)
+                .chain(args.iter().enumerate())
+                .find(|(_, arg)| *arg == token)
+                .map(|(i, _)| i),
+            ArgsError::UnknownFlag { flag, .. } => args
+                .iter()
+                .position(|arg| arg.split_once('=').is_some_and(|(key, _)| key == flag)),
+            _ => None,
+        };
+        let Some(span) = index.and_then(|i| source.spans.get(i)) else {
+            return Ok(());
+        };
+        if source.line.contains('\n') {
+            return Ok(());
+        }
+        let note = match err {
+            ArgsError::UnexpectedToken { .. } => "unexpected argument".to_string(),
+            ArgsError::UnknownFlag { .. } => "unknown flag".to_string(),
+            _ => match self.expected_type(name, args, index.unwrap_or_default()) {
+                Some(arg_type) => format!("expected {arg_type}"),
+                None => "invalid value".to_string(),
+            },
+        };
+        let indent = source.line[..span.start].width();
+        let width = source.line[span.clone()].width().max(1);
+        writeln!(&mut self.out, "  {}", source.line)?;
+        writeln!(
+            &mut self.out,
+            "  {}{} {note}",
+            " ".repeat(indent),
+            "^".repeat(width)
+        )
+    }
+
+    /// Type of argument `index` of `args` in the overloads of command `name` taking as many
+    /// arguments, if they agree on it.
+    fn expected_type(&self, name: &str, args: &[&str], index: usize) -> Option<CommandArgType> {
+        let cmds = self.registry.get(name);
+        let mut types = cmds
+            .iter()
+            .filter(|cmd| cmd.args_info.len() == args.len())
+            .filter_map(|cmd| {
+                let named = |arg: &str| {
+                    let (key, _) = arg.split_once('=')?;
+                    cmd.args_info
+                        .iter()
+                        .position(|info| info.name.as_deref() == Some(key))
+                };
+                // positional arguments fill the places not taken by named ones, in order
+                let place = named(args[index]).or_else(|| {
+                    let taken: Vec<usize> = args.iter().filter_map(|arg| named(arg)).collect();
+                    let before = args[..index].iter().filter(|arg| named(arg).is_none());
+                    (0..args.len())
+                        .filter(|place| !taken.contains(place))
+                        .nth(before.count())
+                })?;
+                Some(cmd.args_info[place].arg_type)
+            });
+        let first = types.next()?;
+        types.all(|t| t == first).then_some(first)
+    }
+
+    /// Print usage of the overloads of command `name` taking as many arguments as `args`, or
+    /// of all of them if there are none.
+    fn print_usage(&mut self, name: &str, args: &[&str]) -> std::io::Result<()> {
+        // in case of ArgsError we know it could not have been a reserved command
+        let mut cmds = self.registry.get(name);
+        if cmds.iter().any(|cmd| cmd.args_info.len() == args.len()) {
+            cmds.retain(|cmd| cmd.args_info.len() == args.len());
+        }
+        writeln!(&mut self.out, "Usage:")?;
+        for cmd in cmds.iter() {
+            writeln!(
+                &mut self.out,
+                "  {} {}",
+                name,
+                cmd.args_info
+                    .iter()
+                    .map(|info| info.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Run a single REPL iteration and return whether this is the last one or not.
+    #[cfg(feature = "terminal")]
+    pub async fn next(&mut self) -> anyhow::Result<LoopStatus> {
+        self.out.finish_line()?;
+        *self.last_input.lock().unwrap() = self.clock.now();
+        let typed = self.pending_input.take();
+        let prompt = self.render_prompt(false).into_owned();
+        self.printer
+            .show_prompt(&prompt, typed.as_deref().unwrap_or_default());
+        let input = match typed {
+            Some(typed) => self.editor.readline_with_initial(&prompt, (&typed, "")),
+            None => self.editor.readline(&prompt),
+        };
+        self.printer.hide_prompt();
+        match input {
+            Ok(line) => {
+                let command = self.pending_command.lock().unwrap().take();
+                if let Some(command) = command {
+                    if !line.is_empty() {
+                        self.pending_input = Some(line);
+                    }
+                    self.handle_line(&command).await
+                } else if line.contains('\n') && !self.validates_input() {
+                    self.handle_paste(&line).await
+                } else if !line.trim().is_empty() {
+                    let Some(line) = self.read_continuation(line) else {
+                        return Ok(LoopStatus::Continue);
+                    };
+                    self.add_history_entry(line.trim());
+                    self.handle_line(&line).await
+                } else {
+                    Ok(LoopStatus::Continue)
+                }
+            }
+            Err(ReadlineError::Interrupted) if self.idle_expired.swap(false, Ordering::Relaxed) => {
+                Ok(self.expire_idle()?)
+            }
+            Err(ReadlineError::Interrupted) => {
+                writeln!(&mut self.out, "CTRL-C")?;
+                Ok(LoopStatus::Break)
+            }
+            Err(ReadlineError::Eof) => Ok(LoopStatus::Break),
+            // TODO: not sure if these should be propagated or handler here
+            Err(err) => {
+                writeln!(&mut self.out, "Error: {err:?}")?;
+                Ok(LoopStatus::Continue)
+            }
+        }
+    }
+
+    async fn handle_command(&mut self, name: &str, args: &[&str]) -> anyhow::Result<CommandStatus> {
+        match name {
+            "help" => {
+                let help = match args {
+                    [] => self.help(),
+                    ["--all"] => self.full_help(),
+                    [name] => self
+                        .namespace_help(name)
+                        .or_else(|| self.command_help(name))
+                        .or_else(|| self.category_help(name))
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("no command, namespace or category '{name}'")
+                        })?,
+                    _ => anyhow::bail!("usage: help [NAME | --all]"),
+                };
+                writeln!(&mut self.out, "{help}")?;
+                Ok(CommandStatus::Done)
+            }
+            "quit" => Ok(CommandStatus::Quit),
+            #[cfg(feature = "terminal")]
+            "bind" => {
+                self.bind_builtin(args)?;
+                Ok(CommandStatus::Done)
+            }
+            "set-option" => {
+                self.set_option_builtin(args)?;
+                Ok(CommandStatus::Done)
+            }
+            "show-output" => {
+                self.show_output_builtin(args)?;
+                Ok(CommandStatus::Done)
+            }
+            name if self.status_command && name == STATUS.0 => {
+                self.status_builtin(args)?;
+                Ok(CommandStatus::Done)
+            }
+            name if self.is_snippet_command(name) => {
+                self.snippet_builtin(args)?;
+                Ok(CommandStatus::Done)
+            }
+            "cd" if self.is_dir_command(name) => {
+                self.cd_builtin(args)?;
+                Ok(CommandStatus::Done)
+            }
+            "pwd" if self.is_dir_command(name) => {
+                if !args.is_empty() {
+                    anyhow::bail!("usage: pwd");
+                }
+                let dir = self.working_dir().unwrap_or_default();
+                writeln!(&mut self.out, "{}", dir.display())?;
+                Ok(CommandStatus::Done)
+            }
+            _ => {
+                // find_command must have returned correct name
+                match self.check_command(name, args)? {
+                    Check::Help(help) => {
+                        writeln!(&mut self.out, "{help}")?;
+                        return Ok(CommandStatus::Done);
+                    }
+                    Check::Run(warnings) => {
+                        for warning in warnings {
+                            writeln!(&mut self.out, "Warning: {warning}")?;
+                        }
+                    }
+                }
+                let mut args = args.to_vec();
+                if self.registry.get(name).iter().any(|cmd| cmd.destructive)
+                    && !self.allow_destructive(name, &mut args)?
+                {
+                    writeln!(&mut self.out, "Cancelled")?;
+                    return Ok(CommandStatus::Done);
+                }
+                let cwd = self.working_dir();
+                let mut changes = Vec::new();
+                let result = self
+                    .registry
+                    .execute(
+                        name,
+                        &args,
+                        &mut self.out,
+                        cwd.as_deref(),
+                        &mut changes,
+                        &self.clock,
+                    )
+                    .await;
+                self.apply_changes(changes)?;
+                result
+            }
+        }
+    }
+
+    /// Bind `key` to run command `line` immediately when pressed, like `run:LINE` in a
+    /// [`Keymap`]. Text typed so far is kept for the next prompt.
+    #[cfg(feature = "terminal")]
+    pub fn bind_command(&mut self, key: KeyEvent, line: &str) {
+        let key = KeyEvent::normalize(key);
+        let handler = RunCommand {
+            line: line.into(),
+            pending: self.pending_command.clone(),
+        };
+        let handler = EventHandler::Conditional(Box::new(handler));
+        self.editor
+            .bind_sequence(key, watched(handler, self.idle_watch.as_ref()));
+        self.key_commands.retain(|(bound, _)| *bound != key);
+        self.key_commands.push((key, line.into()));
+    }
+
+    /// Remove a binding made with [`Repl::bind_command`], returning false if there was none.
+    #[cfg(feature = "terminal")]
+    pub fn unbind_command(&mut self, key: KeyEvent) -> bool {
+        let key = KeyEvent::normalize(key);
+        let count = self.key_commands.len();
+        self.key_commands.retain(|(bound, _)| *bound != key);
+        if self.key_commands.len() == count {
+            return false;
+        }
+        self.editor.unbind_sequence(key);
+        true
+    }
+
+    /// Keys bound to command lines.
+    #[cfg(feature = "terminal")]
+    pub fn key_commands(&self) -> impl Iterator<Item = (KeyEvent, &str)> {
+        self.key_commands
+            .iter()
+            .map(|(key, line)| (*key, line.as_str()))
+    }
+
+    /// Entries of the line editor history matching `text`, most recent first and without
+    /// duplicates, e.g. to show recently used commands.
+    #[cfg(feature = "terminal")]
+    pub fn history_search(&self, text: &str, search: HistorySearch) -> Vec<&str> {
+        let mut seen = std::collections::HashSet::new();
+        self.editor
+            .history()
+            .iter()
+            .rev()
+            .map(String::as_str)
+            .filter(|entry| match search {
+                HistorySearch::Substring => entry.contains(text),
+                HistorySearch::Prefix => entry.starts_with(text),
+            })
+            .filter(|entry| seen.insert(*entry))
+            .collect()
+    }
+
+    /// Read the next lines while `line` ends with a backslash continuing it, see
+    /// [`prompt::continues`]. Returns `None` if reading them is interrupted.
+    #[cfg(feature = "terminal")]
+    fn read_continuation(&mut self, mut line: String) -> Option<String> {
+        while prompt::continues(&line) {
+            let prompt = self.render_prompt(true).into_owned();
+            self.printer.show_prompt(&prompt, "");
+            let next = self.editor.readline(&prompt);
+            self.printer.hide_prompt();
+            line.push('\n');
+            line.push_str(&next.ok()?);
+        }
+        Some(line)
+    }
+
+    #[cfg(feature = "terminal")]
+    fn add_history_entry(&mut self, line: &str) {
+        if self.editor.add_history_entry(line) {
+            // losing history should not interrupt the session
+            match (&self.history_file, &self.history_cipher) {
+                (Some(path), Some(cipher)) => {
+                    let entries = self.editor.history().iter().map(String::as_str);
+                    let _ = history::save(path, cipher.as_ref(), entries);
+                }
+                (Some(path), None) => {
+                    let _ = self.editor.append_history(path);
+                }
+                (None, _) => {}
+            }
+        }
+    }
+
+    #[cfg(feature = "terminal")]
+    fn bind_builtin(&mut self, args: &[&str]) -> anyhow::Result<()> {
+        match args {
+            [] if self.key_commands.is_empty() => writeln!(&mut self.out, "No key bindings")?,
+            [] => {
+                let entries: Vec<_> = self
+                    .key_commands
+                    .iter()
+                    .map(|(key, line)| (key_name(*key), line.clone()))
+                    .collect();
+                let table = self.format_help_entries(&entries);
+                writeln!(&mut self.out, "{}", table.trim_start_matches('\n'))?;
+            }
+            [key] => {
+                if !self.unbind_command(parse_key(key)?) {
+                    anyhow::bail!("key '{key}' is not bound");
+                }
+            }
+            [key, line @ ..] => self.bind_command(parse_key(key)?, &shell_words::join(line)),
+        }
+        Ok(())
+    }
+
+    /// Run the evaluation loop until [`LoopStatus::Break`] is received.
+    #[cfg(feature = "terminal")]
+    pub async fn run(&mut self) -> anyhow::Result<()> {
+        while self.next().await? == LoopStatus::Continue {}
+        Ok(())
+    }
+
+    /// Completion script for `shell`, completing commands of program `bin_name` when used
+    /// with [`Repl::run_args`].
+    ///
+    /// Completes command names, `-h`/`--help`, `key=` names of arguments and values of
+    /// arguments with completers, which are queried once, when generating the script.
+    /// Hidden commands are omitted.
+    pub fn generate_completions(&self, shell: Shell, bin_name: &str) -> String {
+        crate::shell::generate(shell, bin_name, &self.registry.infos())
+    }
+
+    /// Manual page of program `name` in `section` (e.g. 1 for user commands), in the
+    /// `roff` format read by `man`, so that it can be shipped with the program.
+    ///
+    /// The page has the [description](ReplBuilder::description), the visible commands
+    /// grouped by [category](Command::category) with their arguments and the built-in
+    /// commands, aliases and snippets.
+    pub fn help_man(&self, name: &str, section: u8) -> String {
+        crate::man::render(
+            name,
+            section,
+            &self.description,
+            &self.registry.infos(),
+            &self.other_help_entries(),
+        )
+    }
+
+    /// Execute command line arguments as a single command, or run the evaluation loop
+    /// if there are none.
+    ///
+    /// `args` are expected to start with the program name, as returned by
+    /// [`std::env::args`]. This allows the same commands to be used both as
+    /// `mytool status` and in an interactive session:
+    /// ```rust,no_run
+    /// # use mini_async_repl::Repl;
+    /// # async fn main_() -> anyhow::Result<()> {
+    /// let mut repl = Repl::builder().build()?;
+    /// repl.run_args(std::env::args()).await
+    /// # }
+    /// ```
+    /// Arguments are used as given, without shell-like splitting. Unlike in the interactive
+    /// loop, an unknown command or a failing command results in an error, so that the
+    /// program can exit with non-zero status. Without the `terminal` feature there is no
+    /// evaluation loop, so no command is an error too.
+    pub async fn run_args<I>(&mut self, args: I) -> anyhow::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let args: Vec<String> = args
+            .into_iter()
+            .skip(1)
+            .map(|arg| arg.as_ref().to_string())
+            .collect();
+        let Some(prefix) = args.first() else {
+            #[cfg(feature = "terminal")]
+            return self.run().await;
+            #[cfg(not(feature = "terminal"))]
+            anyhow::bail!("no command given");
+        };
+        let name = self
+            .resolve(prefix)
+            .ok_or_else(|| anyhow::anyhow!("command not found: {prefix}"))?;
+        let tail: Vec<_> = args[1..].iter().map(String::as_str).collect();
+        match self.handle_command(&name, &tail).await {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                if err.is::<ArgsError>() {
+                    self.print_usage(&name, &tail)?;
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Drop for Repl {
+    /// Remove the temporary commands still added by the session from a registry that may
+    /// outlive it, see [`Repl::add_temporary`].
+    fn drop(&mut self) {
+        for (name, cmd, _) in &self.temporary {
+            self.registry.remove_overload(name, cmd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::command::{
+        CommandArgType, CommandContext, ExecuteCommand, ExecuteCommandWithContext, Precondition,
+        Retry, SendExecuteCommand, SyncCommandHandler, TrivialCommandHandler,
+    };
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    struct QuitCommandHandler;
+    impl ExecuteCommand for QuitCommandHandler {
+        fn execute<'a>(
+            &'a mut self,
+            _args: &'a [&'a str],
+            _args_info: &'a [CommandArgInfo],
+        ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+            Box::pin(async { Ok(CommandStatus::Quit) })
+        }
+    }
+
+    /// Output that can be inspected after being passed to the REPL.
+    #[derive(Clone, Default)]
+    pub(crate) struct Buffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Buffer {
+        pub(crate) fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Handler printing its arguments after a prefix, on a line of their own.
+    struct Echo(&'static str);
+
+    impl ExecuteCommandWithContext for Echo {
+        fn execute<'a>(
+            &'a mut self,
+            mut ctx: CommandContext<'a>,
+        ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+            Box::pin(async move {
+                let line = ctx.args().join(" ");
+                writeln!(ctx.out(), "{}{line}", self.0)?;
+                Ok(CommandStatus::Done)
+            })
+        }
+    }
+
+    #[test]
+    fn builder_duplicate() {
+        let command_x_1 = Command::new("Command X", vec![], Box::new(TrivialCommandHandler::new()));
+
+        let command_x_2 = Command::new(
+            "Command X 2",
+            vec![],
+            Box::new(TrivialCommandHandler::new()),
+        );
+
+        let result = Repl::builder()
+            .add("name_x", command_x_1)
+            .add("name_x", command_x_2)
+            .build();
+
+        assert!(matches!(result, Err(BuilderError::DuplicateCommands(_))));
+    }
+
+    #[test]
+    #[allow(clippy::useless_conversion, clippy::redundant_pattern_matching)]
+    fn builder_overload() {
+        let command_x_1 = Command::new(
+            "Command X".into(),
+            vec![],
+            Box::new(TrivialCommandHandler::new()),
+        );
 
         let command_x_2 = Command::new(
             "Command X 2",
+            vec![CommandArgInfo::new(CommandArgType::I32)],
+            Box::new(TrivialCommandHandler::new()),
+        );
+
+        #[rustfmt::skip]
+        let result = Repl::builder()
+            .add("name_x", command_x_1)
+            .add("name_x", command_x_2)
+            .build();
+        assert!(matches!(result, Ok(_)));
+    }
+
+    #[test]
+    fn builder_empty() {
+        let command_empty = Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+
+        let result = Repl::builder().add("", command_empty).build();
+        assert!(matches!(result, Err(BuilderError::InvalidName(_))));
+    }
+
+    #[test]
+    fn builder_spaces() {
+        let command_empty = Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+
+        let result = Repl::builder()
+            .add("name-with spaces", command_empty)
+            .build();
+        assert!(matches!(result, Err(BuilderError::InvalidName(_))));
+    }
+
+    #[test]
+    fn builder_reserved() {
+        let command_help = Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+
+        let result = Repl::builder().add("help", command_help).build();
+        assert!(matches!(result, Err(BuilderError::ReservedName(_))));
+
+        let command_quit = Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+
+        let result = Repl::builder().add("quit", command_quit).build();
+        assert!(matches!(result, Err(BuilderError::ReservedName(_))));
+    }
+
+    #[test]
+    fn environment_overrides() {
+        let env = |vars: &'static [(&str, &str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        let vars = &[("TOOL_PROMPT", "$ "), ("PROMPT", "x"), ("NO_COLOR", "1")];
+        let builder = Repl::builder()
+            .prompt("> ")
+            .env_overrides(env(vars))
+            .unwrap();
+        assert_eq!(builder.prompt, "> ");
+
+        let builder = Repl::builder().prompt("> ").env_prefix("TOOL");
+        let builder = builder.env_overrides(env(vars)).unwrap();
+        assert_eq!(builder.prompt, "$ ");
+
+        #[cfg(feature = "terminal")]
+        {
+            use crate::style::Style;
+
+            let theme = Theme {
+                hint: Style::new().dim(),
+                ..Default::default()
+            };
+            let builder = Repl::builder().theme(theme.clone()).env_prefix("TOOL");
+            let builder = builder.env_overrides(env(vars)).unwrap();
+            assert_eq!(builder.theme, Theme::default());
+            // NO_COLOR applies without a prefix too
+            let builder = Repl::builder().theme(theme.clone());
+            let builder = builder.env_overrides(env(vars)).unwrap();
+            assert_eq!(builder.theme, Theme::default());
+            let builder = Repl::builder().theme(theme.clone());
+            let builder = builder.env_overrides(env(&[("NO_COLOR", "")])).unwrap();
+            assert_eq!(builder.theme, theme);
+            assert_eq!(builder.history_file, None);
+
+            let vars = &[("TOOL_EDIT_MODE", "vi"), ("TOOL_HISTFILE", "/tmp/history")];
+            let builder = Repl::builder().env_prefix("TOOL");
+            let builder = builder.env_overrides(env(vars)).unwrap();
+            assert_eq!(
+                builder.keymap,
+                Keymap::new().edit_mode(rustyline::EditMode::Vi)
+            );
+            assert_eq!(builder.history_file, Some(PathBuf::from("/tmp/history")));
+
+            let vars = &[("TOOL_EDIT_MODE", "nano")];
+            let result = Repl::builder().env_prefix("TOOL").env_overrides(env(vars));
+            assert!(
+                matches!(result, Err(BuilderError::InvalidEnvVar(name, _)) if name == "TOOL_EDIT_MODE")
+            );
+        }
+    }
+
+    #[test]
+    fn builder_report() {
+        let command = || Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+        let builder = Repl::builder()
+            .add("run", command())
+            .add("running", command())
+            .predict_commands(false);
+        let report = builder.check();
+        assert_eq!(report.diagnostics.len(), 1);
+        assert_eq!(
+            report.to_string(),
+            "'run' is a prefix of 'running', it cannot be abbreviated\n"
+        );
+
+        let registry = Repl::builder()
+            .add("quiet", command())
+            .build_registry()
+            .unwrap();
+        let (_repl, report) = Repl::builder()
+            .registry(registry)
+            .build_with_report()
+            .unwrap();
+        assert!(report
+            .diagnostics
+            .contains(&crate::diagnostics::Diagnostic::SharedPrefix {
+                prefix: "qui".into(),
+                names: vec!["quiet".into(), "quit".into()]
+            }));
+    }
+
+    #[test]
+    #[cfg(feature = "terminal")]
+    fn history_search() {
+        let mut repl = Repl::builder()
+            .history_search(HistorySearch::Prefix)
+            .build()
+            .unwrap();
+        for line in [
+            "add 1 2",
+            "help",
+            "add 3 4",
+            "set-option verbosity quiet",
+            "add 1 2",
+        ] {
+            repl.add_history_entry(line);
+        }
+        assert_eq!(
+            repl.history_search("add", HistorySearch::Prefix),
+            vec!["add 1 2", "add 3 4"]
+        );
+        assert_eq!(
+            repl.history_search("e", HistorySearch::Substring),
+            vec!["set-option verbosity quiet", "help"]
+        );
+        assert!(repl.history_search("4", HistorySearch::Prefix).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "terminal")]
+    fn builder_strict() {
+        assert!(matches!(
+            Repl::builder().build_strict(),
+            Err(BuilderError::NoCommands)
+        ));
+
+        let command =
+            |args_info| Command::new("", args_info, Box::new(TrivialCommandHandler::new()));
+        let result = Repl::builder()
+            .add(
+                "add",
+                command(vec![CommandArgInfo::new(CommandArgType::I32)]),
+            )
+            .with_filename_completion(true)
+            .build_strict();
+        assert!(matches!(
+            result,
+            Err(BuilderError::UnusedFilenameCompletion)
+        ));
+
+        let result = Repl::builder()
+            .add(
+                "open",
+                command(vec![CommandArgInfo::new(CommandArgType::String)]),
+            )
+            .with_filename_completion(true)
+            .completion_cache_ttl(Duration::from_secs(1))
+            .build_strict();
+        assert!(matches!(result, Err(BuilderError::UnusedCompletionCache)));
+
+        let file = CommandArgInfo::new(CommandArgType::String).with_completer(|_: &str| vec![]);
+        let registry = Repl::builder()
+            .add("open", command(vec![file]))
+            .build_registry()
+            .unwrap();
+        let result = Repl::builder()
+            .registry(registry)
+            .with_filename_completion(true)
+            .completion_cache_ttl(Duration::from_secs(1))
+            .build_strict();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn command_provider() {
+        struct Moves;
+        impl CommandProvider for Moves {
+            fn commands(&self) -> Vec<(String, Command)> {
+                ["left", "right"]
+                    .iter()
+                    .map(|name| {
+                        let cmd =
+                            Command::new(name, vec![], Box::new(TrivialCommandHandler::new()));
+                        (name.to_string(), cmd)
+                    })
+                    .collect()
+            }
+        }
+        let debug = || {
+            let cmd = Command::new("Dump", vec![], Box::new(TrivialCommandHandler::new()));
+            vec![("dump".to_string(), cmd)]
+        };
+
+        let repl = Repl::builder()
+            .add_provider(Moves)
+            .add_provider(debug)
+            .build()
+            .unwrap();
+        let names: Vec<_> = repl.commands().map(|info| info.name).collect();
+        assert_eq!(names, vec!["dump", "left", "right"]);
+
+        assert!(matches!(
+            repl.registry().add_provider(&Moves),
+            Err(BuilderError::DuplicateCommands(_))
+        ));
+        let result = Repl::builder()
+            .add_provider(Moves)
+            .add_provider(Moves)
+            .build();
+        assert!(matches!(result, Err(BuilderError::DuplicateCommands(_))));
+    }
+
+    #[test]
+    fn reload_providers() {
+        let available = Arc::new(Mutex::new(vec!["left"]));
+        let names = available.clone();
+        let plugins = move || {
+            names
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|name| {
+                    let cmd = Command::new(name, vec![], Box::new(TrivialCommandHandler::new()));
+                    (name.to_string(), cmd)
+                })
+                .collect()
+        };
+        let repl = Repl::builder().add_provider(plugins).build().unwrap();
+        let names = |repl: &Repl| repl.commands().map(|info| info.name).collect::<Vec<_>>();
+        assert_eq!(names(&repl), vec!["left"]);
+
+        *available.lock().unwrap() = vec!["right", "up"];
+        repl.reload_providers().unwrap();
+        assert_eq!(names(&repl), vec!["right", "up"]);
+
+        *available.lock().unwrap() = vec!["down", "help"];
+        assert!(matches!(
+            repl.reload_providers(),
+            Err(BuilderError::ReservedName(_))
+        ));
+        assert_eq!(names(&repl), vec!["right", "up"]);
+
+        let registry = repl.registry();
+        assert!(matches!(
+            registry.register_provider(move || vec![(
+                "up".to_string(),
+                Command::new("Up", vec![], Box::new(TrivialCommandHandler::new()))
+            )]),
+            Err(BuilderError::DuplicateCommands(_))
+        ));
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_payload() {
+        use std::sync::{Arc, Mutex};
+
+        let received = Arc::new(Mutex::new(vec![]));
+        let recorded = received.clone();
+        let cmd = Command::new(
+            "Create user",
+            vec![
+                CommandArgInfo::new_with_name(CommandArgType::String, "name"),
+                CommandArgInfo::new_with_name(CommandArgType::Custom, "admin"),
+            ],
+            Box::new(crate::command::SyncCommandHandler::new(move |args| {
+                recorded.lock().unwrap().push(args);
+                Ok(CommandStatus::Done)
+            })),
+        );
+        let mut repl = Repl::builder()
+            .add("create-user", cmd.json_args())
+            .out(Box::new(std::io::sink()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line(r#"create-user {"name": "bo's", "admin": true}"#)
+            .await
+            .unwrap();
+        repl.handle_line(r#"create ["al", false]"#).await.unwrap();
+        repl.handle_line("create-user ed true").await.unwrap();
+        repl.handle_line(r#"create-user {"name": 1, "admin": true}"#)
+            .await
+            .unwrap();
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                vec!["bo's".to_string(), "true".into()],
+                vec!["al".into(), "false".into()],
+                vec!["ed".into(), "true".into()],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn command_help_flag() {
+        let buffer = Buffer::default();
+        let add = Command::new(
+            "Add X to Y",
+            vec![
+                CommandArgInfo::new_with_name(CommandArgType::I32, "X"),
+                CommandArgInfo::new_with_name(CommandArgType::I32, "Y"),
+            ],
+            Box::new(QuitCommandHandler),
+        );
+        let mut repl = Repl::builder()
+            .add(
+                "add",
+                add.category("math")
+                    .docs_url("https://example.com/docs/math/add-two-numbers"),
+            )
+            .text_width(30_usize)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        assert_eq!(
+            repl.command_help("add").unwrap(),
+            "Usage: add X:i32 Y:i32\n  Add X to Y\n  Category: math\n  \
+             Docs: https://example.com/docs/math/add-two-numbers"
+        );
+        assert!(repl.command_help("nope").is_none());
+
+        assert_eq!(
+            repl.handle_line("add --help").await.unwrap(),
+            LoopStatus::Continue
+        );
+        assert_eq!(
+            repl.handle_line("add -h 1").await.unwrap(),
+            LoopStatus::Continue
+        );
+        assert_eq!(
+            buffer.contents().matches("Usage: add X:i32 Y:i32").count(),
+            2
+        );
+        assert_eq!(
+            repl.handle_line("add 1 2").await.unwrap(),
+            LoopStatus::Break
+        );
+    }
+
+    #[tokio::test]
+    async fn command_line_args() {
+        let buffer = Buffer::default();
+        let add = Command::new(
+            "Add X to Y",
+            vec![
+                CommandArgInfo::new_with_name(CommandArgType::I32, "X"),
+                CommandArgInfo::new_with_name(CommandArgType::I32, "Y"),
+            ],
+            Box::new(TrivialCommandHandler::new()),
+        );
+        let mut repl = Repl::builder()
+            .add("add", add)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        assert!(repl.run_args(["tool", "add", "1", "2"]).await.is_ok());
+        let err = repl.run_args(["tool", "sub", "1"]).await.unwrap_err();
+        assert_eq!(err.to_string(), "command not found: sub");
+        let err = repl.run_args(["tool", "add", "x", "2"]).await.unwrap_err();
+        assert!(err.is::<ArgsError>());
+        assert!(buffer.contents().contains("Usage:\n  add X:i32 Y:i32"));
+    }
+
+    #[tokio::test]
+    async fn crlf_input() {
+        let buffer = Buffer::default();
+        let add = Command::new(
+            "Add X to Y",
+            vec![CommandArgInfo::new(CommandArgType::I32); 2],
+            Box::new(TrivialCommandHandler::new()),
+        );
+        let mut repl = Repl::builder()
+            .add("add", add)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        for line in ["add 1 2\r\n", "add 3 4\r", "\r\n"] {
+            assert_eq!(repl.handle_line(line).await.unwrap(), LoopStatus::Continue);
+        }
+        assert_eq!(buffer.contents(), "");
+    }
+
+    #[tokio::test]
+    async fn multiple_lines() {
+        let buffer = Buffer::default();
+        let mut repl = Repl::builder()
+            .add(
+                "stop",
+                Command::new("", vec![], Box::new(QuitCommandHandler)),
+            )
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let status = repl.handle_lines("nope\r\n\nstop\nnever\n").await.unwrap();
+        assert_eq!(status, LoopStatus::Break);
+        let output = buffer.contents();
+        assert!(output.contains("Command not found: nope"));
+        assert!(!output.contains("never"));
+        let status = repl.handle_lines("help\n").await.unwrap();
+        assert_eq!(status, LoopStatus::Continue);
+    }
+
+    #[tokio::test]
+    async fn script_report() {
+        let buffer = Buffer::default();
+        let add = Command::new(
+            "Add X to Y",
+            vec![CommandArgInfo::new(CommandArgType::I32); 2],
+            Box::new(TrivialCommandHandler::new()),
+        );
+        let mut repl = Repl::builder()
+            .add("add", add)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let report = repl
+            .run_script("add 1 2\n\n  add 1 x\nnope\nquit\nadd 1")
+            .await
+            .unwrap();
+        assert_eq!(report.lines, 5);
+        assert!(report.quit);
+        let failures: Vec<_> = report
+            .failures
+            .iter()
+            .map(|failure| (failure.line, failure.command.as_str()))
+            .collect();
+        assert_eq!(failures, [(3, "add 1 x"), (4, "nope")]);
+        assert!(report.failures[0].error.contains("x"));
+        assert_eq!(report.failures[1].error, "command not found: nope");
+        assert!(buffer.contents().contains("Command not found: nope"));
+
+        let report = repl.run_script("add 1 2").await.unwrap();
+        assert!(report.is_success() && !report.quit);
+    }
+
+    #[tokio::test]
+    async fn aliases() {
+        let buffer = Buffer::default();
+        let add = Command::new(
+            "Add X to Y",
+            vec![CommandArgInfo::new(CommandArgType::I32); 2],
+            Box::new(TrivialCommandHandler::new()),
+        );
+        let mut repl = Repl::builder()
+            .add("add", add)
+            .alias("inc", "add 1")
+            .alias("broken", "'")
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let help = repl.help();
+        let line = help
+            .lines()
+            .find(|line| line.trim_start().starts_with("inc "))
+            .unwrap();
+        assert!(line.ends_with(" Alias of 'add 1'"));
+        repl.handle_line("inc 2").await.unwrap();
+        assert_eq!(buffer.contents(), "");
+        repl.handle_line("in 2").await.unwrap();
+        assert!(buffer.contents().contains("Command not found: in"));
+        repl.handle_line("broken").await.unwrap();
+        assert!(buffer.contents().contains("Error: invalid alias 'broken'"));
+
+        let command = Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+        let result = Repl::builder()
+            .add("add", command)
+            .alias("add", "help")
+            .build();
+        assert!(matches!(result, Err(BuilderError::AliasConflict(_))));
+        let result = Repl::builder().alias("help", "quit").build();
+        assert!(matches!(result, Err(BuilderError::ReservedName(_))));
+        let result = Repl::builder().alias("a b", "quit").build();
+        assert!(matches!(result, Err(BuilderError::InvalidName(_))));
+    }
+
+    #[tokio::test]
+    async fn snippets() {
+        let buffer = Buffer::default();
+        let given = Arc::new(Mutex::new(Vec::new()));
+        let args = given.clone();
+        let rollout = SyncCommandHandler::new(move |new_args: Vec<String>| {
+            args.lock().unwrap().push(new_args);
+            Ok(CommandStatus::Done)
+        });
+        let info = |name| CommandArgInfo::new_with_name(CommandArgType::String, name);
+        let mut repl = Repl::builder()
+            .add(
+                "rollout",
+                Command::new(
+                    "Roll out",
+                    vec![info("APP"), info("ENV")],
+                    Box::new(rollout),
+                ),
+            )
+            .snippet("deploy", "rollout $1 --env=$2")
+            .snippet_command(true)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        assert!(repl.help().contains("Snippet 'rollout $1 --env=$2'"));
+        repl.handle_line("deploy 'my app' prod").await.unwrap();
+        repl.handle_line("deploy web").await.unwrap();
+        repl.handle_line("snippet prod = rollout $1 prod")
+            .await
+            .unwrap();
+        assert!(repl.help().contains("Snippet 'rollout $1 prod'"));
+        repl.handle_line("prod web").await.unwrap();
+        repl.handle_line("snippet").await.unwrap();
+        repl.handle_line("snippet -d deploy").await.unwrap();
+        assert!(!repl.help().contains("Snippet 'rollout $1 --env=$2'"));
+        repl.handle_line("snippet rollout = x").await.unwrap();
+        assert_eq!(
+            *given.lock().unwrap(),
+            [["my app", "--env=prod"], ["web", "prod"]]
+        );
+        assert_eq!(
+            buffer.contents(),
+            "Error: snippet 'deploy': missing argument $2\n\
+             deploy = rollout $1 --env=$2\n\
+             prod = rollout $1 prod\n\
+             Error: snippet 'rollout' has the same name as a command or alias\n"
+        );
+        assert_eq!(repl.snippets().len(), 1);
+
+        let result = Repl::builder()
+            .alias("st", "help")
+            .snippet("st", "help $1")
+            .build();
+        assert!(matches!(result, Err(BuilderError::SnippetConflict(_))));
+    }
+
+    #[tokio::test]
+    async fn context_output() {
+        let buffer = Buffer::default();
+        let echo = Command::new_with_context("Echo", vec![], Box::new(Echo(""))).raw_args();
+        let mut repl = Repl::builder()
+            .add("echo", echo)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("echo a b").await.unwrap();
+        repl.handle_line("nope").await.unwrap();
+        assert!(buffer
+            .contents()
+            .starts_with("a b\nCommand not found: nope"));
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_output() {
+        struct Sum;
+        impl ExecuteCommandWithContext for Sum {
+            fn execute<'a>(
+                &'a mut self,
+                mut ctx: CommandContext<'a>,
+            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+                Box::pin(async move {
+                    let x: i32 = ctx.args()[0].parse()?;
+                    let y: i32 = ctx.args()[1].parse()?;
+                    writeln!(ctx.out(), "{}", x + y)?;
+                    ctx.emit(serde_json::json!({ "sum": x + y }))?;
+                    Ok(CommandStatus::Done)
+                })
+            }
+        }
+
+        let buffer = Buffer::default();
+        let args = vec![
+            CommandArgInfo::new_with_name(CommandArgType::I32, "X"),
+            CommandArgInfo::new_with_name(CommandArgType::I32, "Y"),
+        ];
+        let mut repl = Repl::builder()
+            .add("add", Command::new_with_context("Add", args, Box::new(Sum)))
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .json_output(true)
+            .build()
+            .unwrap();
+        repl.handle_line("add 1 2").await.unwrap();
+        repl.handle_line("").await.unwrap();
+        repl.handle_line("add 1").await.unwrap();
+        repl.handle_line("nope").await.unwrap();
+        repl.handle_line("quit").await.unwrap();
+
+        let responses: Vec<serde_json::Value> = buffer
+            .contents()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 4);
+        assert_eq!(
+            responses[0],
+            serde_json::json!({
+                "command": "add",
+                "status": "ok",
+                "output": "3\n",
+                "error": null,
+                "data": [{ "sum": 3 }],
+            })
+        );
+        assert_eq!(responses[1]["status"], "error");
+        assert!(responses[1]["output"].as_str().unwrap().contains("Usage:"));
+        assert_eq!(responses[2]["command"], serde_json::Value::Null);
+        assert_eq!(responses[2]["error"], "command not found: nope");
+        assert_eq!(responses[3]["status"], "quit");
+    }
+
+    #[tokio::test]
+    async fn output_history() {
+        let buffer = Buffer::default();
+        let echo = Command::new_with_context("Echo", vec![], Box::new(Echo(""))).raw_args();
+        let mut repl = Repl::builder()
+            .add("echo", echo)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .output_history(2_usize)
+            .build()
+            .unwrap();
+        repl.handle_lines("echo a\necho b\necho c").await.unwrap();
+        assert_eq!(repl.recent_output(1), Some("c\n"));
+        assert_eq!(repl.recent_output(2), Some("b\n"));
+        assert_eq!(repl.recent_output(3), None);
+        assert_eq!(repl.recent_output(0), None);
+
+        repl.handle_line("show-output 2").await.unwrap();
+        assert!(buffer.contents().ends_with("c\nb\n"));
+        // printing again is not recorded
+        assert_eq!(repl.recent_output(1), Some("c\n"));
+        repl.handle_line("show-output 3").await.unwrap();
+        assert!(buffer
+            .contents()
+            .ends_with("Error: no output of command 3\n"));
+    }
+
+    #[tokio::test]
+    async fn destructive_commands() {
+        let drop = || {
+            Command::new_with_context("Drop", vec![], Box::new(Echo("dropped ")))
+                .raw_args()
+                .destructive(true)
+        };
+        let buffer = Buffer::default();
+        let mut repl = Repl::builder()
+            .add("drop", drop())
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        // there is no terminal to ask on
+        repl.handle_line("drop users").await.unwrap();
+        assert!(buffer
+            .contents()
+            .contains("Error: 'drop' is destructive, add --yes to run it"));
+        repl.handle_line("drop --yes users").await.unwrap();
+        assert!(buffer.contents().ends_with("dropped users\n"));
+
+        for (confirmation, dropped) in [(Confirmation::Always, false), (Confirmation::Never, true)]
+        {
+            let buffer = Buffer::default();
+            let mut repl = Repl::builder()
+                .add("drop", drop())
+                .out(Box::new(buffer.clone()) as Box<dyn Write>)
+                .confirm_destructive(confirmation)
+                .build()
+                .unwrap();
+            repl.handle_line("drop users --yes").await.unwrap();
+            assert_eq!(buffer.contents() == "dropped users\n", dropped);
+        }
+    }
+
+    #[tokio::test]
+    async fn cooldown() {
+        let buffer = Buffer::default();
+        let restart = Command::new(
+            "Restart",
+            vec![CommandArgInfo::new_with_name(CommandArgType::I32, "node")],
+            Box::new(TrivialCommandHandler::new()),
+        )
+        .cooldown(Duration::from_secs(30));
+        let clock = ManualClock::new();
+        let mut repl = Repl::builder()
+            .add("restart", restart)
+            .clock(clock.clone())
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        // invalid arguments do not start the cooldown
+        repl.handle_line("restart x").await.unwrap();
+        repl.handle_line("restart 1").await.unwrap();
+        assert!(!buffer.contents().contains("try again"));
+        repl.handle_line("restart 2").await.unwrap();
+        assert!(buffer
+            .contents()
+            .contains("Error: 'restart' was run recently, try again in 30s"));
+        clock.advance(Duration::from_millis(29_500));
+        repl.handle_line("restart 2").await.unwrap();
+        assert!(buffer.contents().ends_with("try again in 1s\n"));
+        clock.advance(Duration::from_millis(500));
+        let before = buffer.contents();
+        repl.handle_line("restart 2").await.unwrap();
+        assert_eq!(buffer.contents(), before);
+        assert!(repl
+            .command_help("restart")
+            .unwrap()
+            .contains("at most once every 30s"));
+
+        // of concurrent lines, only the first one starts the cooldown and runs
+        struct Yielding;
+        impl ExecuteCommand for Yielding {
+            fn execute<'a>(
+                &'a mut self,
+                _args: &'a [&'a str],
+                _args_info: &'a [CommandArgInfo],
+            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+                Box::pin(async {
+                    tokio::task::yield_now().await;
+                    Ok(CommandStatus::Done)
+                })
+            }
+        }
+        let deploy =
+            Command::new("Deploy", vec![], Box::new(Yielding)).cooldown(Duration::from_secs(30));
+        let mut repl = Repl::builder()
+            .add("deploy", deploy)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let report = repl.run_batch(&["deploy", "deploy"], 2).await;
+        assert_eq!(report.results[0].error, None);
+        assert_eq!(
+            report.results[1].error.as_deref(),
+            Some("'deploy' was run recently, try again in 30s")
+        );
+
+        // commands without a cooldown only read the time to measure how long they took, which
+        // is skipped on wasm32 where there is no clock
+        #[derive(Clone, Default)]
+        struct Counting(Arc<std::sync::atomic::AtomicUsize>);
+        impl Clock for Counting {
+            fn now(&self) -> Instant {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Instant::now()
+            }
+            fn sleep(&self, duration: Duration) -> crate::runtime::BoxFuture<()> {
+                SystemClock::default().sleep(duration)
+            }
+        }
+        let clock = Counting::default();
+        let hello = Command::new("Hello", vec![], Box::new(TrivialCommandHandler::new()));
+        let mut repl = Repl::builder()
+            .add("hello", hello)
+            .clock(clock.clone())
+            .out(Box::new(Buffer::default()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let reads = clock.0.load(Ordering::SeqCst);
+        repl.handle_line("hello").await.unwrap();
+        let report = repl.run_batch(&["hello", "hello"], 2).await;
+        assert!(report.results.iter().all(|result| result.error.is_none()));
+        assert_eq!(clock.0.load(Ordering::SeqCst), reads + 2 * 3);
+    }
+
+    #[tokio::test]
+    async fn preconditions() {
+        let buffer = Buffer::default();
+        let connected = Arc::new(AtomicBool::new(false));
+        let is_connected = {
+            let connected = connected.clone();
+            move || connected.load(Ordering::SeqCst)
+        };
+        let not_connected = "not connected, run 'connect' first";
+        let query = |args| {
+            Command::new("Query", args, Box::new(TrivialCommandHandler::new())).precondition(
+                Precondition::new(is_connected.clone(), not_connected).hide_from_completion(),
+            )
+        };
+        let dump = Command::new("Dump", vec![], Box::new(TrivialCommandHandler::new()))
+            .destructive(true)
+            .precondition(Precondition::new(is_connected.clone(), not_connected));
+        let mut repl = Repl::builder()
+            .add("query", query(vec![]))
+            .add(
+                "query",
+                query(vec![CommandArgInfo::new(CommandArgType::I32)]),
+            )
+            .add(
+                "quit-db",
+                Command::new("Quit", vec![], Box::new(TrivialCommandHandler::new())),
+            )
+            .add("dump", dump)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let names = |repl: &Repl, line| {
+            let (_, candidates) = repl.complete(line, line.len());
+            candidates
+                .into_iter()
+                .map(|c| c.replacement)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&repl, "qu"), ["quit", "quit-db"]);
+        assert_eq!(names(&repl, "d"), ["dump"]);
+        // positions sent by other frontends are not trusted
+        assert_eq!(repl.complete("qu", 99), repl.complete("qu", 2));
+        assert_eq!(repl.complete("dump ü", 6), repl.complete("dump ü", 5));
+        assert_eq!(repl.hint("dump ü", 99), repl.hint("dump ü", 7));
+        repl.handle_line("query 1").await.unwrap();
+        // no confirmation is asked for commands that cannot run
+        repl.handle_line("dump").await.unwrap();
+        assert_eq!(
+            buffer.contents(),
+            format!("Error: {not_connected}\nError: {not_connected}\n")
+        );
+
+        connected.store(true, Ordering::SeqCst);
+        assert_eq!(names(&repl, "qu"), ["query", "quit", "quit-db"]);
+        let before = buffer.contents();
+        repl.handle_line("query 1").await.unwrap();
+        repl.handle_line("query").await.unwrap();
+        assert_eq!(buffer.contents(), before);
+    }
+
+    #[tokio::test]
+    async fn replay() {
+        let buffer = Buffer::default();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let count = runs.clone();
+        let add = SyncCommandHandler::new(move |_: Vec<String>| {
+            count.fetch_add(1, Ordering::SeqCst);
+            Ok(CommandStatus::Done)
+        });
+        let mut repl = Repl::builder()
+            .add(
+                "add",
+                Command::new(
+                    "Add",
+                    vec![CommandArgInfo::new(CommandArgType::I32); 2],
+                    Box::new(add),
+                ),
+            )
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let replay = Replay::new(["add 1 2", "ad 3 4", "add x", "help"]);
+        assert_eq!(replay.len(), 4);
+        assert_eq!(repl.replay(&replay).await.unwrap(), LoopStatus::Continue);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(repl.stats().errors, 1);
+        repl.replay(&replay.dispatch(false)).await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        let quit = Replay::new(["quit", "add 1 2"]);
+        assert_eq!(repl.replay(&quit).await.unwrap(), LoopStatus::Break);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(buffer.contents(), "");
+        repl.handle_line("add 1 2").await.unwrap();
+        repl.handle_line("nope").await.unwrap();
+        assert_ne!(buffer.contents(), "");
+    }
+
+    #[cfg(feature = "fuzz")]
+    #[tokio::test]
+    async fn fuzz_line() {
+        let buffer = Buffer::default();
+        let add = Command::new(
+            "Add",
+            vec![
+                CommandArgInfo::new_with_name(CommandArgType::I32, "x"),
+                CommandArgInfo::new_with_name(CommandArgType::I32, "y"),
+            ],
+            Box::new(TrivialCommandHandler::new()),
+        );
+        let drop =
+            Command::new("Drop", vec![], Box::new(TrivialCommandHandler::new())).destructive(true);
+        let mut repl = Repl::builder()
+            .add("add", add)
+            .add("drop", drop)
+            .alias("plus", "add 1")
+            .snippet("twice", "add $1 $1")
+            .variable_substitution(true)
+            .working_dir(std::env::temp_dir())
+            .confirm_destructive(Confirmation::Always)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("repl-fuzz-{}.txt", std::process::id()));
+        let tee = format!("help | tee {}", path.display());
+        let inputs: Vec<&[u8]> = vec![
+            b"",
+            b"\xff\xfe add 1 2",
+            b"add 'unclosed",
+            b"ad y=1 x=2\r\n\n\tplus 2",
+            b"twice 3 4 $((1/0)) ${X:} ~ * [",
+            b"drop\ncd ..\ncd -\nsnippet",
+            "x=\u{301}\u{fffd} \u{1F600}".as_bytes(),
+            tee.as_bytes(),
+        ];
+        for input in inputs {
+            assert_eq!(repl.fuzz_line(input).await.unwrap(), LoopStatus::Continue);
+        }
+        assert!(!path.exists());
+        assert_eq!(
+            repl.fuzz_line(b"help\nquit\nadd 1 2").await.unwrap(),
+            LoopStatus::Break
+        );
+        assert_eq!(buffer.contents(), "");
+        repl.handle_line("add 1").await.unwrap();
+        assert!(!buffer.contents().is_empty());
+    }
+
+    #[tokio::test]
+    async fn demo() {
+        let buffer = Buffer::default();
+        let echo = Command::new_with_context("Echo", vec![], Box::new(Echo(""))).raw_args();
+        let mut repl = Repl::builder()
+            .add("echo", echo)
+            .prompt("$ ")
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let demo = Demo::new()
+            .keystroke(Duration::from_millis(1))
+            .before_enter(Duration::ZERO)
+            .after_command(Duration::ZERO);
+        let start = Instant::now();
+        let status = repl
+            .play_demo("echo hi\n\nquit\necho again", &demo)
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(11));
+        assert_eq!(status, LoopStatus::Break);
+        assert_eq!(buffer.contents(), "$ echo hi\nhi\n$ \n$ quit\n");
+    }
+
+    #[tokio::test]
+    async fn tutorial() {
+        let buffer = Buffer::default();
+        let connect = Command::new(
+            "Connect",
+            vec![CommandArgInfo::new(CommandArgType::String)],
+            Box::new(TrivialCommandHandler::new()),
+        );
+        let mut repl = Repl::builder()
+            .add("connect", connect)
+            .add(
+                "tables",
+                Command::new("Tables", vec![], Box::new(TrivialCommandHandler::new())),
+            )
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let tutorial = Tutorial::new()
+            .step("Connect to a test database", "connect test-*")
+            .step("List its tables", "tables");
+        repl.start_tutorial(tutorial).unwrap();
+        assert_eq!(repl.tutorial_step(), Some(0));
+        repl.handle_line("tables").await.unwrap();
+        repl.handle_line("help tables").await.unwrap();
+        // failing commands do not advance
+        repl.handle_line("connect test-1 x").await.unwrap();
+        assert_eq!(repl.tutorial_step(), Some(0));
+        repl.handle_line("conn test-1").await.unwrap();
+        assert_eq!(repl.tutorial_step(), Some(1));
+        repl.handle_line("tables").await.unwrap();
+        assert_eq!(repl.tutorial_step(), None);
+        let output = buffer.contents();
+        assert!(output.starts_with(
+            "Step 1/2: Connect to a test database\nThis step expects: connect test-*\nUsage: tables"
+        ));
+        assert!(output.ends_with("Step 2/2: List its tables\nTutorial complete\n"));
+    }
+
+    #[tokio::test]
+    async fn argument_errors() {
+        let buffer = Buffer::default();
+        let point = |names: &[&str]| {
+            let args = names
+                .iter()
+                .map(|name| CommandArgInfo::new_with_name(CommandArgType::I32, name))
+                .collect();
+            Command::new("Move", args, Box::new(TrivialCommandHandler::new()))
+        };
+        let mut repl = Repl::builder()
+            .add("move", point(&["x"]))
+            .add("move", point(&["x", "y"]))
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("move 1 z=2").await.unwrap();
+        let out = buffer.contents();
+        assert!(out.contains("unknown flag 'z', did you mean"));
+        assert!(out.contains("  move x:i32 y:i32"));
+        assert!(!out.contains("  move x:i32\n"));
+        repl.handle_line("move 1 2 3").await.unwrap();
+        assert!(buffer.contents().contains("Error: unexpected argument '"));
+    }
+
+    #[tokio::test]
+    async fn error_carets() {
+        let buffer = Buffer::default();
+        let add = Command::new(
+            "Add X to Y",
+            vec![
+                CommandArgInfo::new_with_name(CommandArgType::I32, "X"),
+                CommandArgInfo::new_with_name(CommandArgType::F32, "Y"),
+            ],
+            Box::new(TrivialCommandHandler::new()),
+        );
+        let mut repl = Repl::builder()
+            .add("add", add)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("add 'ünï' 2").await.unwrap();
+        assert!(buffer
+            .contents()
+            .contains("  add 'ünï' 2\n      ^^^^^ expected i32\nUsage:"));
+        repl.handle_line("add Y=x 1").await.unwrap();
+        assert!(buffer
+            .contents()
+            .contains("  add Y=x 1\n      ^^^ expected f32\n"));
+        repl.handle_line("add Y=1 x").await.unwrap();
+        assert!(buffer
+            .contents()
+            .contains("  add Y=1 x\n          ^ expected i32\n"));
+        repl.handle_line("add 1 2 3").await.unwrap();
+        assert!(buffer
+            .contents()
+            .contains("  add 1 2 3\n          ^ unexpected argument\n"));
+        repl.handle_line("add 1").await.unwrap();
+        assert!(!buffer.contents().contains("  add 1\n"));
+    }
+
+    #[tokio::test]
+    async fn checked_arguments() {
+        let buffer = Buffer::default();
+        let range = Command::new(
+            "Range",
+            vec![
+                CommandArgInfo::new_with_name(CommandArgType::I32, "start"),
+                CommandArgInfo::new_with_name(CommandArgType::I32, "end"),
+            ],
+            Box::new(QuitCommandHandler),
+        )
+        .check_args(
+            |start: i32, end: i32| start <= end,
+            "START must not be after END",
+        );
+        let mut repl = Repl::builder()
+            .add("range", range)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let status = repl.handle_line("range 3 1").await.unwrap();
+        assert_eq!(status, LoopStatus::Continue);
+        let out = buffer.contents();
+        assert!(out.contains("Error: invalid arguments: START must not be after END"));
+        assert!(out.contains("Usage:\n  range start:i32 end:i32"));
+        let status = repl.handle_line("range 1 3").await.unwrap();
+        assert_eq!(status, LoopStatus::Break);
+    }
+
+    #[tokio::test]
+    async fn correction() {
+        let buffer = Buffer::default();
+        let command = || Command::new("Status", vec![], Box::new(TrivialCommandHandler::new()));
+        let mut repl = Repl::builder()
+            .add("status", command())
+            .add("stats", command())
+            .add("clear", command())
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .correction(Correction::Auto)
+            .build()
+            .unwrap();
+        repl.handle_line("claer").await.unwrap();
+        assert!(buffer
+            .contents()
+            .contains("Running 'clear' instead of 'claer'"));
+        // both status and stats are close
+        repl.handle_line("stauts").await.unwrap();
+        assert!(buffer.contents().contains("Command not found: stauts"));
+
+        let buffer = Buffer::default();
+        let mut repl = Repl::builder()
+            .add("clear", command())
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .correction(Correction::Prompt)
+            .build()
+            .unwrap();
+        // there is no terminal to ask on
+        repl.handle_line("cleer").await.unwrap();
+        let output = buffer.contents();
+        assert!(output.contains("Command not found: cleer\nDid you mean 'clear'?"));
+    }
+
+    #[tokio::test]
+    async fn numbered_candidates() {
+        let buffer = Buffer::default();
+        let echo = |name| Command::new_with_context("Echo", vec![], Box::new(Echo(name)));
+        let mut repl = Repl::builder()
+            .add("make", echo("made ").raw_args())
+            .add("move", echo("moved ").raw_args())
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("m 'a b' c").await.unwrap();
+        assert!(buffer.contents().contains("  1) make\n  2) move\n"));
+        repl.handle_line("2").await.unwrap();
+        assert!(buffer.contents().ends_with("moved a b c\n"));
+        // the selection is only offered on the next line
+        repl.handle_line("1").await.unwrap();
+        assert!(buffer
+            .contents()
+            .ends_with("Command not found: 1\nUse 'help' to see available commands.\n"));
+    }
+
+    #[tokio::test]
+    async fn abbreviations() {
+        let buffer = Buffer::default();
+        let configure = Command::new(
+            "Configure the tool",
             vec![],
             Box::new(TrivialCommandHandler::new()),
         );
+        let mut repl = Repl::builder()
+            .add("configure", configure.abbreviation("cfg"))
+            .predict_commands(false)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        assert!(repl.help().contains("configure (cfg)"));
+        repl.handle_line("cfg").await.unwrap();
+        repl.handle_line("conf").await.unwrap();
+        let output = buffer.contents();
+        assert!(!output.contains("Command not found: cfg"));
+        assert!(output.contains("Command not found: conf"));
+    }
+
+    #[tokio::test]
+    async fn namespace_help() {
+        let buffer = Buffer::default();
+        let command = |description: &str| {
+            Command::new(description, vec![], Box::new(TrivialCommandHandler::new()))
+        };
+        let mut repl = Repl::builder()
+            .add("db.migrate", command("Run migrations"))
+            .add("db.seed", command("Load fixtures"))
+            .add("dbg", command("Debug"))
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("help db").await.unwrap();
+        let output = buffer.contents();
+        assert!(output.starts_with("Commands in 'db':"));
+        assert!(output.contains("Run migrations") && output.contains("Load fixtures"));
+        assert!(!output.contains("Debug"));
+
+        repl.handle_line("help dbg").await.unwrap();
+        assert!(buffer.contents().contains("Usage: dbg"));
+        repl.handle_line("help nope").await.unwrap();
+        assert!(buffer
+            .contents()
+            .contains("no command, namespace or category 'nope'"));
+    }
+
+    #[tokio::test]
+    async fn help_contents() {
+        let buffer = Buffer::default();
+        let command = |description: &str| {
+            Command::new(description, vec![], Box::new(TrivialCommandHandler::new()))
+        };
+        let mut repl = Repl::builder()
+            .add("ls", command("List files").category("files"))
+            .add("rm", command("Remove files").category("files"))
+            .add("ping", command("Ping a host").category("network"))
+            .add("version", command("Show the version"))
+            .help_contents(3)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("help").await.unwrap();
+        let output = buffer.contents();
+        assert!(output.contains("  files          2 commands\n"));
+        assert!(output.contains("  network        1 command\n"));
+        assert!(output.contains("  uncategorized  1 command\n"));
+        assert!(output.contains("  quit"));
+        assert!(!output.contains("List files"));
+
+        repl.handle_line("help files").await.unwrap();
+        let output = buffer.contents()[output.len()..].to_string();
+        assert!(output.starts_with("Commands in category 'files':"));
+        assert!(output.contains("List files") && output.contains("Remove files"));
+        assert!(!output.contains("Ping"));
+
+        repl.handle_line("help --all").await.unwrap();
+        assert!(buffer
+            .contents()
+            .ends_with(&format!("{}\n", repl.full_help())));
+        assert!(repl.full_help().contains("Ping a host"));
+        repl.registry().remove("ping");
+        assert!(repl.help().contains("Show the version"));
+    }
+
+    #[tokio::test]
+    async fn tee() {
+        let buffer = Buffer::default();
+        let mut repl = Repl::builder()
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("repl-tee-{}.txt", std::process::id()));
+        let line = format!("help | tee {}", shell_words::quote(&path.to_string_lossy()));
+        repl.handle_line(&line).await.unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("Show this help message"));
+        assert_eq!(buffer.contents(), saved);
+
+        let line = format!(
+            "nope | tee -a {}",
+            shell_words::quote(&path.to_string_lossy())
+        );
+        repl.handle_line(&line).await.unwrap();
+        // only commands that have been run are written
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), saved);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "terminal")]
+    #[tokio::test]
+    async fn pager_option() {
+        let buffer = Buffer::default();
+        let mut repl = Repl::builder()
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .pager("less -R")
+            .build()
+            .unwrap();
+        let pager = |repl: &Repl| {
+            let options = repl.options();
+            options
+                .into_iter()
+                .find(|(name, _)| *name == "pager")
+                .unwrap()
+                .1
+        };
+        assert_eq!(pager(&repl), "less -R");
+        // output to a writer is never paged
+        repl.handle_line("help --no-pager").await.unwrap();
+        assert!(buffer.contents().contains("Show this help message"));
+        repl.handle_line("set-option pager off").await.unwrap();
+        assert_eq!(pager(&repl), "off");
+        repl.set_option("pager", "more").unwrap();
+        assert_eq!(pager(&repl), "more");
+    }
 
+    #[cfg(feature = "terminal")]
+    #[test]
+    fn output_streams() {
+        let editor_config = rustyline::Config::builder()
+            .output_stream(rustyline::OutputStreamType::Stderr)
+            .build();
         let result = Repl::builder()
-            .add("name_x", command_x_1)
-            .add("name_x", command_x_2)
+            .out(std::io::stdout())
+            .editor_config(editor_config)
             .build();
+        assert!(matches!(result, Err(BuilderError::OutputStreamMismatch)));
+        assert!(Repl::builder()
+            .out(std::io::stderr())
+            .editor_config(editor_config)
+            .build()
+            .is_ok());
+        assert!(Repl::builder().out(std::io::stdout()).build().is_ok());
+    }
 
-        assert!(matches!(result, Err(BuilderError::DuplicateCommands(_))));
+    #[tokio::test]
+    async fn runtime_options() {
+        let buffer = Buffer::default();
+        let mut repl = Repl::builder()
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("set-option prompt 'db> '").await.unwrap();
+        assert_eq!(repl.prompt(), "db> ");
+        repl.set_option("text-width", "40").unwrap();
+        assert!(matches!(
+            repl.set_option("predict-commands", "maybe"),
+            Err(OptionError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            repl.set_option("nope", "1"),
+            Err(OptionError::UnknownOption(_))
+        ));
+        repl.handle_line("set-option predict-commands false")
+            .await
+            .unwrap();
+        repl.handle_line("hel").await.unwrap();
+        assert!(buffer.contents().contains("Command not found: hel"));
+        repl.handle_line("set-option text-width").await.unwrap();
+        assert!(buffer
+            .contents()
+            .contains("Error: usage: set-option [NAME VALUE]"));
+
+        repl.handle_line("set-option").await.unwrap();
+        let output = buffer.contents();
+        assert!(output.contains("prompt            'db> '"));
+        assert!(output.contains("predict-commands  false"));
+        assert!(output.contains("text-width        40"));
+    }
+
+    #[tokio::test]
+    async fn verbosity() {
+        let buffer = Buffer::default();
+        let args = vec![CommandArgInfo::new_with_name(CommandArgType::I32, "X")];
+        let command = Command::new("Add", args, Box::new(TrivialCommandHandler::new()));
+        let mut repl = Repl::builder()
+            .add("add", command)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .verbosity(Verbosity::Quiet)
+            .build()
+            .unwrap();
+        repl.handle_line("add x").await.unwrap();
+        repl.handle_line("sub").await.unwrap();
+        let output = buffer.contents();
+        assert!(output.contains("Error:"));
+        assert!(output.contains("Command not found: sub"));
+        assert!(!output.contains("Usage:"));
+        assert!(!output.contains("help"));
+
+        repl.handle_line("set-option verbosity verbose")
+            .await
+            .unwrap();
+        assert_eq!(repl.verbosity(), Verbosity::Verbose);
+        repl.handle_line("ad 1").await.unwrap();
+        assert!(buffer.contents().contains("Running 'add'"));
     }
 
     #[test]
-    fn builder_overload() {
-        let command_x_1 = Command::new(
-            "Command X".into(),
-            vec![],
+    fn help_metadata() {
+        let command = |desc| Command::new(desc, vec![], Box::new(TrivialCommandHandler::new()));
+        let repl = Repl::builder()
+            .add("secret", command("Secret command").hidden(true))
+            .add("old", command("Old command").deprecated("use 'new'"))
+            .build()
+            .unwrap();
+        let help = repl.help();
+        assert!(!help.contains("Secret command"));
+        assert!(help.contains("Old command (deprecated: use 'new')"));
+        let names: Vec<_> = repl.commands().map(|info| info.name).collect();
+        assert_eq!(names, vec!["old", "secret"]);
+    }
+
+    #[tokio::test]
+    async fn lints() {
+        let buffer = Buffer::default();
+        let restore = Command::new(
+            "Restore",
+            vec![CommandArgInfo::new(CommandArgType::String); 2],
             Box::new(TrivialCommandHandler::new()),
         );
+        let mut repl = Repl::builder()
+            .add("restore", restore)
+            .lint(|name, args| {
+                (name == "restore" && args.contains(&"--force") && !args.contains(&"--backup"))
+                    .then(|| "--force without --backup discards the current data".into())
+            })
+            .lint(|_, args| args.contains(&"prod").then(|| "restoring prod".into()))
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("restore --force --backup").await.unwrap();
+        assert_eq!(buffer.contents(), "");
+        repl.handle_line("restore --force prod").await.unwrap();
+        repl.handle_line("help restore").await.unwrap();
+        assert!(buffer.contents().starts_with(
+            "Warning: --force without --backup discards the current data\n\
+             Warning: restoring prod\n"
+        ));
+        assert_eq!(buffer.contents().matches("Warning").count(), 2);
+    }
 
-        let command_x_2 = Command::new(
-            "Command X 2",
-            vec![CommandArgInfo::new(CommandArgType::I32)],
-            Box::new(TrivialCommandHandler::new()),
+    #[cfg(feature = "terminal")]
+    #[test]
+    fn idle_timeout_bound_keys() {
+        let ctrl_k = parse_key("ctrl-k").unwrap();
+        let bound = [
+            ctrl_k,
+            parse_key("ctrl-r").unwrap(),
+            parse_key("f5").unwrap(),
+        ];
+        let build = |timeout: Option<Duration>| {
+            let keymap = Keymap::new()
+                .bind(ctrl_k, Cmd::Kill(rustyline::Movement::EndOfLine))
+                .run(bound[2], "help");
+            let builder = Repl::builder()
+                .keymap(keymap)
+                .history_search(HistorySearch::Prefix);
+            match timeout {
+                Some(timeout) => builder.idle_timeout(timeout),
+                None => builder,
+            }
+            .build()
+            .unwrap()
+        };
+        // keys with a binding do not reach the handler of other keys, so they are wrapped
+        let mut repl = build(Some(Duration::from_secs(60)));
+        for key in bound {
+            assert!(matches!(
+                repl.editor.unbind_sequence(key),
+                Some(EventHandler::Conditional(_))
+            ));
+        }
+        let mut repl = build(None);
+        assert!(matches!(
+            repl.editor.unbind_sequence(ctrl_k),
+            Some(EventHandler::Simple(_))
+        ));
+    }
+
+    #[cfg(feature = "terminal")]
+    #[tokio::test]
+    async fn key_bindings() {
+        let buffer = Buffer::default();
+        let keymap = Keymap::new().run(parse_key("f5").unwrap(), "help");
+        let mut repl = Repl::builder()
+            .keymap(keymap)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let f5 = parse_key("f5").unwrap();
+        assert_eq!(repl.key_commands().collect::<Vec<_>>(), vec![(f5, "help")]);
+
+        repl.handle_line("bind ctrl-r add 1 '2 3'").await.unwrap();
+        let ctrl_r = parse_key("ctrl-r").unwrap();
+        assert!(repl
+            .key_commands()
+            .any(|binding| binding == (ctrl_r, "add 1 '2 3'")));
+        repl.handle_line("bind f5").await.unwrap();
+        repl.handle_line("bind f5").await.unwrap();
+        repl.handle_line("bind hyper-x x").await.unwrap();
+        repl.handle_line("bind").await.unwrap();
+        assert_eq!(
+            buffer.contents(),
+            "Error: key 'f5' is not bound\n\
+             Error: unknown key 'hyper-x'\n  \
+             ctrl-r  add 1 '2 3'\n"
         );
+    }
 
-        #[rustfmt::skip]
-        let result = Repl::builder()
-            .add("name_x", command_x_1)
-            .add("name_x", command_x_2)
-            .build();
-        assert!(matches!(result, Ok(_)));
+    #[test]
+    fn unicode_help_alignment() {
+        let command = |desc| Command::new(desc, vec![], Box::new(TrivialCommandHandler::new()));
+        let repl = Repl::builder()
+            .add("挨拶", command("Greet"))
+            .add("zażółć", command("Scribble"))
+            .build()
+            .unwrap();
+        let help = repl.help();
+        let column = |desc: &str| {
+            let line = help.lines().find(|line| line.ends_with(desc)).unwrap();
+            line[..line.len() - desc.len()].width()
+        };
+        assert_eq!(column("Greet"), column("Scribble"));
     }
 
     #[test]
-    fn builder_empty() {
-        let command_empty = Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+    fn cached_help() {
+        let mut repl = Repl::builder()
+            .add(
+                "first",
+                Command::new(
+                    "First command",
+                    vec![],
+                    Box::new(TrivialCommandHandler::new()),
+                ),
+            )
+            .build()
+            .unwrap();
+        let help = repl.help();
+        assert!(help.contains("First command"));
+        assert_eq!(repl.help(), help);
 
-        let result = Repl::builder().add("", command_empty).build();
-        assert!(matches!(result, Err(BuilderError::InvalidName(_))));
+        let cmd = Command::new(
+            "Second command",
+            vec![],
+            Box::new(TrivialCommandHandler::new()),
+        );
+        repl.registry().add("second", cmd).unwrap();
+        assert!(repl.help().contains("Second command"));
+        repl.registry().remove("first");
+        assert!(!repl.help().contains("First command"));
+
+        // entries of the session are not tied to the registry
+        repl.add_snippet("go", "second").unwrap();
+        assert!(repl.help().contains("Snippet 'second'"));
+        repl.remove_snippet("go");
+        assert!(!repl.help().contains("Snippet 'second'"));
+
+        repl.set_text_width(10);
+        assert!(repl.help().lines().count() > help.lines().count());
     }
 
-    #[test]
-    fn builder_spaces() {
-        let command_empty = Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+    #[tokio::test]
+    async fn shared_registry() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let registry = Repl::builder()
+            .add(
+                "count",
+                Command::new(
+                    "Increment counter",
+                    vec![],
+                    Box::new(crate::command::SyncCommandHandler::new(move |_| {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        Ok(CommandStatus::Done)
+                    })),
+                ),
+            )
+            .build_registry()
+            .unwrap();
+
+        let mut local = Repl::builder().registry(registry.clone()).build().unwrap();
+        assert_eq!(
+            local.handle_line("count").await.unwrap(),
+            LoopStatus::Continue
+        );
+
+        // sessions of remote connections run on their own threads
+        let shared = registry.clone();
+        std::thread::spawn(move || {
+            let mut remote = Repl::builder()
+                .registry(shared.clone())
+                .prompt("remote> ")
+                .out(Box::new(std::io::sink()) as Box<dyn Write>)
+                .build()
+                .unwrap();
+            assert!(Arc::ptr_eq(remote.registry(), &shared));
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap();
+            runtime.block_on(async {
+                assert_eq!(
+                    remote.handle_line("cou").await.unwrap(),
+                    LoopStatus::Continue
+                );
+                assert_eq!(remote.handle_line("quit").await.unwrap(), LoopStatus::Break);
+            });
+        })
+        .join()
+        .unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
 
         let result = Repl::builder()
-            .add("name-with spaces", command_empty)
+            .registry(registry)
+            .add(
+                "other",
+                Command::new("", vec![], Box::new(TrivialCommandHandler::new())),
+            )
             .build();
-        assert!(matches!(result, Err(BuilderError::InvalidName(_))));
+        assert!(matches!(result, Err(BuilderError::SharedRegistry(_))));
     }
 
-    #[test]
-    fn builder_reserved() {
-        let command_help = Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+    #[tokio::test]
+    async fn batch() {
+        use futures::channel::oneshot;
 
-        let result = Repl::builder().add("help", command_help).build();
-        assert!(matches!(result, Err(BuilderError::ReservedName(_))));
+        struct Gate {
+            wait: Option<oneshot::Receiver<()>>,
+            open: Option<oneshot::Sender<()>>,
+        }
+        impl ExecuteCommandWithContext for Gate {
+            fn execute<'a>(
+                &'a mut self,
+                mut ctx: CommandContext<'a>,
+            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+                Box::pin(async move {
+                    if let Some(wait) = self.wait.take() {
+                        wait.await?;
+                    }
+                    if let Some(open) = self.open.take() {
+                        open.send(()).unwrap();
+                    }
+                    let line = ctx.args().join(" ");
+                    write!(ctx.out(), "{line} done")?;
+                    Ok(CommandStatus::Done)
+                })
+            }
+        }
 
-        let command_quit = Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+        let (open, wait) = oneshot::channel();
+        let gate = |wait, open| {
+            Command::new_with_context("", vec![], Box::new(Gate { wait, open })).raw_args()
+        };
+        let old = Command::new("Old", vec![], Box::new(TrivialCommandHandler::new()))
+            .deprecated("use 'open'");
+        let mut repl = Repl::builder()
+            .add("wait", gate(Some(wait), None))
+            .add("open", gate(None, Some(open)))
+            .add("old", old)
+            .alias("o", "open")
+            .lint(|name, _| (name == "wait").then(|| "waiting".into()))
+            .build()
+            .unwrap();
+        let help = repl.command_help("old").unwrap();
+        // the first line only finishes once the second one has run
+        let lines = ["wait a", "o b", "nope", "help", "old", "old -h"];
+        let report = repl.run_batch(&lines, 2).await;
+        let results: Vec<_> = report
+            .results
+            .iter()
+            .map(|result| (result.output.as_str(), result.error.as_deref()))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("Warning: waiting\na done", None),
+                ("b done", None),
+                ("", Some("command not found: nope")),
+                ("", Some("built-in command 'help' cannot run in a batch")),
+                ("Warning: 'old' is deprecated: use 'open'\n", None),
+                (&format!("{help}\n"), None),
+            ]
+        );
+        assert_eq!(report.failures().count(), 2);
+        assert_eq!(report.results[1].command, "o b");
+        assert_eq!(repl.stats().commands, 4);
+    }
 
-        let result = Repl::builder().add("quit", command_quit).build();
-        assert!(matches!(result, Err(BuilderError::ReservedName(_))));
+    #[tokio::test]
+    async fn batch_overlap() {
+        struct Logged(Arc<Mutex<Vec<String>>>);
+        impl SendExecuteCommand for Logged {
+            fn execute<'a>(
+                &'a mut self,
+                args: &'a [&'a str],
+                _args_info: &'a [CommandArgInfo],
+            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + Send + 'a>>
+            {
+                Box::pin(async move {
+                    self.0.lock().unwrap().push(format!("start {}", args[0]));
+                    tokio::task::yield_now().await;
+                    self.0.lock().unwrap().push(format!("end {}", args[0]));
+                    Ok(CommandStatus::Done)
+                })
+            }
+        }
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let logged = || Command::new_send("", vec![], Box::new(Logged(log.clone()))).raw_args();
+        let mut repl = Repl::builder()
+            .add("a", logged())
+            .add("b", logged())
+            .build()
+            .unwrap();
+        // lines of different commands overlap, the ones of the same command run in turn
+        let report = repl.run_batch(&["a 1", "a 2", "b 3"], 3).await;
+        assert_eq!(report.failures().count(), 0);
+        let log = log.lock().unwrap();
+        let at = |entry: &str| log.iter().position(|logged| logged == entry).unwrap();
+        assert!(at("start 3") < at("end 1"));
+        assert!(at("end 1") < at("start 2"));
+    }
+
+    #[tokio::test]
+    async fn temporary_commands() {
+        struct Connect;
+        impl ExecuteCommandWithContext for Connect {
+            fn execute<'a>(
+                &'a mut self,
+                mut ctx: CommandContext<'a>,
+            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+                Box::pin(async move {
+                    let host = ctx.args()[0];
+                    ctx.set_mode(Some(host));
+                    ctx.add_temporary("disconnect", disconnect(), Scope::Mode);
+                    ctx.add_temporary("query", query(), Scope::Mode);
+                    ctx.add_temporary("hosts", query(), Scope::Session);
+                    Ok(CommandStatus::Done)
+                })
+            }
+        }
+        struct Disconnect;
+        impl ExecuteCommandWithContext for Disconnect {
+            fn execute<'a>(
+                &'a mut self,
+                mut ctx: CommandContext<'a>,
+            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+                Box::pin(async move {
+                    ctx.set_mode(None);
+                    Ok(CommandStatus::Done)
+                })
+            }
+        }
+        fn disconnect() -> Command {
+            Command::new_with_context("Disconnect", vec![], Box::new(Disconnect))
+        }
+        fn query() -> Command {
+            Command::new("Query", vec![], Box::new(TrivialCommandHandler::new()))
+        }
+
+        let buffer = Buffer::default();
+        let connect = Command::new_with_context(
+            "Connect",
+            vec![CommandArgInfo::new(CommandArgType::String)],
+            Box::new(Connect),
+        );
+        let registry = Repl::builder()
+            .add("connect", connect)
+            .build_registry()
+            .unwrap();
+        let mut repl = Repl::builder()
+            .registry(registry.clone())
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("connect db1").await.unwrap();
+        assert_eq!(repl.mode(), Some("db1"));
+        assert!(registry.contains("disconnect") && registry.contains("query"));
+        repl.handle_line("query").await.unwrap();
+        repl.handle_line("disconnect").await.unwrap();
+        assert_eq!(repl.mode(), None);
+        assert!(!registry.contains("disconnect") && !registry.contains("query"));
+        assert!(registry.contains("hosts"));
+        assert_eq!(buffer.contents(), "");
+
+        // adding the session commands again fails
+        repl.handle_line("connect db2").await.unwrap();
+        assert!(registry.contains("query"));
+        assert!(buffer
+            .contents()
+            .contains("more than one command with name 'hosts'"));
+        repl.set_mode(Some("other"));
+        assert!(!registry.contains("query"));
+        drop(repl);
+        assert!(!registry.contains("hosts"));
+        assert!(registry.contains("connect"));
+    }
+
+    #[tokio::test]
+    async fn temporary_overloads() {
+        fn query(args: Vec<CommandArgInfo>) -> Command {
+            Command::new("Query", args, Box::new(TrivialCommandHandler::new()))
+        }
+        let overloads =
+            |registry: &CommandRegistry| registry.commands().get("query").map_or(0, Vec::len);
+
+        let registry = Repl::builder()
+            .add(
+                "query",
+                query(vec![CommandArgInfo::new(CommandArgType::I32)]),
+            )
+            .build_registry()
+            .unwrap();
+        let mut first = Repl::builder().registry(registry.clone()).build().unwrap();
+        let mut second = Repl::builder().registry(registry.clone()).build().unwrap();
+        first
+            .add_temporary("query", query(vec![]), Scope::Mode)
+            .unwrap();
+        second
+            .add_temporary(
+                "query",
+                query(vec![CommandArgInfo::new(CommandArgType::String)]),
+                Scope::Session,
+            )
+            .unwrap();
+        assert_eq!(overloads(&registry), 3);
+
+        // only the overload of the session whose scope ends is removed
+        first.set_mode(Some("other"));
+        assert_eq!(overloads(&registry), 2);
+        drop(second);
+        assert_eq!(overloads(&registry), 1);
+        assert_eq!(
+            registry.commands()["query"][0].args_info[0].arg_type,
+            CommandArgType::I32
+        );
+
+        // a command replaced in the meantime is not removed
+        first
+            .add_temporary("hosts", query(vec![]), Scope::Session)
+            .unwrap();
+        assert!(registry.remove("hosts"));
+        registry.add("hosts", query(vec![])).unwrap();
+        drop(first);
+        assert!(registry.contains("hosts"));
+    }
+
+    #[tokio::test]
+    async fn mutex_groups() {
+        use crate::command::WhenBusy;
+        use futures::channel::oneshot;
+
+        struct Step {
+            log: Arc<Mutex<Vec<String>>>,
+            gate: Option<oneshot::Receiver<()>>,
+        }
+        impl ExecuteCommand for Step {
+            fn execute<'a>(
+                &'a mut self,
+                args: &'a [&'a str],
+                _args_info: &'a [CommandArgInfo],
+            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+                Box::pin(async move {
+                    self.log.lock().unwrap().push(format!("{} start", args[0]));
+                    if let Some(gate) = self.gate.take() {
+                        gate.await?;
+                    }
+                    self.log.lock().unwrap().push(format!("{} end", args[0]));
+                    Ok(CommandStatus::Done)
+                })
+            }
+        }
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (open, gate) = oneshot::channel();
+        let step = |gate, when_busy| {
+            let handler = Step {
+                log: log.clone(),
+                gate,
+            };
+            Command::new("", vec![], Box::new(handler))
+                .raw_args()
+                .mutex_group("ops", when_busy)
+        };
+        let registry = Repl::builder()
+            .add("deploy", step(Some(gate), WhenBusy::Wait))
+            .add("migrate", step(None, WhenBusy::Wait))
+            .add("backup", step(None, WhenBusy::Reject))
+            .build_registry()
+            .unwrap();
+        let session = |buffer: &Buffer| {
+            Repl::builder()
+                .registry(registry.clone())
+                .out(Box::new(buffer.clone()) as Box<dyn Write>)
+                .build()
+                .unwrap()
+        };
+        let buffer = Buffer::default();
+        let (mut first, mut second, mut third) =
+            (session(&buffer), session(&buffer), session(&buffer));
+        let (deployed, migrated, ()) = futures::future::join3(
+            first.handle_line("deploy deploy"),
+            second.handle_line("migrate migrate"),
+            async {
+                third.handle_line("backup backup").await.unwrap();
+                open.send(()).unwrap();
+            },
+        )
+        .await;
+        assert!(deployed.is_ok() && migrated.is_ok());
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["deploy start", "deploy end", "migrate start", "migrate end"]
+        );
+        assert!(buffer
+            .contents()
+            .contains("Error: another command of group 'ops' is running"));
+    }
+
+    #[tokio::test]
+    async fn ambiguous_prefix() {
+        let mut repl = Repl::builder()
+            .add(
+                "move",
+                Command::new("", vec![], Box::new(TrivialCommandHandler::new())),
+            )
+            .add(
+                "make",
+                Command::new("", vec![], Box::new(QuitCommandHandler)),
+            )
+            .completion_limit(1)
+            .out(Box::new(std::io::sink()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        // "m" must not resolve to the first of the candidates
+        assert_eq!(repl.handle_line("m").await.unwrap(), LoopStatus::Continue);
+        assert_eq!(repl.handle_line("ma").await.unwrap(), LoopStatus::Break);
     }
 
     #[tokio::test]
+    #[allow(clippy::useless_conversion)]
     async fn repl_quits() {
         let command_foo = Command::new(
             "description",
@@ -506,19 +5742,16 @@ mod tests {
             pub fn new() -> Self {
                 Self {}
             }
-            async fn handle_command(
-                &mut self,
-                _args: Vec<String>,
-            ) -> anyhow::Result<CommandStatus> {
+            async fn handle_command(&mut self, _args: &[&str]) -> anyhow::Result<CommandStatus> {
                 Ok(CommandStatus::Quit)
             }
         }
         impl ExecuteCommand for QuittingCommandHandler {
-            fn execute(
-                &mut self,
-                args: Vec<String>,
-                _args_info: Vec<CommandArgInfo>,
-            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + '_>> {
+            fn execute<'a>(
+                &'a mut self,
+                args: &'a [&'a str],
+                _args_info: &'a [CommandArgInfo],
+            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
                 Box::pin(self.handle_command(args))
             }
         }
@@ -534,4 +5767,510 @@ mod tests {
             LoopStatus::Break
         );
     }
+
+    #[tokio::test]
+    async fn slow_commands() {
+        let buffer = Buffer::default();
+        let slow = Rc::new(RefCell::new(Vec::new()));
+        let recorded = slow.clone();
+        let clock = ManualClock::new();
+        let handler = |millis| {
+            let clock = clock.clone();
+            SyncCommandHandler::new(move |_args| {
+                clock.advance(Duration::from_millis(millis));
+                Ok(CommandStatus::Done)
+            })
+        };
+        let mut repl = Repl::builder()
+            .add("fast", Command::new("Fast", vec![], Box::new(handler(0))))
+            .add("slow", Command::new("Slow", vec![], Box::new(handler(30))))
+            .clock(clock.clone())
+            .slow_command(Duration::from_millis(20))
+            .on_slow_command(move |name, duration| {
+                recorded.borrow_mut().push((name.to_string(), duration))
+            })
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("fast").await.unwrap();
+        assert!(buffer.contents().is_empty());
+        repl.handle_line("slow").await.unwrap();
+        assert_eq!(buffer.contents(), "took 0.0s\n");
+        assert_eq!(slow.borrow().len(), 1);
+        assert_eq!(slow.borrow()[0].0, "slow");
+        assert_eq!(slow.borrow()[0].1, Duration::from_millis(30));
+
+        repl.set_verbosity(Verbosity::Quiet);
+        repl.handle_line("slow").await.unwrap();
+        assert_eq!(buffer.contents(), "took 0.0s\n");
+        assert_eq!(slow.borrow().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn locale() {
+        let buffer = Buffer::default();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let recorded = received.clone();
+        let clock = ManualClock::new();
+        let elapsed = clock.clone();
+        let scale = SyncCommandHandler::new(move |args| {
+            elapsed.advance(Duration::from_millis(10));
+            recorded.lock().unwrap().extend(args);
+            Ok(CommandStatus::Done)
+        });
+        let args = vec![
+            CommandArgInfo::new_with_name(CommandArgType::F32, "x"),
+            CommandArgInfo::new_with_name(CommandArgType::String, "label"),
+        ];
+        let mut repl = Repl::builder()
+            .add("scale", Command::new("Scale", args, Box::new(scale)))
+            .locale(Locale::new(','))
+            .clock(clock)
+            .slow_command(Duration::from_millis(1))
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("scale 1,5 1,5").await.unwrap();
+        repl.handle_line("scale label=2,5 x=2,5").await.unwrap();
+        repl.handle_line("scale 3.5 a").await.unwrap();
+        assert_eq!(
+            *received.lock().unwrap(),
+            ["1.5", "1,5", "2.5", "2,5", "3.5", "a"]
+        );
+        assert_eq!(buffer.contents(), "took 0,0s\n".repeat(3));
+
+        repl.set_locale(Locale::C);
+        repl.handle_line("scale 1,5 a").await.unwrap();
+        assert!(buffer.contents().contains("Error:"));
+        assert_eq!(received.lock().unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn retry_transient_errors() {
+        let buffer = Buffer::default();
+        let failures = Arc::new(Mutex::new(2));
+        let remaining = failures.clone();
+        let flaky = SyncCommandHandler::new(move |_args| {
+            let mut remaining = remaining.lock().unwrap();
+            if *remaining == 0 {
+                return Ok(CommandStatus::Done);
+            }
+            *remaining -= 1;
+            anyhow::bail!("timed out")
+        });
+        let retry = Retry::new(3, Duration::from_millis(1))
+            .when(|err| err.to_string().contains("timed out"));
+        let mut repl = Repl::builder()
+            .add(
+                "fetch",
+                Command::new("Fetch", vec![], Box::new(flaky)).retry(retry),
+            )
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("fetch").await.unwrap();
+        assert_eq!(
+            buffer.contents(),
+            "Attempt 1/3 failed: timed out, retrying in 0.0s\n\
+             Attempt 2/3 failed: timed out, retrying in 0.0s\n"
+        );
+        assert_eq!(repl.stats().errors, 0);
+
+        *failures.lock().unwrap() = 3;
+        repl.handle_line("fetch").await.unwrap();
+        assert_eq!(repl.stats().errors, 1);
+        assert_eq!(*failures.lock().unwrap(), 0);
+
+        // lines of batches are retried too
+        *failures.lock().unwrap() = 1;
+        let report = repl.run_batch(&["fetch"], 1).await;
+        assert_eq!(report.results[0].error, None);
+        assert_eq!(
+            report.results[0].output,
+            "Attempt 1/3 failed: timed out, retrying in 0.0s\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn glob_path_arguments() {
+        use crate::command::PathCompleter;
+
+        let root = std::env::temp_dir().join(format!("repl-globbing-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        for file in ["a.log", "b.log"] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        let dir = format!("{}{}", root.display(), std::path::MAIN_SEPARATOR);
+        let build = |globbing| {
+            let buffer = Buffer::default();
+            let files = CommandArgInfo::new_with_name(CommandArgType::String, "FILES")
+                .with_completer(PathCompleter::new());
+            let rm = Command::new_with_context("Remove", vec![files], Box::new(Echo("")));
+            let repl = Repl::builder()
+                .add("rm", rm.raw_args())
+                .globbing(globbing)
+                .out(Box::new(buffer.clone()) as Box<dyn Write>)
+                .build()
+                .unwrap();
+            (repl, buffer)
+        };
+
+        let (mut repl, buffer) = build(Globbing::Literal);
+        repl.handle_line(&format!("rm -f {dir}*.log"))
+            .await
+            .unwrap();
+        repl.handle_line(&format!("rm {dir}*.txt")).await.unwrap();
+        repl.handle_line(&format!("rm '{dir}*.log'")).await.unwrap();
+        assert_eq!(
+            buffer.contents(),
+            format!("-f {dir}a.log {dir}b.log\n{dir}*.txt\n{dir}*.log\n")
+        );
+
+        let (mut repl, buffer) = build(Globbing::Error);
+        repl.handle_line(&format!("rm {dir}*.txt")).await.unwrap();
+        assert!(buffer.contents().contains("no files match"));
+        assert_eq!(repl.stats().commands, 0);
+
+        let (mut repl, buffer) = build(Globbing::Off);
+        repl.handle_line(&format!("rm {dir}*.log")).await.unwrap();
+        assert_eq!(buffer.contents(), format!("{dir}*.log\n"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn home_dir_in_path_arguments() {
+        use crate::command::PathCompleter;
+
+        let opened = Arc::new(Mutex::new(Vec::new()));
+        let paths = opened.clone();
+        let open = SyncCommandHandler::new(move |mut args: Vec<String>| {
+            paths.lock().unwrap().push(args.remove(0));
+            Ok(CommandStatus::Done)
+        });
+        let file = CommandArgInfo::new_with_name(CommandArgType::String, "FILE")
+            .with_completer(PathCompleter::new());
+        let mut repl = Repl::builder()
+            .add("open", Command::new("Open", vec![file], Box::new(open)))
+            .home_dir(|| Some(PathBuf::from("/home/me")))
+            .build()
+            .unwrap();
+        for line in [
+            "open ~/notes.txt",
+            "open ~'/my notes.txt'",
+            "open '~/notes.txt'",
+            "open $CARGO_MANIFEST_DIR/Cargo.toml",
+            "open \\$CARGO_MANIFEST_DIR",
+            "open a~",
+        ] {
+            repl.handle_line(line).await.unwrap();
+        }
+        let dir = env!("CARGO_MANIFEST_DIR");
+        assert_eq!(
+            *opened.lock().unwrap(),
+            [
+                "/home/me/notes.txt".to_string(),
+                "/home/me/my notes.txt".to_string(),
+                "~/notes.txt".to_string(),
+                format!("{dir}/Cargo.toml"),
+                "$CARGO_MANIFEST_DIR".to_string(),
+                "a~".to_string(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn variable_substitution() {
+        let buffer = Buffer::default();
+        let given = Arc::new(Mutex::new(Vec::new()));
+        let args = given.clone();
+        let echo = SyncCommandHandler::new(move |new_args: Vec<String>| {
+            args.lock().unwrap().push(new_args);
+            Ok(CommandStatus::Done)
+        });
+        let info = |name| CommandArgInfo::new_with_name(CommandArgType::String, name);
+        let mut repl = Repl::builder()
+            .add(
+                "echo",
+                Command::new(
+                    "Echo",
+                    vec![info("A"), info("B"), info("C")],
+                    Box::new(echo),
+                ),
+            )
+            .variable_substitution(true)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("echo part-$((2*3+1)) \"${REPL_UNSET:-dev env}\" '$((1+2))'")
+            .await
+            .unwrap();
+        repl.handle_line("echo $REPL_UNSET \\$((1+2)) ${#REPL_UNSET}")
+            .await
+            .unwrap();
+        repl.handle_line("echo $((1/0)) b c").await.unwrap();
+        assert_eq!(
+            *given.lock().unwrap(),
+            [
+                ["part-7", "dev env", "$((1+2))"],
+                ["$REPL_UNSET", "$((1+2))", "0"]
+            ]
+        );
+        assert_eq!(
+            buffer.contents(),
+            "Error: invalid expression '$((1/0))': division by zero\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn working_dir() {
+        use crate::command::PathCompleter;
+
+        struct Cat;
+        impl ExecuteCommandWithContext for Cat {
+            fn execute<'a>(
+                &'a mut self,
+                mut ctx: CommandContext<'a>,
+            ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+                Box::pin(async move {
+                    for file in ctx.args() {
+                        let text = std::fs::read_to_string(ctx.resolve_path(file))?;
+                        writeln!(ctx.out(), "{text}")?;
+                    }
+                    Ok(CommandStatus::Done)
+                })
+            }
+        }
+
+        let root = std::env::temp_dir().join(format!("repl-working-dir-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("docs").join("a.txt"), "A").unwrap();
+        std::fs::write(root.join("docs").join("b.txt"), "B").unwrap();
+        let buffer = Buffer::default();
+        let files = CommandArgInfo::new_with_name(CommandArgType::String, "FILES")
+            .with_completer(PathCompleter::new());
+        let cat = Command::new_with_context("Print files", vec![files], Box::new(Cat));
+        let mut repl = Repl::builder()
+            .add("cat", cat.raw_args())
+            .working_dir(&root)
+            .globbing(Globbing::Error)
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+
+        let sep = std::path::MAIN_SEPARATOR;
+        let (_, candidates) = repl.complete("cat docs/a", 10);
+        let replacements: Vec<_> = candidates.iter().map(|c| &c.replacement).collect();
+        assert_eq!(replacements, [&format!("docs{sep}a.txt")]);
+        repl.handle_line("cat docs/*.txt").await.unwrap();
+        repl.handle_line("cd docs").await.unwrap();
+        repl.handle_line("cat a.txt").await.unwrap();
+        repl.handle_line("pwd").await.unwrap();
+        repl.handle_line("cd ..").await.unwrap();
+        assert_eq!(repl.working_dir(), Some(root.clone()));
+        repl.handle_line("cd -").await.unwrap();
+        assert_eq!(repl.working_dir(), Some(root.join("docs")));
+        repl.handle_line("cd a.txt").await.unwrap();
+        assert_eq!(repl.working_dir(), Some(root.join("docs")));
+        let docs = root.join("docs").display().to_string();
+        assert_eq!(
+            buffer.contents(),
+            format!("A\nB\nA\n{docs}\n{docs}\nError: not a directory: a.txt\n")
+        );
+        assert!(repl.help().contains("Print the current directory"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn evaluator_fallback() {
+        let buffer = Buffer::default();
+        let double = SyncCommandHandler::new(|args: Vec<String>| {
+            assert_eq!(args, ["7"]);
+            Ok(CommandStatus::Done)
+        });
+        let mut repl = Repl::builder()
+            .add(
+                "double",
+                Command::new(
+                    "Double",
+                    vec![CommandArgInfo::new(CommandArgType::I32)],
+                    Box::new(double),
+                ),
+            )
+            .evaluator(|input: &str| match input {
+                "x = 1" => Ok(None),
+                "'quoted" => Ok(Some("quote".into())),
+                _ => {
+                    let sum: i64 = input
+                        .split('+')
+                        .map(|term| term.trim().parse::<i64>())
+                        .sum::<Result<_, _>>()?;
+                    Ok(Some(sum.to_string()))
+                }
+            })
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        assert_eq!(repl.last_result(), None);
+        repl.handle_line("1 + 2").await.unwrap();
+        repl.handle_line("$_ + 4").await.unwrap();
+        repl.handle_line("x = 1").await.unwrap();
+        assert_eq!(buffer.contents(), "3\n7\n");
+        assert_eq!(repl.last_result(), Some("7"));
+        repl.handle_line("double $_").await.unwrap();
+        repl.handle_line("1 + y").await.unwrap();
+        assert!(buffer.contents().contains("invalid digit"));
+        assert_eq!(repl.last_result(), Some("7"));
+        repl.handle_line("'quoted").await.unwrap();
+        assert_eq!(repl.last_result(), Some("quote"));
+    }
+
+    #[tokio::test]
+    async fn restart() {
+        let reload = SyncCommandHandler::new(|_args| Ok(CommandStatus::Restart));
+        let restarts = Rc::new(RefCell::new(0));
+        let counter = restarts.clone();
+        let mut repl = Repl::builder()
+            .add("reload", Command::new("Reload", vec![], Box::new(reload)))
+            .on_restart(move |repl| {
+                *counter.borrow_mut() += 1;
+                let cmd = Command::new("Ping", vec![], Box::new(TrivialCommandHandler::new()));
+                repl.registry().remove("ping");
+                Ok(repl.registry().add("ping", cmd)?)
+            })
+            .build()
+            .unwrap();
+        repl.set_mode(Some("cfg"));
+        assert_eq!(
+            repl.handle_line("reload").await.unwrap(),
+            LoopStatus::Continue
+        );
+        assert_eq!(repl.mode(), None);
+        assert!(repl.registry().contains("ping"));
+        repl.handle_line("reload").await.unwrap();
+        assert_eq!(*restarts.borrow(), 2);
+    }
+
+    #[tokio::test]
+    async fn critical_error_hook() {
+        use std::cell::Cell;
+
+        let buffer = Buffer::default();
+        let action = Rc::new(Cell::new(CriticalAction::Print));
+        let decided = action.clone();
+        let failing = SyncCommandHandler::new(|_args| {
+            Err(CriticalError::Critical(anyhow::anyhow!("disk gone")).into())
+        });
+        let mut repl = Repl::builder()
+            .add("fail", Command::new("Fail", vec![], Box::new(failing)))
+            .on_critical_error(move |err| {
+                assert_eq!(err.to_string(), "disk gone");
+                decided.get()
+            })
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        assert_eq!(
+            repl.handle_line("fail").await.unwrap(),
+            LoopStatus::Continue
+        );
+        assert!(buffer.contents().contains("disk gone"));
+
+        repl.set_mode(Some("cfg"));
+        action.set(CriticalAction::Restart);
+        assert_eq!(
+            repl.handle_line("fail").await.unwrap(),
+            LoopStatus::Continue
+        );
+        assert_eq!(repl.mode(), None);
+
+        action.set(CriticalAction::Terminate);
+        let err = repl.handle_line("fail").await.unwrap_err();
+        assert!(err.downcast_ref::<CriticalError>().is_some());
+        assert_eq!(repl.stats().errors, 3);
+    }
+
+    #[test]
+    fn prompt_placeholders() {
+        let mut repl = Repl::builder().prompt("{mode}db{jobs}> ").build().unwrap();
+        assert_eq!(repl.prompt(), "db> ");
+        repl.set_mode(Some("cfg"));
+        let job = repl.jobs().start();
+        assert_eq!(repl.prompt(), "[cfg]db(1 job)> ");
+        repl.set_mode(None);
+        drop(job);
+        assert_eq!(repl.prompt(), "db> ");
+        assert_eq!(
+            repl.options()[0],
+            ("prompt", "{mode}db{jobs}> ".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn status_command() {
+        let buffer = Buffer::default();
+        let builder = || {
+            Repl::builder()
+                .add(
+                    "status",
+                    Command::new("", vec![], Box::new(TrivialCommandHandler::new())),
+                )
+                .out(Box::new(buffer.clone()) as Box<dyn Write>)
+        };
+        let mut repl = builder().build().unwrap();
+        repl.handle_line(":status").await.unwrap();
+        assert!(buffer.contents().contains("Command not found: :status"));
+
+        let buffer = Buffer::default();
+        let mut repl = builder()
+            .out(Box::new(buffer.clone()) as Box<dyn Write>)
+            .status_command(true)
+            .build()
+            .unwrap();
+        repl.handle_line("status").await.unwrap();
+        repl.handle_line("status extra").await.unwrap();
+        repl.set_mode(Some("cfg"));
+        repl.handle_line("help").await.unwrap();
+        assert!(buffer.contents().contains(":status"));
+        let _job = repl.jobs().start();
+        let before = buffer.contents().len();
+        repl.handle_line(":status").await.unwrap();
+        let out = buffer.contents()[before..].to_string();
+        assert!(out.starts_with("Commands run:    3\nErrors:          1\n"));
+        assert!(out.contains("Background jobs: 1\n"));
+        assert!(out.contains("Uptime:          0s\n"));
+        assert!(out.ends_with("Mode:            cfg\n"));
+
+        assert_eq!(format_uptime(Duration::from_secs(59)), "59s");
+        assert_eq!(format_uptime(Duration::from_secs(65)), "1m 05s");
+        assert_eq!(format_uptime(Duration::from_secs(3723)), "1h 02m 03s");
+    }
+
+    #[tokio::test]
+    async fn execution_stats() {
+        let mut repl = Repl::builder()
+            .add(
+                "status",
+                Command::new("", vec![], Box::new(TrivialCommandHandler::new())),
+            )
+            .out(Box::new(Buffer::default()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        repl.handle_line("status").await.unwrap();
+        repl.handle_line("status extra").await.unwrap();
+        repl.handle_line("help").await.unwrap();
+        repl.handle_line("nope").await.unwrap();
+        let stats = repl.stats();
+        assert_eq!((stats.commands, stats.errors), (3, 1));
+        assert_eq!(
+            stats.per_command.keys().collect::<Vec<_>>(),
+            ["help", "status"]
+        );
+        let status = &stats.per_command["status"];
+        assert_eq!((status.runs, status.errors), (2, 1));
+        assert!(status.min_time <= status.max_time);
+
+        let exported = repl.reset_stats();
+        assert_eq!(exported.commands, 3);
+        assert_eq!(repl.stats(), &Stats::default());
+    }
 }
@@ -0,0 +1,313 @@
+//! Abstraction over the async runtime used by the REPL.
+//!
+//! The REPL loop itself only needs to be polled, so it can be driven by any executor.
+//! Features that need runtime services (running blocking code, timers) go through the
+//! [`Runtime`] trait instead of calling a specific runtime directly. Implementations
+//! for `tokio`, `async-std` and `smol` are available behind the corresponding feature
+//! flags, and [`ThreadRuntime`] works without any runtime at all by using OS threads.
+
+use std::any::Any;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::Duration;
+
+/// Boxed [`Send`] future returned by [`Runtime`] methods.
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Services the REPL needs from an async runtime.
+pub trait Runtime: Send + Sync {
+    /// Run `f` on a thread where blocking is allowed. The future completes when `f` returns.
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) -> BoxFuture<()>;
+    /// Returns a future that completes after `duration` has elapsed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<()>;
+}
+
+/// Run `f` using [`Runtime::spawn_blocking`] and return its result.
+///
+/// Fails if `f` panics, with the panic message, or if the runtime drops it without
+/// running it, e.g. while shutting down.
+pub async fn spawn_blocking<R, F>(runtime: &dyn Runtime, f: F) -> anyhow::Result<R>
+where
+    R: Send + 'static,
+    F: FnOnce() -> R + Send + 'static,
+{
+    let slot = Arc::new(Mutex::new(None));
+    let result = slot.clone();
+    runtime
+        .spawn_blocking(Box::new(move || {
+            let output = panic::catch_unwind(AssertUnwindSafe(f));
+            *result.lock().unwrap() = Some(output);
+        }))
+        .await;
+    let result = slot.lock().unwrap().take();
+    match result {
+        Some(Ok(output)) => Ok(output),
+        Some(Err(payload)) => Err(anyhow::anyhow!(
+            "blocking task panicked: {}",
+            panic_message(&*payload)
+        )),
+        None => Err(anyhow::anyhow!("blocking task was cancelled")),
+    }
+}
+
+/// Message of a panic with `payload`, as passed to `panic!`.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload
+            .downcast_ref::<String>()
+            .map_or("unknown error", String::as_str),
+    }
+}
+
+/// Await `future` for at most `duration`, returning `None` if the time ran out.
+pub async fn timeout<F: Future>(
+    runtime: &dyn Runtime,
+    duration: Duration,
+    future: F,
+) -> Option<F::Output> {
+    race(runtime.sleep(duration), future).await
+}
+
+/// Await `future` until `sleep` completes, returning `None` if it completes first.
+pub(crate) async fn race<F: Future>(mut sleep: BoxFuture<()>, future: F) -> Option<F::Output> {
+    let mut future = Box::pin(future);
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            Poll::Ready(Some(output))
+        } else if sleep.as_mut().poll(cx).is_ready() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Run a future to completion on the current thread.
+///
+/// Used when a future has to be driven outside of the application's runtime,
+/// e.g. on a blocking thread.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let signal = Arc::new(Signal::default());
+    let waker = Waker::from(signal.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        signal.wait();
+    }
+}
+
+/// Returns the runtime selected by enabled features.
+///
+/// When more than one runtime feature is enabled `tokio` takes precedence over `async-std`,
+/// which takes precedence over `smol`. Without any runtime features [`ThreadRuntime`] is used.
+pub fn default_runtime() -> Arc<dyn Runtime> {
+    #[cfg(feature = "tokio")]
+    return Arc::new(TokioRuntime);
+    #[cfg(all(not(feature = "tokio"), feature = "async-std"))]
+    return Arc::new(AsyncStdRuntime);
+    #[cfg(all(not(feature = "tokio"), not(feature = "async-std"), feature = "smol"))]
+    return Arc::new(SmolRuntime);
+    #[cfg(not(any(feature = "tokio", feature = "async-std", feature = "smol")))]
+    return Arc::new(ThreadRuntime);
+}
+
+/// Runtime-independent implementation using a new OS thread for each operation.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadRuntime;
+
+impl Runtime for ThreadRuntime {
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) -> BoxFuture<()> {
+        let done = Arc::new(Completion::default());
+        let notify = done.clone();
+        thread::spawn(move || {
+            // completes also when `f` panics, so that awaiting does not hang
+            let _notify = CompleteOnDrop(notify);
+            f();
+        });
+        Box::pin(done.wait())
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        self.spawn_blocking(Box::new(move || thread::sleep(duration)))
+    }
+}
+
+/// Runtime using [`tokio`], requires to be called from within a tokio runtime.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioRuntime;
+
+#[cfg(feature = "tokio")]
+impl Runtime for TokioRuntime {
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) -> BoxFuture<()> {
+        let handle = tokio::task::spawn_blocking(f);
+        Box::pin(async move {
+            if let Err(err) = handle.await {
+                if err.is_panic() {
+                    std::panic::resume_unwind(err.into_panic());
+                }
+            }
+        })
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Runtime using [`async_std`].
+#[cfg(feature = "async-std")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AsyncStdRuntime;
+
+#[cfg(feature = "async-std")]
+impl Runtime for AsyncStdRuntime {
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) -> BoxFuture<()> {
+        Box::pin(async_std::task::spawn_blocking(f))
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// Runtime using [`smol`].
+#[cfg(feature = "smol")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SmolRuntime;
+
+#[cfg(feature = "smol")]
+impl Runtime for SmolRuntime {
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) -> BoxFuture<()> {
+        Box::pin(smol::unblock(f))
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(async move {
+            smol::Timer::after(duration).await;
+        })
+    }
+}
+
+/// Thread-parking waker used by [`block_on`].
+#[derive(Default)]
+struct Signal {
+    woken: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl Signal {
+    fn wait(&self) {
+        let mut woken = self.woken.lock().unwrap();
+        while !*woken {
+            woken = self.cond.wait(woken).unwrap();
+        }
+        *woken = false;
+    }
+}
+
+impl Wake for Signal {
+    fn wake(self: Arc<Self>) {
+        *self.woken.lock().unwrap() = true;
+        self.cond.notify_one();
+    }
+}
+
+/// Guard completing a [`Completion`] when dropped.
+struct CompleteOnDrop(Arc<Completion>);
+
+impl Drop for CompleteOnDrop {
+    fn drop(&mut self) {
+        self.0.complete();
+    }
+}
+
+/// One-shot completion flag that can be awaited from any executor.
+#[derive(Default)]
+struct Completion {
+    state: Mutex<(bool, Option<Waker>)>,
+}
+
+impl Completion {
+    fn complete(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.0 = true;
+        if let Some(waker) = state.1.take() {
+            waker.wake();
+        }
+    }
+
+    async fn wait(self: Arc<Self>) {
+        std::future::poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+            if state.0 {
+                Poll::Ready(())
+            } else {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_runtime_spawn_blocking() {
+        let result = block_on(spawn_blocking(&ThreadRuntime, || 40 + 2));
+        assert_eq!(result.unwrap(), 42);
+        let panicked = block_on(spawn_blocking(&ThreadRuntime, || -> i32 { panic!("oops") }));
+        assert_eq!(
+            panicked.unwrap_err().to_string(),
+            "blocking task panicked: oops"
+        );
+
+        // the future completes even if the function given to the runtime panics
+        let spawned = ThreadRuntime.spawn_blocking(Box::new(|| panic!("oops")));
+        block_on(spawned);
+    }
+
+    #[test]
+    fn thread_runtime_timeout() {
+        let runtime = ThreadRuntime;
+        let fast = block_on(timeout(&runtime, Duration::from_secs(5), async { 1 }));
+        assert_eq!(fast, Some(1));
+        let slow = block_on(timeout(
+            &runtime,
+            Duration::from_millis(10),
+            runtime.sleep(Duration::from_secs(5)),
+        ));
+        assert_eq!(slow, None);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn tokio_runtime() {
+        let runtime = TokioRuntime;
+        assert_eq!(spawn_blocking(&runtime, || "done").await.unwrap(), "done");
+        let panicked = spawn_blocking(&runtime, || -> i32 { panic!("{} failed", "task") }).await;
+        assert_eq!(
+            panicked.unwrap_err().to_string(),
+            "blocking task panicked: task failed"
+        );
+        let slow = timeout(
+            &runtime,
+            Duration::from_millis(10),
+            runtime.sleep(Duration::from_secs(5)),
+        )
+        .await;
+        assert_eq!(slow, None);
+    }
+}
@@ -0,0 +1,213 @@
+//! Reports of scripts and batches of command lines, see [`Repl::run_script`] and
+//! [`Repl::run_batch`].
+//!
+//! Errors of scripts are still printed as the lines run, the report lets callers handle
+//! partial failures, e.g. exit with an error status listing the failed lines:
+//!
+//! ```rust
+//! # use mini_async_repl::Repl;
+//! # async fn run() -> anyhow::Result<()> {
+//! let mut repl = Repl::builder().build()?;
+//! let report = repl.run_script("help\nnope").await?;
+//! for failure in &report.failures {
+//!     eprintln!("line {}: {}: {}", failure.line, failure.command, failure.error);
+//! }
+//! assert!(!report.is_success());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Batches run independent lines concurrently, and keep the output of each line apart in
+//! their report instead of printing it.
+//!
+//! Demos play a script as if it was typed at the prompt, with the delays of a [`Demo`], to
+//! record the terminal, see [`Repl::play_demo`], and replays handle preloaded lines as fast
+//! as possible to benchmark commands, see [`Repl::replay`].
+//!
+//! [`Repl::run_script`]: crate::Repl::run_script
+//! [`Repl::run_batch`]: crate::Repl::run_batch
+//! [`Repl::play_demo`]: crate::Repl::play_demo
+//! [`Repl::replay`]: crate::Repl::replay
+
+use std::time::Duration;
+
+/// Outcome of running a script with [`Repl::run_script`](crate::Repl::run_script).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ScriptReport {
+    /// Lines handled, including empty ones and the ones that failed.
+    pub lines: usize,
+    /// Lines that failed, in order.
+    pub failures: Vec<ScriptFailure>,
+    /// Whether a command quit the REPL, stopping the script before its end.
+    pub quit: bool,
+}
+
+impl ScriptReport {
+    /// Returns true if no line failed.
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Line of a script that failed, see [`ScriptReport::failures`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ScriptFailure {
+    /// Number of the line, starting from 1.
+    pub line: usize,
+    /// Text of the line, without surrounding whitespace.
+    pub command: String,
+    /// Error reported by the line, or errors one per line if there were several.
+    pub error: String,
+}
+
+/// Delays of the simulated typing of [`Repl::play_demo`](crate::Repl::play_demo).
+///
+/// Delays are fixed rather than random, so that recordings of the same demo are identical.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Demo {
+    pub(crate) keystroke: Duration,
+    pub(crate) before_enter: Duration,
+    pub(crate) after_command: Duration,
+}
+
+impl Default for Demo {
+    fn default() -> Self {
+        Self {
+            keystroke: Duration::from_millis(60),
+            before_enter: Duration::from_millis(500),
+            after_command: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Demo {
+    /// Demo with default delays: 60ms per character, 500ms before pressing Enter and 1s
+    /// after the output of each command.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time between typed characters.
+    pub fn keystroke(mut self, delay: Duration) -> Self {
+        self.keystroke = delay;
+        self
+    }
+
+    /// Time between the last character of a line and running it.
+    pub fn before_enter(mut self, delay: Duration) -> Self {
+        self.before_enter = delay;
+        self
+    }
+
+    /// Time after a command finished, before the next prompt.
+    pub fn after_command(mut self, delay: Duration) -> Self {
+        self.after_command = delay;
+        self
+    }
+}
+
+/// Input lines handled as fast as possible by [`Repl::replay`](crate::Repl::replay), with
+/// the stages of input handling to measure.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Replay {
+    pub(crate) lines: Vec<String>,
+    pub(crate) completion: bool,
+    pub(crate) hints: bool,
+    pub(crate) dispatch: bool,
+}
+
+impl Replay {
+    /// Replay of `lines`, completing, hinting and handling each of them.
+    pub fn new<I, S>(lines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            lines: lines.into_iter().map(Into::into).collect(),
+            completion: true,
+            hints: true,
+            dispatch: true,
+        }
+    }
+
+    /// Whether to complete each line at its end.
+    pub fn completion(mut self, enabled: bool) -> Self {
+        self.completion = enabled;
+        self
+    }
+
+    /// Whether to compute the hint of each line at its end.
+    pub fn hints(mut self, enabled: bool) -> Self {
+        self.hints = enabled;
+        self
+    }
+
+    /// Whether to validate and run each line, like [`Repl::handle_line`](crate::Repl::handle_line).
+    pub fn dispatch(mut self, enabled: bool) -> Self {
+        self.dispatch = enabled;
+        self
+    }
+
+    /// Number of lines.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns true if there are no lines.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+/// Outcome of running command lines with [`Repl::run_batch`](crate::Repl::run_batch).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct BatchReport {
+    /// Result of each line, in the order of the lines whatever order they finished in.
+    pub results: Vec<BatchResult>,
+}
+
+impl BatchReport {
+    /// Returns true if no line failed.
+    pub fn is_success(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Results of the lines that failed, in order.
+    pub fn failures(&self) -> impl Iterator<Item = &BatchResult> {
+        self.results.iter().filter(|result| result.error.is_some())
+    }
+}
+
+/// Result of a line run by [`Repl::run_batch`](crate::Repl::run_batch).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct BatchResult {
+    /// Text of the line, without surrounding whitespace.
+    pub command: String,
+    /// Output written by the command.
+    pub output: String,
+    /// Error returned by the command, if it failed.
+    pub error: Option<String>,
+    /// Time the line took, including waiting for other lines running the same command.
+    pub duration: Duration,
+}
@@ -0,0 +1,395 @@
+//! Commands receiving their arguments as a typed struct deserialized with [`serde`].
+//!
+//! Arguments are mapped onto struct fields either by position or by name using `key=value`
+//! syntax, which can be mixed: named arguments are assigned first and positional ones fill
+//! the remaining fields in declaration order, as for other commands (see
+//! [`bind_named`](crate::command::bind_named)). Fields can be strings, numbers, bools
+//! (`true`/`false`), unit enum variants (by variant name) and `Option`s of these, which
+//! may be omitted.
+//!
+//! ```rust
+//! # use mini_async_repl::{serde_args, CommandStatus, Repl};
+//! #[derive(serde::Deserialize)]
+//! #[serde(rename_all = "lowercase")]
+//! enum Unit { Celsius, Fahrenheit }
+//!
+//! #[derive(serde::Deserialize)]
+//! struct SetArgs {
+//!     temperature: f32,
+//!     unit: Unit,
+//!     room: Option<String>,
+//! }
+//!
+//! let set = serde_args::command("Set temperature", |args: SetArgs| {
+//!     println!("{} in {:?}", args.temperature, args.room);
+//!     Ok(CommandStatus::Done)
+//! });
+//! // accepts e.g. `set 21.5 celsius`, `set 70 fahrenheit room=kitchen`, `set unit=celsius 20`
+//! let repl = Repl::builder().add("set", set).build().unwrap();
+//! ```
+//!
+//! Requires the `serde` feature.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::command::{
+    bind_partial, ArgsError, Command, CommandArgInfo, CommandArgType, CommandStatus,
+    ExecuteCommand, SendExecuteCommand,
+};
+
+/// Create a [`Command`] deserializing its arguments into `T` and passing them to `f`.
+///
+/// Fields of `T` become the `args_info` shown in help.
+pub fn command<T, F>(desc: &str, f: F) -> Command
+where
+    T: DeserializeOwned + 'static,
+    F: FnMut(T) -> anyhow::Result<CommandStatus> + Send + 'static,
+{
+    let args_info = field_names::<T>()
+        .iter()
+        .map(|name| CommandArgInfo::new_with_name(CommandArgType::String, name))
+        .collect();
+    Command::new_send(desc, args_info, Box::new(DeserializeArgs::new(f))).raw_args()
+}
+
+/// Handler deserializing arguments into `T` before calling `f`, see [`command`].
+///
+/// When used directly, the command should be created with [`Command::raw_args`] as the
+/// number of arguments varies.
+pub struct DeserializeArgs<T, F> {
+    f: F,
+    _args: PhantomData<fn() -> T>,
+}
+
+impl<T, F> DeserializeArgs<T, F>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> anyhow::Result<CommandStatus>,
+{
+    pub fn new(f: F) -> Self {
+        Self {
+            f,
+            _args: PhantomData,
+        }
+    }
+
+    fn handle(&mut self, args: &[&str]) -> anyhow::Result<CommandStatus> {
+        let args = from_args::<T>(args)?;
+        (self.f)(args)
+    }
+}
+
+impl<T, F> ExecuteCommand for DeserializeArgs<T, F>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> anyhow::Result<CommandStatus> + Send,
+{
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        _args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>> {
+        let result = self.handle(args);
+        Box::pin(async move { result })
+    }
+}
+
+impl<T, F> SendExecuteCommand for DeserializeArgs<T, F>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> anyhow::Result<CommandStatus> + Send,
+{
+    fn execute<'a>(
+        &'a mut self,
+        args: &'a [&'a str],
+        _args_info: &'a [CommandArgInfo],
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + Send + 'a>> {
+        let result = self.handle(args);
+        Box::pin(async move { result })
+    }
+}
+
+/// Deserialize `args` into `T`, see the [module documentation](self) for the syntax.
+pub fn from_args<T: DeserializeOwned>(args: &[&str]) -> Result<T, ArgsError> {
+    T::deserialize(ArgsDeserializer { args }).map_err(|Error(err)| err)
+}
+
+/// Names of the fields of struct `T`.
+pub fn field_names<T: DeserializeOwned>() -> &'static [&'static str] {
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> de::Deserializer<'de> for FieldNames<'_> {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("field names collected"))
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map enum
+            identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+#[derive(Debug)]
+struct Error(ArgsError);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error(ArgsError::Invalid(msg.to_string()))
+    }
+}
+
+struct ArgsDeserializer<'a> {
+    args: &'a [&'a str],
+}
+
+impl<'de> de::Deserializer<'de> for ArgsDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom(
+            "arguments can only be deserialized into a struct",
+        ))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let args_info: Vec<CommandArgInfo> = fields
+            .iter()
+            .map(|field| CommandArgInfo::new_with_name(CommandArgType::String, field))
+            .collect();
+        let values = match bind_partial(self.args, &args_info).map_err(Error)? {
+            Some(values) => values,
+            None if self.args.len() > fields.len() => {
+                return Err(Error(ArgsError::WrongNumberOfArguments {
+                    got: self.args.len(),
+                    expected: fields.len(),
+                }));
+            }
+            None => {
+                let mut values: Vec<Option<&str>> = self.args.iter().copied().map(Some).collect();
+                values.resize(fields.len(), None);
+                values
+            }
+        };
+
+        let entries = fields
+            .iter()
+            .zip(values)
+            .filter_map(|(field, value)| Some((*field, ValueDeserializer(value?))));
+        visitor.visit_map(de::value::MapDeserializer::new(entries))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+        byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map enum
+        identifier ignored_any
+    }
+}
+
+/// Deserializer of a single argument value, parsing it into the requested type.
+struct ValueDeserializer<'a>(&'a str);
+
+impl ValueDeserializer<'_> {
+    fn parse<T>(&self) -> Result<T, Error>
+    where
+        T: std::str::FromStr,
+        T::Err: Display,
+    {
+        self.0.parse().map_err(|err: T::Err| {
+            Error(ArgsError::WrongArgumentValue {
+                argument: self.0.into(),
+                error: err.to_string(),
+            })
+        })
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for ValueDeserializer<'_> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let argument = self.0;
+        let variant: de::value::StrDeserializer<'_, Error> = argument.into_deserializer();
+        visitor.visit_enum(variant).map_err(|Error(err)| {
+            Error(ArgsError::WrongArgumentValue {
+                argument: argument.into(),
+                error: err.to_string(),
+            })
+        })
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Level {
+        Low,
+        High,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Args {
+        name: String,
+        count: u8,
+        level: Level,
+        verbose: Option<bool>,
+    }
+
+    #[test]
+    fn positional_and_named() {
+        let expected = Args {
+            name: "x".into(),
+            count: 3,
+            level: Level::High,
+            verbose: None,
+        };
+        assert_eq!(from_args::<Args>(&["x", "3", "high"]).unwrap(), expected);
+        assert_eq!(
+            from_args::<Args>(&["level=high", "x", "count=3"]).unwrap(),
+            expected
+        );
+        let args = from_args::<Args>(&["a=b", "0", "low", "true"]).unwrap();
+        assert_eq!(args.name, "a=b");
+        assert_eq!(args.verbose, Some(true));
+        assert_eq!(field_names::<Args>(), ["name", "count", "level", "verbose"]);
+    }
+
+    #[test]
+    fn errors() {
+        let err = from_args::<Args>(&["x", "300", "low"]).unwrap_err();
+        assert!(matches!(err, ArgsError::WrongArgumentValue { argument, .. } if argument == "300"));
+        let err = from_args::<Args>(&["x", "3", "medium"]).unwrap_err();
+        assert!(
+            matches!(err, ArgsError::WrongArgumentValue { argument, .. } if argument == "medium")
+        );
+        let err = from_args::<Args>(&["x", "3"]).unwrap_err();
+        assert!(matches!(err, ArgsError::Invalid(msg) if msg.contains("level")));
+        let err = from_args::<Args>(&["x", "3", "low", "true", "extra"]).unwrap_err();
+        assert!(matches!(
+            err,
+            ArgsError::WrongNumberOfArguments {
+                got: 5,
+                expected: 4
+            }
+        ));
+        let err = from_args::<Args>(&["count=1", "count=2"]).unwrap_err();
+        assert!(matches!(err, ArgsError::Invalid(msg) if msg.contains("more than once")));
+        let err = from_args::<Args>(&["x", "3", "levl=low"]).unwrap_err();
+        assert!(matches!(
+            err,
+            ArgsError::UnknownFlag { flag, suggestion: Some(name) } if flag == "levl" && name == "level"
+        ));
+    }
+
+    #[tokio::test]
+    async fn deserialize_command() {
+        let mut cmd = command("Test", |args: Args| {
+            assert_eq!(args.level, Level::Low);
+            Ok(CommandStatus::Done)
+        });
+        assert_eq!(cmd.arg_types().len(), 4);
+        assert!(cmd.execute(&["x", "1", "low"]).await.is_ok());
+        assert!(cmd.execute(&["x"]).await.unwrap_err().is::<ArgsError>());
+    }
+}
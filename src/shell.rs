@@ -0,0 +1,289 @@
+//! Completion scripts for shells, used when commands are run from the command line
+//! (see [`Repl::run_args`](crate::Repl::run_args)).
+
+use std::fmt::Write;
+
+use crate::registry::CommandInfo;
+
+/// Shell to generate completion script for, see
+/// [`Repl::generate_completions`](crate::Repl::generate_completions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Shell {
+    /// Script to be sourced in `.bashrc`.
+    Bash,
+    /// Script to be placed in `$fpath` as `_<bin_name>` or sourced in `.zshrc`.
+    Zsh,
+    /// Script to be placed in `~/.config/fish/completions/<bin_name>.fish`.
+    Fish,
+}
+
+/// What can be completed after a command name.
+struct Completions {
+    name: String,
+    description: String,
+    /// Values of positional arguments, by position.
+    positional: Vec<Vec<String>>,
+    /// `key=` prefixes of named arguments.
+    keys: Vec<String>,
+}
+
+const HELP_FLAGS: [&str; 2] = ["-h", "--help"];
+
+impl Completions {
+    fn new(info: &CommandInfo) -> Self {
+        let mut positional: Vec<Vec<String>> = Vec::new();
+        let mut keys = Vec::new();
+        for overload in &info.overloads {
+            for (i, arg) in overload.args.iter().enumerate() {
+                if positional.len() <= i {
+                    positional.push(Vec::new());
+                }
+                if let Some(completer) = &arg.completer {
+                    // values that would need quoting cannot be listed in the scripts
+                    let values = completer.complete("").into_iter().filter(|v| is_word(v));
+                    positional[i].extend(values);
+                }
+                if let Some(name) = arg.name.as_deref().filter(|name| is_word(name)) {
+                    keys.push(format!("{name}="));
+                }
+            }
+        }
+        for values in positional.iter_mut().chain([&mut keys]) {
+            values.sort();
+            values.dedup();
+        }
+        Self {
+            name: info.name.clone(),
+            description: info
+                .overloads
+                .first()
+                .map(|o| o.description.clone())
+                .unwrap_or_default(),
+            positional,
+            keys,
+        }
+    }
+
+    /// Words that can follow the command at `position` (counting from 0).
+    fn words_at(&self, position: usize) -> Vec<&str> {
+        let mut words: Vec<&str> = self
+            .positional
+            .get(position)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        words.extend(self.keys.iter().map(String::as_str));
+        if position == 0 {
+            words.extend(HELP_FLAGS);
+        }
+        words
+    }
+}
+
+fn is_word(s: &str) -> bool {
+    !s.is_empty() && shell_words::quote(s) == s
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn function_name(bin_name: &str) -> String {
+    let name: String = bin_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("_{name}")
+}
+
+/// Generate completion script for `shell` completing `commands` of program `bin_name`.
+pub(crate) fn generate(shell: Shell, bin_name: &str, commands: &[CommandInfo]) -> String {
+    let commands: Vec<_> = commands
+        .iter()
+        .filter(|info| !info.overloads.iter().all(|o| o.hidden))
+        .map(Completions::new)
+        .collect();
+    match shell {
+        Shell::Bash => bash(bin_name, &commands),
+        Shell::Zsh => zsh(bin_name, &commands),
+        Shell::Fish => fish(bin_name, &commands),
+    }
+}
+
+fn names(commands: &[Completions]) -> String {
+    let mut names: Vec<&str> = commands.iter().map(|c| c.name.as_str()).collect();
+    names.push("help");
+    names.join(" ")
+}
+
+fn bash(bin_name: &str, commands: &[Completions]) -> String {
+    let function = function_name(bin_name);
+    let mut out = String::new();
+    let _ = writeln!(out, "{function}() {{");
+    out.push_str("    local cur=\"${COMP_WORDS[COMP_CWORD]}\"\n");
+    out.push_str("    if [ \"$COMP_CWORD\" -eq 1 ]; then\n");
+    let _ = writeln!(
+        out,
+        "        COMPREPLY=($(compgen -W {} -- \"$cur\"))",
+        quote(&names(commands))
+    );
+    out.push_str("        return\n    fi\n");
+    out.push_str("    local words=\"\"\n");
+    out.push_str("    case \"${COMP_WORDS[1]}\" in\n");
+    for cmd in commands {
+        let _ = writeln!(out, "        {})", cmd.name);
+        out.push_str("            case \"$COMP_CWORD\" in\n");
+        for position in 0..cmd.positional.len().max(1) {
+            let _ = writeln!(
+                out,
+                "                {}) words={} ;;",
+                position + 2,
+                quote(&cmd.words_at(position).join(" "))
+            );
+        }
+        let _ = writeln!(
+            out,
+            "                *) words={} ;;",
+            quote(&cmd.keys.join(" "))
+        );
+        out.push_str("            esac ;;\n");
+    }
+    out.push_str("    esac\n");
+    out.push_str("    COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))\n");
+    out.push_str("}\n");
+    let _ = writeln!(out, "complete -o default -F {function} {bin_name}");
+    out
+}
+
+fn zsh(bin_name: &str, commands: &[Completions]) -> String {
+    let function = function_name(bin_name);
+    let mut out = String::new();
+    let _ = writeln!(out, "#compdef {bin_name}\n");
+    let _ = writeln!(out, "{function}() {{");
+    out.push_str("    local -a commands\n    commands=(\n");
+    for cmd in commands {
+        let entry = format!("{}:{}", cmd.name, cmd.description.replace(':', "\\:"));
+        let _ = writeln!(out, "        {}", quote(&entry));
+    }
+    let _ = writeln!(out, "        {}", quote("help:Show help message"));
+    out.push_str("    )\n");
+    out.push_str("    if (( CURRENT == 2 )); then\n");
+    out.push_str("        _describe 'command' commands\n        return\n    fi\n");
+    out.push_str("    case $words[2] in\n");
+    for cmd in commands {
+        let _ = writeln!(out, "        {})", cmd.name);
+        out.push_str("            case $CURRENT in\n");
+        for position in 0..cmd.positional.len().max(1) {
+            let words: Vec<String> = cmd.words_at(position).iter().map(|w| quote(w)).collect();
+            let _ = writeln!(
+                out,
+                "                {}) compadd -- {} ;;",
+                position + 3,
+                words.join(" ")
+            );
+        }
+        let keys: Vec<String> = cmd.keys.iter().map(|k| quote(k)).collect();
+        let _ = writeln!(out, "                *) compadd -- {} ;;", keys.join(" "));
+        out.push_str("            esac ;;\n");
+    }
+    out.push_str("    esac\n}\n\n");
+    let _ = writeln!(out, "compdef {function} {bin_name}");
+    out
+}
+
+fn fish(bin_name: &str, commands: &[Completions]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "complete -c {bin_name} -f");
+    let _ = writeln!(
+        out,
+        "complete -c {bin_name} -n __fish_use_subcommand -a help -d {}",
+        quote("Show help message")
+    );
+    for cmd in commands {
+        let _ = writeln!(
+            out,
+            "complete -c {bin_name} -n __fish_use_subcommand -a {} -d {}",
+            cmd.name,
+            quote(&cmd.description)
+        );
+        let condition = quote(&format!("__fish_seen_subcommand_from {}", cmd.name));
+        // fish does not track argument positions, so all values are offered everywhere
+        let mut words: Vec<&str> = cmd
+            .positional
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        words.extend(cmd.keys.iter().map(String::as_str));
+        if !words.is_empty() {
+            let _ = writeln!(
+                out,
+                "complete -c {bin_name} -n {condition} -a {}",
+                quote(&words.join(" "))
+            );
+        }
+        let _ = writeln!(
+            out,
+            "complete -c {bin_name} -n {condition} -s h -l help -d {}",
+            quote("Show command help")
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Command, CommandArgInfo, CommandArgType, TrivialCommandHandler};
+    use crate::registry::CommandRegistry;
+    use crate::runtime::default_runtime;
+
+    fn commands() -> Vec<CommandInfo> {
+        let level = CommandArgInfo::new_with_name(CommandArgType::String, "level")
+            .with_completer(|_: &str| vec!["low".into(), "high".into(), "very high".into()]);
+        let cmds = vec![
+            (
+                "set".to_string(),
+                Command::new(
+                    "Set level",
+                    vec![level],
+                    Box::new(TrivialCommandHandler::new()),
+                ),
+            ),
+            (
+                "secret".to_string(),
+                Command::new("", vec![], Box::new(TrivialCommandHandler::new())).hidden(true),
+            ),
+        ];
+        CommandRegistry::new(cmds, default_runtime())
+            .unwrap()
+            .infos()
+    }
+
+    #[test]
+    fn bash_script() {
+        let script = generate(Shell::Bash, "my-tool", &commands());
+        assert!(script.contains("_my_tool() {"));
+        assert!(script.contains("compgen -W 'set help'"));
+        assert!(script.contains("2) words='high low level= -h --help' ;;"));
+        assert!(script.contains("complete -o default -F _my_tool my-tool"));
+        assert!(!script.contains("secret"));
+        assert!(!script.contains("very"));
+    }
+
+    #[test]
+    fn zsh_script() {
+        let script = generate(Shell::Zsh, "tool", &commands());
+        assert!(script.starts_with("#compdef tool"));
+        assert!(script.contains("'set:Set level'"));
+        assert!(script.contains("3) compadd -- 'high' 'low' 'level=' '-h' '--help' ;;"));
+    }
+
+    #[test]
+    fn fish_script() {
+        let script = generate(Shell::Fish, "tool", &commands());
+        assert!(script.contains("-n __fish_use_subcommand -a set -d 'Set level'"));
+        assert!(script.contains("-n '__fish_seen_subcommand_from set' -a 'high low level='"));
+    }
+}
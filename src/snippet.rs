@@ -0,0 +1,124 @@
+//! Expansion of snippets, see [`ReplBuilder::snippet`].
+//!
+//! [`ReplBuilder::snippet`]: crate::repl::ReplBuilder::snippet
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::repl::split_args;
+use crate::tokenize::Token;
+
+/// Command line templates of snippets by name, shared with the keys inserting them.
+pub(crate) type Snippets = Arc<Mutex<BTreeMap<String, String>>>;
+
+/// Template of snippet `name`, if there is one.
+pub(crate) fn get(snippets: &Snippets, name: &str) -> Option<String> {
+    let snippets = snippets.lock().unwrap_or_else(PoisonError::into_inner);
+    snippets.get(name).cloned()
+}
+
+/// Arguments of `template` with `$1` to `$9` replaced by the corresponding `args` and `$@`
+/// by all of them, each taking `span`.
+///
+/// Fails if an argument is missing, or if there are more arguments than the template uses.
+pub(crate) fn expand(
+    template: &str,
+    args: &[Token],
+    span: Range<usize>,
+) -> anyhow::Result<Vec<Token<'static>>> {
+    let tokens = split_args(template).map_err(|err| anyhow::anyhow!("invalid snippet: {err}"))?;
+    let mut used = 0;
+    let mut all_used = false;
+    let mut expanded = Vec::with_capacity(tokens.len() + args.len());
+    let token = |text| Token {
+        text: Cow::Owned(text),
+        span: span.clone(),
+        quoted: true,
+    };
+    for template_arg in &tokens {
+        if template_arg.as_str() == "$@" {
+            all_used = true;
+            expanded.extend(args.iter().map(|arg| token(arg.to_string())));
+            continue;
+        }
+        let mut text = String::with_capacity(template_arg.len());
+        let mut rest = template_arg.as_str();
+        while let Some(i) = rest.find('$') {
+            text.push_str(&rest[..i]);
+            let after = &rest[i + 1..];
+            match after.chars().next().and_then(|c| c.to_digit(10)) {
+                Some(n) if n > 0 => {
+                    let n = n as usize;
+                    let arg = args
+                        .get(n - 1)
+                        .ok_or_else(|| anyhow::anyhow!("missing argument ${n}"))?;
+                    text.push_str(arg);
+                    used = used.max(n);
+                    rest = &after[1..];
+                }
+                _ => {
+                    text.push('$');
+                    rest = after;
+                }
+            }
+        }
+        text.push_str(rest);
+        expanded.push(token(text));
+    }
+    if !all_used && args.len() > used {
+        anyhow::bail!("too many arguments, expected {used}");
+    }
+    Ok(expanded)
+}
+
+/// Command line of `words`, quoting them as needed except for their `$`, so that
+/// parameters are still replaced in words such as `--env=$2`.
+pub(crate) fn join(words: &[&str]) -> String {
+    let quoted: Vec<Cow<str>> = words
+        .iter()
+        .map(|word| match shell_words::quote(&word.replace('$', "")) {
+            Cow::Borrowed(_) if !word.is_empty() => Cow::Borrowed(*word),
+            _ => shell_words::quote(word),
+        })
+        .collect();
+    quoted.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_line(template: &str, line: &str) -> anyhow::Result<Vec<String>> {
+        let args = split_args(line).unwrap();
+        let expanded = expand(template, &args, 0..1)?;
+        Ok(expanded.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn parameters() {
+        let template = "rollout $1 --env=$2 '--note=$1 $$'";
+        assert_eq!(
+            expand_line(template, "web prod").unwrap(),
+            ["rollout", "web", "--env=prod", "--note=web $$"]
+        );
+        assert_eq!(
+            expand_line(template, "'my app' prod").unwrap(),
+            ["rollout", "my app", "--env=prod", "--note=my app $$"]
+        );
+        let err = expand_line(template, "web").unwrap_err();
+        assert_eq!(err.to_string(), "missing argument $2");
+        let err = expand_line(template, "web prod x").unwrap_err();
+        assert_eq!(err.to_string(), "too many arguments, expected 2");
+        assert_eq!(
+            expand_line("tail -n $1 $@", "5 a b").unwrap(),
+            ["tail", "-n", "5", "5", "a", "b"]
+        );
+        assert!(expand_line("'", "").is_err());
+        assert_eq!(
+            join(&["rollout", "$1", "--note=$2 now"]),
+            "rollout $1 '--note=$2 now'"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Session state saved with [`Repl::save_state`] and restored with [`Repl::load_state`].
+//!
+//! The state is a TOML file with the [options](Repl::options), [mode](Repl::mode), current
+//! directory, aliases, snippets, key bindings and history of the session, so that a
+//! long-running session can be resumed later:
+//!
+//! ```toml
+//! mode = "prod"
+//! working_dir = "/srv/app"
+//! history = ["status", "restart web-1"]
+//!
+//! [options]
+//! prompt = "prod> "
+//! verbosity = "verbose"
+//!
+//! [aliases]
+//! st = "status"
+//!
+//! [snippets]
+//! restart = "restart $1 --drain"
+//!
+//! [bindings]
+//! f5 = "status"
+//! ```
+//!
+//! Key bindings and history are only available with the `terminal` feature.
+//!
+//! Requires the `config` feature.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "terminal")]
+use crate::keymap::KeymapError;
+use crate::repl::{BuilderError, OptionError};
+#[cfg(doc)]
+use crate::Repl;
+
+/// Error when saving or restoring a [`SessionState`].
+#[derive(Debug, thiserror::Error)]
+pub enum StateError {
+    /// File could not be read or written.
+    #[error("failed to access session state '{0}': {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+    /// File is not valid.
+    #[error("invalid session state: {0}")]
+    Parse(#[from] toml::de::Error),
+    /// State could not be serialized.
+    #[error("failed to serialize session state: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    /// Option cannot be restored.
+    #[error(transparent)]
+    Option(#[from] OptionError),
+    /// Alias or snippet cannot be restored, e.g. because a command with the same name was
+    /// added.
+    #[error(transparent)]
+    Alias(#[from] BuilderError),
+    /// Current directory cannot be restored, e.g. because it has been removed.
+    #[error("failed to restore the current directory: {0}")]
+    WorkingDir(#[source] anyhow::Error),
+    /// Key binding cannot be restored.
+    #[cfg(feature = "terminal")]
+    #[error(transparent)]
+    Binding(#[from] KeymapError),
+}
+
+/// State of a session, see the [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionState {
+    /// Mode shown in the prompt, see [`Repl::set_mode`].
+    pub mode: Option<String>,
+    /// Current directory of sessions managing one, see [`Repl::working_dir`].
+    pub working_dir: Option<PathBuf>,
+    /// Values of [`Repl::options`] by name.
+    pub options: BTreeMap<String, String>,
+    /// Command lines of aliases by name.
+    pub aliases: BTreeMap<String, String>,
+    /// Command line templates of snippets by name, see [`Repl::snippets`].
+    pub snippets: BTreeMap<String, String>,
+    /// Command lines bound to keys, by key name, see [`Repl::bind_command`].
+    #[cfg(feature = "terminal")]
+    pub bindings: BTreeMap<String, String>,
+    /// Entries of the line editor history, oldest first.
+    #[cfg(feature = "terminal")]
+    pub history: Vec<String>,
+}
+
+impl SessionState {
+    /// Read state from the TOML file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).map_err(|e| StateError::Io(path.to_path_buf(), e))?;
+        text.parse()
+    }
+
+    /// Write state to `path` as TOML, replacing the file if it exists.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        let path = path.as_ref();
+        let text = toml::to_string(self)?;
+        std::fs::write(path, text).map_err(|e| StateError::Io(path.to_path_buf(), e))
+    }
+}
+
+impl FromStr for SessionState {
+    type Err = StateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(toml::from_str(s)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Command, TrivialCommandHandler};
+    use crate::Repl;
+
+    #[test]
+    fn save_and_load() {
+        let status = || Command::new("", vec![], Box::new(TrivialCommandHandler::new()));
+        let dir = std::env::temp_dir();
+        let mut repl = Repl::builder()
+            .add("status", status())
+            .alias("st", "status")
+            .snippet("check", "status $1")
+            .working_dir(&dir)
+            .build()
+            .unwrap();
+        #[allow(unused_mut)]
+        let mut state: SessionState = "[options]\nprompt = 'prod> '".parse().unwrap();
+        #[cfg(feature = "terminal")]
+        {
+            state.bindings.insert("f5".into(), "status".into());
+            state.history.push("status".into());
+        }
+        state.mode = Some("prod".into());
+        repl.restore_state(state.clone()).unwrap();
+        assert_eq!(repl.prompt(), "prod> ");
+        assert_eq!(repl.mode(), Some("prod"));
+        repl.set_working_dir("..").unwrap();
+        #[cfg(feature = "terminal")]
+        assert_eq!(repl.state().history, state.history);
+        let path = std::env::temp_dir().join(format!("repl-state-{}.toml", std::process::id()));
+        repl.save_state(&path).unwrap();
+
+        let mut restored = Repl::builder()
+            .add("status", status())
+            .working_dir(&dir)
+            .build()
+            .unwrap();
+        assert!(!restored.help().contains("Alias of 'status'"));
+        restored.load_state(&path).unwrap();
+        assert!(restored.help().contains("Alias of 'status'"));
+        assert_eq!(restored.state(), repl.state());
+        assert_eq!(restored.mode(), Some("prod"));
+        assert_eq!(restored.working_dir(), dir.parent().map(Path::to_path_buf));
+        assert_eq!(restored.snippets()["check"], "status $1");
+        std::fs::remove_file(&path).unwrap();
+
+        // nothing is restored if any entry cannot be
+        let state: SessionState =
+            "mode = 'dev'\n[options]\nprompt = '> '\n[aliases]\nstatus = 'help'"
+                .parse()
+                .unwrap();
+        assert!(matches!(
+            restored.restore_state(state),
+            Err(StateError::Alias(BuilderError::AliasConflict(_)))
+        ));
+        assert_eq!(restored.prompt(), "prod> ");
+        assert_eq!(restored.mode(), Some("prod"));
+        let state: SessionState = "[options]\nprompt = '> '\ntext-width = 'wide'"
+            .parse()
+            .unwrap();
+        assert!(restored.restore_state(state).is_err());
+        assert_eq!(restored.prompt(), "prod> ");
+        let state: SessionState = "working_dir = '/no/such/dir'".parse().unwrap();
+        assert!(matches!(
+            restored.restore_state(state),
+            Err(StateError::WorkingDir(_))
+        ));
+        assert!(matches!(
+            "[options]\nnope = '1'"
+                .parse()
+                .map(|state| restored.restore_state(state)),
+            Ok(Err(StateError::Option(OptionError::UnknownOption(_))))
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+//! Execution statistics gathered by the dispatcher, see [`Repl::stats`].
+//!
+//! Every command run through the REPL is counted, with its errors and the time it took, so
+//! that applications can show them or export them to their metrics. There is no clock in
+//! `wasm32-unknown-unknown`, so durations are zero there.
+//!
+//! [`Repl::stats`]: crate::Repl::stats
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+
+/// Statistics of a session since it started or they were last reset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Stats {
+    /// Commands run, including the failed ones.
+    pub commands: u64,
+    /// Commands that returned an error.
+    pub errors: u64,
+    /// Statistics of each command by name.
+    pub per_command: BTreeMap<String, CommandStats>,
+}
+
+/// Statistics of a single command, see [`Stats::per_command`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct CommandStats {
+    /// Times the command has been run.
+    pub runs: u64,
+    /// Runs that returned an error.
+    pub errors: u64,
+    /// Time taken by all the runs.
+    pub total_time: Duration,
+    /// Time taken by the fastest run.
+    pub min_time: Duration,
+    /// Time taken by the slowest run.
+    pub max_time: Duration,
+}
+
+impl CommandStats {
+    /// Average time taken by a run.
+    pub fn mean_time(&self) -> Duration {
+        match u32::try_from(self.runs) {
+            Ok(0) => Duration::ZERO,
+            Ok(runs) => self.total_time / runs,
+            Err(_) => self.total_time.div_f64(self.runs as f64),
+        }
+    }
+}
+
+impl Stats {
+    /// Count a run of command `name` taking `duration`.
+    pub(crate) fn record(&mut self, name: &str, duration: Duration, failed: bool) {
+        self.commands += 1;
+        self.errors += u64::from(failed);
+        let stats = self.per_command.entry(name.to_string()).or_default();
+        stats.min_time = match stats.runs {
+            0 => duration,
+            _ => stats.min_time.min(duration),
+        };
+        stats.max_time = stats.max_time.max(duration);
+        stats.total_time += duration;
+        stats.runs += 1;
+        stats.errors += u64::from(failed);
+    }
+}
+
+/// Current time of `clock`, or `None` on targets without a clock.
+pub(crate) fn now(clock: &dyn Clock) -> Option<Instant> {
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    return None;
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    Some(clock.now())
+}
+
+/// Time elapsed on `clock` since `start` returned by [`now`].
+pub(crate) fn since(clock: &dyn Clock, start: Option<Instant>) -> Duration {
+    start
+        .map(|start| clock.now().saturating_duration_since(start))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record() {
+        let mut stats = Stats::default();
+        let ms = Duration::from_millis;
+        stats.record("deploy", ms(30), false);
+        stats.record("deploy", ms(10), true);
+        stats.record("status", ms(1), false);
+        assert_eq!((stats.commands, stats.errors), (3, 1));
+        let deploy = &stats.per_command["deploy"];
+        assert_eq!((deploy.runs, deploy.errors), (2, 1));
+        assert_eq!((deploy.min_time, deploy.max_time), (ms(10), ms(30)));
+        assert_eq!(deploy.mean_time(), ms(20));
+        assert_eq!(CommandStats::default().mean_time(), Duration::ZERO);
+    }
+}
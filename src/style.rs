@@ -0,0 +1,157 @@
+//! Styles of the text shown by the line editor, see [`ReplBuilder::theme`].
+//!
+//! [`ReplBuilder::theme`]: crate::repl::ReplBuilder::theme
+
+/// Terminal color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    /// Bright black, usually rendered as gray.
+    Gray,
+}
+
+impl Color {
+    fn code(self) -> u8 {
+        match self {
+            Color::Black => 30,
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Blue => 34,
+            Color::Magenta => 35,
+            Color::Cyan => 36,
+            Color::White => 37,
+            Color::Gray => 90,
+        }
+    }
+}
+
+/// Text style applied with ANSI escape sequences. The default style leaves text unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct Style {
+    pub color: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    /// Style leaving text unchanged.
+    pub const fn new() -> Self {
+        Self {
+            color: None,
+            bold: false,
+            dim: false,
+            italic: false,
+            underline: false,
+        }
+    }
+
+    pub const fn color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub const fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    pub const fn dim(mut self) -> Self {
+        self.dim = true;
+        self
+    }
+
+    pub const fn italic(mut self) -> Self {
+        self.italic = true;
+        self
+    }
+
+    pub const fn underline(mut self) -> Self {
+        self.underline = true;
+        self
+    }
+
+    /// Returns true if the style leaves text unchanged.
+    pub fn is_plain(&self) -> bool {
+        *self == Self::new()
+    }
+
+    /// Wrap `text` in escape sequences setting and resetting the style.
+    pub fn paint(&self, text: &str) -> String {
+        if self.is_plain() || text.is_empty() {
+            return text.to_string();
+        }
+        let flags = [
+            (self.bold, 1),
+            (self.dim, 2),
+            (self.italic, 3),
+            (self.underline, 4),
+        ];
+        let codes: Vec<String> = flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, code)| *code)
+            .chain(self.color.map(Color::code))
+            .map(|code| code.to_string())
+            .collect();
+        format!("\x1b[{}m{text}\x1b[0m", codes.join(";"))
+    }
+}
+
+/// Styles of the parts of the input line.
+///
+/// By default nothing is styled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct Theme {
+    /// Hint shown after the cursor, see [`ReplBuilder::with_hints`].
+    ///
+    /// [`ReplBuilder::with_hints`]: crate::repl::ReplBuilder::with_hints
+    pub hint: Style,
+    /// Candidates listed on completion. Only used with
+    /// [`CompletionType::List`](rustyline::CompletionType::List), as in circular mode the
+    /// candidate is inserted into the line.
+    pub candidate: Style,
+    /// Bracket at the cursor (or just before it) and the bracket matching it, e.g. in
+    /// expressions given as arguments. Brackets inside quotes are ignored.
+    pub bracket: Style,
+    /// Quote that is not closed and the text after it.
+    pub unclosed_quote: Style,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paint() {
+        assert_eq!(Style::new().paint("text"), "text");
+        assert_eq!(Style::new().dim().paint("text"), "\x1b[2mtext\x1b[0m");
+        let style = Style::new().italic().color(Color::Gray);
+        assert_eq!(style.paint("text"), "\x1b[3;90mtext\x1b[0m");
+        assert_eq!(style.paint(""), "");
+    }
+}
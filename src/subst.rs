@@ -0,0 +1,351 @@
+//! Substitution of variables and expressions in arguments, see
+//! [`ReplBuilder::variable_substitution`].
+//!
+//! Like in shells:
+//!
+//! - `$NAME` and `${NAME}` are the value of variable `NAME`, and are left as they are if it
+//!   is not set,
+//! - `${NAME:-default}` is `default` if `NAME` is not set or empty,
+//! - `${#NAME}` is the length of the value, `${NAME^^}` and `${NAME,,}` the value in upper
+//!   and lower case,
+//! - `$((expression))` is the result of an integer expression with `+`, `-`, `*`, `/`, `%`
+//!   and parentheses, in which names are variables (`0` if not set).
+//!
+//! [`ReplBuilder::variable_substitution`]: crate::repl::ReplBuilder::variable_substitution
+
+use std::borrow::Cow;
+
+use anyhow::{self, Context};
+
+/// Deepest nesting of expressions and of `${NAME:-default}` substitutions, beyond which
+/// substitution fails instead of overflowing the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Value of environment variable `name`, the default source of variables.
+pub(crate) fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// Replace variables and expressions in `text` with their values, using `lookup` to get
+/// the values of variables.
+///
+/// Fails if an expression is not valid, e.g. `$((1 +))` or `${NAME:}`.
+pub(crate) fn substitute<'a>(
+    text: &'a str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> anyhow::Result<Cow<'a, str>> {
+    substitute_nested(text, lookup, 0)
+}
+
+/// [`substitute`] within `depth` enclosing substitutions.
+fn substitute_nested<'a>(
+    text: &'a str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    depth: usize,
+) -> anyhow::Result<Cow<'a, str>> {
+    if depth > MAX_DEPTH {
+        anyhow::bail!("expression too deeply nested");
+    }
+    if !text.contains('$') {
+        return Ok(Cow::Borrowed(text));
+    }
+    let mut substituted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('$') {
+        substituted.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        let len = if let Some(expression) = after.strip_prefix("((") {
+            let end = arithmetic_end(expression)
+                .with_context(|| format!("unclosed expression in '{text}'"))?;
+            let value = arithmetic(&expression[..end], lookup).map_err(|err| {
+                anyhow::anyhow!("invalid expression '$(({}))': {err}", &expression[..end])
+            })?;
+            substituted.push_str(&value.to_string());
+            end + 4
+        } else if let Some(braced) = after.strip_prefix('{') {
+            match closing_brace(braced) {
+                Some(end) => {
+                    match braced_value(&braced[..end], lookup, depth)? {
+                        Some(value) => substituted.push_str(&value),
+                        None => substituted.push_str(&rest[i..i + end + 3]),
+                    }
+                    end + 2
+                }
+                None => {
+                    substituted.push('$');
+                    0
+                }
+            }
+        } else {
+            let end = name_len(after);
+            match lookup(&after[..end]) {
+                Some(value) if end > 0 => substituted.push_str(&value),
+                _ => substituted.push_str(&rest[i..i + 1 + end]),
+            }
+            end
+        };
+        rest = &after[len..];
+    }
+    substituted.push_str(rest);
+    Ok(Cow::Owned(substituted))
+}
+
+/// Whether variables are substituted in an argument written as `raw` in the command line,
+/// i.e. unless it is in single quotes or `$` is escaped, like in shells.
+pub(crate) fn substitutes(raw: &str) -> bool {
+    !raw.contains('\'') && !raw.contains("\\$")
+}
+
+/// Length in bytes of the variable name at the start of `text`.
+fn name_len(text: &str) -> usize {
+    text.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(text.len())
+}
+
+/// Position of the `}` closing the `${` just before `text`, skipping nested ones.
+fn closing_brace(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Position of the `))` closing the `$((` just before `text`.
+fn arithmetic_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return text[i + 1..].starts_with(')').then_some(i),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Value of `${inner}`, or `None` if it names a variable that is not set.
+fn braced_value(
+    inner: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    depth: usize,
+) -> anyhow::Result<Option<String>> {
+    if let Some(name) = inner.strip_prefix('#') {
+        if !name.is_empty() && name_len(name) == name.len() {
+            let len = lookup(name).map_or(0, |value| value.chars().count());
+            return Ok(Some(len.to_string()));
+        }
+    }
+    let end = name_len(inner);
+    let (name, operation) = inner.split_at(end);
+    if name.is_empty() {
+        anyhow::bail!("bad substitution '${{{inner}}}'");
+    }
+    let value = lookup(name);
+    Ok(match operation {
+        "" => value,
+        "^^" => Some(value.unwrap_or_default().to_uppercase()),
+        ",," => Some(value.unwrap_or_default().to_lowercase()),
+        _ => match operation.strip_prefix(":-") {
+            Some(default) => match value.filter(|value| !value.is_empty()) {
+                Some(value) => Some(value),
+                None => Some(substitute_nested(default, lookup, depth + 1)?.into_owned()),
+            },
+            None => anyhow::bail!("bad substitution '${{{inner}}}'"),
+        },
+    })
+}
+
+/// Value of an integer `expression`.
+fn arithmetic(expression: &str, lookup: &dyn Fn(&str) -> Option<String>) -> anyhow::Result<i64> {
+    let mut parser = Parser {
+        text: expression,
+        pos: 0,
+        depth: 0,
+        lookup,
+    };
+    let value = parser.sum()?;
+    parser.skip_whitespace();
+    if parser.pos < expression.len() {
+        anyhow::bail!("unexpected '{}'", &expression[parser.pos..]);
+    }
+    Ok(value)
+}
+
+/// Recursive descent parser of arithmetic expressions, evaluating them as it goes.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    /// Number of enclosing unary operators and parentheses.
+    depth: usize,
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Next character after whitespace, which is consumed if it is one of `expected`.
+    fn operator(&mut self, expected: &[char]) -> Option<char> {
+        self.skip_whitespace();
+        let c = self.text[self.pos..].chars().next()?;
+        expected.contains(&c).then(|| {
+            self.pos += c.len_utf8();
+            c
+        })
+    }
+
+    fn sum(&mut self) -> anyhow::Result<i64> {
+        let mut value = self.product()?;
+        while let Some(op) = self.operator(&['+', '-']) {
+            let term = self.product()?;
+            value = match op {
+                '+' => value.checked_add(term),
+                _ => value.checked_sub(term),
+            }
+            .context("overflow")?;
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> anyhow::Result<i64> {
+        let mut value = self.unary()?;
+        while let Some(op) = self.operator(&['*', '/', '%']) {
+            let factor = self.unary()?;
+            if op != '*' && factor == 0 {
+                anyhow::bail!("division by zero");
+            }
+            value = match op {
+                '*' => value.checked_mul(factor),
+                '/' => value.checked_div(factor),
+                _ => value.checked_rem(factor),
+            }
+            .context("overflow")?;
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> anyhow::Result<i64> {
+        match self.operator(&['-', '+', '(']) {
+            Some('-') => self.nested(Self::unary)?.checked_neg().context("overflow"),
+            Some('+') => self.nested(Self::unary),
+            Some(_) => {
+                let value = self.nested(Self::sum)?;
+                self.operator(&[')']).context("missing ')'")?;
+                Ok(value)
+            }
+            None => self.atom(),
+        }
+    }
+
+    /// Parse with `parse` one level deeper, failing past [`MAX_DEPTH`].
+    fn nested(&mut self, parse: fn(&mut Self) -> anyhow::Result<i64>) -> anyhow::Result<i64> {
+        if self.depth == MAX_DEPTH {
+            anyhow::bail!("expression too deeply nested");
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    /// Number or variable.
+    fn atom(&mut self) -> anyhow::Result<i64> {
+        let rest = &self.text[self.pos..];
+        let len = name_len(rest);
+        if len == 0 {
+            match rest.chars().next() {
+                Some(c) => anyhow::bail!("unexpected '{c}'"),
+                None => anyhow::bail!("missing operand"),
+            }
+        }
+        self.pos += len;
+        let word = &rest[..len];
+        if word.starts_with(|c: char| c.is_ascii_digit()) {
+            return word
+                .parse()
+                .with_context(|| format!("invalid number '{word}'"));
+        }
+        match (self.lookup)(word) {
+            Some(value) if !value.trim().is_empty() => value
+                .trim()
+                .parse()
+                .with_context(|| format!("'{word}' is not a number: {value}")),
+            _ => Ok(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "ENV" => Some("Prod".into()),
+            "N" => Some("41".into()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    fn subst(text: &str) -> String {
+        substitute(text, &lookup).unwrap().into_owned()
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(subst("logs/$ENV/${ENV}.log"), "logs/Prod/Prod.log");
+        assert_eq!(subst("$UNSET/${UNSET}/$/${x"), "$UNSET/${UNSET}/$/${x");
+        assert_eq!(subst("${UNSET:-dev}-${EMPTY:-$ENV}"), "dev-Prod");
+        assert_eq!(subst("${ENV:-dev}"), "Prod");
+        assert_eq!(
+            subst("${#ENV} ${#UNSET} ${ENV^^} ${ENV,,}"),
+            "4 0 PROD prod"
+        );
+        assert!(substitute("${ENV:x}", &lookup).is_err());
+        assert!(substitute("${:-x}", &lookup).is_err());
+        assert!(matches!(substitute("plain", &lookup), Ok(Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(subst("$((1+2))"), "3");
+        assert_eq!(subst("part-$(( (N + 1) * 2 % 5 - -1 )).log"), "part-5.log");
+        assert_eq!(subst("$((UNSET * 3 + 7 / 2))"), "3");
+        assert!(substitute("$((1 / 0))", &lookup).is_err());
+        assert!(substitute("$((ENV + 1))", &lookup).is_err());
+        assert!(substitute("$((1 +))", &lookup).is_err());
+        assert!(substitute("$((1 2))", &lookup).is_err());
+        assert!(substitute("$((1 + 2)", &lookup).is_err());
+        assert!(substitute("$((9223372036854775807 + 1))", &lookup).is_err());
+    }
+
+    #[test]
+    fn deep_nesting() {
+        assert_eq!(subst(&format!("$(({}1))", "-".repeat(64))), "1");
+        let too_deep = |text: String| {
+            let err = substitute(&text, &lookup).unwrap_err();
+            assert!(format!("{err:#}").contains("too deeply nested"), "{err:#}");
+        };
+        too_deep(format!("$(({}1))", "-".repeat(200_000)));
+        too_deep(format!(
+            "$(({}1{}))",
+            "(".repeat(200_000),
+            ")".repeat(200_000)
+        ));
+        too_deep(format!(
+            "{}x{}",
+            "${U:-".repeat(200_000),
+            "}".repeat(200_000)
+        ));
+    }
+}
@@ -0,0 +1,349 @@
+//! Splitting of command lines into arguments using shell-like syntax.
+
+use std::borrow::Cow;
+use std::ops::{Deref, Range};
+
+use shell_words::ParseError;
+
+/// Argument of a command line, with its position in the line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Token<'a> {
+    /// Argument after removing quotes and escapes. Borrowed from the line unless they had to
+    /// be removed from the middle of it.
+    pub(crate) text: Cow<'a, str>,
+    /// Bytes of the line taken by the argument, including quotes.
+    pub(crate) span: Range<usize>,
+    /// Whether part of the argument was quoted or escaped, so that it is not syntax like a
+    /// `|` separating a redirection.
+    pub(crate) quoted: bool,
+}
+
+impl<'a> Token<'a> {
+    pub(crate) fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Same token, not borrowing from the line.
+    pub(crate) fn into_owned(self) -> Token<'static> {
+        Token {
+            text: Cow::Owned(self.text.into_owned()),
+            span: self.span,
+            quoted: self.quoted,
+        }
+    }
+}
+
+impl Deref for Token<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl std::fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl AsRef<str> for Token<'_> {
+    fn as_ref(&self) -> &str {
+        &self.text
+    }
+}
+
+/// Text of a token being read.
+enum Text {
+    Empty,
+    /// Contiguous bytes of the line, as long as nothing had to be removed in between.
+    Borrowed(Range<usize>),
+    Owned(String),
+}
+
+struct Word<'a> {
+    line: &'a str,
+    start: usize,
+    text: Text,
+    quoted: bool,
+}
+
+impl<'a> Word<'a> {
+    fn new(line: &'a str, start: usize) -> Self {
+        Self {
+            line,
+            start,
+            text: Text::Empty,
+            quoted: false,
+        }
+    }
+
+    /// Add character `c` found at byte `i` of the line.
+    fn push(&mut self, i: usize, c: char) {
+        let end = i + c.len_utf8();
+        self.text = match std::mem::replace(&mut self.text, Text::Empty) {
+            Text::Empty => Text::Borrowed(i..end),
+            Text::Borrowed(range) if range.end == i => Text::Borrowed(range.start..end),
+            Text::Borrowed(range) => {
+                let mut text = self.line[range].to_string();
+                text.push(c);
+                Text::Owned(text)
+            }
+            Text::Owned(mut text) => {
+                text.push(c);
+                Text::Owned(text)
+            }
+        };
+    }
+
+    /// Token ending before byte `end` of the line.
+    fn finish(self, end: usize) -> Token<'a> {
+        let text = match self.text {
+            Text::Empty => Cow::Borrowed(""),
+            Text::Borrowed(range) => Cow::Borrowed(&self.line[range]),
+            Text::Owned(text) => Cow::Owned(text),
+        };
+        Token {
+            text,
+            span: self.start..end,
+            quoted: self.quoted,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Delimiter,
+    Backslash,
+    Unquoted,
+    UnquotedBackslash,
+    SingleQuoted,
+    DoubleQuoted,
+    DoubleQuotedBackslash,
+    Comment,
+}
+
+/// Split `line` into arguments like [`shell_words::split`] does, keeping their positions.
+///
+/// With `literal_backslashes` a backslash is a regular character, not an escape.
+pub(crate) fn tokenize(
+    line: &str,
+    literal_backslashes: bool,
+) -> Result<Vec<Token<'_>>, ParseError> {
+    lex(line, literal_backslashes, |_, _| {}).map_err(|_| ParseError)
+}
+
+/// Quote still open at the end of a line, see [`lex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UnclosedQuote {
+    /// Byte of the line where the quote starts.
+    pub(crate) start: usize,
+}
+
+/// Same as [`tokenize`], also calling `bare` with the position of each character of an
+/// argument that is neither quoted, escaped nor in a comment, i.e. that may be syntax.
+pub(crate) fn lex(
+    line: &str,
+    literal_backslashes: bool,
+    mut bare: impl FnMut(usize, char),
+) -> Result<Vec<Token<'_>>, UnclosedQuote> {
+    use State::*;
+
+    let mut tokens = Vec::new();
+    let mut word = Word::new(line, 0);
+    let mut state = Delimiter;
+    let mut quote = UnclosedQuote { start: 0 };
+    let mut chars = line.char_indices();
+    loop {
+        let (i, c) = match chars.next() {
+            Some((i, c)) => (i, Some(c)),
+            None => (line.len(), None),
+        };
+        state = match state {
+            Delimiter => match c {
+                None => break,
+                Some(' ' | '\t' | '\n') => Delimiter,
+                Some('#') => Comment,
+                Some(c) => {
+                    word = Word::new(line, i);
+                    word.quoted = matches!(c, '\'' | '"') || (c == '\\' && !literal_backslashes);
+                    match c {
+                        '\'' => {
+                            quote.start = i;
+                            SingleQuoted
+                        }
+                        '"' => {
+                            quote.start = i;
+                            DoubleQuoted
+                        }
+                        '\\' if !literal_backslashes => Backslash,
+                        c => {
+                            bare(i, c);
+                            word.push(i, c);
+                            Unquoted
+                        }
+                    }
+                }
+            },
+            Backslash | UnquotedBackslash => match c {
+                None => {
+                    word.push(i - 1, '\\');
+                    tokens.push(word.finish(i));
+                    break;
+                }
+                // line continuation
+                Some('\n') if matches!(state, Backslash) => Delimiter,
+                Some('\n') => Unquoted,
+                Some(c) => {
+                    word.push(i, c);
+                    Unquoted
+                }
+            },
+            Unquoted => match c {
+                None => {
+                    tokens.push(word.finish(i));
+                    break;
+                }
+                Some('\'') => {
+                    word.quoted = true;
+                    quote.start = i;
+                    SingleQuoted
+                }
+                Some('"') => {
+                    word.quoted = true;
+                    quote.start = i;
+                    DoubleQuoted
+                }
+                Some('\\') if !literal_backslashes => {
+                    word.quoted = true;
+                    UnquotedBackslash
+                }
+                Some(' ' | '\t' | '\n') => {
+                    tokens.push(std::mem::replace(&mut word, Word::new(line, i)).finish(i));
+                    Delimiter
+                }
+                Some(c) => {
+                    bare(i, c);
+                    word.push(i, c);
+                    Unquoted
+                }
+            },
+            SingleQuoted => match c {
+                None => return Err(quote),
+                Some('\'') => Unquoted,
+                Some(c) => {
+                    word.push(i, c);
+                    SingleQuoted
+                }
+            },
+            DoubleQuoted => match c {
+                None => return Err(quote),
+                Some('"') => Unquoted,
+                Some('\\') if !literal_backslashes => DoubleQuotedBackslash,
+                Some(c) => {
+                    word.push(i, c);
+                    DoubleQuoted
+                }
+            },
+            DoubleQuotedBackslash => match c {
+                None => return Err(quote),
+                Some('\n') => DoubleQuoted,
+                Some(c @ ('$' | '`' | '"' | '\\')) => {
+                    word.push(i, c);
+                    DoubleQuoted
+                }
+                // not an escape, the backslash is kept
+                Some(c) => {
+                    word.push(i - 1, '\\');
+                    word.push(i, c);
+                    DoubleQuoted
+                }
+            },
+            Comment => match c {
+                None => break,
+                Some('\n') => Delimiter,
+                Some(_) => Comment,
+            },
+        };
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(line: &str, literal_backslashes: bool) -> Vec<String> {
+        let tokens = tokenize(line, literal_backslashes).unwrap();
+        tokens.iter().map(|token| token.to_string()).collect()
+    }
+
+    #[test]
+    fn same_as_shell_words() {
+        let lines = [
+            "",
+            "  add 1  2 ",
+            r#"say "hello world" 'a b' c\ d"#,
+            r#"echo "a\"b\$c\d" 'x\y' e\"#,
+            "multi\\\nline \"quoted\\\nbreak\" # comment\nnext",
+            "é 'ü'ñ",
+            "empty '' \"\"",
+        ];
+        for line in lines {
+            assert_eq!(texts(line, false), shell_words::split(line).unwrap());
+        }
+        assert!(tokenize("say 'unclosed", false).is_err());
+        assert!(tokenize("say \"unclosed\\", false).is_err());
+
+        let mut bare = String::new();
+        let lexed = lex("a'b'\\c \"d\" e # f 'g", false, |_, c| bare.push(c));
+        assert_eq!(lexed.map(|tokens| tokens.len()), Ok(3));
+        assert_eq!(bare, "ae");
+        assert_eq!(
+            lex("say 'a' \"b", false, |_, _| {}),
+            Err(UnclosedQuote { start: 8 })
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let line = r#"set  name="a b" 'x'y plain"#;
+        let tokens = tokenize(line, false).unwrap();
+        let spans: Vec<&str> = tokens.iter().map(|t| &line[t.span.clone()]).collect();
+        assert_eq!(spans, vec!["set", r#"name="a b""#, "'x'y", "plain"]);
+        // only tokens with quotes or escapes within the text need to be copied
+        let borrowed: Vec<bool> = tokens
+            .iter()
+            .map(|t| matches!(t.text, Cow::Borrowed(_)))
+            .collect();
+        assert_eq!(borrowed, vec![true, false, false, true]);
+        assert_eq!(tokens[2].as_str(), "xy");
+        let quoted: Vec<bool> = tokens.iter().map(|t| t.quoted).collect();
+        assert_eq!(quoted, vec![false, true, true, false]);
+        let tokens = tokenize(r#"| "|" \| a\b '' C:\x"#, true).unwrap();
+        let quoted: Vec<bool> = tokens.iter().map(|t| t.quoted).collect();
+        assert_eq!(quoted, vec![false, true, false, false, true, false]);
+        let tokens = tokenize(r"\| a\b", false).unwrap();
+        assert!(tokens.iter().all(|t| t.quoted));
+    }
+
+    #[test]
+    fn windows_paths() {
+        let line = r#"open C:\dir\file 'a\b' "\\server\share" x\ y"#;
+        assert_eq!(
+            texts(line, true),
+            vec![
+                "open",
+                r"C:\dir\file",
+                r"a\b",
+                r"\\server\share",
+                r"x\",
+                "y"
+            ]
+        );
+        assert_eq!(
+            texts(line, false),
+            vec!["open", "C:dirfile", r"a\b", r"\server\share", "x y"]
+        );
+    }
+}
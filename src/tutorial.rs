@@ -0,0 +1,133 @@
+//! Guided walkthroughs of the commands of an application, see
+//! [`Repl::start_tutorial`](crate::Repl::start_tutorial).
+//!
+//! A [`Tutorial`] is a sequence of steps, each with an instruction and the command line
+//! expected from the user. The expected line is a pattern: its words match the command
+//! name and its arguments one by one, with the shell syntax of [globs](crate::repl::Globbing)
+//! (`*`, `?` and `[abc]`), and a last word `...` matches any remaining arguments. Command
+//! names are matched after resolving prefixes and abbreviations.
+//!
+//! ```rust
+//! use mini_async_repl::tutorial::Tutorial;
+//!
+//! let tutorial = Tutorial::new()
+//!     .step("Connect to the test database", "connect test-*")
+//!     .step("List its tables", "tables ...")
+//!     .finished("You are ready to go!");
+//! assert_eq!(tutorial.len(), 2);
+//! ```
+
+use crate::glob;
+
+/// Step of a [`Tutorial`].
+struct Step {
+    instruction: String,
+    /// Expected command line as given.
+    expected: String,
+    /// Words of the expected command line.
+    pattern: Vec<String>,
+}
+
+/// Sequence of steps guiding the user through commands, see [`tutorial`](self).
+#[derive(Default)]
+pub struct Tutorial {
+    steps: Vec<Step>,
+    finished: Option<String>,
+}
+
+impl Tutorial {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a step showing `instruction` and waiting for a command line matching
+    /// `expected`, e.g. `connect *`.
+    pub fn step(mut self, instruction: &str, expected: &str) -> Self {
+        let pattern = shell_words::split(expected)
+            .unwrap_or_else(|_| expected.split_whitespace().map(str::to_string).collect());
+        self.steps.push(Step {
+            instruction: instruction.into(),
+            expected: expected.into(),
+            pattern,
+        });
+        self
+    }
+
+    /// Message printed after the last step. Defaults to "Tutorial complete".
+    pub fn finished(mut self, message: &str) -> Self {
+        self.finished = Some(message.into());
+        self
+    }
+
+    /// Number of steps.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Returns true if there are no steps.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Instruction of step `index` (starting at 0) with the progress, e.g. `Step 1/3: ...`.
+    pub(crate) fn instruction(&self, index: usize) -> Option<String> {
+        let step = self.steps.get(index)?;
+        Some(format!(
+            "Step {}/{}: {}",
+            index + 1,
+            self.steps.len(),
+            step.instruction
+        ))
+    }
+
+    /// Expected command line of step `index`, as given.
+    pub(crate) fn expected(&self, index: usize) -> &str {
+        self.steps.get(index).map_or("", |step| &step.expected)
+    }
+
+    /// Message printed after the last step.
+    pub(crate) fn finished_message(&self) -> &str {
+        self.finished.as_deref().unwrap_or("Tutorial complete")
+    }
+
+    /// Returns true if command `name` with `args` is the one expected by step `index`.
+    pub(crate) fn matches(&self, index: usize, name: &str, args: &[&str]) -> bool {
+        let Some(step) = self.steps.get(index) else {
+            return false;
+        };
+        let (rest, words) = match step.pattern.split_last() {
+            Some((last, words)) if last == "..." => (true, words),
+            _ => (false, &step.pattern[..]),
+        };
+        let line = std::iter::once(name).chain(args.iter().copied());
+        let count = args.len() + 1;
+        (count == words.len() || (rest && count >= words.len()))
+            && words
+                .iter()
+                .zip(line)
+                .all(|(pattern, word)| glob::matches_word(pattern, word))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        let tutorial = Tutorial::new()
+            .step("Connect", "connect test-*")
+            .step("List", "tables ...")
+            .step("Quoted", "echo 'a b' [xy]");
+        assert!(tutorial.matches(0, "connect", &["test-1"]));
+        assert!(!tutorial.matches(0, "connect", &["prod-1"]));
+        assert!(!tutorial.matches(0, "connect", &["test-1", "x"]));
+        assert!(!tutorial.matches(0, "disconnect", &["test-1"]));
+        assert!(tutorial.matches(1, "tables", &[]));
+        assert!(tutorial.matches(1, "tables", &["--all", "x"]));
+        assert!(tutorial.matches(2, "echo", &["a b", "y"]));
+        assert!(!tutorial.matches(3, "echo", &[]));
+        assert_eq!(tutorial.instruction(1).unwrap(), "Step 2/3: List");
+        assert_eq!(tutorial.expected(0), "connect test-*");
+    }
+}
@@ -0,0 +1,406 @@
+//! Composable validation of command arguments, see [`Command::validator`].
+//!
+//! Arguments are always checked against the declared number and types of arguments first
+//! (see [`Types`]). Commands can add constraints built from:
+//!
+//! - [`Arg`]: a check of the value of a single argument, e.g. a range of ports,
+//! - [`Cross`]: a check of a combination of arguments, e.g. `start < end`,
+//! - [`Typed`]: a check of all the arguments by a closure taking their parsed values,
+//!   e.g. `|x: i32, y: i32| x <= y`, usually added with [`Command::check_args`],
+//! - [`All`]: validators run in order until one fails,
+//! - any `Fn(&[&str], &[CommandArgInfo]) -> Result<(), ArgsError>` closure.
+//!
+//! ```
+//! # use mini_async_repl::command::{Command, CommandArgInfo, CommandArgType, TrivialCommandHandler};
+//! # use mini_async_repl::validate::{Arg, Cross};
+//! let range = Command::new(
+//!     "Show lines from START to END",
+//!     vec![
+//!         CommandArgInfo::new_with_name(CommandArgType::I32, "start"),
+//!         CommandArgInfo::new_with_name(CommandArgType::I32, "end"),
+//!     ],
+//!     Box::new(TrivialCommandHandler::new()),
+//! )
+//! .validator(Arg::range("start", 1..))
+//! .validator(Cross::less_than("start", "end"));
+//! ```
+//!
+//! Failures are [`ArgsError`]s, so the REPL reports them together with the usage of the
+//! command, and overload resolution moves on to the next overload, just like for arguments
+//! of the wrong type.
+//!
+//! [`Command::validator`]: crate::command::Command::validator
+//! [`Command::check_args`]: crate::command::Command::check_args
+
+use std::fmt::Display;
+use std::ops::RangeBounds;
+use std::str::FromStr;
+
+use crate::bytes;
+use crate::command::{ArgsError, CommandArgInfo, CommandArgType};
+
+/// Check of command arguments, see the [module documentation](self).
+pub trait Validate: Send + Sync {
+    /// Check `args` of a command taking `args_info`.
+    ///
+    /// Except for [`Types`], validators can assume that the arguments have the declared
+    /// number and types.
+    fn validate(&self, args: &[&str], args_info: &[CommandArgInfo]) -> Result<(), ArgsError>;
+}
+
+impl<F> Validate for F
+where
+    F: Fn(&[&str], &[CommandArgInfo]) -> Result<(), ArgsError> + Send + Sync,
+{
+    fn validate(&self, args: &[&str], args_info: &[CommandArgInfo]) -> Result<(), ArgsError> {
+        self(args, args_info)
+    }
+}
+
+/// Checks the number of arguments and that [`i32`](CommandArgType::I32),
+/// [`f32`](CommandArgType::F32) and [`bytes`](CommandArgType::Bytes) arguments parse.
+///
+/// This is [`validate`](crate::command::validate), which runs before the validators of
+/// every command.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Types;
+
+impl Validate for Types {
+    fn validate(&self, args: &[&str], args_info: &[CommandArgInfo]) -> Result<(), ArgsError> {
+        if let Some(token) = args.get(args_info.len()) {
+            return Err(ArgsError::UnexpectedToken {
+                token: token.to_string(),
+                expected: args_info.len(),
+            });
+        } else if args.len() != args_info.len() {
+            return Err(ArgsError::WrongNumberOfArguments {
+                got: args.len(),
+                expected: args_info.len(),
+            });
+        }
+
+        for (arg_value, arg_info) in args.iter().zip(args_info) {
+            let parsed = match arg_info.arg_type {
+                CommandArgType::I32 => arg_value
+                    .parse::<i32>()
+                    .map(drop)
+                    .map_err(|e| e.to_string()),
+                CommandArgType::F32 => arg_value
+                    .parse::<f32>()
+                    .map(drop)
+                    .map_err(|e| e.to_string()),
+                CommandArgType::Bytes => {
+                    bytes::parse(arg_value).map(drop).map_err(|e| e.to_string())
+                }
+                CommandArgType::String | CommandArgType::Custom => Ok(()),
+            };
+            parsed.map_err(|error| ArgsError::WrongArgumentValue {
+                argument: arg_value.to_string(),
+                error,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Validators run in order, failing with the error of the first one that fails.
+#[derive(Default)]
+pub struct All(Vec<Box<dyn Validate>>);
+
+impl All {
+    /// No validators, accepting any arguments.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `validator` after the validators added before.
+    pub fn and<V: Validate + 'static>(mut self, validator: V) -> Self {
+        self.0.push(Box::new(validator));
+        self
+    }
+
+    /// Returns true if there are no validators.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Validate for All {
+    fn validate(&self, args: &[&str], args_info: &[CommandArgInfo]) -> Result<(), ArgsError> {
+        self.0
+            .iter()
+            .try_for_each(|validator| validator.validate(args, args_info))
+    }
+}
+
+/// Check of an argument value, returning why it is invalid.
+type ArgCheck = dyn Fn(&str) -> Result<(), String> + Send + Sync;
+/// Check of argument values, returning why they are invalid.
+type CrossCheck = dyn Fn(&[&str]) -> Result<(), String> + Send + Sync;
+
+/// Position of the argument `name` in `args_info`.
+fn position(name: &str, args_info: &[CommandArgInfo]) -> Result<usize, ArgsError> {
+    args_info
+        .iter()
+        .position(|info| info.name.as_deref() == Some(name))
+        .ok_or_else(|| ArgsError::Invalid(format!("validator of unknown argument '{name}'")))
+}
+
+/// Check of the value of a single argument, identified by its name.
+///
+/// A failure is reported as [`ArgsError::WrongArgumentValue`] with the message returned by
+/// the check.
+pub struct Arg {
+    name: String,
+    check: Box<ArgCheck>,
+}
+
+impl Arg {
+    /// Check the value of argument `name` with `check`, which returns why it is invalid.
+    pub fn new<F>(name: &str, check: F) -> Self
+    where
+        F: Fn(&str) -> Result<(), String> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            check: Box::new(check),
+        }
+    }
+
+    /// Require the value of argument `name` to be in `range`, e.g. `1..=65535`.
+    pub fn range<T, R>(name: &str, range: R) -> Self
+    where
+        T: FromStr + PartialOrd + Display,
+        T::Err: Display,
+        R: RangeBounds<T> + std::fmt::Debug + Send + Sync + 'static,
+    {
+        Self::new(name, move |value| {
+            let value: T = value.parse().map_err(|e: T::Err| e.to_string())?;
+            match range.contains(&value) {
+                true => Ok(()),
+                false => Err(format!("{value} is not in range {range:?}")),
+            }
+        })
+    }
+
+    /// Require the value of argument `name` to be one of `values`.
+    pub fn one_of<I, S>(name: &str, values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let values: Vec<String> = values.into_iter().map(Into::into).collect();
+        Self::new(name, move |value| match values.iter().any(|v| v == value) {
+            true => Ok(()),
+            false => Err(format!("expected one of: {}", values.join(", "))),
+        })
+    }
+}
+
+impl Validate for Arg {
+    fn validate(&self, args: &[&str], args_info: &[CommandArgInfo]) -> Result<(), ArgsError> {
+        let value = args[position(&self.name, args_info)?];
+        (self.check)(value).map_err(|error| ArgsError::WrongArgumentValue {
+            argument: value.into(),
+            error,
+        })
+    }
+}
+
+/// Check of a combination of arguments, identified by their names.
+///
+/// A failure is reported as [`ArgsError::Invalid`] with the message returned by the check.
+pub struct Cross {
+    names: Vec<String>,
+    check: Box<CrossCheck>,
+}
+
+impl Cross {
+    /// Check the values of the arguments `names` with `check`, which receives them in the
+    /// same order and returns why they are invalid.
+    pub fn new<I, S, F>(names: I, check: F) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+        F: Fn(&[&str]) -> Result<(), String> + Send + Sync + 'static,
+    {
+        Self {
+            names: names.into_iter().map(Into::into).collect(),
+            check: Box::new(check),
+        }
+    }
+
+    /// Require the number given as argument `lower` to be less than the one given as `upper`.
+    pub fn less_than(lower: &str, upper: &str) -> Self {
+        let message = format!("'{lower}' must be less than '{upper}'");
+        Self::new([lower, upper], move |values| {
+            let numbers: Vec<f64> = values.iter().filter_map(|v| v.parse().ok()).collect();
+            match numbers[..] {
+                [lower, upper] if lower < upper => Ok(()),
+                _ => Err(message.clone()),
+            }
+        })
+    }
+}
+
+impl Validate for Cross {
+    fn validate(&self, args: &[&str], args_info: &[CommandArgInfo]) -> Result<(), ArgsError> {
+        let values = self
+            .names
+            .iter()
+            .map(|name| Ok(args[position(name, args_info)?]))
+            .collect::<Result<Vec<_>, ArgsError>>()?;
+        (self.check)(&values).map_err(ArgsError::Invalid)
+    }
+}
+
+/// Closure receiving all the arguments of a command, parsed to the types of its
+/// parameters, and returning whether they are valid, see [`Typed`].
+///
+/// Implemented for closures with up to 4 parameters implementing [`FromStr`], e.g.
+/// `|x: i32, y: i32| x <= y`.
+pub trait TypedCheck<Args> {
+    /// Parse `args` and call the closure, or return `None` if there are not as many as the
+    /// closure takes or one of them does not parse.
+    fn call(&self, args: &[&str]) -> Option<bool>;
+}
+
+macro_rules! typed_check {
+    ($($arg:ident: $ty:ident),+) => {
+        impl<F, $($ty),+> TypedCheck<($($ty,)+)> for F
+        where
+            F: Fn($($ty),+) -> bool,
+            $($ty: FromStr),+
+        {
+            fn call(&self, args: &[&str]) -> Option<bool> {
+                let [$($arg),+] = args else {
+                    return None;
+                };
+                Some(self($($arg.parse().ok()?),+))
+            }
+        }
+    };
+}
+
+typed_check!(a: A);
+typed_check!(a: A, b: B);
+typed_check!(a: A, b: B, c: C);
+typed_check!(a: A, b: B, c: C, d: D);
+
+/// Check of all the arguments of a command by a closure taking their parsed values, see
+/// [`Command::check_args`](crate::command::Command::check_args).
+///
+/// A failure is reported as [`ArgsError::Invalid`] with the message of the validator. So are
+/// arguments that do not parse as the types of the closure parameters, or whose number
+/// differs from the number of parameters.
+pub struct Typed<F, Args> {
+    check: F,
+    message: String,
+    args: std::marker::PhantomData<fn(Args)>,
+}
+
+impl<F, Args> Typed<F, Args>
+where
+    F: TypedCheck<Args>,
+{
+    /// Check the arguments with `check`, failing with `message`.
+    pub fn new(check: F, message: &str) -> Self {
+        Self {
+            check,
+            message: message.into(),
+            args: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<F, Args> Validate for Typed<F, Args>
+where
+    F: TypedCheck<Args> + Send + Sync,
+{
+    fn validate(&self, args: &[&str], _args_info: &[CommandArgInfo]) -> Result<(), ArgsError> {
+        match self.check.call(args) {
+            Some(true) => Ok(()),
+            _ => Err(ArgsError::Invalid(self.message.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline() {
+        let args_info = vec![
+            CommandArgInfo::new_with_name(CommandArgType::I32, "start"),
+            CommandArgInfo::new_with_name(CommandArgType::I32, "end"),
+            CommandArgInfo::new_with_name(CommandArgType::String, "mode"),
+        ];
+        let validator = All::new()
+            .and(Arg::range("start", 1..))
+            .and(Arg::one_of("mode", ["fast", "slow"]))
+            .and(Cross::less_than("start", "end"));
+        assert!(validator.validate(&["1", "2", "fast"], &args_info).is_ok());
+
+        let err = validator
+            .validate(&["0", "2", "fast"], &args_info)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to parse argument value '0': 0 is not in range 1.."
+        );
+        let err = validator
+            .validate(&["1", "2", "quick"], &args_info)
+            .unwrap_err();
+        assert!(err.to_string().ends_with("expected one of: fast, slow"));
+        let err = validator
+            .validate(&["3", "2", "slow"], &args_info)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid arguments: 'start' must be less than 'end'"
+        );
+
+        let closure = |args: &[&str], _: &[CommandArgInfo]| match args[2] {
+            "slow" => Err(ArgsError::Invalid("too slow".into())),
+            _ => Ok(()),
+        };
+        let validator = All::new().and(closure);
+        assert!(validator.validate(&["3", "2", "slow"], &args_info).is_err());
+        assert!(matches!(
+            Arg::range("count", 0..3).validate(&["1", "2", "fast"], &args_info),
+            Err(ArgsError::Invalid(msg)) if msg.contains("'count'")
+        ));
+    }
+
+    #[test]
+    fn typed() {
+        let args_info = vec![
+            CommandArgInfo::new(CommandArgType::I32),
+            CommandArgInfo::new(CommandArgType::F32),
+            CommandArgInfo::new(CommandArgType::String),
+        ];
+        let validator = Typed::new(
+            |x: i32, y: f32, verbose: bool| verbose || x as f32 <= y,
+            "x must not be greater than y",
+        );
+        assert!(validator
+            .validate(&["1", "2.5", "false"], &args_info)
+            .is_ok());
+        assert!(validator
+            .validate(&["3", "2.5", "true"], &args_info)
+            .is_ok());
+        let err = validator
+            .validate(&["3", "2.5", "false"], &args_info)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid arguments: x must not be greater than y"
+        );
+        // not a bool
+        assert!(validator
+            .validate(&["1", "2.5", "yes"], &args_info)
+            .is_err());
+        assert!(validator.validate(&["1", "2.5"], &args_info).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Driving the REPL from JavaScript, e.g. in a web playground.
+//!
+//! Without the `terminal` feature the crate compiles to `wasm32-unknown-unknown`, so the
+//! command definitions of a native tool can be reused in the browser. The application
+//! builds the REPL as usual and hands a [`JsRepl`] to JavaScript:
+//!
+//! ```rust,ignore
+//! #[wasm_bindgen]
+//! pub fn playground() -> JsRepl {
+//!     JsRepl::new(my_tool::commands(Repl::builder())).unwrap()
+//! }
+//! ```
+//!
+//! which can then be used with any text widget:
+//!
+//! ```text
+//! const repl = playground();
+//! output.textContent += repl.prompt() + line + "\n" + await repl.handleLine(line);
+//! ```
+//!
+//! Commands created with [`Command::new_heavy`](crate::command::Command::new_heavy) need a
+//! [`Runtime`](crate::runtime::Runtime) that works in the browser, see
+//! [`ReplBuilder::runtime`], and [`ReplBuilder::completion_cache_ttl`] cannot be used as
+//! there is no clock in `wasm32-unknown-unknown`.
+//!
+//! Requires the `wasm` feature.
+
+use std::cell::{Cell, RefCell};
+use std::io::Write;
+use std::rc::Rc;
+
+use futures::lock::Mutex as AsyncMutex;
+use wasm_bindgen::prelude::*;
+
+use crate::repl::{BuilderError, LoopStatus, Repl, ReplBuilder};
+
+/// REPL exported to JavaScript.
+///
+/// Lines are handled one at a time; while a line is being handled, [`JsRepl::prompt`] and
+/// [`JsRepl::complete`] return empty results.
+#[wasm_bindgen]
+pub struct JsRepl {
+    repl: Rc<AsyncMutex<Repl>>,
+    output: Rc<RefCell<Vec<u8>>>,
+    closed: Rc<Cell<bool>>,
+}
+
+impl JsRepl {
+    /// Build a REPL from `builder` with its output collected for [`JsRepl::handle_line`].
+    pub fn new(builder: ReplBuilder) -> Result<Self, BuilderError> {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let out: Box<dyn Write> = Box::new(SharedOutput(output.clone()));
+        let repl = builder.out(out).build()?;
+        Ok(Self {
+            repl: Rc::new(AsyncMutex::new(repl)),
+            output,
+            closed: Rc::new(Cell::new(false)),
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl JsRepl {
+    /// Handle `line` as if it has been entered by the user. Pasted text with multiple lines
+    /// is handled line by line, see [`Repl::handle_lines`].
+    ///
+    /// Resolves to the output produced, or rejects with the message of a critical error.
+    #[wasm_bindgen(js_name = handleLine)]
+    pub fn handle_line(&self, line: String) -> js_sys::Promise {
+        let repl = self.repl.clone();
+        let output = self.output.clone();
+        let closed = self.closed.clone();
+        wasm_bindgen_futures::future_to_promise(async move {
+            let mut repl = repl.lock().await;
+            let result = repl.handle_lines(&line).await;
+            let text = String::from_utf8_lossy(&output.take()).into_owned();
+            match result {
+                Ok(status) => {
+                    closed.set(closed.get() || status == LoopStatus::Break);
+                    Ok(JsValue::from_str(&text))
+                }
+                Err(err) => Err(JsValue::from_str(&format!("{text}{err}"))),
+            }
+        })
+    }
+
+    /// Whether a command has quit the REPL.
+    #[wasm_bindgen(getter, js_name = isClosed)]
+    pub fn is_closed(&self) -> bool {
+        self.closed.get()
+    }
+
+    /// Prompt to show before the input.
+    pub fn prompt(&self) -> String {
+        self.repl
+            .try_lock()
+            .map(|repl| repl.prompt().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Help message listing all commands.
+    pub fn help(&self) -> String {
+        self.repl
+            .try_lock()
+            .map(|repl| repl.help())
+            .unwrap_or_default()
+    }
+
+    /// Lines resulting from accepting each completion of `line` with cursor at `pos`.
+    ///
+    /// `pos` is an index into the JavaScript (UTF-16) string.
+    pub fn complete(&self, line: String, pos: usize) -> Vec<String> {
+        let Some(repl) = self.repl.try_lock() else {
+            return Vec::new();
+        };
+        let pos = byte_index(&line, pos);
+        let (start, candidates) = repl.complete(&line, pos);
+        candidates
+            .into_iter()
+            .map(|c| format!("{}{}{}", &line[..start], c.replacement, &line[pos..]))
+            .collect()
+    }
+}
+
+/// Byte index of the character at UTF-16 index `pos` (or end of `line`).
+fn byte_index(line: &str, pos: usize) -> usize {
+    let mut utf16 = 0;
+    for (i, c) in line.char_indices() {
+        if utf16 >= pos {
+            return i;
+        }
+        utf16 += c.len_utf16();
+    }
+    line.len()
+}
+
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf16_positions() {
+        assert_eq!(byte_index("abc", 1), 1);
+        assert_eq!(byte_index("żółw x", 4), 7);
+        assert_eq!(byte_index("🐢 x", 2), 4);
+        assert_eq!(byte_index("ab", 10), 2);
+    }
+}